@@ -0,0 +1,46 @@
+//! Compare hashing a large file through a buffer and by memory-mapping it.
+//!
+//! Run with `cargo bench --features mmap --bench hash_mmap`.
+
+use std::fs::File;
+use std::io::{Seek, SeekFrom, Write};
+use std::time::{Duration, Instant};
+
+use in_toto::crypto::{calculate_file_hashes, HashAlgorithm, DEFAULT_HASH_BUFFER_SIZE};
+
+const FILE_SIZE: usize = 256 * 1024 * 1024;
+const ROUNDS: u32 = 5;
+
+fn time<F: FnMut()>(mut f: F) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        f();
+    }
+    start.elapsed() / ROUNDS
+}
+
+fn main() {
+    let mut file = tempfile::tempfile().unwrap();
+    let chunk: Vec<u8> = (0..1024 * 1024).map(|i| (i % 251) as u8).collect();
+    for _ in 0..FILE_SIZE / chunk.len() {
+        file.write_all(&chunk).unwrap();
+    }
+    let algs = [HashAlgorithm::Sha256];
+
+    let hash = |file: &mut File, threshold| {
+        let _ = file.seek(SeekFrom::Start(0)).unwrap();
+        calculate_file_hashes(file, &algs, DEFAULT_HASH_BUFFER_SIZE, threshold).unwrap()
+    };
+    assert_eq!(hash(&mut file, u64::MAX), hash(&mut file, 0));
+
+    let buffered = time(|| {
+        let _ = hash(&mut file, u64::MAX);
+    });
+    let mapped = time(|| {
+        let _ = hash(&mut file, 0);
+    });
+
+    println!("{} MiB file", FILE_SIZE / (1024 * 1024));
+    println!("buffered: {:?}", buffered);
+    println!("mmap:     {:?}", mapped);
+}
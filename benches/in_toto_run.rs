@@ -0,0 +1,53 @@
+//! Compare recording a step whose materials and products overlap with and without a shared
+//! `HashCache`.
+//!
+//! Run with `cargo bench --bench in_toto_run`.
+
+use std::env;
+use std::fs;
+use std::time::{Duration, Instant};
+
+use in_toto::crypto::{PrivateKey, SignatureScheme};
+use in_toto::runlib::{in_toto_run, record_artifacts};
+
+const FILES: usize = 2000;
+const FILE_SIZE: usize = 64 * 1024;
+const ROUNDS: u32 = 5;
+
+fn time<F: FnMut()>(mut f: F) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        f();
+    }
+    start.elapsed() / ROUNDS
+}
+
+fn main() {
+    let key: &[u8] = include_bytes!("../tests/ed25519/ed25519-1.pk8.der");
+    let key = PrivateKey::from_pkcs8(key, SignatureScheme::Ed25519).unwrap();
+
+    // Recorded paths must be relative, so the tree lives in the crate root.
+    let dir = tempfile::tempdir_in(".").unwrap();
+    for i in 0..FILES {
+        let content = vec![(i % 251) as u8; FILE_SIZE];
+        fs::write(dir.path().join(format!("file-{}", i)), content).unwrap();
+    }
+    let cwd = env::current_dir().unwrap();
+    let root = dir.path().strip_prefix(&cwd).unwrap().to_str().unwrap();
+
+    let uncached = time(|| {
+        let _ = record_artifacts(&[root], None).unwrap();
+        let _ = record_artifacts(&[root], None).unwrap();
+    });
+    let cached = time(|| {
+        let _ = in_toto_run("bench", None, &[root], &[root], &["true"], &key, None).unwrap();
+    });
+
+    println!(
+        "{} files of {} KiB, materials == products",
+        FILES,
+        FILE_SIZE / 1024
+    );
+    println!("two uncached recording passes: {:?}", uncached);
+    println!("in_toto_run with shared cache: {:?}", cached);
+}
@@ -0,0 +1,50 @@
+//! Compare recording a large tree serially and on the rayon thread pool.
+//!
+//! Run with `cargo bench --features rayon --bench record_artifacts`.
+
+use std::env;
+use std::fs;
+use std::time::{Duration, Instant};
+
+use in_toto::runlib::{record_artifacts_with_options, RecordOptions};
+
+const DIRS: usize = 20;
+const FILES_PER_DIR: usize = 200;
+const FILE_SIZE: usize = 64 * 1024;
+const ROUNDS: u32 = 5;
+
+fn time<F: FnMut()>(mut f: F) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        f();
+    }
+    start.elapsed() / ROUNDS
+}
+
+fn main() {
+    // Recorded paths must be relative, so the tree lives in the crate root.
+    let dir = tempfile::tempdir_in(".").unwrap();
+    for d in 0..DIRS {
+        let sub = dir.path().join(format!("dir-{}", d));
+        fs::create_dir(&sub).unwrap();
+        for f in 0..FILES_PER_DIR {
+            let content = vec![((d * FILES_PER_DIR + f) % 251) as u8; FILE_SIZE];
+            fs::write(sub.join(format!("file-{}", f)), content).unwrap();
+        }
+    }
+    let cwd = env::current_dir().unwrap();
+    let root = dir.path().strip_prefix(&cwd).unwrap().to_str().unwrap();
+
+    let serial_options = RecordOptions::new();
+    let parallel_options = RecordOptions::new().parallel(true);
+    let serial = time(|| {
+        let _ = record_artifacts_with_options(&[root], None, &serial_options).unwrap();
+    });
+    let parallel = time(|| {
+        let _ = record_artifacts_with_options(&[root], None, &parallel_options).unwrap();
+    });
+
+    println!("{} files of {} KiB", DIRS * FILES_PER_DIR, FILE_SIZE / 1024);
+    println!("serial:   {:?}", serial);
+    println!("parallel: {:?}", parallel);
+}
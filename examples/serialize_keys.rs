@@ -1,4 +1,4 @@
-use in_toto::crypto::{PrivateKey, KeyType, SignatureScheme};
+use in_toto::crypto::{PrivateKey, SignatureScheme};
 use std::fs;
 use std::path::Path;
 use std::fs::OpenOptions;
@@ -7,8 +7,7 @@ use std::io::prelude::*;
 
 fn main() {
     // Generate a new Ed25519 signing key
-    let key = PrivateKey::new(KeyType::Ed25519).unwrap();
-    let mut privkey = PrivateKey::from_pkcs8(&key, SignatureScheme::Ed25519).unwrap();
+    let mut privkey = PrivateKey::new(SignatureScheme::Ed25519).unwrap();
     println!("Generated keypair {:?}", &privkey.public());
 
     let mut target = OpenOptions::new()
@@ -16,7 +15,7 @@ fn main() {
            .write(true)
            .create(true)
            .open("test-key").unwrap();
-    target.write_all(&key).unwrap();
+    target.write_all(&privkey.to_pkcs8()).unwrap();
 
     let loaded_key = fs::read("test-key").unwrap();
     privkey = PrivateKey::from_pkcs8(&loaded_key, SignatureScheme::Ed25519).unwrap();
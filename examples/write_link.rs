@@ -1,18 +1,19 @@
-use in_toto::models::{VirtualTargetPath, LinkMetadataBuilder};
-use in_toto::crypto::{PrivateKey, KeyType, SignatureScheme};
+use in_toto::models::LinkMetadataBuilder;
+use in_toto::crypto::{HashAlgorithm, PrivateKey, SignatureScheme};
 use in_toto::interchange::Json;
 use serde_json;
 
 fn main() {
     // Generate a new Ed25519 signing key
-    let key = PrivateKey::new(KeyType::Ed25519).unwrap();
-    println!("Generated keypair: {:?}", key);
-    let privkey  = PrivateKey::from_pkcs8(&key, SignatureScheme::Ed25519).unwrap();
+    let privkey = PrivateKey::new(SignatureScheme::Ed25519).unwrap();
+    println!("Generated keypair: {:?}", privkey.public());
 
     let link = LinkMetadataBuilder::new()
                 .name(String::from("test"))
-                .add_material(VirtualTargetPath::new("LICENSE".to_string()).unwrap())
-                .add_product(VirtualTargetPath::new("Makefile".to_string()).unwrap())
+                .add_material_from_path("LICENSE", &[HashAlgorithm::Sha256])
+                .unwrap()
+                .add_product_from_path("Makefile", &[HashAlgorithm::Sha256])
+                .unwrap()
                 .signed::<Json>(&privkey)
                 .unwrap();
 
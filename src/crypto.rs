@@ -1,32 +1,43 @@
 //! Cryptographic structures and functions.
 
-use data_encoding::{BASE64URL, HEXLOWER};
+use data_encoding::{BASE64, BASE64URL, HEXLOWER};
 use derp::{self, Der, Tag};
+use pkcs8::{EncryptedPrivateKeyInfo, SecretDocument};
 use ring;
-use ring::digest::{self, SHA256, SHA512};
+use ring::digest::{self, SHA256, SHA384, SHA512};
 use ring::rand::SystemRandom;
 use ring::signature::{
-    Ed25519KeyPair, KeyPair, RsaKeyPair, ED25519, RSA_PSS_2048_8192_SHA256,
-    RSA_PSS_2048_8192_SHA512, RSA_PSS_SHA256, RSA_PSS_SHA512,
+    EcdsaKeyPair, Ed25519KeyPair, KeyPair, RsaKeyPair, ECDSA_P256_SHA256_ASN1,
+    ECDSA_P256_SHA256_ASN1_SIGNING, ED25519, RSA_PKCS1_2048_8192_SHA256, RSA_PKCS1_SHA256,
+    RSA_PSS_2048_8192_SHA256, RSA_PSS_2048_8192_SHA512, RSA_PSS_SHA256, RSA_PSS_SHA512,
 };
 use serde::de::{Deserialize, Deserializer, Error as DeserializeError};
 use serde::ser::{Error as SerializeError, Serialize, Serializer};
 use serde_derive::{Deserialize, Serialize};
 use std::cmp::Ordering;
 use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::fmt::{self, Debug, Display};
 use std::hash;
-use std::io::{Read, Write};
+use std::io::{self, Read, Write};
 use std::process::{Command, Stdio};
-use std::str::FromStr;
+use std::str::{self, FromStr};
 use std::sync::Arc;
 use untrusted::Input;
+use zeroize::Zeroizing;
 
 use crate::error::Error;
 use crate::interchange::cjson::shims;
 use crate::Result;
 
-const HASH_ALG_PREFS: &[HashAlgorithm] = &[HashAlgorithm::Sha512, HashAlgorithm::Sha256];
+#[cfg(feature = "keyless")]
+pub mod keyless;
+
+const HASH_ALG_PREFS: &[HashAlgorithm] = &[
+    HashAlgorithm::Sha512,
+    HashAlgorithm::Sha384,
+    HashAlgorithm::Sha256,
+];
 
 /// 1.2.840.113549.1.1.1 rsaEncryption(PKCS #1)
 const RSA_SPKI_OID: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x01];
@@ -34,6 +45,12 @@ const RSA_SPKI_OID: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x
 /// 1.3.101.112 curveEd25519(EdDSA 25519 signature algorithm)
 const ED25519_SPKI_OID: &[u8] = &[0x2b, 0x65, 0x70];
 
+/// 1.2.840.10045.2.1 id-ecPublicKey(Elliptic curve public key cryptography)
+const ECDSA_SPKI_OID: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01];
+
+/// 1.2.840.10045.3.1.7 prime256v1(NIST P-256 curve)
+const NISTP256_OID: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07];
+
 /// The length of an ed25519 private key in bytes
 const ED25519_PRIVATE_KEY_LENGTH: usize = 32;
 
@@ -43,6 +60,14 @@ const ED25519_PUBLIC_KEY_LENGTH: usize = 32;
 /// The length of an ed25519 keypair in bytes
 const ED25519_KEYPAIR_LENGTH: usize = ED25519_PRIVATE_KEY_LENGTH + ED25519_PUBLIC_KEY_LENGTH;
 
+/// The PKCS#8v1 encoding of an ed25519 private key, up to its 32 byte seed
+const ED25519_PKCS8_V1_PREFIX: &[u8] = &[
+    0x30, 0x2e, 0x02, 0x01, 0x00, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x04, 0x22, 0x04, 0x20,
+];
+
+/// The length of the base64 lines of a PEM block
+const PEM_LINE_LENGTH: usize = 64;
+
 fn python_sslib_compatibility_keyid_hash_algorithms() -> Option<Vec<String>> {
     Some(vec!["sha256".to_string(), "sha512".to_string()])
 }
@@ -79,49 +104,158 @@ pub fn hash_preference<'a>(
 pub(crate) fn calculate_hash(data: &[u8], hash_alg: HashAlgorithm) -> HashValue {
     let mut context = hash_alg.digest_context().unwrap();
     context.update(data);
-    HashValue::new(context.finish().as_ref().to_vec())
+    HashValue::new(context.finish())
 }
 
+/// Size of the buffer `calculate_hashes` reads through.
+pub const DEFAULT_HASH_BUFFER_SIZE: usize = 8 * 1024;
+
 /// Calculate the size and hash digest from a given `Read`.
 pub fn calculate_hashes<R: Read>(
+    read: R,
+    hash_algs: &[HashAlgorithm],
+) -> Result<(u64, HashMap<HashAlgorithm, HashValue>)> {
+    calculate_hashes_with_buffer_size(read, hash_algs, DEFAULT_HASH_BUFFER_SIZE)
+}
+
+/// Calculate the size and hash digest from a given `Read`, reading it in chunks of at most
+/// `buffer_size` bytes. Every digest is fed from the same chunk in a single pass, so memory use
+/// does not depend on the size of the input.
+pub fn calculate_hashes_with_buffer_size<R: Read>(
     mut read: R,
     hash_algs: &[HashAlgorithm],
+    buffer_size: usize,
 ) -> Result<(u64, HashMap<HashAlgorithm, HashValue>)> {
-    if hash_algs.is_empty() {
+    let mut hasher = Hasher::new(hash_algs)?;
+    if buffer_size == 0 {
         return Err(Error::IllegalArgument(
-            "Cannot provide empty set of hash algorithms".into(),
+            "Cannot hash through an empty buffer".into(),
         ));
     }
 
-    let mut size = 0;
-    let mut hashes = HashMap::new();
-    for alg in hash_algs {
-        let _ = hashes.insert(alg, alg.digest_context()?);
-    }
-
-    let mut buf = vec![0; 1024];
+    let mut buf = vec![0; buffer_size];
     loop {
         match read.read(&mut buf) {
             Ok(read_bytes) => {
                 if read_bytes == 0 {
                     break;
                 }
-
-                size += read_bytes as u64;
-
-                for context in hashes.values_mut() {
-                    context.update(&buf[0..read_bytes]);
-                }
+                hasher.update(&buf[0..read_bytes]);
             }
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
             e @ Err(_) => e.map(|_| ())?,
         }
     }
 
-    let hashes = hashes
-        .drain()
-        .map(|(k, v)| (k.clone(), HashValue::new(v.finish().as_ref().to_vec())))
-        .collect();
-    Ok((size, hashes))
+    let size = hasher.size();
+    Ok((size, hasher.finalize()))
+}
+
+/// Files at least this large are memory-mapped by `calculate_file_hashes` unless told otherwise.
+#[cfg(feature = "mmap")]
+pub const DEFAULT_MMAP_THRESHOLD: u64 = 16 * 1024 * 1024;
+
+/// Calculate the size and hash digest of `file` like `calculate_hashes_with_buffer_size`, but
+/// memory-map it instead of reading it if it is a regular file of at least `mmap_threshold`
+/// bytes. Smaller files and special files, such as pipes, are read through a buffer of
+/// `buffer_size` bytes. Both ways produce the same digests.
+///
+/// A mapped file must not be truncated by another process while it is hashed: reading the pages
+/// past the new end raises `SIGBUS`, which kills the whole process instead of returning an
+/// error. Pass `u64::MAX` as `mmap_threshold` for files that may change while they are recorded.
+#[cfg(feature = "mmap")]
+pub fn calculate_file_hashes(
+    file: &std::fs::File,
+    hash_algs: &[HashAlgorithm],
+    buffer_size: usize,
+    mmap_threshold: u64,
+) -> Result<(u64, HashMap<HashAlgorithm, HashValue>)> {
+    let metadata = file.metadata()?;
+    // Empty files cannot be mapped on every platform, and have nothing to gain from it.
+    if !metadata.is_file() || metadata.len() == 0 || metadata.len() < mmap_threshold {
+        return calculate_hashes_with_buffer_size(file, hash_algs, buffer_size);
+    }
+
+    let mut hasher = Hasher::new(hash_algs)?;
+    // SAFETY: the map is only read, and dropped before returning. Another process can still
+    // change the file under it: writes show up in the mapped bytes, so the digests may mix old
+    // and new content, and truncation raises SIGBUS on the next read past the new end, which
+    // terminates the process. This is accepted because mapping is opt-in through the `mmap`
+    // feature and `mmap_threshold`, and a file that changes while it is recorded has no
+    // meaningful digest anyway; the documentation above tells callers to avoid it for such files.
+    let map = unsafe { memmap2::Mmap::map(file)? };
+    hasher.update(&map);
+    Ok((hasher.size(), hasher.finalize()))
+}
+
+/// Incremental computation of the digests of data fed in chunks, for data that is not available
+/// as a `Read`. It uses the same digests as `calculate_hashes`, and also implements `Write`.
+///
+/// ```
+/// # use in_toto::crypto::{calculate_hashes, HashAlgorithm, Hasher};
+/// let algs = [HashAlgorithm::Sha256, HashAlgorithm::Sha512];
+/// let mut hasher = Hasher::new(&algs).unwrap();
+/// hasher.update(b"hello ");
+/// hasher.update(b"world");
+/// assert_eq!(hasher.size(), 11);
+/// assert_eq!(
+///     hasher.finalize(),
+///     calculate_hashes(&b"hello world"[..], &algs).unwrap().1
+/// );
+/// ```
+pub struct Hasher {
+    contexts: HashMap<HashAlgorithm, DigestContext>,
+    size: u64,
+}
+
+impl Hasher {
+    /// Start computing the digests of `hash_algs`. Fails if `hash_algs` is empty or holds an
+    /// algorithm that is not supported.
+    pub fn new(hash_algs: &[HashAlgorithm]) -> Result<Self> {
+        if hash_algs.is_empty() {
+            return Err(Error::IllegalArgument(
+                "Cannot provide empty set of hash algorithms".into(),
+            ));
+        }
+
+        let mut contexts = HashMap::new();
+        for alg in hash_algs {
+            let _ = contexts.insert(alg.clone(), alg.digest_context()?);
+        }
+        Ok(Hasher { contexts, size: 0 })
+    }
+
+    /// Feed the next chunk of data to every digest.
+    pub fn update(&mut self, data: &[u8]) {
+        self.size += data.len() as u64;
+        for context in self.contexts.values_mut() {
+            context.update(data);
+        }
+    }
+
+    /// The number of bytes fed so far.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Finish the digests, keyed by their algorithm like a `models::TargetDescription`.
+    pub fn finalize(self) -> HashMap<HashAlgorithm, HashValue> {
+        self.contexts
+            .into_iter()
+            .map(|(alg, context)| (alg, HashValue::new(context.finish())))
+            .collect()
+    }
+}
+
+impl Write for Hasher {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
 }
 
 fn shim_public_key(
@@ -132,7 +266,10 @@ fn shim_public_key(
 ) -> ::std::result::Result<shims::PublicKey, derp::Error> {
     let key = match key_type {
         KeyType::Ed25519 => HEXLOWER.encode(public_key),
-        KeyType::Rsa | KeyType::Unknown(_) => {
+        KeyType::Rsa | KeyType::Ecdsa => {
+            write_pem("PUBLIC KEY", &write_spki(public_key, key_type)?)
+        }
+        KeyType::Unknown(_) => {
             let bytes = write_spki(public_key, &key_type)?;
             BASE64URL.encode(&bytes)
         }
@@ -173,10 +310,18 @@ fn calculate_key_id(
 ///
 /// # Calculating
 /// A `KeyId` is calculated as the hex digest of the SHA-256 hash of the canonical form of the
-/// public key, or `hexdigest(sha256(cjson(public_key)))`.
+/// public key, or `hexdigest(sha256(cjson(public_key)))`. See `PublicKey::key_id` for the exact
+/// form that is hashed.
 #[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct KeyId(String);
 
+impl KeyId {
+    /// The hex digest this key ID consists of.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
 impl FromStr for KeyId {
     type Err = Error;
 
@@ -219,6 +364,14 @@ pub enum SignatureScheme {
     /// [RSASSA-PSS](https://tools.ietf.org/html/rfc5756) calculated over SHA512
     #[serde(rename = "rsassa-pss-sha512")]
     RsaSsaPssSha512,
+    /// [RSASSA-PKCS1-v1_5](https://tools.ietf.org/html/rfc8017#section-8.2) calculated over
+    /// SHA256
+    #[serde(rename = "rsa-pkcs1v15-sha256")]
+    RsaPkcs1v15Sha256,
+    /// [ECDSA](https://tools.ietf.org/html/rfc5480) on the NIST P-256 curve, calculated over
+    /// SHA256, with ASN.1 DER encoded signatures
+    #[serde(rename = "ecdsa-sha2-nistp256")]
+    EcdsaSha2Nistp256,
     /// Placeholder for an unknown scheme.
     Unknown(String),
 }
@@ -263,6 +416,9 @@ pub enum KeyType {
     Ed25519,
     /// [RSA](https://en.wikipedia.org/wiki/RSA_%28cryptosystem%29)
     Rsa,
+    /// [ECDSA](https://en.wikipedia.org/wiki/Elliptic_Curve_Digital_Signature_Algorithm) on the
+    /// NIST P-256 curve
+    Ecdsa,
     /// Placeholder for an unknown key type.
     Unknown(String),
 }
@@ -272,6 +428,7 @@ impl KeyType {
         match oid {
             x if x == RSA_SPKI_OID => Ok(KeyType::Rsa),
             x if x == ED25519_SPKI_OID => Ok(KeyType::Ed25519),
+            x if x == ECDSA_SPKI_OID => Ok(KeyType::Ecdsa),
             x => Err(Error::Encoding(format!(
                 "Unknown OID: {}",
                 x.iter().map(|b| format!("{:x}", b)).collect::<String>()
@@ -283,6 +440,7 @@ impl KeyType {
         match *self {
             KeyType::Rsa => Ok(RSA_SPKI_OID),
             KeyType::Ed25519 => Ok(ED25519_SPKI_OID),
+            KeyType::Ecdsa => Ok(ECDSA_SPKI_OID),
             KeyType::Unknown(ref s) => Err(Error::UnknownKeyType(s.clone())),
         }
     }
@@ -295,6 +453,7 @@ impl FromStr for KeyType {
         match s {
             "ed25519" => Ok(KeyType::Ed25519),
             "rsa" => Ok(KeyType::Rsa),
+            "ecdsa" => Ok(KeyType::Ecdsa),
             typ => Err(Error::Encoding(typ.into())),
         }
     }
@@ -305,6 +464,7 @@ impl ToString for KeyType {
         match *self {
             KeyType::Ed25519 => "ed25519".to_string(),
             KeyType::Rsa => "rsa".to_string(),
+            KeyType::Ecdsa => "ecdsa".to_string(),
             KeyType::Unknown(ref s) => s.to_string(),
         }
     }
@@ -331,6 +491,7 @@ impl<'de> Deserialize<'de> for KeyType {
 enum PrivateKeyType {
     Ed25519(Ed25519KeyPair),
     Rsa(Arc<RsaKeyPair>),
+    Ecdsa(EcdsaKeyPair),
 }
 
 impl Debug for PrivateKeyType {
@@ -338,6 +499,7 @@ impl Debug for PrivateKeyType {
         let s = match *self {
             PrivateKeyType::Ed25519(_) => "Ed25519",
             PrivateKeyType::Rsa(_) => "Rsa",
+            PrivateKeyType::Ecdsa(_) => "Ecdsa",
         };
         f.debug_tuple(s).field(&"_").finish()
     }
@@ -347,20 +509,37 @@ impl Debug for PrivateKeyType {
 pub struct PrivateKey {
     private: PrivateKeyType,
     public: PublicKey,
+    /// Wiped from memory when the key is dropped.
+    pkcs8: Zeroizing<Vec<u8>>,
 }
 
 impl PrivateKey {
-    /// Generate a new `PrivateKey` bytes in pkcs8 format.
+    /// Generate a new `PrivateKey` that signs with `scheme`. Its key ID is the one it gets when
+    /// loaded back from `to_pkcs8` with `from_pkcs8`.
     ///
     /// Note: For RSA keys, `openssl` needs to the on the `$PATH`.
-    pub fn new(key_type: KeyType) -> Result<Vec<u8>> {
-        match key_type {
-            KeyType::Ed25519 => Ed25519KeyPair::generate_pkcs8(&SystemRandom::new())
+    pub fn new(scheme: SignatureScheme) -> Result<Self> {
+        let rng = SystemRandom::new();
+        let pkcs8 = match scheme {
+            SignatureScheme::Ed25519 => Ed25519KeyPair::generate_pkcs8(&rng)
                 .map(|bytes| bytes.as_ref().to_vec())
-                .map_err(|_| Error::Opaque("Failed to generate Ed25519 key".into())),
-            KeyType::Rsa => Self::rsa_gen(),
-            KeyType::Unknown(s) => Err(Error::IllegalArgument(format!("Unknown key type: {}", s))),
-        }
+                .map_err(|_| Error::Opaque("Failed to generate Ed25519 key".into()))?,
+            SignatureScheme::EcdsaSha2Nistp256 => {
+                EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &rng)
+                    .map(|bytes| bytes.as_ref().to_vec())
+                    .map_err(|_| Error::Opaque("Failed to generate ECDSA key".into()))?
+            }
+            SignatureScheme::RsaSsaPssSha256
+            | SignatureScheme::RsaSsaPssSha512
+            | SignatureScheme::RsaPkcs1v15Sha256 => Self::rsa_gen()?,
+            SignatureScheme::Unknown(ref s) => {
+                return Err(Error::IllegalArgument(format!(
+                    "Unknown signature scheme: {}",
+                    s
+                )));
+            }
+        };
+        Self::from_pkcs8(&pkcs8, scheme)
     }
 
     /// Create a new `PrivateKey` from an ed25519 keypair, a 64 byte slice, where the first 32
@@ -392,8 +571,13 @@ impl PrivateKey {
             key.public_key().as_ref().to_vec(),
         )?;
         let private = PrivateKeyType::Ed25519(key);
+        let pkcs8 = Zeroizing::new([ED25519_PKCS8_V1_PREFIX, private_key_bytes].concat());
 
-        Ok(PrivateKey { private, public })
+        Ok(PrivateKey {
+            private,
+            public,
+            pkcs8,
+        })
     }
 
     /// Create a private key from PKCS#8v2 DER bytes.
@@ -429,6 +613,9 @@ impl PrivateKey {
     ///     openssl pkcs8 -topk8 -nocrypt -outform der > rsa-4096-private-key.pk8
     /// ```
     pub fn from_pkcs8(der_key: &[u8], scheme: SignatureScheme) -> Result<Self> {
+        if scheme == SignatureScheme::EcdsaSha2Nistp256 {
+            return Self::from_ecdsa(der_key);
+        }
         match Self::ed25519_from_pkcs8(der_key) {
             Ok(k) => {
                 match scheme {
@@ -452,6 +639,72 @@ impl PrivateKey {
         }
     }
 
+    /// Create a private key from encrypted PKCS#8 DER bytes, as written by
+    /// `openssl pkcs8 -topk8 -v2 aes-256-cbc`, decrypting them with `passphrase`. The decrypted
+    /// key is then parsed like `from_pkcs8`.
+    ///
+    /// Returns `Error::KeyDecryption` if the key cannot be decrypted, e.g. because the passphrase
+    /// is wrong.
+    pub fn from_pkcs8_encrypted(
+        der_key: &[u8],
+        passphrase: &str,
+        scheme: SignatureScheme,
+    ) -> Result<Self> {
+        Self::from_pkcs8(decrypt_pkcs8(der_key, passphrase)?.as_bytes(), scheme)
+    }
+
+    /// Like `from_pkcs8_encrypted`, for a PEM encoded `ENCRYPTED PRIVATE KEY`.
+    pub fn from_pem_encrypted(
+        pem_key: &[u8],
+        passphrase: &str,
+        scheme: SignatureScheme,
+    ) -> Result<Self> {
+        let pem = str::from_utf8(pem_key)
+            .map_err(|_| Error::Encoding("PEM private key is not valid UTF-8".into()))?;
+        let der_key = read_pem("ENCRYPTED PRIVATE KEY", pem)?;
+        Self::from_pkcs8_encrypted(&der_key, passphrase, scheme)
+    }
+
+    /// Create an RSA private key from PKCS#8 bytes, either DER or PEM encoded, that signs with
+    /// `scheme`: `RsaSsaPssSha256`, `RsaSsaPssSha512` or `RsaPkcs1v15Sha256`.
+    ///
+    /// ```
+    /// # use in_toto::crypto::{PrivateKey, SignatureScheme};
+    /// let pem = include_bytes!("../tests/rsa/rsa-2048.pk8.pem");
+    /// let key = PrivateKey::from_rsa(pem, SignatureScheme::RsaPkcs1v15Sha256).unwrap();
+    /// ```
+    pub fn from_rsa(key: &[u8], scheme: SignatureScheme) -> Result<Self> {
+        Self::rsa_from_pkcs8(&pkcs8_der(key)?, scheme)
+    }
+
+    /// Create an ECDSA private key on the NIST P-256 curve from PKCS#8 bytes, either DER or PEM
+    /// encoded, that signs with `EcdsaSha2Nistp256`.
+    ///
+    /// ```
+    /// # use in_toto::crypto::PrivateKey;
+    /// let pem = include_bytes!("../tests/ecdsa/ecdsa-p256.pk8.pem");
+    /// let key = PrivateKey::from_ecdsa(pem).unwrap();
+    /// ```
+    pub fn from_ecdsa(key: &[u8]) -> Result<Self> {
+        let pkcs8 = pkcs8_der(key)?;
+        let key = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &pkcs8)
+            .map_err(|_| Error::Encoding("Could not parse key as a PKCS#8 P-256 key".into()))?;
+
+        let public = PublicKey::new(
+            KeyType::Ecdsa,
+            SignatureScheme::EcdsaSha2Nistp256,
+            python_sslib_compatibility_keyid_hash_algorithms(),
+            key.public_key().as_ref().to_vec(),
+        )?;
+        let private = PrivateKeyType::Ecdsa(key);
+
+        Ok(PrivateKey {
+            private,
+            public,
+            pkcs8,
+        })
+    }
+
     fn ed25519_from_pkcs8(der_key: &[u8]) -> Result<Self> {
         Self::ed25519_from_pkcs8_with_keyid_hash_algorithms(
             der_key,
@@ -463,8 +716,10 @@ impl PrivateKey {
         der_key: &[u8],
         keyid_hash_algorithms: Option<Vec<String>>,
     ) -> Result<Self> {
+        // openssl writes PKCS#8v1 keys, which leave out the public key.
         let key = Ed25519KeyPair::from_pkcs8(der_key)
-            .map_err(|_| Error::Encoding("Could not parse key as PKCS#8v2".into()))?;
+            .or_else(|_| Ed25519KeyPair::from_pkcs8_maybe_unchecked(der_key))
+            .map_err(|_| Error::Encoding("Could not parse key as PKCS#8".into()))?;
 
         let public = PublicKey::new(
             KeyType::Ed25519,
@@ -474,7 +729,11 @@ impl PrivateKey {
         )?;
         let private = PrivateKeyType::Ed25519(key);
 
-        Ok(PrivateKey { private, public })
+        Ok(PrivateKey {
+            private,
+            public,
+            pkcs8: Zeroizing::new(der_key.to_vec()),
+        })
     }
 
     fn rsa_from_pkcs8(der_key: &[u8], scheme: SignatureScheme) -> Result<Self> {
@@ -504,7 +763,11 @@ impl PrivateKey {
         )?;
         let private = PrivateKeyType::Rsa(Arc::new(key));
 
-        Ok(PrivateKey { private, public })
+        Ok(PrivateKey {
+            private,
+            public,
+            pkcs8: Zeroizing::new(der_key.to_vec()),
+        })
     }
 
     /// Sign a message.
@@ -524,6 +787,19 @@ impl PrivateKey {
                     .map_err(|_| Error::Opaque("Failed to sign message.".into()))?;
                 SignatureValue(buf)
             }
+            (PrivateKeyType::Rsa(rsa), SignatureScheme::RsaPkcs1v15Sha256) => {
+                let rng = SystemRandom::new();
+                let mut buf = vec![0; rsa.public_modulus_len()];
+                rsa.sign(&RSA_PKCS1_SHA256, &rng, msg, &mut buf)
+                    .map_err(|_| Error::Opaque("Failed to sign message.".into()))?;
+                SignatureValue(buf)
+            }
+            (PrivateKeyType::Ecdsa(ecdsa), SignatureScheme::EcdsaSha2Nistp256) => {
+                let sig = ecdsa
+                    .sign(&SystemRandom::new(), msg)
+                    .map_err(|_| Error::Opaque("Failed to sign message.".into()))?;
+                SignatureValue(sig.as_ref().to_vec())
+            }
             (&PrivateKeyType::Ed25519(ref ed), &SignatureScheme::Ed25519) => {
                 SignatureValue(ed.sign(msg).as_ref().into())
             }
@@ -538,6 +814,8 @@ impl PrivateKey {
         Ok(Signature {
             key_id: self.key_id().clone(),
             value,
+            #[cfg(feature = "keyless")]
+            cert_chain: Vec::new(),
         })
     }
 
@@ -577,6 +855,12 @@ impl PrivateKey {
         &self.public
     }
 
+    /// The PKCS#8 DER bytes of the key, which `from_pkcs8` loads back. Unlike the copy held by
+    /// the key, the returned bytes are not wiped when dropped.
+    pub fn to_pkcs8(&self) -> Vec<u8> {
+        self.pkcs8.to_vec()
+    }
+
     /// Return the key ID of the public key.
     pub fn key_id(&self) -> &KeyId {
         &self.public.key_id
@@ -640,8 +924,16 @@ impl PublicKey {
                     let typ = KeyType::from_oid(typ.as_slice_less_safe())
                         .map_err(|_| derp::Error::WrongValue)?;
 
-                    // for RSA / ed25519 this is null, so don't both parsing it
-                    derp::read_null(input)?;
+                    if typ == KeyType::Ecdsa {
+                        // ECDSA keys name their curve, which must be P-256
+                        let curve = derp::expect_tag_and_get_value(input, Tag::Oid)?;
+                        if curve.as_slice_less_safe() != NISTP256_OID {
+                            return Err(derp::Error::WrongValue);
+                        }
+                    } else {
+                        // for RSA / ed25519 this is null, so don't both parsing it
+                        derp::read_null(input)?;
+                    }
                     Ok(typ)
                 })?;
                 let value = derp::bit_string_with_no_unused_bits(input)?;
@@ -695,10 +987,37 @@ impl PublicKey {
     }
 
     /// An immutable reference to the key's ID.
+    ///
+    /// The ID is computed like the Python reference implementation does: the hex encoded SHA-256
+    /// digest of the canonical JSON of the key's metadata. Its fields are `keyid_hash_algorithms`
+    /// (left out when `None`), `keytype`, `keyval` and `scheme`, in that order. `keyval` holds the
+    /// single field `public`: the hex encoded key for ed25519 and the PEM encoded SPKI otherwise.
+    ///
+    /// ```
+    /// # use in_toto::crypto::PublicKey;
+    /// let key = "eb8ac26b5c9ef0279e3be3e82262a93bce16fe58ee422500d38caf461c65a3b6";
+    /// let key = PublicKey::from_ed25519_with_keyid_hash_algorithms(
+    ///     data_encoding::HEXLOWER.decode(key.as_bytes()).unwrap(),
+    ///     Some(vec!["sha256".into(), "sha512".into()]),
+    /// )
+    /// .unwrap();
+    /// // hexdigest(sha256(b'{"keyid_hash_algorithms":["sha256","sha512"],"keytype":"ed25519",
+    /// //   "keyval":{"public":"eb8a...a3b6"},"scheme":"ed25519"}'))
+    /// assert_eq!(
+    ///     key.key_id().as_str(),
+    ///     "a9f3ebc9b138762563a9c27b6edd439959e559709babd123e8d449ba2c18c61a"
+    /// );
+    /// ```
     pub fn key_id(&self) -> &KeyId {
         &self.key_id
     }
 
+    /// The hash algorithms the key ID is advertised as computed with, as in the Python reference
+    /// implementation. Keys loaded from PKCS#8 or SPKI use `["sha256", "sha512"]`.
+    pub fn keyid_hash_algorithms(&self) -> &Option<Vec<String>> {
+        &self.keyid_hash_algorithms
+    }
+
     /// Return the public key as bytes.
     pub fn as_bytes(&self) -> &[u8] {
         &self.value.0
@@ -710,6 +1029,8 @@ impl PublicKey {
             SignatureScheme::Ed25519 => &ED25519,
             SignatureScheme::RsaSsaPssSha256 => &RSA_PSS_2048_8192_SHA256,
             SignatureScheme::RsaSsaPssSha512 => &RSA_PSS_2048_8192_SHA512,
+            SignatureScheme::RsaPkcs1v15Sha256 => &RSA_PKCS1_2048_8192_SHA256,
+            SignatureScheme::EcdsaSha2Nistp256 => &ECDSA_P256_SHA256_ASN1,
             SignatureScheme::Unknown(ref s) => {
                 return Err(Error::IllegalArgument(format!(
                     "Unknown signature scheme: {}",
@@ -801,10 +1122,18 @@ impl<'de> Deserialize<'de> for PublicKey {
                     DeserializeError::custom(format!("Couldn't parse key as ed25519: {:?}", e))
                 })?
             }
-            KeyType::Rsa | KeyType::Unknown(_) => {
-                let bytes = BASE64URL
-                    .decode(intermediate.public_key().as_bytes())
-                    .map_err(|e| DeserializeError::custom(format!("{:?}", e)))?;
+            KeyType::Rsa | KeyType::Ecdsa | KeyType::Unknown(_) => {
+                // RSA and ECDSA keys are PEM encoded, but RSA keys may still be base64url
+                // encoded in metadata written by earlier versions.
+                let public = intermediate.public_key();
+                let bytes = if public.starts_with("-----BEGIN") {
+                    read_pem("PUBLIC KEY", public)
+                        .map_err(|e| DeserializeError::custom(format!("{:?}", e)))?
+                } else {
+                    BASE64URL
+                        .decode(public.as_bytes())
+                        .map_err(|e| DeserializeError::custom(format!("{:?}", e)))?
+                };
 
                 PublicKey::from_spki_with_keyid_hash_algorithms(
                     &bytes,
@@ -848,9 +1177,22 @@ pub struct Signature {
     key_id: KeyId,
     #[serde(rename = "sig")]
     value: SignatureValue,
+    #[cfg(feature = "keyless")]
+    #[serde(rename = "cert", default, skip_serializing_if = "Vec::is_empty")]
+    cert_chain: Vec<String>,
 }
 
 impl Signature {
+    /// A signature made by the key `key_id`, e.g. one carried in another envelope format.
+    pub(crate) fn new(key_id: KeyId, value: SignatureValue) -> Self {
+        Signature {
+            key_id,
+            value,
+            #[cfg(feature = "keyless")]
+            cert_chain: Vec::new(),
+        }
+    }
+
     /// An immutable reference to the `KeyId` of the key that produced the signature.
     pub fn key_id(&self) -> &KeyId {
         &self.key_id
@@ -860,32 +1202,172 @@ impl Signature {
     pub fn value(&self) -> &SignatureValue {
         &self.value
     }
+
+    /// The PEM certificates binding the signing key to an identity, leaf first. Empty unless the
+    /// signature was made with an ephemeral key.
+    #[cfg(feature = "keyless")]
+    pub fn cert_chain(&self) -> &[String] {
+        &self.cert_chain
+    }
+
+    /// Attach the PEM certificate chain of an ephemeral signing key, leaf first.
+    #[cfg(feature = "keyless")]
+    pub fn with_cert_chain(mut self, cert_chain: Vec<String>) -> Self {
+        self.cert_chain = cert_chain;
+        self
+    }
 }
 
 /// The available hash algorithms.
-#[derive(Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[derive(Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
 pub enum HashAlgorithm {
     /// SHA256 as describe in [RFC-6234](https://tools.ietf.org/html/rfc6234)
-    #[serde(rename = "sha256")]
     Sha256,
+    /// SHA384 as describe in [RFC-6234](https://tools.ietf.org/html/rfc6234)
+    Sha384,
     /// SHA512 as describe in [RFC-6234](https://tools.ietf.org/html/rfc6234)
-    #[serde(rename = "sha512")]
     Sha512,
+    /// BLAKE2b with a 256 bit digest, as described in [RFC-7693](https://tools.ietf.org/html/rfc7693)
+    #[cfg(feature = "blake")]
+    Blake2b256,
+    /// [BLAKE3](https://github.com/BLAKE3-team/BLAKE3-specs) with its default 256 bit digest
+    #[cfg(feature = "blake")]
+    Blake3,
     /// Placeholder for an unknown hash algorithm.
     Unknown(String),
 }
 
 impl HashAlgorithm {
-    /// Create a new `digest::Context` suitable for computing the hash of some data using this hash
+    /// The name of the algorithm in metadata.
+    ///
+    /// ```
+    /// # use in_toto::crypto::HashAlgorithm;
+    /// assert_eq!(HashAlgorithm::Sha256.name(), "sha256");
+    /// assert_eq!(HashAlgorithm::Unknown("md5".into()).name(), "md5");
+    /// ```
+    pub fn name(&self) -> &str {
+        match self {
+            HashAlgorithm::Sha256 => "sha256",
+            HashAlgorithm::Sha384 => "sha384",
+            HashAlgorithm::Sha512 => "sha512",
+            #[cfg(feature = "blake")]
+            HashAlgorithm::Blake2b256 => "blake2b-256",
+            #[cfg(feature = "blake")]
+            HashAlgorithm::Blake3 => "blake3",
+            HashAlgorithm::Unknown(ref s) => s,
+        }
+    }
+
+    /// Return all supported hash algorithms, keyed by the name used for them in metadata.
+    ///
+    /// Every algorithm listed here must have a digest implementation in `digest_context`; the
+    /// `return_all_algorithms_are_wired_up` test checks each one against a known vector.
+    pub fn return_all() -> HashMap<String, HashAlgorithm> {
+        let mut map = HashMap::new();
+        let _ = map.insert(String::from("sha256"), HashAlgorithm::Sha256);
+        let _ = map.insert(String::from("sha384"), HashAlgorithm::Sha384);
+        let _ = map.insert(String::from("sha512"), HashAlgorithm::Sha512);
+        #[cfg(feature = "blake")]
+        {
+            let _ = map.insert(String::from("blake2b-256"), HashAlgorithm::Blake2b256);
+            let _ = map.insert(String::from("blake3"), HashAlgorithm::Blake3);
+        }
+        map
+    }
+
+    /// Create a new `DigestContext` suitable for computing the hash of some data using this hash
     /// algorithm.
-    pub(crate) fn digest_context(&self) -> Result<digest::Context> {
+    pub(crate) fn digest_context(&self) -> Result<DigestContext> {
         match self {
-            HashAlgorithm::Sha256 => Ok(digest::Context::new(&SHA256)),
-            HashAlgorithm::Sha512 => Ok(digest::Context::new(&SHA512)),
-            HashAlgorithm::Unknown(ref s) => Err(Error::IllegalArgument(format!(
-                "Unknown hash algorithm: {}",
-                s
-            ))),
+            HashAlgorithm::Sha256 => Ok(DigestContext::ring(&SHA256)),
+            HashAlgorithm::Sha384 => Ok(DigestContext::ring(&SHA384)),
+            HashAlgorithm::Sha512 => Ok(DigestContext::ring(&SHA512)),
+            #[cfg(feature = "blake")]
+            HashAlgorithm::Blake2b256 => Ok(DigestContext::Blake2b256(Box::default())),
+            #[cfg(feature = "blake")]
+            HashAlgorithm::Blake3 => Ok(DigestContext::Blake3(Box::default())),
+            HashAlgorithm::Unknown(ref s) => Err(Error::UnknownHashAlgorithm(s.clone())),
+        }
+    }
+}
+
+impl FromStr for HashAlgorithm {
+    type Err = Error;
+
+    /// Parse the name of a supported algorithm, ignoring case and accepting the spellings with a
+    /// dash used by standards, like `SHA-256`. Names in metadata are read as is instead, by
+    /// deserializing.
+    ///
+    /// ```
+    /// # use in_toto::crypto::HashAlgorithm;
+    /// assert_eq!("SHA-256".parse::<HashAlgorithm>().unwrap(), HashAlgorithm::Sha256);
+    /// assert!("md5".parse::<HashAlgorithm>().is_err());
+    /// ```
+    fn from_str(s: &str) -> Result<Self> {
+        let name = s.to_ascii_lowercase();
+        let name = match name.as_str() {
+            "sha-256" => "sha256",
+            "sha-384" => "sha384",
+            "sha-512" => "sha512",
+            name => name,
+        };
+        HashAlgorithm::return_all()
+            .remove(name)
+            .ok_or_else(|| Error::UnknownHashAlgorithm(s.to_string()))
+    }
+}
+
+impl Serialize for HashAlgorithm {
+    fn serialize<S>(&self, ser: S) -> ::std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        ser.serialize_str(self.name())
+    }
+}
+
+impl<'de> Deserialize<'de> for HashAlgorithm {
+    fn deserialize<D: Deserializer<'de>>(de: D) -> ::std::result::Result<Self, D::Error> {
+        let name: String = Deserialize::deserialize(de)?;
+        HashAlgorithm::return_all()
+            .remove(&name)
+            .ok_or_else(|| DeserializeError::custom(format!("Unknown hash algorithm: {}", name)))
+    }
+}
+
+/// A hash computation in progress, for any of the `HashAlgorithm`s.
+pub(crate) enum DigestContext {
+    Ring(Box<digest::Context>),
+    #[cfg(feature = "blake")]
+    Blake2b256(Box<blake2::Blake2b<blake2::digest::consts::U32>>),
+    #[cfg(feature = "blake")]
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl DigestContext {
+    fn ring(algorithm: &'static digest::Algorithm) -> Self {
+        DigestContext::Ring(Box::new(digest::Context::new(algorithm)))
+    }
+
+    pub(crate) fn update(&mut self, data: &[u8]) {
+        match self {
+            DigestContext::Ring(context) => context.update(data),
+            #[cfg(feature = "blake")]
+            DigestContext::Blake2b256(context) => blake2::Digest::update(&mut **context, data),
+            #[cfg(feature = "blake")]
+            DigestContext::Blake3(hasher) => {
+                let _ = hasher.update(data);
+            }
+        }
+    }
+
+    pub(crate) fn finish(self) -> Vec<u8> {
+        match self {
+            DigestContext::Ring(context) => context.finish().as_ref().to_vec(),
+            #[cfg(feature = "blake")]
+            DigestContext::Blake2b256(context) => blake2::Digest::finalize(*context).to_vec(),
+            #[cfg(feature = "blake")]
+            DigestContext::Blake3(hasher) => hasher.finalize().as_bytes().to_vec(),
         }
     }
 }
@@ -928,7 +1410,10 @@ fn write_spki(public: &[u8], key_type: &KeyType) -> ::std::result::Result<Vec<u8
             der.sequence(|der| match key_type.as_oid().ok() {
                 Some(tag) => {
                     der.element(Tag::Oid, tag)?;
-                    der.null()
+                    match key_type {
+                        KeyType::Ecdsa => der.element(Tag::Oid, NISTP256_OID),
+                        _ => der.null(),
+                    }
                 }
                 None => Err(derp::Error::WrongValue),
             })?;
@@ -939,6 +1424,55 @@ fn write_spki(public: &[u8], key_type: &KeyType) -> ::std::result::Result<Vec<u8
     Ok(output)
 }
 
+/// Encode `der` as a PEM block labelled `label`, without a trailing newline, as the Python
+/// reference implementation stores public keys.
+fn write_pem(label: &str, der: &[u8]) -> String {
+    let encoded = BASE64.encode(der);
+    let mut pem = format!("-----BEGIN {}-----\n", label);
+    for line in encoded.as_bytes().chunks(PEM_LINE_LENGTH) {
+        pem.push_str(&String::from_utf8_lossy(line));
+        pem.push('\n');
+    }
+    pem.push_str(&format!("-----END {}-----", label));
+    pem
+}
+
+/// Decrypt the encrypted PKCS#8 DER bytes `der_key` with `passphrase`. The decrypted key is
+/// zeroized when the returned document is dropped.
+fn decrypt_pkcs8(der_key: &[u8], passphrase: &str) -> Result<SecretDocument> {
+    let encrypted = EncryptedPrivateKeyInfo::try_from(der_key)
+        .map_err(|_| Error::Encoding("Key is not an encrypted PKCS#8 key".into()))?;
+    encrypted
+        .decrypt(passphrase)
+        .map_err(|e| Error::KeyDecryption(e.to_string()))
+}
+
+/// The DER bytes of a PKCS#8 private key given either as DER or as PEM.
+fn pkcs8_der(key: &[u8]) -> Result<Zeroizing<Vec<u8>>> {
+    if !key.starts_with(b"-----BEGIN") {
+        return Ok(Zeroizing::new(key.to_vec()));
+    }
+    let pem = str::from_utf8(key)
+        .map_err(|_| Error::Encoding("PEM private key is not valid UTF-8".into()))?;
+    read_pem("PRIVATE KEY", pem).map(Zeroizing::new)
+}
+
+/// Decode the first PEM block labelled `label` in `pem`.
+fn read_pem(label: &str, pem: &str) -> Result<Vec<u8>> {
+    let begin = format!("-----BEGIN {}-----", label);
+    let end = format!("-----END {}-----", label);
+    let missing = || Error::Encoding(format!("No PEM {} block found", label));
+    let start = pem.find(&begin).ok_or_else(missing)? + begin.len();
+    let stop = start + pem[start..].find(&end).ok_or_else(missing)?;
+    let body = pem[start..stop]
+        .chars()
+        .filter(|c| !c.is_ascii_whitespace())
+        .collect::<String>();
+    BASE64
+        .decode(body.as_bytes())
+        .map_err(|e| Error::Encoding(format!("Invalid PEM {} block: {}", label, e)))
+}
+
 fn extract_rsa_pub_from_pkcs8(der_key: &[u8]) -> ::std::result::Result<Vec<u8>, derp::Error> {
     let input = Input::from(der_key);
     input.read_all(derp::Error::Read, |input| {
@@ -994,6 +1528,16 @@ mod test {
     const RSA_2048_PK8: &'static [u8] = include_bytes!("../tests/rsa/rsa-2048.pk8.der");
     const RSA_2048_SPKI: &'static [u8] = include_bytes!("../tests/rsa/rsa-2048.spki.der");
     const RSA_2048_PKCS1: &'static [u8] = include_bytes!("../tests/rsa/rsa-2048.pkcs1.der");
+    const RSA_2048_PK8_PEM: &[u8] = include_bytes!("../tests/rsa/rsa-2048.pk8.pem");
+    const RSA_2048_PK8_ENC_PEM: &[u8] = include_bytes!("../tests/rsa/rsa-2048.pk8.enc.pem");
+    const RSA_2048_SPKI_PEM: &str = include_str!("../tests/rsa/rsa-2048.spki.pem");
+
+    const ECDSA_P256_PK8: &[u8] = include_bytes!("../tests/ecdsa/ecdsa-p256.pk8.der");
+    const ECDSA_P256_PK8_PEM: &[u8] = include_bytes!("../tests/ecdsa/ecdsa-p256.pk8.pem");
+    const ECDSA_P256_SPKI: &[u8] = include_bytes!("../tests/ecdsa/ecdsa-p256.spki.der");
+    const ECDSA_P256_SPKI_PEM: &str = include_str!("../tests/ecdsa/ecdsa-p256.spki.pem");
+    const ECDSA_MESSAGE: &[u8] = include_bytes!("../tests/ecdsa/message");
+    const ECDSA_MESSAGE_SIG: &[u8] = include_bytes!("../tests/ecdsa/message.ecdsa-p256.sig");
 
     const RSA_4096_PK8: &'static [u8] = include_bytes!("../tests/rsa/rsa-4096.pk8.der");
     const RSA_4096_SPKI: &'static [u8] = include_bytes!("../tests/rsa/rsa-4096.spki.der");
@@ -1004,6 +1548,10 @@ mod test {
     const ED25519_1_PK8: &'static [u8] = include_bytes!("../tests/ed25519/ed25519-1.pk8.der");
     const ED25519_1_SPKI: &'static [u8] = include_bytes!("../tests/ed25519/ed25519-1.spki.der");
     const ED25519_2_PK8: &'static [u8] = include_bytes!("../tests/ed25519/ed25519-2.pk8.der");
+    const ED25519_1_PK8_ENC: &[u8] = include_bytes!("../tests/ed25519/ed25519-1.pk8.enc.der");
+
+    /// The passphrase the encrypted fixtures are encrypted with.
+    const PASSPHRASE: &str = "in-toto";
 
     #[test]
     fn parse_public_rsa_2048_spki() {
@@ -1026,6 +1574,53 @@ mod test {
         assert_eq!(key.scheme, SignatureScheme::Ed25519);
     }
 
+    #[test]
+    fn key_id_matches_reference() {
+        // As computed by securesystemslib's `_get_keyid` for the public half of ed25519-1.
+        let expected = "a9f3ebc9b138762563a9c27b6edd439959e559709babd123e8d449ba2c18c61a";
+        let key = PublicKey::from_spki(ED25519_1_SPKI, SignatureScheme::Ed25519).unwrap();
+        assert_eq!(
+            key.keyid_hash_algorithms(),
+            &Some(vec!["sha256".to_string(), "sha512".to_string()])
+        );
+        assert_eq!(key.key_id().as_str(), expected);
+
+        let private = PrivateKey::from_pkcs8(ED25519_1_PK8, SignatureScheme::Ed25519).unwrap();
+        assert_eq!(private.key_id().as_str(), expected);
+
+        // The ID covers `keyid_hash_algorithms`, and the field is left out when absent.
+        let key = PublicKey::from_ed25519(ED25519_1_PUBLIC_KEY).unwrap();
+        assert_eq!(key.keyid_hash_algorithms(), &None);
+        assert_eq!(
+            key.key_id().as_str(),
+            "e0294a3f17cc8563c3ed5fceb3bd8d3f6bfeeaca499b5c9572729ae015566554"
+        );
+    }
+
+    #[test]
+    fn rsa_key_id_matches_reference() {
+        // As computed by securesystemslib's `_get_keyid` for rsa-2048.spki.pem, imported with
+        // its default `rsassa-pss-sha256` scheme.
+        let expected = "c2620e94b6ff57f433c24436013a89d403fa6934a2ee490f44f897176c2c52e9";
+        let key = PublicKey::from_spki(RSA_2048_SPKI, SignatureScheme::RsaSsaPssSha256).unwrap();
+        assert_eq!(key.key_id().as_str(), expected);
+
+        let private = PrivateKey::from_rsa(RSA_2048_PK8, SignatureScheme::RsaSsaPssSha256).unwrap();
+        assert_eq!(private.key_id().as_str(), expected);
+    }
+
+    #[test]
+    fn ecdsa_key_id_matches_reference() {
+        // As computed by securesystemslib's `_get_keyid` for ecdsa-p256.spki.pem.
+        let expected = "0b15c89ce369a12ee320ff3f1ac649d54858d5c0f0986bb1df307aae8f615804";
+        let key =
+            PublicKey::from_spki(ECDSA_P256_SPKI, SignatureScheme::EcdsaSha2Nistp256).unwrap();
+        assert_eq!(key.key_id().as_str(), expected);
+
+        let private = PrivateKey::from_ecdsa(ECDSA_P256_PK8).unwrap();
+        assert_eq!(private.key_id().as_str(), expected);
+    }
+
     #[test]
     fn parse_public_ed25519() {
         let key = PublicKey::from_ed25519(ED25519_1_PUBLIC_KEY).unwrap();
@@ -1080,6 +1675,135 @@ mod test {
         key.public.verify(msg, &sig).unwrap();
     }
 
+    #[test]
+    fn rsa_2048_from_rsa_and_sign() {
+        let msg = b"test";
+
+        for scheme in [
+            SignatureScheme::RsaSsaPssSha256,
+            SignatureScheme::RsaSsaPssSha512,
+            SignatureScheme::RsaPkcs1v15Sha256,
+        ] {
+            let key = PrivateKey::from_rsa(RSA_2048_PK8_PEM, scheme.clone()).unwrap();
+            let der = PrivateKey::from_rsa(RSA_2048_PK8, scheme.clone()).unwrap();
+            assert_eq!(key.key_id(), der.key_id());
+            assert_eq!(key.public().scheme(), &scheme);
+
+            let sig = key.sign(msg).unwrap();
+            key.public.verify(msg, &sig).unwrap();
+            der.public.verify(msg, &sig).unwrap();
+            assert!(key.public.verify(b"tampered", &sig).is_err());
+        }
+
+        // PKCS#1 v1.5 signatures are deterministic, and do not verify under PSS.
+        let pkcs1 = PrivateKey::from_rsa(RSA_2048_PK8, SignatureScheme::RsaPkcs1v15Sha256).unwrap();
+        let pss = PrivateKey::from_rsa(RSA_2048_PK8, SignatureScheme::RsaSsaPssSha256).unwrap();
+        let sig = pkcs1.sign(msg).unwrap();
+        assert_eq!(sig.value(), pkcs1.sign(msg).unwrap().value());
+        assert!(pss.public.verify(msg, &sig).is_err());
+
+        assert!(PrivateKey::from_rsa(RSA_2048_PK8, SignatureScheme::Ed25519).is_err());
+        assert!(PrivateKey::from_rsa(ED25519_1_PK8, SignatureScheme::RsaSsaPssSha256).is_err());
+        assert!(PrivateKey::from_rsa(
+            RSA_2048_SPKI_PEM.as_bytes(),
+            SignatureScheme::RsaSsaPssSha256
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn ecdsa_p256_from_ecdsa_and_sign() {
+        let key = PrivateKey::from_ecdsa(ECDSA_P256_PK8_PEM).unwrap();
+        let der = PrivateKey::from_ecdsa(ECDSA_P256_PK8).unwrap();
+        assert_eq!(key.key_id(), der.key_id());
+        assert_eq!(key.public().typ(), &KeyType::Ecdsa);
+        assert_eq!(key.public().scheme(), &SignatureScheme::EcdsaSha2Nistp256);
+
+        let spki = PublicKey::from_spki(ECDSA_P256_SPKI, SignatureScheme::EcdsaSha2Nistp256);
+        let spki = spki.unwrap();
+        assert_eq!(&spki, key.public());
+        assert_eq!(spki.as_spki().unwrap(), ECDSA_P256_SPKI);
+
+        let sig = key.sign(b"test").unwrap();
+        spki.verify(b"test", &sig).unwrap();
+        assert!(spki.verify(b"tampered", &sig).is_err());
+
+        // Signed with openssl.
+        let sig: Signature = serde_json::from_value(json!({
+            "keyid": key.key_id(),
+            "sig": HEXLOWER.encode(ECDSA_MESSAGE_SIG),
+        }))
+        .unwrap();
+        spki.verify(ECDSA_MESSAGE, &sig).unwrap();
+        assert!(spki.verify(b"tampered", &sig).is_err());
+
+        assert!(PrivateKey::from_ecdsa(RSA_2048_PK8).is_err());
+        assert!(PrivateKey::from_ecdsa(ED25519_1_PK8).is_err());
+    }
+
+    #[test]
+    fn serde_ecdsa_public_key() {
+        let key = PrivateKey::from_ecdsa(ECDSA_P256_PK8).unwrap();
+        let jsn = json!({
+            "keytype": "ecdsa",
+            "scheme": "ecdsa-sha2-nistp256",
+            "keyid_hash_algorithms": ["sha256", "sha512"],
+            "keyval": {
+                "public": ECDSA_P256_SPKI_PEM.trim_end(),
+            }
+        });
+        assert_eq!(serde_json::to_value(key.public()).unwrap(), jsn);
+
+        let decoded: PublicKey = serde_json::from_value(jsn).unwrap();
+        assert_eq!(&decoded, key.public());
+        assert_eq!(decoded.key_id(), key.key_id());
+        let sig = key.sign(b"test").unwrap();
+        decoded.verify(b"test", &sig).unwrap();
+    }
+
+    #[test]
+    fn read_encrypted_pkcs8() {
+        let plain = PrivateKey::from_pkcs8(ED25519_1_PK8, SignatureScheme::Ed25519).unwrap();
+        let key = PrivateKey::from_pkcs8_encrypted(
+            ED25519_1_PK8_ENC,
+            PASSPHRASE,
+            SignatureScheme::Ed25519,
+        )
+        .unwrap();
+        assert_eq!(key.key_id(), plain.key_id());
+        let sig = key.sign(b"test").unwrap();
+        plain.public().verify(b"test", &sig).unwrap();
+
+        let scheme = SignatureScheme::RsaSsaPssSha256;
+        let plain = PrivateKey::from_rsa(RSA_2048_PK8, scheme.clone()).unwrap();
+        let key = PrivateKey::from_pem_encrypted(RSA_2048_PK8_ENC_PEM, PASSPHRASE, scheme).unwrap();
+        assert_eq!(key.key_id(), plain.key_id());
+    }
+
+    #[test]
+    fn read_encrypted_pkcs8_with_wrong_passphrase() {
+        let ed25519 = SignatureScheme::Ed25519;
+        let rsa = SignatureScheme::RsaSsaPssSha256;
+        assert!(matches!(
+            PrivateKey::from_pkcs8_encrypted(ED25519_1_PK8_ENC, "wrong", ed25519.clone()),
+            Err(Error::KeyDecryption(_))
+        ));
+        assert!(matches!(
+            PrivateKey::from_pem_encrypted(RSA_2048_PK8_ENC_PEM, "", rsa.clone()),
+            Err(Error::KeyDecryption(_))
+        ));
+
+        // Keys that are not encrypted are rejected before trying any passphrase.
+        assert!(matches!(
+            PrivateKey::from_pkcs8_encrypted(ED25519_1_PK8, PASSPHRASE, ed25519),
+            Err(Error::Encoding(_))
+        ));
+        assert!(matches!(
+            PrivateKey::from_pem_encrypted(RSA_2048_PK8_PEM, PASSPHRASE, rsa),
+            Err(Error::Encoding(_))
+        ));
+    }
+
     #[test]
     fn rsa_4096_read_pkcs8_and_sign() {
         let msg = b"test";
@@ -1217,7 +1941,7 @@ mod test {
             "scheme": "rsassa-pss-sha256",
             "keyid_hash_algorithms": ["sha256", "sha512"],
             "keyval": {
-                "public": BASE64URL.encode(der),
+                "public": RSA_2048_SPKI_PEM.trim_end(),
             }
         });
         assert_eq!(encoded, jsn);
@@ -1232,7 +1956,7 @@ mod test {
             "scheme": "rsassa-pss-sha256",
             "keyid_hash_algorithms": ["sha256", "sha512"],
             "keyval": {
-                "public": BASE64URL.encode(RSA_2048_SPKI),
+                "public": RSA_2048_SPKI_PEM.trim_end(),
             }
         });
 
@@ -1242,13 +1966,33 @@ mod test {
         assert_eq!(original, encoded);
     }
 
+    #[test]
+    fn de_rsa_public_key_from_base64url() {
+        let mut original = json!({
+            "keytype": "rsa",
+            "scheme": "rsa-pkcs1v15-sha256",
+            "keyid_hash_algorithms": ["sha256", "sha512"],
+            "keyval": {
+                "public": BASE64URL.encode(RSA_2048_SPKI),
+            }
+        });
+
+        let decoded: PublicKey = serde_json::from_value(original.clone()).unwrap();
+        assert_eq!(decoded.scheme(), &SignatureScheme::RsaPkcs1v15Sha256);
+        assert_eq!(decoded.as_spki().unwrap(), RSA_2048_SPKI);
+
+        // Written back in the PEM encoding used by the reference implementation.
+        original["keyval"]["public"] = json!(RSA_2048_SPKI_PEM.trim_end());
+        assert_eq!(serde_json::to_value(&decoded).unwrap(), original);
+    }
+
     #[test]
     fn de_ser_rsa_public_key_without_keyid_hash_algo() {
         let original = json!({
             "keytype": "rsa",
             "scheme": "rsassa-pss-sha256",
             "keyval": {
-                "public": BASE64URL.encode(RSA_2048_SPKI),
+                "public": RSA_2048_SPKI_PEM.trim_end(),
             }
         });
 
@@ -1373,14 +2117,227 @@ mod test {
     #[test]
     #[cfg(not(any(target_os = "fuchsia", windows)))]
     fn new_rsa_key() {
-        let bytes = PrivateKey::new(KeyType::Rsa).unwrap();
-        let _ = PrivateKey::from_pkcs8(&bytes, SignatureScheme::RsaSsaPssSha256).unwrap();
+        let key = PrivateKey::new(SignatureScheme::RsaSsaPssSha256).unwrap();
+        let loaded =
+            PrivateKey::from_pkcs8(&key.to_pkcs8(), SignatureScheme::RsaSsaPssSha256).unwrap();
+        assert_eq!(loaded.key_id(), key.key_id());
     }
 
     #[test]
-    fn new_ed25519_key() {
-        let bytes = PrivateKey::new(KeyType::Ed25519).unwrap();
-        let _ = PrivateKey::from_pkcs8(&bytes, SignatureScheme::Ed25519).unwrap();
+    fn new_keys_sign_and_verify() {
+        for scheme in [SignatureScheme::Ed25519, SignatureScheme::EcdsaSha2Nistp256] {
+            let key = PrivateKey::new(scheme.clone()).unwrap();
+            let other = PrivateKey::new(scheme.clone()).unwrap();
+            assert_ne!(key.key_id(), other.key_id());
+
+            let loaded = PrivateKey::from_pkcs8(&key.to_pkcs8(), scheme).unwrap();
+            assert_eq!(loaded.key_id(), key.key_id());
+            assert_eq!(loaded.public(), key.public());
+
+            let sig = key.sign(b"test").unwrap();
+            loaded.public().verify(b"test", &sig).unwrap();
+            assert!(other.public().verify(b"test", &sig).is_err());
+        }
+        assert!(PrivateKey::new(SignatureScheme::Unknown("foo".into())).is_err());
+    }
+
+    #[test]
+    fn to_pkcs8_round_trips() {
+        let key = PrivateKey::from_pkcs8(ED25519_1_PK8, SignatureScheme::Ed25519).unwrap();
+        assert_eq!(key.to_pkcs8(), ED25519_1_PK8);
+
+        // Keys given as a raw keypair are exported as PKCS#8v1.
+        let raw = PrivateKey::from_ed25519(ED25519_1_PRIVATE_KEY).unwrap();
+        let loaded = PrivateKey::from_pkcs8(&raw.to_pkcs8(), SignatureScheme::Ed25519).unwrap();
+        assert_eq!(loaded.public().as_bytes(), raw.public().as_bytes());
+
+        let key = PrivateKey::from_rsa(RSA_2048_PK8_PEM, SignatureScheme::RsaPkcs1v15Sha256);
+        assert_eq!(key.unwrap().to_pkcs8(), RSA_2048_PK8);
+        let key = PrivateKey::from_ecdsa(ECDSA_P256_PK8_PEM).unwrap();
+        assert_eq!(key.to_pkcs8(), ECDSA_P256_PK8);
+    }
+
+    #[test]
+    fn return_all_algorithms_are_wired_up() {
+        let vectors: HashMap<&str, &str> = vec![
+            (
+                "sha256",
+                "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+            ),
+            (
+                "sha384",
+                "cb00753f45a35e8bb5a03d699ac65007272c32ab0eded163\
+                 1a8b605a43ff5bed8086072ba1e7cc2358baeca134c825a7",
+            ),
+            (
+                "sha512",
+                "ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a\
+                 2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f",
+            ),
+            #[cfg(feature = "blake")]
+            (
+                "blake2b-256",
+                "bddd813c634239723171ef3fee98579b94964e3bb1cb3e427262c8c068d52319",
+            ),
+            #[cfg(feature = "blake")]
+            (
+                "blake3",
+                "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85",
+            ),
+        ]
+        .into_iter()
+        .collect();
+
+        let all = HashAlgorithm::return_all();
+        assert_eq!(all.len(), vectors.len());
+        for (name, alg) in all {
+            assert_eq!(serde_json::to_value(&alg).unwrap(), json!(name));
+
+            let (size, hashes) = calculate_hashes(&b"abc"[..], std::slice::from_ref(&alg)).unwrap();
+            assert_eq!(size, 3);
+            let digest = hashes.get(&alg).unwrap();
+            assert!(!digest.value().is_empty());
+            assert_eq!(digest.to_string(), vectors[name.as_str()]);
+        }
+    }
+
+    #[test]
+    fn sha384_target_description_round_trip() {
+        let (_, hashes) = calculate_hashes(&b"abc"[..], &[HashAlgorithm::Sha384]).unwrap();
+        let jsn = serde_json::to_value(&hashes).unwrap();
+        assert_eq!(
+            jsn,
+            json!({
+                "sha384": "cb00753f45a35e8bb5a03d699ac65007272c32ab0eded163\
+                           1a8b605a43ff5bed8086072ba1e7cc2358baeca134c825a7"
+            })
+        );
+        let decoded: HashMap<HashAlgorithm, HashValue> = serde_json::from_value(jsn).unwrap();
+        assert_eq!(decoded, hashes);
+    }
+
+    #[test]
+    fn hasher_matches_one_shot_hashes() {
+        let data: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+        let algs = [HashAlgorithm::Sha256, HashAlgorithm::Sha384];
+        let (size, expected) = calculate_hashes(&data[..], &algs).unwrap();
+
+        let mut hasher = Hasher::new(&algs).unwrap();
+        for chunk in data.chunks(997) {
+            hasher.update(chunk);
+        }
+        hasher.update(&[]);
+        assert_eq!(hasher.size(), size);
+        assert_eq!(hasher.finalize(), expected);
+
+        let mut hasher = Hasher::new(&algs).unwrap();
+        let _ = io::copy(&mut &data[..], &mut hasher).unwrap();
+        assert_eq!(hasher.finalize(), expected);
+
+        assert!(Hasher::new(&[]).is_err());
+        assert!(Hasher::new(&[HashAlgorithm::Unknown("md5".into())]).is_err());
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn mmap_hashes_match_buffered_hashes() {
+        let data: Vec<u8> = (0..300_000u32).map(|i| (i % 251) as u8).collect();
+        let mut file = tempfile::tempfile().unwrap();
+        file.write_all(&data).unwrap();
+        let algs = [HashAlgorithm::Sha256, HashAlgorithm::Sha512];
+        let expected = calculate_hashes(&data[..], &algs).unwrap();
+
+        for &threshold in &[0, data.len() as u64, data.len() as u64 + 1, u64::MAX] {
+            io::Seek::seek(&mut file, io::SeekFrom::Start(0)).unwrap();
+            let hashes = calculate_file_hashes(&file, &algs, 4096, threshold).unwrap();
+            assert_eq!(hashes, expected);
+        }
+
+        let empty = tempfile::tempfile().unwrap();
+        assert_eq!(
+            calculate_file_hashes(&empty, &algs, 4096, 0).unwrap(),
+            calculate_hashes(&b""[..], &algs).unwrap()
+        );
+
+        #[cfg(unix)]
+        {
+            let null = std::fs::File::open("/dev/null").unwrap();
+            assert_eq!(
+                calculate_file_hashes(&null, &algs, 4096, 0).unwrap(),
+                calculate_hashes(&b""[..], &algs).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn parse_hash_algorithm_aliases() {
+        for name in &["sha256", "SHA256", "sha-256", "Sha-256"] {
+            assert_eq!(name.parse::<HashAlgorithm>(), Ok(HashAlgorithm::Sha256));
+        }
+        assert_eq!(
+            "SHA-384".parse::<HashAlgorithm>(),
+            Ok(HashAlgorithm::Sha384)
+        );
+        assert_eq!("sha512".parse::<HashAlgorithm>(), Ok(HashAlgorithm::Sha512));
+        for (name, alg) in HashAlgorithm::return_all() {
+            assert_eq!(name.to_uppercase().parse::<HashAlgorithm>(), Ok(alg));
+        }
+
+        for name in &["md5", "sha2", "sha_256", "sha256 ", ""] {
+            assert_eq!(
+                name.parse::<HashAlgorithm>(),
+                Err(Error::UnknownHashAlgorithm(name.to_string()))
+            );
+        }
+        assert_eq!(
+            Error::UnknownHashAlgorithm("md5".into()).to_string(),
+            "unknown hash algorithm: md5"
+        );
+    }
+
+    #[test]
+    fn calculate_hashes_names_unknown_algorithm() {
+        let res = calculate_hashes(&b"abc"[..], &[HashAlgorithm::Unknown("md5".into())]);
+        assert_eq!(res, Err(Error::UnknownHashAlgorithm("md5".into())));
+    }
+
+    #[test]
+    fn calculate_hashes_reads_large_files_in_chunks() {
+        /// Fails any read asking for more than `limit` bytes at once.
+        struct Chunked<R> {
+            inner: R,
+            limit: usize,
+        }
+
+        impl<R: Read> Read for Chunked<R> {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                assert!(buf.len() <= self.limit, "read of {} bytes", buf.len());
+                self.inner.read(buf)
+            }
+        }
+
+        // A sparse file takes no disk space, and is far larger than any buffer used to hash it.
+        const SIZE: u64 = 64 * 1024 * 1024;
+        let file = tempfile::tempfile().unwrap();
+        file.set_len(SIZE).unwrap();
+
+        let algs = [HashAlgorithm::Sha256, HashAlgorithm::Sha512];
+        let mut digests = Vec::new();
+        for &buffer_size in &[DEFAULT_HASH_BUFFER_SIZE, 1024 * 1024] {
+            let mut file = file.try_clone().unwrap();
+            io::Seek::seek(&mut file, io::SeekFrom::Start(0)).unwrap();
+            let reader = Chunked {
+                inner: file,
+                limit: buffer_size,
+            };
+            let (size, hashes) =
+                calculate_hashes_with_buffer_size(reader, &algs, buffer_size).unwrap();
+            assert_eq!(size, SIZE);
+            digests.push(hashes);
+        }
+        assert_eq!(digests[0], digests[1]);
+
+        assert!(calculate_hashes_with_buffer_size(&b"abc"[..], &algs, 0).is_err());
     }
 
     #[test]
@@ -0,0 +1,349 @@
+//! Keyless signing: signatures made with an ephemeral key whose binding to an identity is
+//! attested by a short-lived certificate, as issued by Sigstore's Fulcio.
+//!
+//! The signer attaches the certificate chain of its ephemeral key to the signature (see
+//! `SignedMetadataBuilder::sign_with_cert_chain`). A verifier trusts a set of root certificates
+//! and an identity, the e-mail address or URI found in the leaf certificate's subject alternative
+//! names, rather than a set of public keys.
+
+use x509_parser::certificate::X509Certificate;
+use x509_parser::extensions::GeneralName;
+use x509_parser::pem::Pem;
+use x509_parser::time::ASN1Time;
+
+use super::{
+    python_sslib_compatibility_keyid_hash_algorithms, PublicKey, SignatureScheme, ECDSA_SPKI_OID,
+    ED25519_SPKI_OID,
+};
+use crate::error::Error;
+use crate::interchange::DataInterchange;
+use crate::models::{Metadata, SignedMetadata};
+use crate::Result;
+
+/// The roots and identity a keyless signature must chain to.
+///
+/// Certificate validity periods are only checked if a verification time is set with `valid_at`.
+/// Keyless certificates typically expire minutes after issuance, so that time should be a trusted
+/// signing time, such as a transparency log inclusion time, rather than the current time.
+#[derive(Debug, Clone)]
+pub struct KeylessPolicy {
+    roots: Vec<Vec<u8>>,
+    identity: String,
+    time: Option<i64>,
+}
+
+impl KeylessPolicy {
+    /// Trust the PEM root certificates in `roots` to issue certificates for `identity`.
+    pub fn new(roots: &[u8], identity: &str) -> Result<Self> {
+        let roots = Pem::iter_from_buffer(roots)
+            .map(|pem| {
+                pem.map(|pem| pem.contents)
+                    .map_err(|e| Error::Encoding(format!("Invalid root certificate: {:?}", e)))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if roots.is_empty() {
+            return Err(Error::IllegalArgument(
+                "No root certificates were provided".into(),
+            ));
+        }
+
+        Ok(KeylessPolicy {
+            roots,
+            identity: identity.to_string(),
+            time: None,
+        })
+    }
+
+    /// Require every certificate in the chain to be valid at `unix_time`, in seconds.
+    pub fn valid_at(mut self, unix_time: i64) -> Self {
+        self.time = Some(unix_time);
+        self
+    }
+
+    /// Check that `cert_chain`, PEM certificates ordered leaf first, chains to one of the trusted
+    /// roots and that its leaf is a code signing certificate for the trusted identity. Returns
+    /// the public key certified by the leaf.
+    pub fn verify_cert_chain(&self, cert_chain: &[String]) -> Result<PublicKey> {
+        let ders = cert_chain
+            .iter()
+            .map(|pem| parse_pem(pem.as_bytes()))
+            .collect::<Result<Vec<_>>>()?;
+        let chain = ders
+            .iter()
+            .map(|der| parse_der(der))
+            .collect::<Result<Vec<_>>>()?;
+        let roots = self
+            .roots
+            .iter()
+            .map(|der| parse_der(der))
+            .collect::<Result<Vec<_>>>()?;
+
+        let leaf = chain
+            .first()
+            .ok_or_else(|| Error::VerificationFailure("Empty certificate chain".into()))?;
+        self.verify_leaf(leaf)?;
+
+        for (depth, pair) in chain.windows(2).enumerate() {
+            verify_issued_by(&pair[0], &pair[1], depth)?;
+        }
+
+        let last = &chain[chain.len() - 1];
+        let last_der = &ders[ders.len() - 1];
+        let anchored = self.roots.iter().any(|root| root == last_der)
+            || roots
+                .iter()
+                .any(|root| verify_issued_by(last, root, chain.len() - 1).is_ok());
+        if !anchored {
+            return Err(Error::VerificationFailure(
+                "Certificate chain does not lead to a trusted root".into(),
+            ));
+        }
+
+        if let Some(time) = self.time {
+            let time = ASN1Time::from_timestamp(time)
+                .map_err(|e| Error::IllegalArgument(format!("Invalid time: {:?}", e)))?;
+            if !chain
+                .iter()
+                .chain(roots.iter())
+                .all(|cert| cert.validity().is_valid_at(time))
+            {
+                return Err(Error::VerificationFailure(
+                    "Certificate chain is not valid at the verification time".into(),
+                ));
+            }
+        }
+
+        // Fulcio issues ECDSA P-256 leaves; Ed25519 ones are accepted too.
+        let spki = leaf.public_key();
+        match spki.algorithm.algorithm.as_bytes() {
+            ED25519_SPKI_OID => PublicKey::from_ed25519_with_keyid_hash_algorithms(
+                spki.subject_public_key.data.to_vec(),
+                python_sslib_compatibility_keyid_hash_algorithms(),
+            ),
+            ECDSA_SPKI_OID => PublicKey::from_spki(spki.raw, SignatureScheme::EcdsaSha2Nistp256),
+            _ => Err(Error::VerificationFailure(
+                "Only Ed25519 and ECDSA P-256 ephemeral keys are supported".into(),
+            )),
+        }
+    }
+
+    fn verify_leaf(&self, leaf: &X509Certificate) -> Result<()> {
+        if leaf.is_ca() {
+            return Err(Error::VerificationFailure(
+                "Leaf certificate is a CA certificate".into(),
+            ));
+        }
+
+        let code_signing = leaf
+            .extended_key_usage()
+            .map_err(x509_error)?
+            .map(|eku| eku.value.code_signing)
+            .unwrap_or(false);
+        if !code_signing {
+            return Err(Error::VerificationFailure(
+                "Leaf certificate is not valid for code signing".into(),
+            ));
+        }
+
+        let identities = match leaf.subject_alternative_name().map_err(x509_error)? {
+            Some(san) => san
+                .value
+                .general_names
+                .iter()
+                .filter_map(|name| match name {
+                    GeneralName::RFC822Name(name) | GeneralName::URI(name) => Some(*name),
+                    _ => None,
+                })
+                .collect::<Vec<_>>(),
+            None => Vec::new(),
+        };
+        if !identities.contains(&self.identity.as_str()) {
+            return Err(Error::VerificationFailure(format!(
+                "Leaf certificate identities {:?} do not include {:?}",
+                identities, self.identity
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+impl<D, M> SignedMetadata<D, M>
+where
+    D: DataInterchange,
+    M: Metadata,
+{
+    /// Verify this metadata against a keyless `policy`: at least one signature must carry a
+    /// certificate chain accepted by `policy` and verify under the key it certifies.
+    pub fn verify_keyless(&self, policy: &KeylessPolicy) -> Result<M> {
+        let mut last_error = None;
+        let keys = self
+            .signatures()
+            .iter()
+            .filter(|sig| !sig.cert_chain().is_empty())
+            .filter_map(|sig| match policy.verify_cert_chain(sig.cert_chain()) {
+                Ok(key) if key.key_id() == sig.key_id() => Some(key),
+                Ok(_) => {
+                    last_error = Some(Error::VerificationFailure(
+                        "Certificate does not match the signing key".into(),
+                    ));
+                    None
+                }
+                Err(e) => {
+                    last_error = Some(e);
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+
+        if keys.is_empty() {
+            return Err(last_error.unwrap_or_else(|| {
+                Error::VerificationFailure("No signature carries a certificate chain".into())
+            }));
+        }
+        self.verify(1, &keys)
+    }
+}
+
+fn verify_issued_by(cert: &X509Certificate, issuer: &X509Certificate, depth: usize) -> Result<()> {
+    let constraints = issuer
+        .basic_constraints()
+        .map_err(x509_error)?
+        .map(|bc| bc.value.clone());
+    match constraints {
+        Some(ref bc) if bc.ca => (),
+        _ => {
+            return Err(Error::VerificationFailure(
+                "Issuer is not a CA certificate".into(),
+            ))
+        }
+    }
+    // `depth` CA certificates sit between `issuer` and the leaf.
+    if let Some(max) = constraints.and_then(|bc| bc.path_len_constraint) {
+        if depth > max as usize {
+            return Err(Error::VerificationFailure(
+                "Certificate chain exceeds an issuer's path length constraint".into(),
+            ));
+        }
+    }
+
+    cert.verify_signature(Some(issuer.public_key()))
+        .map_err(|e| Error::VerificationFailure(format!("Bad certificate signature: {:?}", e)))
+}
+
+fn parse_pem(pem: &[u8]) -> Result<Vec<u8>> {
+    match Pem::iter_from_buffer(pem).next() {
+        Some(Ok(pem)) => Ok(pem.contents),
+        Some(Err(e)) => Err(Error::Encoding(format!("Invalid certificate: {:?}", e))),
+        None => Err(Error::Encoding("No PEM certificate found".into())),
+    }
+}
+
+fn parse_der(der: &[u8]) -> Result<X509Certificate<'_>> {
+    x509_parser::parse_x509_certificate(der)
+        .map(|(_, cert)| cert)
+        .map_err(|e| Error::Encoding(format!("Invalid certificate: {:?}", e)))
+}
+
+fn x509_error(err: x509_parser::error::X509Error) -> Error {
+    Error::Encoding(format!("Invalid certificate: {:?}", err))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::crypto::PrivateKey;
+    use crate::interchange::Json;
+    use crate::models::{LinkMetadata, LinkMetadataBuilder, SignedMetadataBuilder};
+
+    const EPHEMERAL_PK8: &[u8] = include_bytes!("../../tests/ed25519/ed25519-2.pk8.der");
+    const EPHEMERAL_P256_PK8: &[u8] = include_bytes!("../../tests/ecdsa/ecdsa-p256.pk8.der");
+    const ROOT: &str = include_str!("../../tests/keyless/root.pem");
+    const INTERMEDIATE: &str = include_str!("../../tests/keyless/intermediate.pem");
+    const LEAF: &str = include_str!("../../tests/keyless/leaf.pem");
+    const LEAF_P256: &str = include_str!("../../tests/keyless/leaf-p256.pem");
+    const OTHER_ROOT: &str = include_str!("../../tests/keyless/other-root.pem");
+    const IDENTITY: &str = "builder@example.com";
+
+    fn signed_link(cert_chain: Vec<String>) -> SignedMetadata<Json, LinkMetadata> {
+        let key = PrivateKey::from_pkcs8(EPHEMERAL_PK8, SignatureScheme::Ed25519).unwrap();
+        signed_link_with(&key, cert_chain)
+    }
+
+    fn signed_link_with(
+        key: &PrivateKey,
+        cert_chain: Vec<String>,
+    ) -> SignedMetadata<Json, LinkMetadata> {
+        let link = LinkMetadataBuilder::new()
+            .name("build".to_string())
+            .build()
+            .unwrap();
+        SignedMetadataBuilder::<Json, _>::from_metadata(&link)
+            .unwrap()
+            .sign_with_cert_chain(key, cert_chain)
+            .unwrap()
+            .build()
+    }
+
+    fn chain() -> Vec<String> {
+        vec![LEAF.to_string(), INTERMEDIATE.to_string()]
+    }
+
+    #[test]
+    fn verify_keyless_with_trusted_chain() {
+        let signed = signed_link(chain());
+        let policy = KeylessPolicy::new(ROOT.as_bytes(), IDENTITY).unwrap();
+        assert_eq!(signed.verify_keyless(&policy).unwrap().name(), "build");
+
+        // The chain survives serialization alongside the signature.
+        let jsn = serde_json::to_value(&signed).unwrap();
+        let decoded: SignedMetadata<Json, LinkMetadata> = serde_json::from_value(jsn).unwrap();
+        assert!(decoded.verify_keyless(&policy).is_ok());
+    }
+
+    #[test]
+    fn verify_keyless_with_ecdsa_p256_leaf() {
+        let key = PrivateKey::from_ecdsa(EPHEMERAL_P256_PK8).unwrap();
+        let chain = vec![LEAF_P256.to_string(), INTERMEDIATE.to_string()];
+        let policy = KeylessPolicy::new(ROOT.as_bytes(), IDENTITY).unwrap();
+
+        let certified = policy.verify_cert_chain(&chain).unwrap();
+        assert_eq!(certified.scheme(), &SignatureScheme::EcdsaSha2Nistp256);
+        assert_eq!(certified.key_id(), key.key_id());
+        let signed = signed_link_with(&key, chain);
+        assert_eq!(signed.verify_keyless(&policy).unwrap().name(), "build");
+
+        // The P-256 leaf does not certify the Ed25519 key.
+        let other = signed_link(vec![LEAF_P256.to_string(), INTERMEDIATE.to_string()]);
+        assert!(other.verify_keyless(&policy).is_err());
+    }
+
+    #[test]
+    fn verify_keyless_rejects_mismatched_identity() {
+        let signed = signed_link(chain());
+        let policy = KeylessPolicy::new(ROOT.as_bytes(), "attacker@example.com").unwrap();
+        assert!(signed.verify_keyless(&policy).is_err());
+    }
+
+    #[test]
+    fn verify_keyless_rejects_untrusted_chain() {
+        let policy = KeylessPolicy::new(OTHER_ROOT.as_bytes(), IDENTITY).unwrap();
+        assert!(signed_link(chain()).verify_keyless(&policy).is_err());
+
+        let policy = KeylessPolicy::new(ROOT.as_bytes(), IDENTITY).unwrap();
+        assert!(signed_link(vec![LEAF.to_string()])
+            .verify_keyless(&policy)
+            .is_err());
+        assert!(signed_link(Vec::new()).verify_keyless(&policy).is_err());
+    }
+
+    #[test]
+    fn verify_keyless_checks_validity_time() {
+        let signed = signed_link(chain());
+        let policy = KeylessPolicy::new(ROOT.as_bytes(), IDENTITY).unwrap();
+        assert!(signed
+            .verify_keyless(&policy.clone().valid_at(1_800_000_000))
+            .is_ok());
+        assert!(signed.verify_keyless(&policy.valid_at(0)).is_err());
+    }
+}
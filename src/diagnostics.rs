@@ -0,0 +1,126 @@
+//! Structured diagnostics collected while recording and verifying.
+//!
+//! Functions that accept a `Diagnostics` sink report what they skipped or tolerated into it, in
+//! addition to logging, so callers get a machine-readable list they can summarize or fail on.
+
+use std::fmt::{self, Display};
+
+/// How serious a diagnostic is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    /// Expected behavior worth reporting, such as an excluded file.
+    Info,
+    /// Something was skipped or tolerated that may indicate a problem.
+    Warning,
+}
+
+/// What a diagnostic is about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DiagnosticCategory {
+    /// A symbolic link was not followed because it would close a cycle.
+    SymlinkCycle,
+    /// A path was excluded from recording, e.g. by an `.in-toto-ignore` file.
+    Excluded,
+    /// A signature from a revoked key was ignored.
+    RevokedKey,
+    /// A signature from an authorized key failed to verify.
+    BadSignature,
+    /// A signature was made by a key that is not authorized.
+    UnknownKey,
+}
+
+impl DiagnosticCategory {
+    /// The severity diagnostics in this category are reported with.
+    pub fn severity(self) -> Severity {
+        match self {
+            DiagnosticCategory::Excluded => Severity::Info,
+            DiagnosticCategory::SymlinkCycle
+            | DiagnosticCategory::RevokedKey
+            | DiagnosticCategory::BadSignature
+            | DiagnosticCategory::UnknownKey => Severity::Warning,
+        }
+    }
+}
+
+/// A single diagnostic record.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    category: DiagnosticCategory,
+    message: String,
+}
+
+impl Diagnostic {
+    /// Create a diagnostic in `category`.
+    pub fn new<S: Into<String>>(category: DiagnosticCategory, message: S) -> Self {
+        Diagnostic {
+            category,
+            message: message.into(),
+        }
+    }
+
+    /// What the diagnostic is about.
+    pub fn category(&self) -> DiagnosticCategory {
+        self.category
+    }
+
+    /// How serious the diagnostic is.
+    pub fn severity(&self) -> Severity {
+        self.category.severity()
+    }
+
+    /// A human-readable description.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}: {}", self.severity(), self.message)
+    }
+}
+
+/// A sink collecting diagnostics in the order they were reported.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Diagnostics {
+    records: Vec<Diagnostic>,
+}
+
+impl Diagnostics {
+    /// Create an empty sink.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Report `diagnostic`.
+    pub fn push(&mut self, diagnostic: Diagnostic) {
+        self.records.push(diagnostic);
+    }
+
+    /// All diagnostics reported so far.
+    pub fn records(&self) -> &[Diagnostic] {
+        &self.records
+    }
+
+    /// The diagnostics at or above `severity`.
+    pub fn at_least(&self, severity: Severity) -> impl Iterator<Item = &Diagnostic> {
+        self.records
+            .iter()
+            .filter(move |record| record.severity() >= severity)
+    }
+
+    /// The highest severity reported, if any diagnostic was reported.
+    pub fn max_severity(&self) -> Option<Severity> {
+        self.records.iter().map(Diagnostic::severity).max()
+    }
+
+    /// The number of diagnostics reported.
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    /// Whether no diagnostic was reported.
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+}
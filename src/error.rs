@@ -1,10 +1,15 @@
 //! Error types and converters.
 
+use chrono::{DateTime, Utc};
 use data_encoding::DecodeError;
+use std::fmt;
 use std::io;
 use std::path::Path;
+use std::time::Duration;
 use thiserror::Error;
 
+use crate::crypto::KeyId;
+
 
 /// Error type for all in-toto related errors.
 #[derive(Error, Debug, PartialEq, Eq)]
@@ -13,14 +18,53 @@ pub enum Error {
     #[error("bad signature")]
     BadSignature,
 
+    /// A command exited with a non-zero exit code, or was terminated by a signal, where that is
+    /// treated as a failure.
+    #[error("command failed with exit code {code:?}")]
+    CommandFailed {
+        /// The exit code of the command, or `None` if it was terminated by a signal.
+        code: Option<i32>,
+        /// What the command wrote to stderr, lossily decoded as UTF-8.
+        stderr: String,
+    },
+
+    /// The program of a command could not be found.
+    #[error("command not found: {0}")]
+    CommandNotFound(String),
+
+    /// A command did not exit within its timeout and was killed. Holds the output it produced
+    /// before it was killed, lossily decoded as UTF-8.
+    #[error("command timed out after {timeout:?}")]
+    CommandTimeout {
+        /// The timeout the command exceeded.
+        timeout: Duration,
+        /// What the command wrote to stdout before it was killed.
+        stdout: String,
+        /// What the command wrote to stderr before it was killed.
+        stderr: String,
+    },
+
+    /// The step dependencies of a layout form a cycle, or a step matches artifacts of a step
+    /// declared to run after it.
+    #[error("step dependency cycle or violation: {0}")]
+    DependencyCycleOrViolation(String),
+
     /// There was a problem encoding or decoding.
     #[error("encoding: {0}")]
     Encoding(String),
 
+    /// The layout expired at the given time, before the time it was verified at.
+    #[error("layout expired at {0}")]
+    ExpiredLayout(DateTime<Utc>),
+
     /// An illegal argument was passed into a function.
     #[error("illegal argument: {0}")]
     IllegalArgument(String),
 
+    /// An encrypted private key could not be decrypted, e.g. because the passphrase was wrong.
+    #[error("key decryption: {0}")]
+    KeyDecryption(String),
+
     /// There were no available hash algorithms.
     #[error("no supported hash algorithm")]
     NoSupportedHashAlgorithm,
@@ -34,6 +78,14 @@ pub enum Error {
     #[error("opaque: {0}")]
     Opaque(String),
 
+    /// The metadata does not match its JSON Schema.
+    #[error("schema violations: {}", join_violations(.0))]
+    Schema(Vec<SchemaViolation>),
+
+    /// The metadata was only signed by keys that have been revoked.
+    #[error("revoked key: {0:?}")]
+    RevokedKey(KeyId),
+
     /// There was a library internal error. These errors are *ALWAYS* bugs and should be reported.
     #[error("programming: {0}")]
     Programming(String),
@@ -43,25 +95,91 @@ pub enum Error {
     #[error("target unavailable")]
     TargetUnavailable,
 
+    /// Not enough authorized keys signed the metadata, although no signature was bad, unknown or
+    /// revoked.
+    #[error("signature threshold not met: {valid}/{threshold}")]
+    ThresholdNotMet {
+        /// The number of distinct authorized keys with a good signature.
+        valid: u32,
+        /// The number of distinct authorized keys required.
+        threshold: u32,
+    },
+
     /// There is no known or available hash algorithm.
     #[error("unknown hash algorithm: {0}")]
-    UnkonwnHashAlgorithm(String),
+    UnknownHashAlgorithm(String),
+
+    /// The metadata was signed by a key that is not among the authorized keys.
+    #[error("unknown key ID: {0:?}")]
+    UnknownKeyId(KeyId),
 
     /// There is no known or available key type.
     #[error("unknown key type: {0}")]
     UnknownKeyType(String),
 
+    /// An artifact rule has a keyword that is neither built in nor registered as a custom rule.
+    #[error("unknown artifact rule: {0}")]
+    UnknownRule(String),
+
+    /// Provenance was generated by a builder that is not trusted to build the artifacts.
+    #[error("untrusted builder: {0}")]
+    UntrustedBuilder(String),
+
     /// The metadata or target failed to verify.
     #[error("verification failure: {0}")]
     VerificationFailure(String),
 }
 
+/// A value that does not match the JSON Schema it is validated against.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaViolation {
+    /// JSON pointer to the offending value.
+    pub pointer: String,
+    /// Description of the violation.
+    pub message: String,
+}
+
+impl fmt::Display for SchemaViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.pointer, self.message)
+    }
+}
+
+fn join_violations(violations: &[SchemaViolation]) -> String {
+    violations
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
 impl From<serde_json::error::Error> for Error {
     fn from(err: serde_json::error::Error) -> Error {
         Error::Encoding(format!("JSON: {:?}", err))
     }
 }
 
+#[cfg(feature = "cbor")]
+impl From<ciborium::de::Error<io::Error>> for Error {
+    fn from(err: ciborium::de::Error<io::Error>) -> Error {
+        Error::Encoding(format!("CBOR: {:?}", err))
+    }
+}
+
+#[cfg(feature = "cbor")]
+impl From<ciborium::ser::Error<io::Error>> for Error {
+    fn from(err: ciborium::ser::Error<io::Error>) -> Error {
+        Error::Encoding(format!("CBOR: {:?}", err))
+    }
+}
+
+#[cfg(feature = "cbor")]
+impl From<ciborium::value::Error> for Error {
+    fn from(err: ciborium::value::Error) -> Error {
+        Error::Encoding(format!("CBOR: {:?}", err))
+    }
+}
+
 impl Error {
     /// Helper to include the path that causd the error for FS I/O errors.
     pub fn from_io(err: &io::Error, path: &Path) -> Error {
@@ -0,0 +1,214 @@
+use ciborium::value::Value;
+use serde::de::DeserializeOwned;
+use serde::ser::Serialize;
+use std::io::{Read, Write};
+
+use crate::interchange::DataInterchange;
+use crate::Result;
+
+/// CBOR data interchange, for compact binary metadata.
+///
+/// Signatures are computed over the core deterministic encoding of RFC 8949, section 4.2.1:
+/// integers, lengths and floats use their shortest form, only definite lengths are used, and the
+/// entries of every map are sorted by the bytes of their encoded keys.
+///
+/// ```
+/// # use in_toto::crypto::{PrivateKey, SignatureScheme};
+/// # use in_toto::interchange::{Cbor, DataInterchange};
+/// # use in_toto::models::{LinkMetadata, LinkMetadataBuilder, SignedMetadata};
+/// let key = PrivateKey::new(SignatureScheme::Ed25519).unwrap();
+/// let signed = LinkMetadataBuilder::new()
+///     .name("build".to_string())
+///     .signed::<Cbor>(&key)
+///     .unwrap();
+///
+/// let mut buf = Vec::new();
+/// Cbor::to_writer(&mut buf, &signed).unwrap();
+/// let read: SignedMetadata<Cbor, LinkMetadata> = Cbor::from_slice(&buf).unwrap();
+/// assert_eq!(read.verify(1, vec![key.public()]).unwrap().name(), "build");
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cbor;
+
+impl DataInterchange for Cbor {
+    type RawData = Value;
+
+    /// ```
+    /// # use in_toto::interchange::{Cbor, DataInterchange};
+    /// assert_eq!(Cbor::extension(), "cbor");
+    /// ```
+    fn extension() -> &'static str {
+        "cbor"
+    }
+
+    /// ```
+    /// # use ciborium::value::Value;
+    /// # use in_toto::interchange::{Cbor, DataInterchange};
+    /// let raw = Value::Map(vec![
+    ///     (Value::Text("foo".into()), Value::Integer(1.into())),
+    ///     (Value::Text("ba".into()), Value::Integer(1000.into())),
+    /// ]);
+    /// assert_eq!(
+    ///     Cbor::canonicalize(&raw).unwrap(),
+    ///     b"\xa2\x62ba\x19\x03\xe8\x63foo\x01"
+    /// );
+    /// ```
+    fn canonicalize(raw_data: &Self::RawData) -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        ciborium::into_writer(&sort_maps(raw_data)?, &mut buf)?;
+        Ok(buf)
+    }
+
+    /// ```
+    /// # use ciborium::value::Value;
+    /// # use in_toto::interchange::{Cbor, DataInterchange};
+    /// let raw = Value::Text("wat".into());
+    /// let de: String = Cbor::deserialize(&raw).unwrap();
+    /// assert_eq!(de, "wat");
+    /// ```
+    fn deserialize<T>(raw_data: &Self::RawData) -> Result<T>
+    where
+        T: DeserializeOwned,
+    {
+        Ok(raw_data.deserialized()?)
+    }
+
+    /// ```
+    /// # use ciborium::value::Value;
+    /// # use in_toto::interchange::{Cbor, DataInterchange};
+    /// let se = Cbor::serialize(&vec![1u8]).unwrap();
+    /// assert_eq!(se, Value::Array(vec![Value::Integer(1.into())]));
+    /// ```
+    fn serialize<T>(data: &T) -> Result<Self::RawData>
+    where
+        T: Serialize,
+    {
+        Ok(Value::serialized(data)?)
+    }
+
+    /// Write `value` in its deterministic encoding.
+    fn to_writer<W, T>(mut writer: W, value: &T) -> Result<()>
+    where
+        W: Write,
+        T: Sized + Serialize,
+    {
+        let bytes = Self::canonicalize(&Self::serialize(value)?)?;
+        writer.write_all(&bytes)?;
+        Ok(())
+    }
+
+    /// ```
+    /// # use in_toto::interchange::{Cbor, DataInterchange};
+    /// let cbor: &[u8] = b"\x63wat";
+    /// let de: String = Cbor::from_reader(cbor).unwrap();
+    /// assert_eq!(de, "wat");
+    /// ```
+    fn from_reader<R, T>(rdr: R) -> Result<T>
+    where
+        R: Read,
+        T: DeserializeOwned,
+    {
+        Ok(ciborium::from_reader(rdr)?)
+    }
+
+    /// ```
+    /// # use in_toto::interchange::{Cbor, DataInterchange};
+    /// let de: String = Cbor::from_slice(b"\x63wat").unwrap();
+    /// assert_eq!(de, "wat");
+    /// ```
+    fn from_slice<T>(slice: &[u8]) -> Result<T>
+    where
+        T: DeserializeOwned,
+    {
+        Self::from_reader(slice)
+    }
+}
+
+/// Copy `value` with the entries of every map ordered by their encoded keys.
+fn sort_maps(value: &Value) -> Result<Value> {
+    Ok(match value {
+        Value::Array(items) => Value::Array(items.iter().map(sort_maps).collect::<Result<_>>()?),
+        Value::Map(entries) => {
+            let mut sorted = Vec::with_capacity(entries.len());
+            for (k, v) in entries {
+                let k = sort_maps(k)?;
+                let mut encoded = Vec::new();
+                ciborium::into_writer(&k, &mut encoded)?;
+                sorted.push((encoded, k, sort_maps(v)?));
+            }
+            sorted.sort_by(|a, b| a.0.cmp(&b.0));
+            Value::Map(sorted.into_iter().map(|(_, k, v)| (k, v)).collect())
+        }
+        Value::Tag(tag, inner) => Value::Tag(*tag, Box::new(sort_maps(inner)?)),
+        other => other.clone(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::crypto::{PrivateKey, SignatureScheme};
+    use crate::models::{LinkMetadata, LinkMetadataBuilder, SignedMetadata};
+    use crate::runlib::record_artifacts;
+    use serde_json::json;
+    use std::collections::BTreeMap;
+
+    const ED25519_1_PK8: &[u8] = include_bytes!("../../tests/ed25519/ed25519-1.pk8.der");
+
+    #[test]
+    fn nested_maps_are_sorted_by_encoded_key() {
+        // Shorter keys sort first because their length is part of the encoding.
+        let raw =
+            Cbor::serialize(&json!({"bb": {"z": 1, "a": [{"y": 0, "x": -1}]}, "c": true})).unwrap();
+        assert_eq!(
+            Cbor::canonicalize(&raw).unwrap(),
+            b"\xa2\x61c\xf5\x62bb\xa2\x61a\x81\xa2\x61x\x20\x61y\x00\x61z\x01"
+        );
+    }
+
+    #[test]
+    fn signed_link_round_trips() {
+        let key = PrivateKey::from_pkcs8(ED25519_1_PK8, SignatureScheme::Ed25519).unwrap();
+        let mut env = BTreeMap::new();
+        env.insert("PWD".to_string(), "/src".to_string());
+        let link = LinkMetadataBuilder::new()
+            .name("package".to_string())
+            .command(vec!["tar".to_string(), "-czf".to_string()])
+            .materials(record_artifacts(&["tests/test_runlib/release"], None).unwrap())
+            .env(env)
+            .structured_byproduct("return-value".to_string(), json!(0))
+            .build()
+            .unwrap();
+
+        let signed = SignedMetadata::<Cbor, _>::new(&link, &key).unwrap();
+        let mut buf = Vec::new();
+        Cbor::to_writer(&mut buf, &signed).unwrap();
+        let mut again = Vec::new();
+        Cbor::to_writer(
+            &mut again,
+            &SignedMetadata::<Cbor, _>::new(&link, &key).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(buf, again);
+
+        let read: SignedMetadata<Cbor, LinkMetadata> = Cbor::from_slice(&buf).unwrap();
+        assert_eq!(read.verify(1, vec![key.public()]).unwrap(), link);
+
+        let mut tampered: Value = Cbor::from_slice(&buf).unwrap();
+        if let Value::Map(entries) = &mut tampered {
+            let signed = entries
+                .iter_mut()
+                .find(|(k, _)| k.as_text() == Some("signed"))
+                .unwrap();
+            if let Value::Map(fields) = &mut signed.1 {
+                let name = fields
+                    .iter_mut()
+                    .find(|(k, _)| k.as_text() == Some("name"))
+                    .unwrap();
+                name.1 = Value::Text("publish".into());
+            }
+        }
+        let tampered: SignedMetadata<Cbor, LinkMetadata> = Cbor::deserialize(&tampered).unwrap();
+        assert!(tampered.verify(1, vec![key.public()]).is_err());
+    }
+}
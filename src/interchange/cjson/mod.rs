@@ -39,11 +39,13 @@ pub use pretty::JsonPretty;
 /// }
 /// ```
 ///
-/// `PUBLIC` is a base64url encoded `SubjectPublicKeyInfo` DER public key.
+/// `PUBLIC` is the PEM encoded `SubjectPublicKeyInfo` of RSA and ECDSA keys, and the hex encoded
+/// bytes of ed25519 keys.
 ///
-/// `KEY_TYPE` is a string (either `rsa` or `ed25519`).
+/// `KEY_TYPE` is a string (either `rsa`, `ecdsa` or `ed25519`).
 ///
-/// `SCHEME` is a string (either `ed25519`, `rsassa-pss-sha256`, or `rsassa-pss-sha512`
+/// `SCHEME` is a string (either `ed25519`, `rsassa-pss-sha256`, `rsassa-pss-sha512`,
+/// `rsa-pkcs1v15-sha256`, or `ecdsa-sha2-nistp256`
 ///
 /// `HASH_VALUE` is a hex encoded hash value.
 ///
@@ -182,7 +184,7 @@ impl DataInterchange for Json {
     /// assert_eq!(out, br#"{"baz":"quux","foo":"bar"}"#);
     /// ```
     fn canonicalize(raw_data: &Self::RawData) -> Result<Vec<u8>> {
-        canonicalize(raw_data).map_err(Error::Opaque)
+        CanonicalJson::to_vec(raw_data)
     }
 
     /// ```
@@ -295,11 +297,50 @@ impl DataInterchange for Json {
     }
 }
 
-fn canonicalize(jsn: &serde_json::Value) -> std::result::Result<Vec<u8>, String> {
-    let converted = convert(jsn)?;
-    let mut buf = Vec::new();
-    let _ = converted.write(&mut buf); // Vec<u8> impl always succeeds (or panics).
-    Ok(buf)
+/// The OLPC canonical JSON form used by in-toto and TUF for the bytes that get signed.
+///
+/// Object keys are sorted, there is no insignificant whitespace, numbers must be integers, and
+/// strings are written as UTF-8 with only `"` and `\` escaped. This is what the reference
+/// implementation signs, so it must not be used for anything meant to be read by people; see
+/// [JsonPretty] for that.
+///
+/// ```
+/// # use in_toto::interchange::CanonicalJson;
+/// # use serde_json::json;
+/// let jsn = json!({"b": ["new\nline", -1], "a": {"quote": "\""}});
+/// assert_eq!(
+///     CanonicalJson::to_vec(&jsn).unwrap(),
+///     b"{\"a\":{\"quote\":\"\\\"\"},\"b\":[\"new\nline\",-1]}"
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct CanonicalJson;
+
+impl CanonicalJson {
+    /// Serialize `value` into its canonical bytes.
+    pub fn to_vec<T: Serialize>(value: &T) -> Result<Vec<u8>> {
+        let converted = convert(&serde_json::to_value(value)?).map_err(Error::Opaque)?;
+        let mut buf = Vec::new();
+        converted.write(&mut buf).map_err(Error::Opaque)?;
+        Ok(buf)
+    }
+
+    /// Write the canonical bytes of `value` to a stream.
+    pub fn to_writer<W: Write, T: Serialize>(mut writer: W, value: &T) -> Result<()> {
+        writer.write_all(&Self::to_vec(value)?)?;
+        Ok(())
+    }
+}
+
+fn write_str(buf: &mut Vec<u8>, s: &str) {
+    buf.push(b'"');
+    for &byte in s.as_bytes() {
+        if byte == b'"' || byte == b'\\' {
+            buf.push(b'\\');
+        }
+        buf.push(byte);
+    }
+    buf.push(b'"');
 }
 
 enum Value {
@@ -333,10 +374,7 @@ impl Value {
                 .map(|_| ())
                 .map_err(|err| format!("Write error: {}", err)),
             Value::String(ref s) => {
-                // this mess is abusing serde_json to get json escaping
-                let s = serde_json::Value::String(s.clone());
-                let s = serde_json::to_string(&s).map_err(|e| format!("{:?}", e))?;
-                buf.extend(s.as_bytes());
+                write_str(buf, s);
                 Ok(())
             }
             Value::Array(ref arr) => {
@@ -361,10 +399,7 @@ impl Value {
                     }
                     first = false;
 
-                    // this mess is abusing serde_json to get json escaping
-                    let k = serde_json::Value::String(k.clone());
-                    let k = serde_json::to_string(&k).map_err(|e| format!("{:?}", e))?;
-                    buf.extend(k.as_bytes());
+                    write_str(buf, k);
 
                     buf.push(b':');
                     v.write(&mut buf)?;
@@ -444,6 +479,40 @@ mod test {
         let jsn = Value::Object(map);
         let mut out = Vec::new();
         jsn.write(&mut out).unwrap();
-        assert_eq!(&out, &b"{\"lol\":[\"haha\",\"new\\nline\"]}");
+        assert_eq!(&out, &b"{\"lol\":[\"haha\",\"new\nline\"]}");
+    }
+
+    #[test]
+    fn matches_reference_canonicalization() {
+        // Expected bytes are those of `securesystemslib.formats.encode_canonical`.
+        let jsn = serde_json::json!({
+            "signed": {
+                "_type": "link",
+                "byproducts": {"return-value": -1, "stderr": "fatal: \"x\"\n", "stdout": ""},
+                "command": ["sh", "-c", "echo C:\\tmp\t\u{1}"],
+                "environment": {"empty": {}, "list": [], "null": null, "ok": true, "no": false},
+                "materials": {"caf\u{e9}/\u{1f980}.rs": {"sha256": "ab"}},
+                "name": "\u{2028}",
+                "products": {"Z": 18446744073709551615u64, "a": 0, "B": [[1], [2, [3]]]},
+            },
+            "signatures": [],
+        });
+        let expected = concat!(
+            r#"{"signatures":[],"signed":{"_type":"link","#,
+            r#""byproducts":{"return-value":-1,"stderr":"fatal: \"x\""#,
+            "\n",
+            r#"","stdout":""},"command":["sh","-c","echo C:\\tmp"#,
+            "\t\u{1}",
+            r#""],"environment":{"empty":{},"list":[],"no":false,"null":null,"ok":true},"#,
+            "\"materials\":{\"caf\u{e9}/\u{1f980}.rs\":{\"sha256\":\"ab\"}},",
+            "\"name\":\"\u{2028}\",",
+            r#""products":{"B":[[1],[2,[3]]],"Z":18446744073709551615,"a":0}}}"#,
+        );
+        assert_eq!(
+            String::from_utf8(CanonicalJson::to_vec(&jsn).unwrap()).unwrap(),
+            expected
+        );
+
+        assert!(CanonicalJson::to_vec(&serde_json::json!({"ratio": 1.5})).is_err());
     }
 }
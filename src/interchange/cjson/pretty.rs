@@ -10,7 +10,9 @@ use crate::Result;
 /// Pretty JSON data interchange.
 ///
 /// This is identical to [Json] in all manners except for the `to_writer` method. Instead of
-/// writing the metadata in the canonical format, it instead pretty prints the metadata.
+/// writing the metadata in the canonical format, it instead pretty prints the metadata. Object
+/// keys are still written in sorted order, so that the same metadata is always written as the
+/// same bytes.
 #[derive(Debug, Clone, PartialEq)]
 pub struct JsonPretty;
 
@@ -124,10 +126,12 @@ impl DataInterchange for JsonPretty {
         W: Write,
         T: Serialize,
     {
-        Ok(serde_json::to_writer_pretty(
-            writer,
-            &Self::serialize(value)?,
-        )?)
+        // Objects are only kept sorted as long as no crate in the build enables serde_json's
+        // `preserve_order` feature, in which case maps serialized from a `HashMap` would be
+        // written in its random iteration order.
+        let mut value = Self::serialize(value)?;
+        value.sort_all_objects();
+        Ok(serde_json::to_writer_pretty(writer, &value)?)
     }
 
     /// ```
@@ -1,7 +1,11 @@
 //! Structures and functions to aid in various in-toto data interchange formats.
 
+#[cfg(feature = "cbor")]
+mod cbor;
+#[cfg(feature = "cbor")]
+pub use cbor::Cbor;
 pub(crate) mod cjson;
-pub use cjson::{Json, JsonPretty};
+pub use cjson::{CanonicalJson, Json, JsonPretty};
 
 use serde::de::DeserializeOwned;
 use serde::ser::Serialize;
@@ -11,12 +11,14 @@
 
 pub mod runlib;
 pub mod crypto;
+pub mod diagnostics;
 pub mod error;
 pub mod interchange;
 pub mod models;
 pub mod verifylib;
 
 mod format_hex;
+mod staging;
 
 pub use crate::error::*;
 
@@ -0,0 +1,410 @@
+//! DSSE envelopes: the Dead Simple Signing Envelope used by in-toto attestations.
+
+use data_encoding::{BASE64, BASE64URL};
+use serde::de::{Deserialize, Deserializer, Error as DeserializeError};
+use serde::ser::{Serialize, Serializer};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::crypto::{KeyId, PrivateKey, PublicKey, Signature, SignatureValue};
+use crate::error::Error;
+use crate::interchange::{DataInterchange, Json};
+use crate::models::{LinkMetadata, Statement, Subject};
+use crate::Result;
+
+/// The payload type of in-toto statements.
+pub const PAYLOAD_TYPE_IN_TOTO: &str = "application/vnd.in-toto+json";
+
+/// The predicate type of a statement whose predicate is an in-toto link.
+pub const LINK_PREDICATE_TYPE: &str = "https://in-toto.io/Link/v1";
+
+/// The pre-authentication encoding of `payload`, which is what DSSE signatures are made over:
+/// `DSSEv1 <len(type)> <type> <len(payload)> <payload>`, with lengths in ASCII decimal.
+///
+/// ```
+/// # use in_toto::models::pae;
+/// assert_eq!(
+///     pae("http://example.com/HelloWorld", b"hello world"),
+///     b"DSSEv1 29 http://example.com/HelloWorld 11 hello world".to_vec()
+/// );
+/// ```
+pub fn pae(payload_type: &str, payload: &[u8]) -> Vec<u8> {
+    let mut encoded = format!(
+        "DSSEv1 {} {} {} ",
+        payload_type.len(),
+        payload_type,
+        payload.len()
+    )
+    .into_bytes();
+    encoded.extend_from_slice(payload);
+    encoded
+}
+
+/// A signature in a `DsseEnvelope`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DsseSignature {
+    key_id: String,
+    sig: Vec<u8>,
+}
+
+impl DsseSignature {
+    /// The ID of the key that made the signature. Other implementations may leave it empty.
+    pub fn key_id(&self) -> &str {
+        &self.key_id
+    }
+
+    /// The signature over the pre-authentication encoding of the payload.
+    pub fn sig(&self) -> &[u8] {
+        &self.sig
+    }
+}
+
+/// A payload signed as a DSSE envelope.
+///
+/// ```
+/// # use in_toto::crypto::{PrivateKey, SignatureScheme};
+/// # use in_toto::models::{DsseEnvelope, LinkMetadataBuilder, PAYLOAD_TYPE_IN_TOTO};
+/// let key: &[u8] = include_bytes!("../../tests/ed25519/ed25519-1.pk8.der");
+/// let key = PrivateKey::from_pkcs8(&key, SignatureScheme::Ed25519).unwrap();
+///
+/// let link = LinkMetadataBuilder::new().name("build".into()).build().unwrap();
+/// let envelope = DsseEnvelope::from_link(&link, &key).unwrap();
+/// assert_eq!(envelope.payload_type(), PAYLOAD_TYPE_IN_TOTO);
+/// assert!(envelope.verify(1, vec![key.public()]).is_ok());
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct DsseEnvelope {
+    payload_type: String,
+    payload: Vec<u8>,
+    signatures: Vec<DsseSignature>,
+}
+
+impl DsseEnvelope {
+    /// Create an envelope for `payload` without any signatures.
+    pub fn new(payload_type: &str, payload: Vec<u8>) -> Self {
+        DsseEnvelope {
+            payload_type: payload_type.to_string(),
+            payload,
+            signatures: Vec::new(),
+        }
+    }
+
+    /// Wrap `link` in a v1 statement of type `LINK_PREDICATE_TYPE` whose subjects are the link's
+    /// products, and sign it with `private_key` like `from_statement`.
+    pub fn from_link(link: &LinkMetadata, private_key: &PrivateKey) -> Result<Self> {
+        let statement = Statement::new(
+            Subject::from_artifacts(link.products()),
+            LINK_PREDICATE_TYPE,
+            Json::serialize(link)?,
+        );
+        Self::from_statement(&statement, private_key)
+    }
+
+    /// Sign `statement` with `private_key`. The statement is serialized as canonical JSON.
+    pub fn from_statement(statement: &Statement, private_key: &PrivateKey) -> Result<Self> {
+        let payload = Json::canonicalize(&Json::serialize(statement)?)?;
+        let mut envelope = Self::new(PAYLOAD_TYPE_IN_TOTO, payload);
+        envelope.add_signature(private_key)?;
+        Ok(envelope)
+    }
+
+    /// Sign the envelope with `private_key`, replacing any signature it already has from the
+    /// same key.
+    pub fn add_signature(&mut self, private_key: &PrivateKey) -> Result<()> {
+        let sig = private_key.sign(&pae(&self.payload_type, &self.payload))?;
+        let key_id = private_key.key_id().as_str();
+        self.signatures.retain(|sig| sig.key_id != key_id);
+        self.signatures.push(DsseSignature {
+            key_id: key_id.to_string(),
+            sig: sig.value().as_bytes().to_vec(),
+        });
+        Ok(())
+    }
+
+    /// The type of the payload, e.g. `PAYLOAD_TYPE_IN_TOTO`.
+    pub fn payload_type(&self) -> &str {
+        &self.payload_type
+    }
+
+    /// The payload, which has not been verified.
+    pub fn payload(&self) -> &[u8] {
+        &self.payload
+    }
+
+    /// The signatures over the payload.
+    pub fn signatures(&self) -> &[DsseSignature] {
+        &self.signatures
+    }
+
+    /// Verify the envelope and return its payload. At least `threshold` distinct keys in
+    /// `authorized_keys` must have signed it. Key IDs are unauthenticated hints, which other
+    /// implementations compute differently, so a signature whose key ID is empty or names no
+    /// authorized key is checked against every authorized key.
+    ///
+    /// Errors are those of `SignedMetadata::verify`: `Error::BadSignature` if a signature does
+    /// not verify, `Error::UnknownKeyId` if one names a key outside `authorized_keys` and no
+    /// authorized key verifies it, and `Error::ThresholdNotMet` otherwise.
+    pub fn verify<'a, I>(&self, threshold: u32, authorized_keys: I) -> Result<&[u8]>
+    where
+        I: IntoIterator<Item = &'a PublicKey>,
+    {
+        if threshold < 1 {
+            return Err(Error::VerificationFailure(
+                "Threshold must be strictly greater than zero".into(),
+            ));
+        }
+
+        let authorized_keys = authorized_keys
+            .into_iter()
+            .map(|key| (key.key_id().as_str(), key))
+            .collect::<HashMap<_, _>>();
+        let encoded = pae(&self.payload_type, &self.payload);
+
+        let mut valid = Vec::new();
+        let mut bad = false;
+        let mut unknown = Vec::new();
+        for sig in &self.signatures {
+            let named = authorized_keys.get(sig.key_id.as_str()).copied();
+            let candidates = match named {
+                Some(key) => vec![key],
+                None => authorized_keys.values().copied().collect::<Vec<_>>(),
+            };
+            let signer = candidates.into_iter().find(|key| {
+                let signature =
+                    Signature::new(key.key_id().clone(), SignatureValue::new(sig.sig.clone()));
+                key.verify(&encoded, &signature).is_ok()
+            });
+            match signer {
+                Some(key) if !valid.contains(&key.key_id()) => valid.push(key.key_id()),
+                Some(_) => (),
+                None if named.is_some() || sig.key_id.is_empty() => bad = true,
+                None => unknown.push(&sig.key_id),
+            }
+        }
+
+        if valid.len() as u32 >= threshold {
+            return Ok(&self.payload);
+        }
+        if bad {
+            return Err(Error::BadSignature);
+        }
+        if let Some(key_id) = unknown.into_iter().min() {
+            // Key IDs from other implementations need not be well formed.
+            return Err(match key_id.parse::<KeyId>() {
+                Ok(key_id) => Error::UnknownKeyId(key_id),
+                Err(_) => Error::VerificationFailure(format!("Unknown key ID {:?}", key_id)),
+            });
+        }
+        Err(Error::ThresholdNotMet {
+            valid: valid.len() as u32,
+            threshold,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct DsseSignatureJson {
+    #[serde(default)]
+    keyid: String,
+    sig: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct DsseEnvelopeJson {
+    payload: String,
+    #[serde(rename = "payloadType")]
+    payload_type: String,
+    signatures: Vec<DsseSignatureJson>,
+}
+
+/// Decode standard or URL-safe base64, either of which DSSE allows.
+fn decode_base64(encoded: &str) -> ::std::result::Result<Vec<u8>, String> {
+    BASE64
+        .decode(encoded.as_bytes())
+        .or_else(|_| BASE64URL.decode(encoded.as_bytes()))
+        .map_err(|e| format!("Invalid base64 {:?}: {}", encoded, e))
+}
+
+impl Serialize for DsseEnvelope {
+    fn serialize<S>(&self, ser: S) -> ::std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        DsseEnvelopeJson {
+            payload: BASE64.encode(&self.payload),
+            payload_type: self.payload_type.clone(),
+            signatures: self
+                .signatures
+                .iter()
+                .map(|sig| DsseSignatureJson {
+                    keyid: sig.key_id.clone(),
+                    sig: BASE64.encode(&sig.sig),
+                })
+                .collect(),
+        }
+        .serialize(ser)
+    }
+}
+
+impl<'de> Deserialize<'de> for DsseEnvelope {
+    fn deserialize<D: Deserializer<'de>>(de: D) -> ::std::result::Result<Self, D::Error> {
+        let intermediate: DsseEnvelopeJson = Deserialize::deserialize(de)?;
+        let signatures = intermediate
+            .signatures
+            .into_iter()
+            .map(|sig| {
+                Ok(DsseSignature {
+                    key_id: sig.keyid,
+                    sig: decode_base64(&sig.sig)?,
+                })
+            })
+            .collect::<::std::result::Result<Vec<_>, String>>()
+            .map_err(DeserializeError::custom)?;
+        Ok(DsseEnvelope {
+            payload_type: intermediate.payload_type,
+            payload: decode_base64(&intermediate.payload).map_err(DeserializeError::custom)?,
+            signatures,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::crypto::{calculate_hash, HashAlgorithm, SignatureScheme};
+    use crate::models::{LinkMetadataBuilder, VirtualTargetPath, STATEMENT_V1_TYPE};
+    use serde_json::Value;
+
+    const ED25519_1_PK8: &[u8] = include_bytes!("../../tests/ed25519/ed25519-1.pk8.der");
+    const ED25519_2_PK8: &[u8] = include_bytes!("../../tests/ed25519/ed25519-2.pk8.der");
+    const GO_VECTORS: &str = include_str!("../../tests/dsse/go-securesystemslib.json");
+
+    fn key(pk8: &[u8]) -> PrivateKey {
+        PrivateKey::from_pkcs8(pk8, SignatureScheme::Ed25519).unwrap()
+    }
+
+    #[test]
+    fn envelope_from_link_wraps_statement() {
+        let (key_1, key_2) = (key(ED25519_1_PK8), key(ED25519_2_PK8));
+        let mut hashes = HashMap::new();
+        let _ = hashes.insert(
+            HashAlgorithm::Sha256,
+            calculate_hash(b"foo", HashAlgorithm::Sha256),
+        );
+        let mut products = std::collections::BTreeMap::new();
+        let _ = products.insert(VirtualTargetPath::new("foo.tar.gz".into()).unwrap(), hashes);
+        let link = LinkMetadataBuilder::new()
+            .name("package".into())
+            .products(products)
+            .build()
+            .unwrap();
+
+        let envelope = DsseEnvelope::from_link(&link, &key_1).unwrap();
+        let payload = envelope.verify(1, vec![key_1.public()]).unwrap();
+        let statement: Value = serde_json::from_slice(payload).unwrap();
+        assert_eq!(statement["_type"], STATEMENT_V1_TYPE);
+        let typed: Statement = serde_json::from_slice(payload).unwrap();
+        assert_eq!(typed.predicate_type(), LINK_PREDICATE_TYPE);
+        assert_eq!(typed.subject()[0].name(), "foo.tar.gz");
+        assert_eq!(statement["predicateType"], LINK_PREDICATE_TYPE);
+        assert_eq!(statement["subject"][0]["name"], "foo.tar.gz");
+        assert_eq!(
+            statement["subject"][0]["digest"]["sha256"],
+            "2c26b46b68ffc68ff99b453c1d30413413422d706483bfa0f98a5e886266e7ae"
+        );
+        let predicate: LinkMetadata = Json::deserialize(&statement["predicate"]).unwrap();
+        assert_eq!(predicate, link);
+
+        let jsn = serde_json::to_value(&envelope).unwrap();
+        assert_eq!(jsn["payloadType"], PAYLOAD_TYPE_IN_TOTO);
+        assert_eq!(jsn["payload"], BASE64.encode(payload));
+        assert_eq!(jsn["signatures"][0]["keyid"], key_1.key_id().as_str());
+        let decoded: DsseEnvelope = serde_json::from_value(jsn).unwrap();
+        assert_eq!(decoded, envelope);
+
+        assert_eq!(
+            envelope.verify(2, vec![key_1.public(), key_2.public()]),
+            Err(Error::ThresholdNotMet {
+                valid: 1,
+                threshold: 2
+            })
+        );
+        assert_eq!(
+            envelope.verify(1, vec![key_2.public()]),
+            Err(Error::UnknownKeyId(key_1.key_id().clone()))
+        );
+        let mut tampered = envelope.clone();
+        tampered.payload_type = "text/plain".into();
+        assert_eq!(
+            tampered.verify(1, vec![key_1.public()]),
+            Err(Error::BadSignature)
+        );
+    }
+
+    #[test]
+    fn verify_envelopes_from_go_securesystemslib() {
+        let vectors: Value = serde_json::from_str(GO_VECTORS).unwrap();
+        let pem = vectors["public_key_pem"].as_str().unwrap();
+        let spki = pem
+            .lines()
+            .filter(|line| !line.starts_with("-----"))
+            .collect::<String>();
+        let spki = BASE64.decode(spki.as_bytes()).unwrap();
+        // An Ed25519 SPKI ends with the 32 byte key.
+        let public = PublicKey::from_ed25519(spki[spki.len() - 32..].to_vec()).unwrap();
+        let other = key(ED25519_1_PK8);
+
+        let fixtures = vectors["fixtures"].as_array().unwrap();
+        assert!(!fixtures.is_empty());
+        for fixture in fixtures {
+            let name = fixture["name"].as_str().unwrap();
+            let envelope: DsseEnvelope =
+                serde_json::from_value(fixture["envelope"].clone()).unwrap();
+            let payload = BASE64
+                .decode(fixture["canonical_b64"].as_str().unwrap().as_bytes())
+                .unwrap();
+            let encoded = BASE64
+                .decode(fixture["pae_b64"].as_str().unwrap().as_bytes())
+                .unwrap();
+            assert_eq!(
+                pae(envelope.payload_type(), envelope.payload()),
+                encoded,
+                "{}",
+                name
+            );
+
+            // Their key IDs are not ours, so the signature is checked against every key.
+            assert_ne!(envelope.signatures()[0].key_id(), public.key_id().as_str());
+            assert_eq!(
+                envelope.verify(1, vec![&public]),
+                Ok(&payload[..]),
+                "{}",
+                name
+            );
+            assert_eq!(
+                envelope.verify(1, vec![other.public(), &public]),
+                Ok(&payload[..]),
+                "{}",
+                name
+            );
+            assert!(
+                envelope.verify(1, vec![other.public()]).is_err(),
+                "{}",
+                name
+            );
+
+            let mut unnamed = envelope.clone();
+            unnamed.signatures[0].key_id = String::new();
+            assert_eq!(
+                unnamed.verify(1, vec![&public]),
+                Ok(&payload[..]),
+                "{}",
+                name
+            );
+
+            let mut tampered = envelope.clone();
+            tampered.payload_type.push('x');
+            assert!(tampered.verify(1, vec![&public]).is_err(), "{}", name);
+        }
+    }
+}
@@ -98,29 +98,41 @@ static PATH_ILLEGAL_STRINGS: &'static [&str] = &[
 ];
 
 pub fn safe_path(path: &str) -> Result<()> {
+    check_path(path, false)
+}
+
+/// `safe_path`, also accepting a path that starts with a single `/` if `allow_absolute` is set.
+fn check_path(path: &str, allow_absolute: bool) -> Result<()> {
     if path.is_empty() {
         return Err(Error::IllegalArgument("Path cannot be empty".into()));
     }
 
-    if path.starts_with('/') {
-        return Err(Error::IllegalArgument("Cannot start with '/'".into()));
+    let relative = match path.strip_prefix('/') {
+        Some(relative) if allow_absolute => relative,
+        _ => path,
+    };
+    if relative.is_empty() || relative.starts_with('/') {
+        return Err(Error::IllegalArgument(format!(
+            "Path {:?} cannot start with '/'",
+            path
+        )));
     }
 
     for bad_str in PATH_ILLEGAL_STRINGS {
         if path.contains(bad_str) {
             return Err(Error::IllegalArgument(format!(
-                "Path cannot contain {:?}",
-                bad_str
+                "Path {:?} cannot contain {:?}",
+                path, bad_str
             )));
         }
     }
 
-    for component in path.split('/') {
+    for component in relative.split('/') {
         for bad_str in PATH_ILLEGAL_COMPONENTS {
             if component == *bad_str {
                 return Err(Error::IllegalArgument(format!(
-                    "Path cannot have component {:?}",
-                    component
+                    "Path {:?} cannot have component {:?}",
+                    path, component
                 )));
             }
         }
@@ -129,8 +141,8 @@ pub fn safe_path(path: &str) -> Result<()> {
         for bad_str in PATH_ILLEGAL_COMPONENTS_CASE_INSENSITIVE {
             if component_lower.as_str() == *bad_str {
                 return Err(Error::IllegalArgument(format!(
-                    "Path cannot have component {:?}",
-                    component
+                    "Path {:?} cannot have component {:?}",
+                    path, component
                 )));
             }
         }
@@ -168,6 +180,26 @@ impl VirtualTargetPath {
         Ok(VirtualTargetPath(path))
     }
 
+    /// Create a new `VirtualTargetPath` like `new`, but also accept an absolute path. Absolute
+    /// paths only match across machines that keep artifacts at the same location, so use this
+    /// only where that holds. Traversal with `..` is still rejected.
+    ///
+    /// Deserialization only accepts the paths `new` accepts, so that metadata from elsewhere
+    /// cannot carry absolute paths, and `LinkMetadataBuilder` rejects them for the same reason:
+    /// record artifacts with `lstrip_paths` to keep them relative instead.
+    ///
+    /// ```
+    /// # use in_toto::models::{VirtualTargetPath};
+    /// assert!(VirtualTargetPath::allow_absolute("/srv/foo".into()).is_ok());
+    /// assert!(VirtualTargetPath::allow_absolute("foo".into()).is_ok());
+    /// assert!(VirtualTargetPath::allow_absolute("/srv/../foo".into()).is_err());
+    /// assert!(VirtualTargetPath::allow_absolute("//foo".into()).is_err());
+    /// ```
+    pub fn allow_absolute(path: String) -> Result<Self> {
+        check_path(&path, true)?;
+        Ok(VirtualTargetPath(path))
+    }
+
     /// Split `VirtualTargetPath` into components that can be joined to create URL paths, Unix
     /// paths, or Windows paths.
     ///
@@ -240,6 +272,125 @@ impl VirtualTargetPath {
     pub fn value(&self) -> &str {
         &self.0
     }
+
+    /// Whether the path matches the shell-style `pattern` of an artifact rule, with the semantics
+    /// of Python's `fnmatch` used by the reference implementation: `*` matches any sequence of
+    /// characters and `?` any single character, both including `/` and leading dots, `[...]`
+    /// matches a set of characters or ranges, negated by a leading `!`, and any other character,
+    /// including an unterminated `[`, matches itself.
+    ///
+    /// ```
+    /// # use in_toto::models::VirtualTargetPath;
+    /// let path = VirtualTargetPath::new("src/lib.rs".into()).unwrap();
+    /// assert!(path.matches("*.rs"));
+    /// assert!(path.matches("src/[!m]*"));
+    /// assert!(!path.matches("*.py"));
+    /// ```
+    pub fn matches(&self, pattern: &str) -> bool {
+        let tokens = parse_pattern(pattern);
+        let name: Vec<char> = self.0.chars().collect();
+
+        // On a mismatch, backtrack to the last `*` and let it swallow one more character.
+        let (mut t, mut n) = (0, 0);
+        let mut star = None;
+        while n < name.len() {
+            match tokens.get(t) {
+                Some(PatternToken::Star) => {
+                    star = Some((t, n));
+                    t += 1;
+                    continue;
+                }
+                Some(token) if token.matches(name[n]) => {
+                    t += 1;
+                    n += 1;
+                    continue;
+                }
+                _ => {}
+            }
+            match star {
+                Some((star_t, star_n)) => {
+                    star = Some((star_t, star_n + 1));
+                    t = star_t + 1;
+                    n = star_n + 1;
+                }
+                None => return false,
+            }
+        }
+        tokens[t..].iter().all(|token| *token == PatternToken::Star)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum PatternToken {
+    Star,
+    Any,
+    Set { negated: bool, ranges: Vec<(char, char)> },
+    Literal(char),
+}
+
+impl PatternToken {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            PatternToken::Star | PatternToken::Any => true,
+            PatternToken::Set { negated, ranges } => {
+                ranges.iter().any(|&(lo, hi)| lo <= c && c <= hi) != *negated
+            }
+            PatternToken::Literal(l) => *l == c,
+        }
+    }
+}
+
+/// Split `pattern` into tokens the way `fnmatch.translate` does.
+fn parse_pattern(pattern: &str) -> Vec<PatternToken> {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        i += 1;
+        match c {
+            '*' => tokens.push(PatternToken::Star),
+            '?' => tokens.push(PatternToken::Any),
+            '[' => {
+                // A `]` right after the opening `[` or `[!` is part of the set.
+                let mut end = i;
+                if chars.get(end) == Some(&'!') {
+                    end += 1;
+                }
+                if chars.get(end) == Some(&']') {
+                    end += 1;
+                }
+                while end < chars.len() && chars[end] != ']' {
+                    end += 1;
+                }
+                if end >= chars.len() {
+                    tokens.push(PatternToken::Literal('['));
+                    continue;
+                }
+
+                let mut set = &chars[i..end];
+                i = end + 1;
+                let negated = set.first() == Some(&'!');
+                if negated {
+                    set = &set[1..];
+                }
+                // Reversed ranges such as `z-a` match nothing, as they do in the reference.
+                let mut ranges = Vec::new();
+                while !set.is_empty() {
+                    if set.len() >= 3 && set[1] == '-' {
+                        ranges.push((set[0], set[2]));
+                        set = &set[3..];
+                    } else {
+                        ranges.push((set[0], set[0]));
+                        set = &set[1..];
+                    }
+                }
+                tokens.push(PatternToken::Set { negated, ranges });
+            }
+            c => tokens.push(PatternToken::Literal(c)),
+        }
+    }
+    tokens
 }
 
 impl ToString for VirtualTargetPath {
@@ -254,3 +405,90 @@ impl<'de> Deserialize<'de> for VirtualTargetPath {
         VirtualTargetPath::new(s).map_err(|e| DeserializeError::custom(format!("{:?}", e)))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn virtual_target_path_must_be_relative() {
+        let error = |path: &str| match VirtualTargetPath::new(path.to_string()) {
+            Err(Error::IllegalArgument(msg)) => msg,
+            other => panic!("unexpected result {:?}", other),
+        };
+        assert_eq!(error("/etc/passwd"), r#"Path "/etc/passwd" cannot start with '/'"#);
+        assert_eq!(
+            error("../secret"),
+            r#"Path "../secret" cannot have component "..""#
+        );
+        assert_eq!(
+            VirtualTargetPath::new("src/main.rs".into()).unwrap().value(),
+            "src/main.rs"
+        );
+
+        assert_eq!(
+            VirtualTargetPath::allow_absolute("/etc/passwd".into())
+                .unwrap()
+                .value(),
+            "/etc/passwd"
+        );
+        assert!(VirtualTargetPath::allow_absolute("../secret".into()).is_err());
+        assert!(VirtualTargetPath::allow_absolute("/".into()).is_err());
+        assert!(VirtualTargetPath::allow_absolute("/etc/./passwd".into()).is_err());
+    }
+
+    #[test]
+    fn matches_like_reference_fnmatch() {
+        // Cases from CPython's test_fnmatch, and the path handling in-toto relies on.
+        let cases = [
+            ("abc", "abc", true),
+            ("abc", "?*?", true),
+            ("abc", "???*", true),
+            ("abc", "*???", true),
+            ("abc", "???", true),
+            ("abc", "*", true),
+            ("abc", "ab[cd]", true),
+            ("abc", "ab[!de]", true),
+            ("abc", "ab[de]", false),
+            ("a", "??", false),
+            ("a", "b", false),
+            ("a", "[!\\]", true),
+            ("a", "[a-c]", true),
+            ("d", "[a-c]", false),
+            ("-", "[a-]", true),
+            ("-", "[!a-]", false),
+            ("b", "[z-a]", false),
+            ("b", "[!z-a]", true),
+            ("]", "[]]", true),
+            ("a", "[]a]", true),
+            ("]", "[!]]", false),
+            ("a", "[!]]", true),
+            ("[", "[", true),
+            ("[ab", "[ab", true),
+            ("a[b", "a[b", true),
+            ("abc", "ABC", false),
+            ("foo/bar.py", "*.py", true),
+            ("foo/bar.py", "foo*", true),
+            ("foo/bar.py", "foo?bar.py", true),
+            ("foo/bar.py", "*/*/*", false),
+            ("foo/.git/config", "foo/*", true),
+            (".hidden", "*", true),
+            (".hidden", "?hidden", true),
+            ("foo/bar", "foo/", false),
+            ("foo/bar", "bar", false),
+            ("foobar", "foo**bar", true),
+            ("a/b/c.tar.gz", "a/*.tar.*", true),
+            ("a/b/c.tar", "a/*.tar.*", false),
+        ];
+        for (path, pattern, expected) in cases.iter() {
+            let path = VirtualTargetPath::new(path.to_string()).unwrap();
+            assert_eq!(
+                path.matches(pattern),
+                *expected,
+                "{:?} against {:?}",
+                path.value(),
+                pattern
+            );
+        }
+    }
+}
@@ -1 +0,0 @@
-//! in-toto layout: used by the project owner to generate a desired supply chain layout file.
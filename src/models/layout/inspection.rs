@@ -0,0 +1,107 @@
+//! An inspection of a supply chain layout.
+
+use serde::de::{Deserialize, Deserializer, Error as DeserializeError};
+use serde::ser::{Serialize, Serializer};
+use serde_derive::{Deserialize, Serialize};
+
+use super::SupplyChainRule;
+
+/// A command run by the verifier itself, once the links of all steps are verified, to check the
+/// final product.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Inspection {
+    name: String,
+    expected_materials: Vec<SupplyChainRule>,
+    expected_products: Vec<SupplyChainRule>,
+    run: Vec<String>,
+}
+
+impl Inspection {
+    /// Create the inspection `name` running the command `run`, with no artifact rules.
+    pub fn new(name: &str, run: &[&str]) -> Self {
+        Inspection {
+            name: name.to_string(),
+            expected_materials: Vec::new(),
+            expected_products: Vec::new(),
+            run: run.iter().map(|arg| arg.to_string()).collect(),
+        }
+    }
+
+    /// Add a rule the materials of the inspection must satisfy, applied after those already
+    /// added.
+    pub fn add_material_rule(mut self, rule: SupplyChainRule) -> Self {
+        self.expected_materials.push(rule);
+        self
+    }
+
+    /// Add a rule the products of the inspection must satisfy, applied after those already
+    /// added.
+    pub fn add_product_rule(mut self, rule: SupplyChainRule) -> Self {
+        self.expected_products.push(rule);
+        self
+    }
+
+    /// The name of the inspection, which its link is recorded under.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The rules the materials of the inspection must satisfy, in the order they are applied.
+    pub fn expected_materials(&self) -> &[SupplyChainRule] {
+        &self.expected_materials
+    }
+
+    /// The rules the products of the inspection must satisfy, in the order they are applied.
+    pub fn expected_products(&self) -> &[SupplyChainRule] {
+        &self.expected_products
+    }
+
+    /// The command the verifier runs.
+    pub fn run(&self) -> &[String] {
+        &self.run
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct InspectionJson {
+    #[serde(rename = "_type")]
+    typ: String,
+    name: String,
+    expected_materials: Vec<SupplyChainRule>,
+    expected_products: Vec<SupplyChainRule>,
+    run: Vec<String>,
+}
+
+impl Serialize for Inspection {
+    fn serialize<S>(&self, ser: S) -> ::std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        InspectionJson {
+            typ: "inspection".into(),
+            name: self.name.clone(),
+            expected_materials: self.expected_materials.clone(),
+            expected_products: self.expected_products.clone(),
+            run: self.run.clone(),
+        }
+        .serialize(ser)
+    }
+}
+
+impl<'de> Deserialize<'de> for Inspection {
+    fn deserialize<D: Deserializer<'de>>(de: D) -> ::std::result::Result<Self, D::Error> {
+        let intermediate: InspectionJson = Deserialize::deserialize(de)?;
+        if intermediate.typ != "inspection" {
+            return Err(DeserializeError::custom(format!(
+                "Expected an inspection, found {:?}",
+                intermediate.typ
+            )));
+        }
+        Ok(Inspection {
+            name: intermediate.name,
+            expected_materials: intermediate.expected_materials,
+            expected_products: intermediate.expected_products,
+            run: intermediate.run,
+        })
+    }
+}
@@ -0,0 +1,435 @@
+//! in-toto layout: used by the project owner to generate a desired supply chain layout file.
+
+use chrono::{DateTime, Duration, NaiveDateTime, TimeZone, Timelike, Utc};
+use serde::de::{Deserialize, Deserializer, Error as DeserializeError};
+use serde::ser::{Serialize, Serializer};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+use crate::crypto::{KeyId, PrivateKey, PublicKey};
+use crate::error::Error;
+use crate::interchange::DataInterchange;
+use crate::models::{Metadata, SignedMetadata};
+use crate::Result;
+
+mod inspection;
+mod rule;
+mod step;
+
+pub use inspection::Inspection;
+pub use rule::{ArtifactType, SupplyChainRule};
+pub use step::{Step, StepBuilder};
+
+fn parse_datetime(ts: &str) -> Result<DateTime<Utc>> {
+    NaiveDateTime::parse_from_str(ts, "%FT%TZ")
+        .map(|ts| Utc.from_utc_datetime(&ts))
+        .map_err(|e| Error::Encoding(format!("Can't parse DateTime: {:?}", e)))
+}
+
+fn format_datetime(ts: &DateTime<Utc>) -> String {
+    ts.format("%FT%TZ").to_string()
+}
+
+/// Helper to construct a `Layout`.
+pub struct LayoutBuilder {
+    steps: Vec<Step>,
+    inspections: Vec<Inspection>,
+    keys: BTreeMap<KeyId, PublicKey>,
+    expires: DateTime<Utc>,
+    readme: String,
+    step_dependencies: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl LayoutBuilder {
+    /// Create a builder for a layout without steps, inspections or keys, that expires 30 days
+    /// from now.
+    pub fn new() -> Self {
+        LayoutBuilder {
+            steps: Vec::new(),
+            inspections: Vec::new(),
+            keys: BTreeMap::new(),
+            expires: Utc::now() + Duration::days(30),
+            readme: String::new(),
+            step_dependencies: BTreeMap::new(),
+        }
+    }
+
+    /// Set when the layout expires. Sub-second precision is dropped.
+    pub fn expires(mut self, expires: DateTime<Utc>) -> Self {
+        self.expires = expires;
+        self
+    }
+
+    /// Set the human-readable description of the supply chain.
+    pub fn readme(mut self, readme: String) -> Self {
+        self.readme = readme;
+        self
+    }
+
+    /// Add a functionary key that steps can authorize.
+    pub fn add_key(mut self, key: PublicKey) -> Self {
+        let _ = self.keys.insert(key.key_id().clone(), key);
+        self
+    }
+
+    /// Add a step to the end of the supply chain.
+    pub fn add_step(mut self, step: Step) -> Self {
+        self.steps.push(step);
+        self
+    }
+
+    /// Add an inspection, run after those already added.
+    pub fn add_inspection(mut self, inspection: Inspection) -> Self {
+        self.inspections.push(inspection);
+        self
+    }
+
+    /// Declare that step `step` runs after step `depends_on`, see `Layout::step_dependencies`.
+    pub fn add_step_dependency(mut self, step: &str, depends_on: &str) -> Self {
+        let _ = self
+            .step_dependencies
+            .entry(step.to_string())
+            .or_default()
+            .insert(depends_on.to_string());
+        self
+    }
+
+    /// Construct the `Layout`.
+    pub fn build(self) -> Result<Layout> {
+        Layout::new(
+            self.steps,
+            self.inspections,
+            self.keys,
+            self.expires,
+            self.readme,
+        )?
+        .with_step_dependencies(self.step_dependencies)
+    }
+
+    /// Construct a new `SignedMetadata<D, Layout>`.
+    pub fn signed<D>(self, private_key: &PrivateKey) -> Result<SignedMetadata<D, Layout>>
+    where
+        D: DataInterchange,
+    {
+        SignedMetadata::new(&self.build()?, private_key)
+    }
+}
+
+impl Default for LayoutBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A supply chain layout: the steps that make up the supply chain, the functionaries authorized
+/// to carry them out, and the inspections the verifier runs on the final product.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Layout {
+    steps: Vec<Step>,
+    inspections: Vec<Inspection>,
+    keys: BTreeMap<KeyId, PublicKey>,
+    expires: DateTime<Utc>,
+    readme: String,
+    step_dependencies: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl Layout {
+    /// Create a new `Layout`. Every key must be listed under its own key ID, and steps and
+    /// inspections must all have distinct names. Sub-second precision of `expires` is dropped.
+    pub fn new(
+        steps: Vec<Step>,
+        inspections: Vec<Inspection>,
+        keys: BTreeMap<KeyId, PublicKey>,
+        expires: DateTime<Utc>,
+        readme: String,
+    ) -> Result<Self> {
+        if let Some((key_id, _)) = keys.iter().find(|(key_id, key)| key.key_id() != *key_id) {
+            return Err(Error::IllegalArgument(format!(
+                "Layout key {:?} is listed under another key ID",
+                key_id.as_str()
+            )));
+        }
+
+        let mut names = HashSet::new();
+        let all_names = steps
+            .iter()
+            .map(Step::name)
+            .chain(inspections.iter().map(Inspection::name));
+        for name in all_names {
+            if !names.insert(name) {
+                return Err(Error::IllegalArgument(format!(
+                    "Layout has more than one step or inspection named {:?}",
+                    name
+                )));
+            }
+        }
+
+        Ok(Layout {
+            steps,
+            inspections,
+            keys,
+            expires: expires.with_nanosecond(0).unwrap_or(expires),
+            readme,
+            step_dependencies: BTreeMap::new(),
+        })
+    }
+
+    /// Declare the order steps run in, as the steps each step depends on, by name. Fails if a
+    /// name is not that of a step, or with `Error::DependencyCycleOrViolation` if the
+    /// dependencies form a cycle.
+    pub fn with_step_dependencies(
+        mut self,
+        step_dependencies: BTreeMap<String, BTreeSet<String>>,
+    ) -> Result<Self> {
+        let unknown = step_dependencies
+            .iter()
+            .flat_map(|(step, depends_on)| Some(step).into_iter().chain(depends_on))
+            .find(|name| !self.steps.iter().any(|step| step.name() == name.as_str()));
+        if let Some(name) = unknown {
+            return Err(Error::IllegalArgument(format!(
+                "Layout declares a dependency of or on {:?}, which is not one of its steps",
+                name
+            )));
+        }
+        for step in step_dependencies.keys() {
+            if dependencies_of(&step_dependencies, step).contains(step) {
+                return Err(Error::DependencyCycleOrViolation(format!(
+                    "Step {:?} depends on itself",
+                    step
+                )));
+            }
+        }
+        self.step_dependencies = step_dependencies;
+        Ok(self)
+    }
+
+    /// The steps of the supply chain, in order.
+    pub fn steps(&self) -> &[Step] {
+        &self.steps
+    }
+
+    /// The inspections the verifier runs, in order.
+    pub fn inspections(&self) -> &[Inspection] {
+        &self.inspections
+    }
+
+    /// The functionary keys, by key ID.
+    pub fn keys(&self) -> &BTreeMap<KeyId, PublicKey> {
+        &self.keys
+    }
+
+    /// When the layout expires.
+    pub fn expires(&self) -> &DateTime<Utc> {
+        &self.expires
+    }
+
+    /// The human-readable description of the supply chain.
+    pub fn readme(&self) -> &str {
+        &self.readme
+    }
+
+    /// The steps each step depends on, by name: a step only runs after those it depends on,
+    /// directly or through other steps. Steps without declared dependencies are left out.
+    /// Verification checks that no step matches artifacts of a step that depends on it.
+    pub fn step_dependencies(&self) -> &BTreeMap<String, BTreeSet<String>> {
+        &self.step_dependencies
+    }
+
+    /// The steps `step` depends on, directly or through other steps.
+    pub fn dependencies_of(&self, step: &str) -> BTreeSet<String> {
+        dependencies_of(&self.step_dependencies, step)
+    }
+}
+
+/// The steps `step` depends on in `step_dependencies`, directly or through other steps.
+fn dependencies_of(
+    step_dependencies: &BTreeMap<String, BTreeSet<String>>,
+    step: &str,
+) -> BTreeSet<String> {
+    let mut dependencies = BTreeSet::new();
+    let mut pending = vec![step];
+    while let Some(step) = pending.pop() {
+        for dependency in step_dependencies.get(step).into_iter().flatten() {
+            if dependencies.insert(dependency.clone()) {
+                pending.push(dependency);
+            }
+        }
+    }
+    dependencies
+}
+
+impl Metadata for Layout {
+    fn version(&self) -> u32 {
+        0u32
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct LayoutJson {
+    #[serde(rename = "_type")]
+    typ: String,
+    steps: Vec<Step>,
+    inspect: Vec<Inspection>,
+    keys: BTreeMap<KeyId, PublicKey>,
+    expires: String,
+    readme: String,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    step_dependencies: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl Serialize for Layout {
+    fn serialize<S>(&self, ser: S) -> ::std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        LayoutJson {
+            typ: "layout".into(),
+            steps: self.steps.clone(),
+            inspect: self.inspections.clone(),
+            keys: self.keys.clone(),
+            expires: format_datetime(&self.expires),
+            readme: self.readme.clone(),
+            step_dependencies: self.step_dependencies.clone(),
+        }
+        .serialize(ser)
+    }
+}
+
+impl<'de> Deserialize<'de> for Layout {
+    fn deserialize<D: Deserializer<'de>>(de: D) -> ::std::result::Result<Self, D::Error> {
+        let intermediate: LayoutJson = Deserialize::deserialize(de)?;
+        if intermediate.typ != "layout" {
+            return Err(DeserializeError::custom(format!(
+                "Expected a layout, found {:?}",
+                intermediate.typ
+            )));
+        }
+        let expires = parse_datetime(&intermediate.expires)
+            .map_err(|e| DeserializeError::custom(format!("{:?}", e)))?;
+        let step_dependencies = intermediate.step_dependencies;
+        Layout::new(
+            intermediate.steps,
+            intermediate.inspect,
+            intermediate.keys,
+            expires,
+            intermediate.readme,
+        )
+        .and_then(|layout| layout.with_step_dependencies(step_dependencies))
+        .map_err(|e| DeserializeError::custom(format!("{:?}", e)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    use crate::crypto::SignatureScheme;
+    use crate::interchange::Json;
+
+    const ED25519_1_PK8: &[u8] = include_bytes!("../../../tests/ed25519/ed25519-1.pk8.der");
+    const ED25519_2_PK8: &[u8] = include_bytes!("../../../tests/ed25519/ed25519-2.pk8.der");
+
+    #[test]
+    fn layout_round_trips_through_json() {
+        let owner = PrivateKey::from_pkcs8(ED25519_1_PK8, SignatureScheme::Ed25519).unwrap();
+        let functionary = PrivateKey::from_pkcs8(ED25519_2_PK8, SignatureScheme::Ed25519).unwrap();
+        let expires = Utc.with_ymd_and_hms(2030, 1, 2, 3, 4, 5).unwrap();
+        let build = StepBuilder::new("build")
+            .add_pubkey(functionary.key_id().clone())
+            .build()
+            .unwrap();
+
+        let signed = LayoutBuilder::new()
+            .expires(expires)
+            .readme("Build and package".to_string())
+            .add_key(functionary.public().clone())
+            .add_step(build.clone())
+            .add_inspection(Inspection::new("untar", &["tar", "xf", "pkg.tar"]))
+            .signed::<Json>(&owner)
+            .unwrap();
+        let jsn = serde_json::to_value(&signed).unwrap();
+        let key_id = functionary.key_id().as_str();
+        assert_eq!(jsn["signed"]["_type"], json!("layout"));
+        assert_eq!(jsn["signed"]["expires"], json!("2030-01-02T03:04:05Z"));
+        assert_eq!(jsn["signed"]["steps"][0]["_type"], json!("step"));
+        assert_eq!(jsn["signed"]["inspect"][0]["_type"], json!("inspection"));
+        assert_eq!(jsn["signed"]["inspect"][0]["run"][0], json!("tar"));
+        assert_eq!(jsn["signed"]["keys"][key_id]["keytype"], json!("ed25519"));
+
+        let decoded: SignedMetadata<Json, Layout> = serde_json::from_value(jsn).unwrap();
+        let layout = decoded.verify(1, vec![owner.public()]).unwrap();
+        assert_eq!(layout.expires(), &expires);
+        assert_eq!(layout.readme(), "Build and package");
+        assert_eq!(layout.steps(), &[build]);
+        assert_eq!(layout.keys()[functionary.key_id()], *functionary.public());
+    }
+
+    #[test]
+    fn layout_rejects_inconsistent_metadata() {
+        let key = PrivateKey::from_pkcs8(ED25519_1_PK8, SignatureScheme::Ed25519).unwrap();
+        let other = PrivateKey::from_pkcs8(ED25519_2_PK8, SignatureScheme::Ed25519).unwrap();
+
+        let build = StepBuilder::new("build")
+            .add_pubkey(key.key_id().clone())
+            .build()
+            .unwrap();
+        assert!(LayoutBuilder::new()
+            .add_step(build)
+            .add_inspection(Inspection::new("build", &["true"]))
+            .build()
+            .is_err());
+
+        let mut keys = BTreeMap::new();
+        let _ = keys.insert(other.key_id().clone(), key.public().clone());
+        assert!(Layout::new(Vec::new(), Vec::new(), keys, Utc::now(), String::new()).is_err());
+
+        let mut jsn = serde_json::to_value(LayoutBuilder::new().build().unwrap()).unwrap();
+        jsn["_type"] = json!("link");
+        assert!(serde_json::from_value::<Layout>(jsn).is_err());
+    }
+
+    #[test]
+    fn layout_step_dependencies() {
+        let key = PrivateKey::from_pkcs8(ED25519_1_PK8, SignatureScheme::Ed25519).unwrap();
+        let layout = |dependencies: &[(&str, &str)]| {
+            let mut builder = LayoutBuilder::new();
+            for name in ["clone", "build", "package"] {
+                let step = StepBuilder::new(name)
+                    .add_pubkey(key.key_id().clone())
+                    .build()
+                    .unwrap();
+                builder = builder.add_step(step);
+            }
+            for (step, depends_on) in dependencies {
+                builder = builder.add_step_dependency(step, depends_on);
+            }
+            builder.build()
+        };
+
+        let chain = layout(&[("build", "clone"), ("package", "build")]).unwrap();
+        let names = |names: BTreeSet<String>| names.into_iter().collect::<Vec<_>>();
+        assert_eq!(names(chain.dependencies_of("package")), ["build", "clone"]);
+        assert!(chain.dependencies_of("clone").is_empty());
+        let jsn = serde_json::to_value(&chain).unwrap();
+        assert_eq!(jsn["step_dependencies"]["build"], json!(["clone"]));
+        assert_eq!(serde_json::from_value::<Layout>(jsn).unwrap(), chain);
+
+        // Layouts without dependencies serialize as before.
+        let jsn = serde_json::to_value(layout(&[]).unwrap()).unwrap();
+        assert!(jsn.get("step_dependencies").is_none());
+
+        assert!(matches!(
+            layout(&[
+                ("build", "clone"),
+                ("package", "build"),
+                ("clone", "package")
+            ]),
+            Err(Error::DependencyCycleOrViolation(_))
+        ));
+        assert!(matches!(
+            layout(&[("build", "untar")]),
+            Err(Error::IllegalArgument(_))
+        ));
+    }
+}
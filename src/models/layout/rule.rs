@@ -0,0 +1,341 @@
+//! Artifact rules: the constraints steps and inspections place on their materials and products.
+
+use serde::de::{Deserialize, Deserializer, Error as DeserializeError};
+use serde::ser::{Serialize, Serializer};
+use std::fmt;
+
+use crate::error::Error;
+use crate::Result;
+
+/// Which artifacts of a link a `MATCH` rule compares against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ArtifactType {
+    /// The materials of the link.
+    Materials,
+    /// The products of the link.
+    Products,
+}
+
+impl ArtifactType {
+    fn token(self) -> &'static str {
+        match self {
+            ArtifactType::Materials => "MATERIALS",
+            ArtifactType::Products => "PRODUCTS",
+        }
+    }
+}
+
+/// A rule artifacts must satisfy, serialized as the list of tokens used by in-toto layouts, e.g.
+/// `["MATCH", "*", "IN", "src", "WITH", "PRODUCTS", "FROM", "build"]`.
+///
+/// Each rule applies to the artifacts whose path matches its pattern, a glob in which `*` also
+/// matches `/`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum SupplyChainRule {
+    /// Matching artifacts must have identical hashes among the `dest_type` artifacts of the link
+    /// for step `dest_name`. With `source_prefix`, the pattern is matched against paths below
+    /// that directory; with `dest_prefix`, the artifacts are looked up below that directory.
+    Match {
+        /// The pattern selecting the artifacts.
+        pattern: String,
+        /// The directory the pattern is relative to, in this step's artifacts.
+        source_prefix: Option<String>,
+        /// The directory the artifacts are looked up in, in the other step's artifacts.
+        dest_prefix: Option<String>,
+        /// Whether the other step's materials or products are compared against.
+        dest_type: ArtifactType,
+        /// The name of the other step.
+        dest_name: String,
+    },
+    /// Matching artifacts are allowed.
+    Allow(String),
+    /// Matching artifacts are not allowed.
+    Disallow(String),
+    /// Matching artifacts must be products that were not materials.
+    Create(String),
+    /// Matching artifacts must be materials that are not products.
+    Delete(String),
+    /// Matching artifacts must be both materials and products, with different hashes.
+    Modify(String),
+    /// An artifact with exactly this path must be present.
+    Require(String),
+    /// A rule whose keyword is not one of the above, applied by the custom rule registered for
+    /// `keyword` at verification time. Verification fails if there is none.
+    Custom {
+        /// The keyword, in upper case.
+        keyword: String,
+        /// The tokens following the keyword.
+        args: Vec<String>,
+    },
+}
+
+/// The keywords of the rules in-toto defines.
+const BUILT_IN_KEYWORDS: &[&str] = &[
+    "MATCH", "ALLOW", "DISALLOW", "CREATE", "DELETE", "MODIFY", "REQUIRE",
+];
+
+impl SupplyChainRule {
+    /// Parse a rule from its tokens. Keywords are case insensitive. A keyword that is not built
+    /// in gives a `SupplyChainRule::Custom` rule, with any tokens following it.
+    pub fn from_tokens<S: AsRef<str>>(tokens: &[S]) -> Result<Self> {
+        let tokens = tokens.iter().map(AsRef::as_ref).collect::<Vec<_>>();
+        let invalid = || Error::IllegalArgument(format!("Invalid artifact rule {:?}", tokens));
+        let keyword = |index: usize, expected: &str| {
+            let token = tokens.get(index);
+            token.map(|token| token.eq_ignore_ascii_case(expected)) == Some(true)
+        };
+
+        match tokens.first() {
+            Some(&"") => return Err(invalid()),
+            Some(rule) if !BUILT_IN_KEYWORDS.contains(&rule.to_ascii_uppercase().as_str()) => {
+                return Ok(SupplyChainRule::Custom {
+                    keyword: rule.to_ascii_uppercase(),
+                    args: tokens[1..].iter().map(|token| token.to_string()).collect(),
+                });
+            }
+            _ => (),
+        }
+
+        let (rule, pattern) = match tokens.as_slice() {
+            [rule, pattern, ..] => (rule.to_ascii_uppercase(), pattern.to_string()),
+            _ => return Err(invalid()),
+        };
+        if rule != "MATCH" {
+            if tokens.len() != 2 {
+                return Err(invalid());
+            }
+            return match rule.as_str() {
+                "ALLOW" => Ok(SupplyChainRule::Allow(pattern)),
+                "DISALLOW" => Ok(SupplyChainRule::Disallow(pattern)),
+                "CREATE" => Ok(SupplyChainRule::Create(pattern)),
+                "DELETE" => Ok(SupplyChainRule::Delete(pattern)),
+                "MODIFY" => Ok(SupplyChainRule::Modify(pattern)),
+                "REQUIRE" => Ok(SupplyChainRule::Require(pattern)),
+                _ => Err(invalid()),
+            };
+        }
+
+        // MATCH <pattern> [IN <prefix>] WITH <type> [IN <prefix>] FROM <step>
+        let mut index = 2;
+        let prefix = |index: &mut usize| {
+            if keyword(*index, "IN") {
+                *index += 2;
+                tokens.get(*index - 1).map(|prefix| prefix.to_string())
+            } else {
+                None
+            }
+        };
+        let source_prefix = prefix(&mut index);
+        if !keyword(index, "WITH") {
+            return Err(invalid());
+        }
+        let dest_type = match tokens.get(index + 1) {
+            Some(token) if token.eq_ignore_ascii_case("MATERIALS") => ArtifactType::Materials,
+            Some(token) if token.eq_ignore_ascii_case("PRODUCTS") => ArtifactType::Products,
+            _ => return Err(invalid()),
+        };
+        index += 2;
+        let dest_prefix = prefix(&mut index);
+        if !keyword(index, "FROM") || tokens.len() != index + 2 {
+            return Err(invalid());
+        }
+
+        Ok(SupplyChainRule::Match {
+            pattern,
+            source_prefix,
+            dest_prefix,
+            dest_type,
+            dest_name: tokens[index + 1].to_string(),
+        })
+    }
+
+    /// The tokens of the rule, with keywords in upper case.
+    pub fn tokens(&self) -> Vec<String> {
+        let (rule, pattern) = match self {
+            SupplyChainRule::Match {
+                pattern,
+                source_prefix,
+                dest_prefix,
+                dest_type,
+                dest_name,
+            } => {
+                let mut tokens = vec!["MATCH".to_string(), pattern.clone()];
+                if let Some(prefix) = source_prefix {
+                    tokens.extend(vec!["IN".to_string(), prefix.clone()]);
+                }
+                tokens.extend(vec!["WITH".to_string(), dest_type.token().to_string()]);
+                if let Some(prefix) = dest_prefix {
+                    tokens.extend(vec!["IN".to_string(), prefix.clone()]);
+                }
+                tokens.extend(vec!["FROM".to_string(), dest_name.clone()]);
+                return tokens;
+            }
+            SupplyChainRule::Allow(pattern) => ("ALLOW", pattern),
+            SupplyChainRule::Disallow(pattern) => ("DISALLOW", pattern),
+            SupplyChainRule::Create(pattern) => ("CREATE", pattern),
+            SupplyChainRule::Delete(pattern) => ("DELETE", pattern),
+            SupplyChainRule::Modify(pattern) => ("MODIFY", pattern),
+            SupplyChainRule::Require(pattern) => ("REQUIRE", pattern),
+            SupplyChainRule::Custom { keyword, args } => {
+                let mut tokens = vec![keyword.clone()];
+                tokens.extend(args.iter().cloned());
+                return tokens;
+            }
+        };
+        vec![rule.to_string(), pattern.clone()]
+    }
+}
+
+impl fmt::Display for SupplyChainRule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.tokens().join(" "))
+    }
+}
+
+impl Serialize for SupplyChainRule {
+    fn serialize<S>(&self, ser: S) -> ::std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.tokens().serialize(ser)
+    }
+}
+
+impl<'de> Deserialize<'de> for SupplyChainRule {
+    fn deserialize<D: Deserializer<'de>>(de: D) -> ::std::result::Result<Self, D::Error> {
+        let tokens: Vec<String> = Deserialize::deserialize(de)?;
+        SupplyChainRule::from_tokens(&tokens)
+            .map_err(|e| DeserializeError::custom(format!("{:?}", e)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    fn parse(tokens: &[&str]) -> Result<SupplyChainRule> {
+        SupplyChainRule::from_tokens(tokens)
+    }
+
+    fn matching(
+        source_prefix: Option<&str>,
+        dest_prefix: Option<&str>,
+        dest_type: ArtifactType,
+    ) -> SupplyChainRule {
+        SupplyChainRule::Match {
+            pattern: "*".into(),
+            source_prefix: source_prefix.map(str::to_string),
+            dest_prefix: dest_prefix.map(str::to_string),
+            dest_type,
+            dest_name: "build".into(),
+        }
+    }
+
+    #[test]
+    fn parses_simple_rules() {
+        let cases = vec![
+            (["ALLOW", "*.c"], SupplyChainRule::Allow("*.c".into())),
+            (["DISALLOW", "*"], SupplyChainRule::Disallow("*".into())),
+            (["CREATE", "foo"], SupplyChainRule::Create("foo".into())),
+            (["DELETE", "foo"], SupplyChainRule::Delete("foo".into())),
+            (["MODIFY", "foo"], SupplyChainRule::Modify("foo".into())),
+            (["REQUIRE", "foo"], SupplyChainRule::Require("foo".into())),
+        ];
+        for (tokens, rule) in cases {
+            assert_eq!(parse(&tokens).unwrap(), rule);
+            assert_eq!(rule.tokens(), tokens);
+            assert_eq!(serde_json::to_value(&rule).unwrap(), json!(tokens));
+        }
+        assert_eq!(
+            parse(&["create", "foo"]).unwrap(),
+            SupplyChainRule::Create("foo".into())
+        );
+    }
+
+    #[test]
+    fn parses_match_rules() {
+        let cases = vec![
+            (
+                vec!["MATCH", "*", "WITH", "PRODUCTS", "FROM", "build"],
+                matching(None, None, ArtifactType::Products),
+            ),
+            (
+                vec![
+                    "MATCH",
+                    "*",
+                    "IN",
+                    "src",
+                    "WITH",
+                    "MATERIALS",
+                    "FROM",
+                    "build",
+                ],
+                matching(Some("src"), None, ArtifactType::Materials),
+            ),
+            (
+                vec![
+                    "MATCH", "*", "WITH", "PRODUCTS", "IN", "out", "FROM", "build",
+                ],
+                matching(None, Some("out"), ArtifactType::Products),
+            ),
+            (
+                vec![
+                    "MATCH", "*", "IN", "src", "WITH", "PRODUCTS", "IN", "out", "FROM", "build",
+                ],
+                matching(Some("src"), Some("out"), ArtifactType::Products),
+            ),
+        ];
+        for (tokens, rule) in cases {
+            assert_eq!(parse(&tokens).unwrap(), rule);
+            assert_eq!(rule.tokens(), tokens);
+            let decoded: SupplyChainRule = serde_json::from_value(json!(tokens)).unwrap();
+            assert_eq!(decoded, rule);
+        }
+        assert_eq!(
+            parse(&["match", "*", "with", "products", "from", "build"]).unwrap(),
+            matching(None, None, ArtifactType::Products)
+        );
+    }
+
+    #[test]
+    fn parses_custom_rules() {
+        let rule = parse(&["require-signed", "*.tar", "BY", "release"]).unwrap();
+        assert_eq!(
+            rule,
+            SupplyChainRule::Custom {
+                keyword: "REQUIRE-SIGNED".into(),
+                args: vec!["*.tar".into(), "BY".into(), "release".into()],
+            }
+        );
+        assert_eq!(rule.tokens(), ["REQUIRE-SIGNED", "*.tar", "BY", "release"]);
+        let decoded: SupplyChainRule = serde_json::from_value(json!(rule.tokens())).unwrap();
+        assert_eq!(decoded, rule);
+        assert_eq!(
+            parse(&["RENAME"]).unwrap(),
+            SupplyChainRule::Custom {
+                keyword: "RENAME".into(),
+                args: Vec::new(),
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_rules() {
+        let malformed: Vec<&[&str]> = vec![
+            &[],
+            &["CREATE"],
+            &["CREATE", "foo", "bar"],
+            &[""],
+            &["MATCH", "*"],
+            &["MATCH", "*", "WITH", "ARTIFACTS", "FROM", "build"],
+            &["MATCH", "*", "WITH", "PRODUCTS", "FROM"],
+            &["MATCH", "*", "WITH", "PRODUCTS", "FROM", "build", "extra"],
+            &["MATCH", "*", "IN", "WITH", "PRODUCTS", "FROM", "build"],
+            &["MATCH", "*", "IN", "src", "PRODUCTS", "FROM", "build"],
+        ];
+        for tokens in malformed {
+            assert!(parse(tokens).is_err(), "{:?} was accepted", tokens);
+        }
+    }
+}
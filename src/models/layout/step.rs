@@ -0,0 +1,250 @@
+//! A step of a supply chain layout.
+
+use serde::de::{Deserialize, Deserializer, Error as DeserializeError};
+use serde::ser::{Serialize, Serializer};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+use super::SupplyChainRule;
+use crate::crypto::KeyId;
+use crate::error::Error;
+use crate::Result;
+
+/// Helper to construct a `Step`.
+pub struct StepBuilder {
+    step: Step,
+}
+
+impl StepBuilder {
+    /// Start building the step `name`, which requires a single link by default.
+    pub fn new(name: &str) -> Self {
+        StepBuilder {
+            step: Step {
+                name: name.to_string(),
+                expected_materials: Vec::new(),
+                expected_products: Vec::new(),
+                pubkeys: Vec::new(),
+                expected_command: Vec::new(),
+                threshold: 1,
+            },
+        }
+    }
+
+    /// Add a rule the materials of the step must satisfy, applied after those already added.
+    pub fn add_material_rule(mut self, rule: SupplyChainRule) -> Self {
+        self.step.expected_materials.push(rule);
+        self
+    }
+
+    /// Add a rule the products of the step must satisfy, applied after those already added.
+    pub fn add_product_rule(mut self, rule: SupplyChainRule) -> Self {
+        self.step.expected_products.push(rule);
+        self
+    }
+
+    /// Authorize the key `key_id` to sign links for the step.
+    pub fn add_pubkey(mut self, key_id: KeyId) -> Self {
+        self.step.pubkeys.push(key_id);
+        self
+    }
+
+    /// Set the command the functionary is expected to run.
+    pub fn expected_command(mut self, command: &[&str]) -> Self {
+        self.step.expected_command = command.iter().map(|arg| arg.to_string()).collect();
+        self
+    }
+
+    /// Set the number of links from distinct authorized keys the step requires.
+    pub fn threshold(mut self, threshold: u32) -> Self {
+        self.step.threshold = threshold;
+        self
+    }
+
+    /// Construct the `Step`. The threshold must be at least one and at most the number of
+    /// authorized keys, and no key may be authorized twice.
+    pub fn build(self) -> Result<Step> {
+        self.step.validate()?;
+        Ok(self.step)
+    }
+}
+
+/// A step of the supply chain, carried out by a functionary who records it in a link.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Step {
+    name: String,
+    expected_materials: Vec<SupplyChainRule>,
+    expected_products: Vec<SupplyChainRule>,
+    pubkeys: Vec<KeyId>,
+    expected_command: Vec<String>,
+    threshold: u32,
+}
+
+impl Step {
+    fn validate(&self) -> Result<()> {
+        if self.threshold == 0 {
+            return Err(Error::IllegalArgument(format!(
+                "Step {:?} has a threshold of zero",
+                self.name
+            )));
+        }
+        if self.threshold as usize > self.pubkeys.len() {
+            return Err(Error::IllegalArgument(format!(
+                "Step {:?} has a threshold of {} but only {} authorized keys",
+                self.name,
+                self.threshold,
+                self.pubkeys.len()
+            )));
+        }
+        let distinct = self.pubkeys.iter().collect::<HashSet<_>>();
+        if distinct.len() != self.pubkeys.len() {
+            return Err(Error::IllegalArgument(format!(
+                "Step {:?} authorizes a key more than once",
+                self.name
+            )));
+        }
+        Ok(())
+    }
+
+    /// The name of the step, which its links are recorded under.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The rules the materials of the step must satisfy, in the order they are applied.
+    pub fn expected_materials(&self) -> &[SupplyChainRule] {
+        &self.expected_materials
+    }
+
+    /// The rules the products of the step must satisfy, in the order they are applied.
+    pub fn expected_products(&self) -> &[SupplyChainRule] {
+        &self.expected_products
+    }
+
+    /// The keys authorized to sign links for the step.
+    pub fn pubkeys(&self) -> &[KeyId] {
+        &self.pubkeys
+    }
+
+    /// The command the functionary is expected to run.
+    pub fn expected_command(&self) -> &[String] {
+        &self.expected_command
+    }
+
+    /// The number of links from distinct authorized keys the step requires.
+    pub fn threshold(&self) -> u32 {
+        self.threshold
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct StepJson {
+    #[serde(rename = "_type")]
+    typ: String,
+    name: String,
+    expected_materials: Vec<SupplyChainRule>,
+    expected_products: Vec<SupplyChainRule>,
+    pubkeys: Vec<KeyId>,
+    expected_command: Vec<String>,
+    threshold: u32,
+}
+
+impl Serialize for Step {
+    fn serialize<S>(&self, ser: S) -> ::std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        StepJson {
+            typ: "step".into(),
+            name: self.name.clone(),
+            expected_materials: self.expected_materials.clone(),
+            expected_products: self.expected_products.clone(),
+            pubkeys: self.pubkeys.clone(),
+            expected_command: self.expected_command.clone(),
+            threshold: self.threshold,
+        }
+        .serialize(ser)
+    }
+}
+
+impl<'de> Deserialize<'de> for Step {
+    fn deserialize<D: Deserializer<'de>>(de: D) -> ::std::result::Result<Self, D::Error> {
+        let intermediate: StepJson = Deserialize::deserialize(de)?;
+        if intermediate.typ != "step" {
+            return Err(DeserializeError::custom(format!(
+                "Expected a step, found {:?}",
+                intermediate.typ
+            )));
+        }
+        let step = Step {
+            name: intermediate.name,
+            expected_materials: intermediate.expected_materials,
+            expected_products: intermediate.expected_products,
+            pubkeys: intermediate.pubkeys,
+            expected_command: intermediate.expected_command,
+            threshold: intermediate.threshold,
+        };
+        step.validate()
+            .map_err(|e| DeserializeError::custom(format!("{:?}", e)))?;
+        Ok(step)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+    use std::str::FromStr;
+
+    const KEY_ID_1: &str = "556caebdc0877eed53d419b60eddb1e57fa773e4e31d70698b58f3e9cc48b35d";
+    const KEY_ID_2: &str = "d3ffd1086938b3698618adf088bf14b13db4c8bd4d89a5bd5b2fd2b6d1e2c6fc";
+
+    #[test]
+    fn step_matches_reference_serialization() {
+        // As written by the Python reference implementation.
+        let reference = json!({
+            "_type": "step",
+            "name": "clone",
+            "expected_command": ["git", "clone", "https://example.com/project.git"],
+            "expected_materials": [],
+            "expected_products": [["CREATE", "project/foo.py"], ["DISALLOW", "*"]],
+            "pubkeys": [KEY_ID_1],
+            "threshold": 1,
+        });
+        let step = StepBuilder::new("clone")
+            .expected_command(&["git", "clone", "https://example.com/project.git"])
+            .add_product_rule(SupplyChainRule::Create("project/foo.py".into()))
+            .add_product_rule(SupplyChainRule::Disallow("*".into()))
+            .add_pubkey(KeyId::from_str(KEY_ID_1).unwrap())
+            .build()
+            .unwrap();
+
+        assert_eq!(serde_json::to_value(&step).unwrap(), reference);
+        assert_eq!(serde_json::from_value::<Step>(reference).unwrap(), step);
+    }
+
+    #[test]
+    fn step_threshold_is_bounded_by_authorized_keys() {
+        let key_1 = KeyId::from_str(KEY_ID_1).unwrap();
+        let key_2 = KeyId::from_str(KEY_ID_2).unwrap();
+        let two_keys = || {
+            StepBuilder::new("review")
+                .add_pubkey(key_1.clone())
+                .add_pubkey(key_2.clone())
+        };
+
+        assert_eq!(two_keys().threshold(2).build().unwrap().threshold(), 2);
+        assert!(two_keys().threshold(3).build().is_err());
+        assert!(two_keys().threshold(0).build().is_err());
+        assert!(StepBuilder::new("review").build().is_err());
+        assert!(StepBuilder::new("review")
+            .add_pubkey(key_1.clone())
+            .add_pubkey(key_1.clone())
+            .threshold(2)
+            .build()
+            .is_err());
+
+        let mut jsn = serde_json::to_value(two_keys().build().unwrap()).unwrap();
+        jsn["threshold"] = json!(3);
+        assert!(serde_json::from_value::<Step>(jsn).is_err());
+    }
+}
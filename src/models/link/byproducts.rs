@@ -0,0 +1,314 @@
+//! Typed view of the byproducts of a command run for a step.
+
+use data_encoding::BASE64;
+use serde::de::{Deserialize, Deserializer, Error as DeserializeError};
+use serde::ser::{Serialize, Serializer};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+
+use super::LinkMetadata;
+use crate::error::Error;
+
+const STDOUT: &str = "stdout";
+const STDERR: &str = "stderr";
+/// Suffix of the key holding an output stream that is not valid UTF-8, base64 encoded.
+const BINARY_SUFFIX: &str = "-b64";
+const RETURN_VALUE: &str = "return-value";
+const SIGNAL: &str = "signal";
+
+/// What a command wrote to one of its output streams.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommandOutput {
+    /// Output that is valid UTF-8.
+    Text(String),
+    /// Output that is not valid UTF-8, kept as raw bytes.
+    Binary(Vec<u8>),
+}
+
+impl CommandOutput {
+    /// Keep `bytes` as text if they are valid UTF-8, and as raw bytes otherwise.
+    pub fn from_bytes(bytes: Vec<u8>) -> Self {
+        String::from_utf8(bytes)
+            .map(CommandOutput::Text)
+            .unwrap_or_else(|e| CommandOutput::Binary(e.into_bytes()))
+    }
+
+    /// The output as text, if it is valid UTF-8.
+    pub fn as_text(&self) -> Option<&str> {
+        match self {
+            CommandOutput::Text(text) => Some(text),
+            CommandOutput::Binary(_) => None,
+        }
+    }
+
+    /// The raw bytes of the output.
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            CommandOutput::Text(text) => text.as_bytes(),
+            CommandOutput::Binary(bytes) => bytes,
+        }
+    }
+
+    fn insert_into(self, map: &mut BTreeMap<String, String>, key: &str) {
+        let _ = match self {
+            CommandOutput::Text(text) => map.insert(key.to_string(), text),
+            CommandOutput::Binary(bytes) => {
+                map.insert(format!("{}{}", key, BINARY_SUFFIX), BASE64.encode(&bytes))
+            }
+        };
+    }
+
+    fn read_from(map: &BTreeMap<String, String>, key: &str) -> Result<Self, Error> {
+        let binary_key = format!("{}{}", key, BINARY_SUFFIX);
+        match (map.get(key), map.get(&binary_key)) {
+            (Some(_), Some(_)) => Err(Error::Encoding(format!(
+                "Byproducts hold both {} and {}",
+                key, binary_key
+            ))),
+            (Some(text), None) => Ok(CommandOutput::Text(text.clone())),
+            (None, Some(encoded)) => BASE64
+                .decode(encoded.as_bytes())
+                .map(CommandOutput::Binary)
+                .map_err(|e| Error::Encoding(format!("Byproduct {}: {}", binary_key, e))),
+            (None, None) => Ok(CommandOutput::default()),
+        }
+    }
+}
+
+impl Default for CommandOutput {
+    fn default() -> Self {
+        CommandOutput::Text(String::new())
+    }
+}
+
+/// What a command run for a step produced besides its products.
+///
+/// Converts to and from the string map stored as a link's `byproducts`, and serializes as that
+/// map, where the exit code is
+/// kept as a decimal string under `return-value` and the signal that terminated the command, if
+/// any, under `signal`. Output that is not valid UTF-8 is stored base64 encoded under
+/// `stdout-b64` or `stderr-b64` instead of `stdout` or `stderr`. When deserializing, or reading a
+/// link with `TryFrom<&LinkMetadata>`, an integer `return-value` as written by the reference
+/// implementation is accepted too.
+///
+/// ```
+/// # use in_toto::models::Byproducts;
+/// # use std::collections::BTreeMap;
+/// # use std::convert::TryFrom;
+/// let mut map = BTreeMap::new();
+/// let _ = map.insert("stdout".to_string(), "ok\n".to_string());
+/// let _ = map.insert("return-value".to_string(), "0".to_string());
+///
+/// let byproducts = Byproducts::try_from(&map).unwrap();
+/// assert_eq!(byproducts.exit_code, Some(0));
+/// assert_eq!(serde_json::to_value(&byproducts).unwrap()["stdout"], "ok\n");
+/// assert_eq!(BTreeMap::from(byproducts)["return-value"], "0");
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Byproducts {
+    /// What the command wrote to stdout.
+    pub stdout: CommandOutput,
+    /// What the command wrote to stderr.
+    pub stderr: CommandOutput,
+    /// The exit code of the command, or `None` if it was terminated by a signal.
+    pub exit_code: Option<i32>,
+    /// The name of the signal that terminated the command, such as `SIGKILL`, or its number if
+    /// it has no portable name.
+    pub signal: Option<String>,
+}
+
+impl From<Byproducts> for BTreeMap<String, String> {
+    fn from(byproducts: Byproducts) -> Self {
+        let mut map = BTreeMap::new();
+        byproducts.stdout.insert_into(&mut map, STDOUT);
+        byproducts.stderr.insert_into(&mut map, STDERR);
+        if let Some(exit_code) = byproducts.exit_code {
+            let _ = map.insert(RETURN_VALUE.to_string(), exit_code.to_string());
+        }
+        if let Some(signal) = byproducts.signal {
+            let _ = map.insert(SIGNAL.to_string(), signal);
+        }
+        map
+    }
+}
+
+impl TryFrom<&BTreeMap<String, String>> for Byproducts {
+    type Error = Error;
+
+    /// Read the byproducts of a link. Missing entries are left empty, and other entries are
+    /// ignored, but a `return-value` that is not an integer or undecodable base64 output is an
+    /// error.
+    fn try_from(map: &BTreeMap<String, String>) -> Result<Self, Error> {
+        let exit_code = match map.get(RETURN_VALUE) {
+            Some(value) => Some(value.parse().map_err(|e| {
+                Error::Encoding(format!("Byproduct {} {:?}: {}", RETURN_VALUE, value, e))
+            })?),
+            None => None,
+        };
+        Ok(Byproducts {
+            stdout: CommandOutput::read_from(map, STDOUT)?,
+            stderr: CommandOutput::read_from(map, STDERR)?,
+            exit_code,
+            signal: map.get(SIGNAL).cloned(),
+        })
+    }
+}
+
+impl TryFrom<&LinkMetadata> for Byproducts {
+    type Error = Error;
+
+    /// Read the byproducts of `link`. Its `return-value` may be an integer, as the reference
+    /// implementation writes it, which links keep apart from the string byproducts.
+    ///
+    /// ```
+    /// # use in_toto::interchange::Json;
+    /// # use in_toto::models::{Byproducts, LinkMetadata, SignedMetadata};
+    /// # use std::convert::TryFrom;
+    /// let path = "tests/test_link/package.a9f3ebc9.link";
+    /// let signed = SignedMetadata::<Json, LinkMetadata>::from_file(path).unwrap();
+    /// let link = signed.assume_valid().unwrap();
+    /// assert_eq!(Byproducts::try_from(&link).unwrap().exit_code, Some(0));
+    /// ```
+    fn try_from(link: &LinkMetadata) -> Result<Self, Error> {
+        let return_value = link.structured_byproducts().get(RETURN_VALUE);
+        Byproducts::read(link.byproducts().clone(), return_value)
+    }
+}
+
+impl Byproducts {
+    /// Read the byproducts in `map`, with the integer `return_value` found next to its strings,
+    /// if any.
+    fn read(
+        mut map: BTreeMap<String, String>,
+        return_value: Option<&Value>,
+    ) -> Result<Self, Error> {
+        match return_value {
+            Some(Value::Number(code)) if code.is_i64() => {
+                let _ = map.insert(RETURN_VALUE.to_string(), code.to_string());
+            }
+            Some(value) => {
+                return Err(Error::Encoding(format!(
+                    "Byproduct {} {} is not an integer",
+                    RETURN_VALUE, value
+                )));
+            }
+            None => (),
+        }
+        Byproducts::try_from(&map)
+    }
+}
+
+impl Serialize for Byproducts {
+    fn serialize<S>(&self, ser: S) -> ::std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        BTreeMap::from(self.clone()).serialize(ser)
+    }
+}
+
+impl<'de> Deserialize<'de> for Byproducts {
+    fn deserialize<D: Deserializer<'de>>(de: D) -> ::std::result::Result<Self, D::Error> {
+        // Entries other than strings are ignored, except for an integer `return-value`.
+        let values: BTreeMap<String, Value> = Deserialize::deserialize(de)?;
+        let mut map = BTreeMap::new();
+        let mut return_value = None;
+        for (key, value) in values {
+            match value {
+                Value::String(value) => {
+                    let _ = map.insert(key, value);
+                }
+                value if key == RETURN_VALUE => return_value = Some(value),
+                _ => (),
+            }
+        }
+        Byproducts::read(map, return_value.as_ref())
+            .map_err(|e| DeserializeError::custom(format!("{:?}", e)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::interchange::Json;
+    use crate::models::SignedMetadata;
+
+    #[test]
+    fn byproducts_round_trip_through_map() {
+        let killed = Byproducts {
+            stdout: CommandOutput::Text("partial".into()),
+            stderr: CommandOutput::Binary(vec![0xff, 0xfe]),
+            exit_code: None,
+            signal: Some("SIGKILL".into()),
+        };
+        let map = BTreeMap::from(killed.clone());
+        assert!(!map.contains_key(RETURN_VALUE));
+        assert!(!map.contains_key(STDERR));
+        assert_eq!(map["stderr-b64"], "//4=");
+        assert_eq!(Byproducts::try_from(&map), Ok(killed));
+
+        let mut map = BTreeMap::new();
+        let _ = map.insert(RETURN_VALUE.to_string(), "-1".to_string());
+        assert_eq!(Byproducts::try_from(&map).unwrap().exit_code, Some(-1));
+        let _ = map.insert(RETURN_VALUE.to_string(), "zero".to_string());
+        assert!(Byproducts::try_from(&map).is_err());
+
+        let mut map = BTreeMap::new();
+        let _ = map.insert(STDOUT.to_string(), "text".to_string());
+        let _ = map.insert("stdout-b64".to_string(), "dGV4dA==".to_string());
+        assert!(Byproducts::try_from(&map).is_err());
+    }
+
+    #[test]
+    fn byproducts_serialize_as_map() {
+        let byproducts = Byproducts {
+            stdout: CommandOutput::Text("ok\n".into()),
+            stderr: CommandOutput::Binary(vec![0xff]),
+            exit_code: Some(0),
+            signal: None,
+        };
+        let json = serde_json::to_value(&byproducts).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({"stdout": "ok\n", "stderr-b64": "/w==", "return-value": "0"})
+        );
+        assert_eq!(
+            serde_json::from_value::<Byproducts>(json).unwrap(),
+            byproducts
+        );
+        assert!(
+            serde_json::from_value::<Byproducts>(serde_json::json!({"return-value": "zero"}))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn reads_integer_return_value_of_reference_links() {
+        let read = |json| serde_json::from_value::<Byproducts>(json).map(|b| b.exit_code);
+        assert_eq!(
+            read(serde_json::json!({"return-value": 0})).unwrap(),
+            Some(0)
+        );
+        assert_eq!(
+            read(serde_json::json!({"return-value": -2})).unwrap(),
+            Some(-2)
+        );
+        assert!(read(serde_json::json!({"return-value": 1.5})).is_err());
+        assert!(read(serde_json::json!({"return-value": [0]})).is_err());
+
+        let path = "tests/test_link/package.a9f3ebc9.link";
+        let signed = SignedMetadata::<Json, LinkMetadata>::from_file(path).unwrap();
+        let link = signed.assume_valid().unwrap();
+        let byproducts = Byproducts::try_from(&link).unwrap();
+        assert_eq!(byproducts.exit_code, Some(0));
+        assert_eq!(byproducts.signal, None);
+        assert_eq!(byproducts.stdout.as_text(), Some(""));
+
+        let signed: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(path).unwrap()).unwrap();
+        let byproducts: Byproducts =
+            serde_json::from_value(signed["signed"]["byproducts"].clone()).unwrap();
+        assert_eq!(byproducts.exit_code, Some(0));
+    }
+}
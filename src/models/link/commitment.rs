@@ -0,0 +1,274 @@
+//! Merkle commitments to a link's artifact set (experimental).
+//!
+//! A functionary who does not want to reveal every path it recorded can sign a commitment to
+//! the artifact set instead, and later disclose individual artifacts together with an
+//! `InclusionProof` against the signed root.
+//!
+//! The tree follows RFC 6962: leaves are the artifacts in path order, each hashed as
+//! `SHA-256(0x00 || cjson({"path": path, "hashes": hashes}))`, and interior nodes as
+//! `SHA-256(0x01 || left || right)`, with the left subtree holding the largest power of two
+//! leaves smaller than the number of leaves. The root of an empty set is `SHA-256("")`.
+
+use ring::constant_time::verify_slices_are_equal;
+use ring::digest::{self, SHA256};
+use serde_derive::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::BTreeMap;
+
+use crate::crypto::HashValue;
+use crate::error::Error;
+use crate::interchange::{DataInterchange, Json};
+use crate::models::{TargetDescription, VirtualTargetPath};
+use crate::Result;
+
+const LEAF_PREFIX: u8 = 0x00;
+const NODE_PREFIX: u8 = 0x01;
+
+/// A commitment to a set of artifacts: the Merkle root and the number of leaves.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArtifactCommitment {
+    root: HashValue,
+    size: u64,
+}
+
+impl ArtifactCommitment {
+    /// Commit to `artifacts`.
+    pub fn new(artifacts: &BTreeMap<VirtualTargetPath, TargetDescription>) -> Result<Self> {
+        let leaves = leaf_hashes(artifacts)?;
+        Ok(ArtifactCommitment {
+            root: HashValue::new(subtree_root(&leaves)),
+            size: leaves.len() as u64,
+        })
+    }
+
+    /// The Merkle root of the committed artifact set.
+    pub fn root(&self) -> &HashValue {
+        &self.root
+    }
+
+    /// The number of committed artifacts.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Check that the artifact at `path` with `hashes` is part of the committed set.
+    pub fn verify_inclusion(
+        &self,
+        path: &VirtualTargetPath,
+        hashes: &TargetDescription,
+        proof: &InclusionProof,
+    ) -> Result<()> {
+        let failure = || {
+            Error::VerificationFailure(format!(
+                "Artifact {:?} is not included in the committed artifact set",
+                path.value()
+            ))
+        };
+
+        if proof.index >= self.size {
+            return Err(failure());
+        }
+
+        // RFC 9162, section 2.1.3.2.
+        let mut fn_ = proof.index;
+        let mut sn = self.size - 1;
+        let mut r = leaf_hash(path, hashes)?;
+        for p in &proof.path {
+            if sn == 0 {
+                return Err(failure());
+            }
+            if fn_ & 1 == 1 || fn_ == sn {
+                r = node_hash(p.value(), &r);
+                if fn_ & 1 == 0 {
+                    while fn_ & 1 == 0 && fn_ != 0 {
+                        fn_ >>= 1;
+                        sn >>= 1;
+                    }
+                }
+            } else {
+                r = node_hash(&r, p.value());
+            }
+            fn_ >>= 1;
+            sn >>= 1;
+        }
+
+        if sn != 0 || verify_slices_are_equal(&r, self.root.value()).is_err() {
+            return Err(failure());
+        }
+        Ok(())
+    }
+}
+
+/// Proof that a single artifact is part of an `ArtifactCommitment`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InclusionProof {
+    index: u64,
+    path: Vec<HashValue>,
+}
+
+impl InclusionProof {
+    /// Prove that the artifact at `path` is part of the commitment to `artifacts`.
+    ///
+    /// Returns `Error::NotFound` if `artifacts` has no entry for `path`.
+    pub fn new(
+        artifacts: &BTreeMap<VirtualTargetPath, TargetDescription>,
+        path: &VirtualTargetPath,
+    ) -> Result<Self> {
+        let index = artifacts
+            .keys()
+            .position(|p| p == path)
+            .ok_or(Error::NotFound)?;
+        let leaves = leaf_hashes(artifacts)?;
+        Ok(InclusionProof {
+            index: index as u64,
+            path: audit_path(index, &leaves)
+                .into_iter()
+                .map(HashValue::new)
+                .collect(),
+        })
+    }
+
+    /// The position of the artifact among the committed artifacts, in path order.
+    pub fn index(&self) -> u64 {
+        self.index
+    }
+}
+
+fn leaf_hashes(artifacts: &BTreeMap<VirtualTargetPath, TargetDescription>) -> Result<Vec<Vec<u8>>> {
+    artifacts
+        .iter()
+        .map(|(path, hashes)| leaf_hash(path, hashes))
+        .collect()
+}
+
+fn leaf_hash(path: &VirtualTargetPath, hashes: &TargetDescription) -> Result<Vec<u8>> {
+    let leaf = Json::serialize(&json!({ "path": path, "hashes": hashes }))?;
+    let mut ctx = digest::Context::new(&SHA256);
+    ctx.update(&[LEAF_PREFIX]);
+    ctx.update(&Json::canonicalize(&leaf)?);
+    Ok(ctx.finish().as_ref().to_vec())
+}
+
+fn node_hash(left: &[u8], right: &[u8]) -> Vec<u8> {
+    let mut ctx = digest::Context::new(&SHA256);
+    ctx.update(&[NODE_PREFIX]);
+    ctx.update(left);
+    ctx.update(right);
+    ctx.finish().as_ref().to_vec()
+}
+
+/// The largest power of two strictly smaller than `n`, for `n > 1`.
+fn split_point(n: usize) -> usize {
+    let mut k = 1;
+    while k << 1 < n {
+        k <<= 1;
+    }
+    k
+}
+
+fn subtree_root(leaves: &[Vec<u8>]) -> Vec<u8> {
+    match leaves.len() {
+        0 => digest::digest(&SHA256, &[]).as_ref().to_vec(),
+        1 => leaves[0].clone(),
+        n => {
+            let k = split_point(n);
+            node_hash(&subtree_root(&leaves[..k]), &subtree_root(&leaves[k..]))
+        }
+    }
+}
+
+fn audit_path(index: usize, leaves: &[Vec<u8>]) -> Vec<Vec<u8>> {
+    if leaves.len() <= 1 {
+        return Vec::new();
+    }
+    let k = split_point(leaves.len());
+    let (mut path, sibling) = if index < k {
+        (audit_path(index, &leaves[..k]), subtree_root(&leaves[k..]))
+    } else {
+        (
+            audit_path(index - k, &leaves[k..]),
+            subtree_root(&leaves[..k]),
+        )
+    };
+    path.push(sibling);
+    path
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::crypto::{calculate_hash, HashAlgorithm};
+
+    fn artifacts(count: usize) -> BTreeMap<VirtualTargetPath, TargetDescription> {
+        (0..count)
+            .map(|i| {
+                let mut hashes = TargetDescription::new();
+                let _ = hashes.insert(
+                    HashAlgorithm::Sha256,
+                    calculate_hash(format!("artifact {}", i).as_bytes(), HashAlgorithm::Sha256),
+                );
+                (
+                    VirtualTargetPath::new(format!("dist/file-{:02}", i)).unwrap(),
+                    hashes,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn inclusion_proofs_verify_for_every_artifact() {
+        for count in 1..=9 {
+            let artifacts = artifacts(count);
+            let commitment = ArtifactCommitment::new(&artifacts).unwrap();
+            assert_eq!(commitment.size(), count as u64);
+
+            for (path, hashes) in &artifacts {
+                let proof = InclusionProof::new(&artifacts, path).unwrap();
+                assert_eq!(commitment.verify_inclusion(path, hashes, &proof), Ok(()));
+            }
+        }
+    }
+
+    #[test]
+    fn inclusion_proofs_reject_excluded_artifacts() {
+        let artifacts = artifacts(5);
+        let commitment = ArtifactCommitment::new(&artifacts).unwrap();
+        let (path, hashes) = artifacts.iter().nth(2).unwrap();
+        let proof = InclusionProof::new(&artifacts, path).unwrap();
+
+        let mut tampered = hashes.clone();
+        let _ = tampered.insert(
+            HashAlgorithm::Sha256,
+            calculate_hash(b"tampered", HashAlgorithm::Sha256),
+        );
+        assert!(commitment
+            .verify_inclusion(path, &tampered, &proof)
+            .is_err());
+
+        let renamed = VirtualTargetPath::new("dist/other".into()).unwrap();
+        assert!(commitment
+            .verify_inclusion(&renamed, hashes, &proof)
+            .is_err());
+
+        let (other_path, other_hashes) = artifacts.iter().nth(3).unwrap();
+        assert!(commitment
+            .verify_inclusion(other_path, other_hashes, &proof)
+            .is_err());
+
+        let missing = VirtualTargetPath::new("dist/missing".into()).unwrap();
+        assert_eq!(
+            InclusionProof::new(&artifacts, &missing),
+            Err(Error::NotFound)
+        );
+    }
+
+    #[test]
+    fn commitment_of_empty_set() {
+        let commitment = ArtifactCommitment::new(&BTreeMap::new()).unwrap();
+        assert_eq!(commitment.size(), 0);
+        assert_eq!(
+            commitment.root(),
+            &calculate_hash(b"", HashAlgorithm::Sha256)
+        );
+    }
+}
@@ -0,0 +1,57 @@
+//! Typed view of the environment a step ran in.
+
+use std::collections::BTreeMap;
+
+const WORKDIR: &str = "workdir";
+
+/// The environment a step ran in.
+///
+/// Converts to and from the string map stored as a link's `environment`, where the working
+/// directory is kept under `workdir` and each variable under its own name. A variable named
+/// `workdir` is therefore shadowed by the working directory.
+///
+/// ```
+/// # use in_toto::models::{Environment, LinkMetadataBuilder};
+/// let mut environment = Environment::default();
+/// environment.workdir = Some("/src/project".to_string());
+/// let _ = environment.variables.insert("CC".to_string(), "clang".to_string());
+///
+/// let link = LinkMetadataBuilder::new()
+///     .environment(environment.clone())
+///     .build()
+///     .unwrap();
+/// assert_eq!(link.env()["workdir"], "/src/project");
+/// assert_eq!(link.environment(), environment);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Environment {
+    /// The directory the command ran in, with `/` separators.
+    pub workdir: Option<String>,
+    /// The environment variables recorded for the command.
+    pub variables: BTreeMap<String, String>,
+}
+
+impl Environment {
+    /// Whether nothing is recorded, in which case links leave out their `environment`.
+    pub fn is_empty(&self) -> bool {
+        self.workdir.is_none() && self.variables.is_empty()
+    }
+}
+
+impl From<Environment> for BTreeMap<String, String> {
+    fn from(environment: Environment) -> Self {
+        let mut map = environment.variables;
+        if let Some(workdir) = environment.workdir {
+            let _ = map.insert(WORKDIR.to_string(), workdir);
+        }
+        map
+    }
+}
+
+impl From<&BTreeMap<String, String>> for Environment {
+    fn from(map: &BTreeMap<String, String>) -> Self {
+        let mut variables = map.clone();
+        let workdir = variables.remove(WORKDIR);
+        Environment { workdir, variables }
+    }
+}
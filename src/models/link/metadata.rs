@@ -2,24 +2,39 @@
 
 use serde::de::{Deserialize, Deserializer, Error as DeserializeError};
 use serde::ser::{Error as SerializeError, Serialize, Serializer};
+use serde_json::Value;
 use std::collections::{BTreeMap};
 use std::fmt::{Debug};
 use std::fs::File;
-use std::io::BufReader;
+use std::io::{BufReader, Write};
+use std::path::{Path, PathBuf};
 
-use crate::crypto::{self, PrivateKey};
-use crate::interchange::DataInterchange;
+use crate::crypto::{self, HashAlgorithm, HashValue, PrivateKey};
+use crate::interchange::{DataInterchange, Json, JsonPretty};
+use crate::error::Error;
+use crate::staging::staged_file;
 use crate::Result;
 
-use crate::models::{SignedMetadata, Metadata, Link, VirtualTargetPath, TargetDescription};
+use crate::models::{
+    Environment, Link, Metadata, SignedMetadata, TargetDescription, VirtualTargetPath,
+};
+#[cfg(feature = "experimental")]
+use crate::models::{ArtifactCommitment, InclusionProof};
 
 /// Helper to construct `LinkMetadata`.
 pub struct LinkMetadataBuilder {
   name: String,
+  command: Vec<String>,
   materials: BTreeMap<VirtualTargetPath, TargetDescription>,
   products: BTreeMap<VirtualTargetPath, TargetDescription>,
   env: BTreeMap<String, String>,
   byproducts: BTreeMap<String, String>,
+  custom: BTreeMap<String, Value>,
+  structured_byproducts: BTreeMap<String, Value>,
+  layout_digest: Option<HashValue>,
+  required_algorithms: Vec<HashAlgorithm>,
+  #[cfg(feature = "experimental")]
+  artifact_commitment: Option<ArtifactCommitment>,
 }
 
 impl LinkMetadataBuilder {
@@ -28,10 +43,17 @@ impl LinkMetadataBuilder {
   pub fn new() -> Self {
       LinkMetadataBuilder {
           name: String::new(),
+          command: Vec::new(),
           materials: BTreeMap::new(),
           products: BTreeMap::new(),
           env: BTreeMap::new(),
-          byproducts: BTreeMap::new()
+          byproducts: BTreeMap::new(),
+          custom: BTreeMap::new(),
+          structured_byproducts: BTreeMap::new(),
+          layout_digest: None,
+          required_algorithms: Vec::new(),
+          #[cfg(feature = "experimental")]
+          artifact_commitment: None,
       }
   }
 
@@ -41,6 +63,12 @@ impl LinkMetadataBuilder {
       self
   }
 
+  /// Set the command that was run for this link
+  pub fn command(mut self, command: Vec<String>) -> Self {
+      self.command = command;
+      self
+  }
+
   /// Set the materials for this metadata
   pub fn materials(mut self, materials: BTreeMap<VirtualTargetPath, TargetDescription>) -> Self {
       self.materials = materials;
@@ -53,39 +81,126 @@ impl LinkMetadataBuilder {
       self
   }
 
-  pub fn add_material(mut self, material_path: VirtualTargetPath) -> Self {
-      let file = File::open(material_path.to_string()).unwrap();
-      let mut reader = BufReader::new(file);
-      let (_length, hashes) = crypto::calculate_hashes(&mut reader,
-              &[crypto::HashAlgorithm::Sha256]).unwrap();
-      self.materials.insert(material_path, hashes);
+  /// Add a single material to the ones set so far, replacing any previous entry for `path`.
+  pub fn add_material(mut self, path: VirtualTargetPath, description: TargetDescription) -> Self {
+      self.materials.insert(path, description);
       self
   }
 
-  pub fn add_product(mut self, material_path: VirtualTargetPath) -> Self {
-      let file = File::open(material_path.to_string()).unwrap();
-      let mut reader = BufReader::new(file);
-      let (_length, hashes) = crypto::calculate_hashes(&mut reader,
-              &[crypto::HashAlgorithm::Sha256]).unwrap();
-      self.products.insert(material_path, hashes);
+  /// Add a single product to the ones set so far, replacing any previous entry for `path`.
+  pub fn add_product(mut self, path: VirtualTargetPath, description: TargetDescription) -> Self {
+      self.products.insert(path, description);
       self
   }
 
+  /// Hash the file at `path` with each of `hash_algorithms` and add it as a material.
+  ///
+  /// ```
+  /// # use in_toto::crypto::HashAlgorithm;
+  /// # use in_toto::models::LinkMetadataBuilder;
+  /// let link = LinkMetadataBuilder::new()
+  ///     .add_material_from_path("LICENSE", &[HashAlgorithm::Sha256])
+  ///     .unwrap()
+  ///     .build()
+  ///     .unwrap();
+  /// assert_eq!(link.materials().len(), 1);
+  /// ```
+  pub fn add_material_from_path(
+      self,
+      path: &str,
+      hash_algorithms: &[HashAlgorithm],
+  ) -> Result<Self> {
+      let (path, description) = hash_file(path, hash_algorithms)?;
+      Ok(self.add_material(path, description))
+  }
+
+  /// Hash the file at `path` with each of `hash_algorithms` and add it as a product.
+  pub fn add_product_from_path(
+      self,
+      path: &str,
+      hash_algorithms: &[HashAlgorithm],
+  ) -> Result<Self> {
+      let (path, description) = hash_file(path, hash_algorithms)?;
+      Ok(self.add_product(path, description))
+  }
+
   /// Set the products for this metadata
   pub fn env(mut self, env: BTreeMap<String, String>) -> Self {
       self.env = env;
       self
   }
 
-  /// Set the products for this metadata
-  pub fn byproducts(mut self, byproducts: BTreeMap<String, String>) -> Self {
-      self.byproducts = byproducts;
+  /// Set the environment the step ran in, replacing any set with `env`.
+  pub fn environment(mut self, environment: Environment) -> Self {
+      self.env = environment.into();
+      self
+  }
+
+  /// Set the byproducts for this metadata, either as a `Byproducts` or as the string map stored
+  /// in the link.
+  pub fn byproducts<B: Into<BTreeMap<String, String>>>(mut self, byproducts: B) -> Self {
+      self.byproducts = byproducts.into();
+      self
+  }
+
+  /// Record a structured byproduct, such as parsed test results or coverage figures. It is
+  /// stored alongside the other byproducts and covered by the link's signature. Canonical JSON
+  /// has no floating point numbers, so `value` must only use integers.
+  pub fn structured_byproduct(mut self, key: String, value: Value) -> Self {
+      self.structured_byproducts.insert(key, value);
       self
   }
 
+  /// Set the custom fields for this metadata. These are covered by the link's signature.
+  pub fn custom(mut self, custom: BTreeMap<String, Value>) -> Self {
+      self.custom = custom;
+      self
+  }
+
+  /// Record the digest of the layout this link is meant to fulfill, binding the link to it.
+  pub fn layout_digest(mut self, digest: HashValue) -> Self {
+      self.layout_digest = Some(digest);
+      self
+  }
+
+  /// Require every material and product to be hashed with each of `algorithms`. This is
+  /// checked by `build`.
+  pub fn require_algorithms(mut self, algorithms: &[HashAlgorithm]) -> Self {
+      self.required_algorithms = algorithms.to_vec();
+      self
+  }
+
+  /// Sign a commitment to the artifact set instead of, or alongside, the artifacts themselves.
+  /// Artifacts can then be disclosed one at a time with an `InclusionProof`.
+  #[cfg(feature = "experimental")]
+  pub fn artifact_commitment(mut self, commitment: ArtifactCommitment) -> Self {
+      self.artifact_commitment = Some(commitment);
+      self
+  }
+
+  /// Build the link. Fails if an artifact is not hashed with every required algorithm, or has
+  /// an absolute path, which could not be read back from the link.
   pub fn build(self) -> Result<LinkMetadata> {
-      LinkMetadata::new(self.name, self.materials, self.products,
-          self.env, self.byproducts)
+      for (path, hashes) in self.materials.iter().chain(&self.products) {
+          if path.value().starts_with('/') {
+              return Err(Error::IllegalArgument(format!(
+                  "Artifact {:?} has an absolute path, which links cannot hold",
+                  path.value())));
+          }
+          if let Some(missing) = self.required_algorithms.iter().find(|a| !hashes.contains_key(a)) {
+              return Err(Error::IllegalArgument(format!(
+                  "Artifact {:?} is not hashed with required algorithm {:?}",
+                  path.value(), missing)));
+          }
+      }
+
+      let link = LinkMetadata::new(self.name, self.materials, self.products,
+          self.env, self.byproducts, self.custom, self.structured_byproducts)?
+          .with_layout_digest(self.layout_digest)
+          .with_command(self.command);
+      #[cfg(feature = "experimental")]
+      let link = link.with_artifact_commitment(self.artifact_commitment);
+      Ok(link)
   }
 
     /// Construct a new `SignedMetadata<D, LinkMetadata>`.
@@ -97,14 +212,33 @@ impl LinkMetadataBuilder {
   }
 }
 
+/// Hash the file at `path` for `LinkMetadataBuilder::add_material_from_path` and
+/// `add_product_from_path`.
+fn hash_file(
+    path: &str,
+    hash_algorithms: &[HashAlgorithm],
+) -> Result<(VirtualTargetPath, TargetDescription)> {
+    let file = File::open(path).map_err(|e| Error::from_io(&e, Path::new(path)))?;
+    let (_length, hashes) = crypto::calculate_hashes(BufReader::new(file), hash_algorithms)?;
+    Ok((VirtualTargetPath::new(path.to_string())?, hashes))
+}
+
 /// link metadata
 #[derive(Debug, Clone, PartialEq)]
 pub struct LinkMetadata {
   name: String,
+  command: Vec<String>,
   materials: BTreeMap<VirtualTargetPath, TargetDescription>,
   products: BTreeMap<VirtualTargetPath, TargetDescription>,
   env: BTreeMap<String, String>,
   byproducts: BTreeMap<String, String>,
+  custom: BTreeMap<String, Value>,
+  structured_byproducts: BTreeMap<String, Value>,
+  layout_digest: Option<HashValue>,
+  #[cfg(feature = "experimental")]
+  artifact_commitment: Option<ArtifactCommitment>,
+  unrecognized: BTreeMap<String, Value>,
+  write_empty_env: bool,
 }
 
 impl LinkMetadata {
@@ -115,23 +249,96 @@ impl LinkMetadata {
       products: BTreeMap<VirtualTargetPath, TargetDescription>,
       env: BTreeMap<String, String>,
       byproducts: BTreeMap<String, String>,
+      custom: BTreeMap<String, Value>,
+      structured_byproducts: BTreeMap<String, Value>,
   ) -> Result<Self> {
+      let mut byproducts = byproducts;
+      let mut structured_byproducts = structured_byproducts;
+
+      // Structured strings are indistinguishable from plain byproducts once serialized.
+      let strings = structured_byproducts.iter()
+          .filter(|(_, value)| value.is_string())
+          .map(|(key, _)| key.clone())
+          .collect::<Vec<_>>();
+      for key in strings {
+          if let Some(Value::String(value)) = structured_byproducts.remove(&key) {
+              if byproducts.insert(key.clone(), value).is_some() {
+                  return Err(Error::IllegalArgument(format!(
+                      "Byproduct {:?} is recorded twice", key)));
+              }
+          }
+      }
+      if let Some(key) = structured_byproducts.keys().find(|k| byproducts.contains_key(*k)) {
+          return Err(Error::IllegalArgument(format!(
+              "Byproduct {:?} is recorded twice", key)));
+      }
 
       Ok(LinkMetadata {
           name,
+          command: Vec::new(),
           materials,
           products,
           env,
-          byproducts
+          byproducts,
+          custom,
+          structured_byproducts,
+          layout_digest: None,
+          #[cfg(feature = "experimental")]
+          artifact_commitment: None,
+          unrecognized: BTreeMap::new(),
+          write_empty_env: false,
       })
   }
 
+  pub(crate) fn with_command(mut self, command: Vec<String>) -> Self {
+      self.command = command;
+      self
+  }
+
+  pub(crate) fn with_unrecognized_fields(mut self, unrecognized: BTreeMap<String, Value>) -> Self {
+      self.unrecognized = unrecognized;
+      self
+  }
+
+  /// Write the environment even when it is empty, as links read with an empty `environment`
+  /// had it, so that they serialize as they were read.
+  pub(crate) fn with_empty_env_written(mut self, write: bool) -> Self {
+      self.write_empty_env = write;
+      self
+  }
+
+  pub(crate) fn writes_empty_env(&self) -> bool {
+      self.write_empty_env
+  }
+
+  pub(crate) fn with_layout_digest(mut self, digest: Option<HashValue>) -> Self {
+      self.layout_digest = digest;
+      self
+  }
+
+  #[cfg(feature = "experimental")]
+  pub(crate) fn with_artifact_commitment(mut self, commitment: Option<ArtifactCommitment>) -> Self {
+      self.artifact_commitment = commitment;
+      self
+  }
+
 
   // The step this link is associated to
   pub fn name(&self) -> &String {
       &self.name
   }
 
+  // The command that was run
+  pub fn command(&self) -> &[String] {
+      &self.command
+  }
+
+  /// Fields of the link this crate does not model, such as ones added by newer versions of the
+  /// reference implementation. They are written back out when the link is serialized.
+  pub fn unrecognized_fields(&self) -> &BTreeMap<String, Value> {
+      &self.unrecognized
+  }
+
   // The materials used as inputs
   pub fn materials(&self) -> &BTreeMap<VirtualTargetPath, TargetDescription> {
       &self.materials
@@ -147,10 +354,64 @@ impl LinkMetadata {
       &self.env
   }
 
+  /// The environment the step ran in, as a typed view of `env`.
+  pub fn environment(&self) -> Environment {
+      Environment::from(&self.env)
+  }
+
   // The Environment where things were built
   pub fn byproducts(&self) -> &BTreeMap<String, String> {
       &self.byproducts
   }
+
+  // Byproducts holding structured data rather than plain strings
+  pub fn structured_byproducts(&self) -> &BTreeMap<String, Value> {
+      &self.structured_byproducts
+  }
+
+  // Organization-specific fields covered by the signature
+  pub fn custom(&self) -> &BTreeMap<String, Value> {
+      &self.custom
+  }
+
+  // Digest of the layout this link claims to fulfill
+  pub fn layout_digest(&self) -> Option<&HashValue> {
+      self.layout_digest.as_ref()
+  }
+
+  /// Check that this link was produced for the layout with digest `expected`. Fails if the link
+  /// records a different layout digest or none at all.
+  pub fn verify_layout_digest(&self, expected: &HashValue) -> Result<()> {
+      match self.layout_digest {
+          Some(ref digest) if digest == expected => Ok(()),
+          Some(ref digest) => Err(Error::VerificationFailure(format!(
+              "Link {:?} was produced for layout {}, not {}", self.name, digest, expected))),
+          None => Err(Error::VerificationFailure(format!(
+              "Link {:?} does not record a layout digest", self.name))),
+      }
+  }
+
+  // Commitment to an undisclosed artifact set
+  #[cfg(feature = "experimental")]
+  pub fn artifact_commitment(&self) -> Option<&ArtifactCommitment> {
+      self.artifact_commitment.as_ref()
+  }
+
+  /// Check that the artifact at `path` with `hashes` is part of the artifact set this link
+  /// commits to. Fails if the link carries no commitment.
+  #[cfg(feature = "experimental")]
+  pub fn verify_artifact_inclusion(
+      &self,
+      path: &VirtualTargetPath,
+      hashes: &TargetDescription,
+      proof: &InclusionProof,
+  ) -> Result<()> {
+      match self.artifact_commitment {
+          Some(ref commitment) => commitment.verify_inclusion(path, hashes, proof),
+          None => Err(Error::VerificationFailure(format!(
+              "Link {:?} does not commit to an artifact set", self.name))),
+      }
+  }
 }
 
 impl Metadata for LinkMetadata {
@@ -178,3 +439,403 @@ impl<'de> Deserialize<'de> for LinkMetadata {
           .map_err(|e| DeserializeError::custom(format!("{:?}", e)))
   }
 }
+
+impl SignedMetadata<Json, LinkMetadata> {
+  /// The conventional file name of this link, `FILENAME_FORMAT` filled in with the step name and
+  /// the key ID of the first signature, or `{step_name}.link` if the link is not signed.
+  ///
+  /// ```
+  /// # use in_toto::crypto::{PrivateKey, SignatureScheme};
+  /// # use in_toto::models::LinkMetadataBuilder;
+  /// # let der = include_bytes!("../../../tests/ed25519/ed25519-1.pk8.der");
+  /// let key = PrivateKey::from_pkcs8(der, SignatureScheme::Ed25519).unwrap();
+  /// let signed = LinkMetadataBuilder::new()
+  ///     .name("package".to_string())
+  ///     .signed(&key)
+  ///     .unwrap();
+  /// assert_eq!(signed.filename().unwrap(), "package.a9f3ebc9.link");
+  /// ```
+  pub fn filename(&self) -> Result<String> {
+      let link = self.assume_valid()?;
+      Ok(match self.signatures().first() {
+          Some(sig) => format!("{}.{:.8}.link", link.name(), sig.key_id().as_str()),
+          None => format!("{}.link", link.name()),
+      })
+  }
+
+  /// Write this link to `writer` as pretty printed JSON.
+  pub fn write<W: Write>(&self, writer: W) -> Result<()> {
+      JsonPretty::to_writer(writer, self)
+  }
+
+  /// Write this link into the directory `dir` under its `filename`, replacing any link already
+  /// there. Returns the path of the link.
+  pub fn to_file<P: AsRef<Path>>(&self, dir: P) -> Result<PathBuf> {
+      let dir = dir.as_ref();
+      let path = dir.join(self.filename()?);
+      let mut staged = staged_file(dir).map_err(|e| Error::from_io(&e, &path))?;
+      self.write(&mut staged)?;
+      let _ = staged
+          .persist(&path)
+          .map_err(|e| Error::from_io(&e.error, &path))?;
+      Ok(path)
+  }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    use crate::crypto::SignatureScheme;
+    use crate::models::{Byproducts, CommandOutput, SignedMetadataBuilder};
+    use crate::runlib::record_artifacts;
+    #[cfg(unix)]
+    use crate::staging::mode;
+
+    const ED25519_1_PK8: &[u8] = include_bytes!("../../../tests/ed25519/ed25519-1.pk8.der");
+
+    #[test]
+    fn custom_fields_are_signed() {
+        let key = PrivateKey::from_pkcs8(ED25519_1_PK8, SignatureScheme::Ed25519).unwrap();
+        let mut custom = BTreeMap::new();
+        custom.insert("ticket".to_string(), json!("SEC-1234"));
+        custom.insert("approvers".to_string(), json!(["alice", "bob"]));
+
+        let signed = LinkMetadataBuilder::new()
+            .name("test".to_string())
+            .custom(custom.clone())
+            .signed::<Json>(&key)
+            .unwrap();
+        let link = signed.verify(1, vec![key.public()]).unwrap();
+        assert_eq!(link.custom(), &custom);
+
+        let mut tampered = serde_json::to_value(&signed).unwrap();
+        tampered["signed"]["custom"]["ticket"] = json!("SEC-9999");
+        let tampered: SignedMetadata<Json, LinkMetadata> =
+            serde_json::from_value(tampered).unwrap();
+        assert!(tampered.verify(1, vec![key.public()]).is_err());
+    }
+
+    #[test]
+    fn custom_fields_omitted_when_empty() {
+        let link = LinkMetadataBuilder::new()
+            .name("test".to_string())
+            .build()
+            .unwrap();
+        let jsn = serde_json::to_value(&link).unwrap();
+        assert!(jsn.get("custom").is_none());
+
+        let decoded: LinkMetadata = serde_json::from_value(jsn).unwrap();
+        assert_eq!(decoded, link);
+    }
+
+    #[test]
+    fn builder_rejects_absolute_artifact_paths() {
+        let key = PrivateKey::from_pkcs8(ED25519_1_PK8, SignatureScheme::Ed25519).unwrap();
+        let path = VirtualTargetPath::allow_absolute("/srv/app.tar".into()).unwrap();
+        let artifacts = record_artifacts(&["tests/test_runlib/release/artifact.txt"], None)
+            .unwrap()
+            .into_values()
+            .map(|hashes| (path.clone(), hashes))
+            .collect::<BTreeMap<_, _>>();
+
+        let products = LinkMetadataBuilder::new()
+            .name("deploy".to_string())
+            .products(artifacts.clone())
+            .signed::<Json>(&key);
+        match products {
+            Err(Error::IllegalArgument(msg)) => assert!(msg.contains("/srv/app.tar"), "{}", msg),
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert!(LinkMetadataBuilder::new()
+            .name("deploy".to_string())
+            .materials(artifacts)
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn layout_digest_binds_link_to_layout() {
+        let key = PrivateKey::from_pkcs8(ED25519_1_PK8, SignatureScheme::Ed25519).unwrap();
+        let layout = crypto::calculate_hash(b"root.layout", crypto::HashAlgorithm::Sha256);
+        let other = crypto::calculate_hash(b"other.layout", crypto::HashAlgorithm::Sha256);
+
+        let signed = LinkMetadataBuilder::new()
+            .name("test".to_string())
+            .layout_digest(layout.clone())
+            .signed::<Json>(&key)
+            .unwrap();
+        let link = signed.verify(1, vec![key.public()]).unwrap();
+        assert_eq!(link.layout_digest(), Some(&layout));
+        assert_eq!(link.verify_layout_digest(&layout), Ok(()));
+        assert!(link.verify_layout_digest(&other).is_err());
+
+        let mut tampered = serde_json::to_value(&signed).unwrap();
+        tampered["signed"]["layout_digest"] = json!(other.to_string());
+        let tampered: SignedMetadata<Json, LinkMetadata> =
+            serde_json::from_value(tampered).unwrap();
+        assert!(tampered.verify(1, vec![key.public()]).is_err());
+
+        let unbound = LinkMetadataBuilder::new()
+            .name("test".to_string())
+            .build()
+            .unwrap();
+        assert!(unbound.verify_layout_digest(&layout).is_err());
+    }
+
+    #[test]
+    fn require_algorithms_checks_every_artifact() {
+        let hashes = |algorithms: &[HashAlgorithm]| {
+            algorithms
+                .iter()
+                .map(|alg| (alg.clone(), crypto::calculate_hash(b"artifact", alg.clone())))
+                .collect::<TargetDescription>()
+        };
+        let artifact = |path: &str, algorithms: &[HashAlgorithm]| {
+            let mut artifacts = BTreeMap::new();
+            artifacts.insert(VirtualTargetPath::new(path.to_string()).unwrap(), hashes(algorithms));
+            artifacts
+        };
+        let both = [HashAlgorithm::Sha256, HashAlgorithm::Sha512];
+
+        assert!(LinkMetadataBuilder::new()
+            .materials(artifact("src/main.c", &both))
+            .products(artifact("main", &[HashAlgorithm::Sha256]))
+            .require_algorithms(&[HashAlgorithm::Sha256])
+            .build()
+            .is_ok());
+        assert!(LinkMetadataBuilder::new()
+            .materials(artifact("src/main.c", &both))
+            .products(artifact("main", &[HashAlgorithm::Sha256]))
+            .require_algorithms(&both)
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn structured_byproducts_round_trip_and_are_signed() {
+        let key = PrivateKey::from_pkcs8(ED25519_1_PK8, SignatureScheme::Ed25519).unwrap();
+        let mut byproducts = BTreeMap::new();
+        byproducts.insert("stdout".to_string(), "ok".to_string());
+        let coverage = json!({"lines": {"covered": 812, "total": 1000}, "percent": 81});
+
+        let signed = LinkMetadataBuilder::new()
+            .name("test".to_string())
+            .byproducts(byproducts.clone())
+            .structured_byproduct("coverage".to_string(), coverage.clone())
+            .signed::<Json>(&key)
+            .unwrap();
+        let jsn = serde_json::to_value(&signed).unwrap();
+        assert_eq!(jsn["signed"]["byproducts"]["stdout"], json!("ok"));
+        assert_eq!(jsn["signed"]["byproducts"]["coverage"], coverage);
+
+        let link = signed.verify(1, vec![key.public()]).unwrap();
+        assert_eq!(link.byproducts(), &byproducts);
+        assert_eq!(link.structured_byproducts()["coverage"], coverage);
+
+        let mut tampered = jsn;
+        tampered["signed"]["byproducts"]["coverage"]["percent"] = json!(95);
+        let tampered: SignedMetadata<Json, LinkMetadata> =
+            serde_json::from_value(tampered).unwrap();
+        assert!(tampered.verify(1, vec![key.public()]).is_err());
+    }
+
+    #[test]
+    fn byproducts_accept_typed_byproducts() {
+        let byproducts = Byproducts {
+            stdout: CommandOutput::Text("ok".into()),
+            exit_code: Some(0),
+            ..Byproducts::default()
+        };
+        let typed = LinkMetadataBuilder::new()
+            .byproducts(byproducts.clone())
+            .build()
+            .unwrap();
+        let mapped = LinkMetadataBuilder::new()
+            .byproducts(BTreeMap::from(byproducts))
+            .build()
+            .unwrap();
+        assert_eq!(typed, mapped);
+        assert_eq!(typed.byproducts()["return-value"], "0");
+    }
+
+    #[test]
+    fn environment_round_trips_and_is_omitted_when_empty() {
+        let key = PrivateKey::from_pkcs8(ED25519_1_PK8, SignatureScheme::Ed25519).unwrap();
+        let mut variables = BTreeMap::new();
+        variables.insert("CC".to_string(), "clang".to_string());
+        variables.insert("PATH".to_string(), "/usr/bin".to_string());
+        let environment = Environment {
+            workdir: Some("/src/project".to_string()),
+            variables,
+        };
+
+        let signed = LinkMetadataBuilder::new()
+            .name("build".to_string())
+            .environment(environment.clone())
+            .signed::<Json>(&key)
+            .unwrap();
+        let jsn = serde_json::to_value(&signed).unwrap();
+        assert_eq!(
+            jsn["signed"]["environment"],
+            json!({"CC": "clang", "PATH": "/usr/bin", "workdir": "/src/project"})
+        );
+        let read: SignedMetadata<Json, LinkMetadata> = serde_json::from_value(jsn).unwrap();
+        assert_eq!(read.verify(1, vec![key.public()]).unwrap().environment(), environment);
+
+        let signed = LinkMetadataBuilder::new()
+            .environment(Environment::default())
+            .signed::<Json>(&key)
+            .unwrap();
+        let jsn = serde_json::to_value(&signed).unwrap();
+        assert!(jsn["signed"].get("environment").is_none());
+        let read: SignedMetadata<Json, LinkMetadata> = serde_json::from_value(jsn).unwrap();
+        assert!(read.verify(1, vec![key.public()]).unwrap().environment().is_empty());
+    }
+
+    #[test]
+    fn structured_byproducts_cannot_shadow_byproducts() {
+        let mut byproducts = BTreeMap::new();
+        byproducts.insert("stdout".to_string(), "ok".to_string());
+
+        assert!(LinkMetadataBuilder::new()
+            .byproducts(byproducts)
+            .structured_byproduct("stdout".to_string(), json!(["ok"]))
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn reads_reference_link() {
+        let key = PrivateKey::from_pkcs8(ED25519_1_PK8, SignatureScheme::Ed25519).unwrap();
+        let path = "tests/test_link/package.a9f3ebc9.link";
+        let signed = SignedMetadata::<Json, LinkMetadata>::from_file(path).unwrap();
+        let link = signed.verify(1, vec![key.public()]).unwrap();
+
+        assert_eq!(link.name(), "package");
+        assert_eq!(
+            link.command(),
+            &["tar", "--exclude", ".git", "-zcvf", "demo-project.tar.gz", "demo-project"]
+        );
+        assert_eq!(link.structured_byproducts()["return-value"], json!(0));
+        assert_eq!(link.byproducts()["stdout"], "");
+
+        let original: Value = serde_json::from_slice(&std::fs::read(path).unwrap()).unwrap();
+        assert_eq!(serde_json::to_value(&link).unwrap(), original["signed"]);
+
+        let mut extended = original["signed"].clone();
+        extended["attestations"] = json!([{"type": "sbom"}]);
+        let link: LinkMetadata = serde_json::from_value(extended.clone()).unwrap();
+        assert_eq!(link.unrecognized_fields()["attestations"], json!([{"type": "sbom"}]));
+        assert_eq!(serde_json::to_value(&link).unwrap(), extended);
+    }
+
+    #[test]
+    fn to_file_uses_reference_filename() {
+        let key = PrivateKey::from_pkcs8(ED25519_1_PK8, SignatureScheme::Ed25519).unwrap();
+        let link = LinkMetadataBuilder::new()
+            .name("package".to_string())
+            .build()
+            .unwrap();
+        let dir = tempfile::tempdir().unwrap();
+
+        let signed = SignedMetadata::<Json, _>::new(&link, &key).unwrap();
+        let path = signed.to_file(dir.path()).unwrap();
+        assert_eq!(path, dir.path().join(format!("package.{:.8}.link", key.key_id().as_str())));
+        assert_eq!(path.file_name().unwrap(), "package.a9f3ebc9.link");
+        let read = SignedMetadata::<Json, LinkMetadata>::from_file(&path).unwrap();
+        assert_eq!(read.verify(1, vec![key.public()]).unwrap(), link);
+        assert!(std::fs::read_to_string(&path).unwrap().starts_with("{\n  \""));
+
+        let unsigned = SignedMetadataBuilder::<Json, _>::from_metadata(&link)
+            .unwrap()
+            .build();
+        let path = unsigned.to_file(dir.path()).unwrap();
+        assert_eq!(path.file_name().unwrap(), "package.link");
+        let read = SignedMetadata::<Json, LinkMetadata>::from_file(&path).unwrap();
+        assert!(read.signatures().is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn to_file_creates_link_like_file_create() {
+        let link = LinkMetadataBuilder::new()
+            .name("package".to_string())
+            .build()
+            .unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let created = dir.path().join("created");
+        let _ = File::create(&created).unwrap();
+
+        let signed = SignedMetadataBuilder::<Json, _>::from_metadata(&link)
+            .unwrap()
+            .build();
+        let path = signed.to_file(dir.path()).unwrap();
+        assert_eq!(mode(&path), mode(&created));
+    }
+
+    #[test]
+    fn incrementally_added_artifacts_match_bulk_artifacts() {
+        let algorithms = [HashAlgorithm::Sha256, HashAlgorithm::Sha512];
+        let names = ["LICENSE", "Makefile", "README.md"];
+        let artifacts = record_artifacts(&names, Some(&["sha256", "sha512"])).unwrap();
+
+        let bulk = LinkMetadataBuilder::new()
+            .name("build".to_string())
+            .materials(artifacts.clone())
+            .products(artifacts.clone())
+            .build()
+            .unwrap();
+
+        let mut builder = LinkMetadataBuilder::new().name("build".to_string());
+        for (path, description) in &artifacts {
+            builder = builder.add_product(path.clone(), description.clone());
+        }
+        for name in &names {
+            builder = builder.add_material_from_path(name, &algorithms).unwrap();
+        }
+        assert_eq!(builder.build().unwrap(), bulk);
+
+        assert!(LinkMetadataBuilder::new()
+            .add_material_from_path("tests/missing", &algorithms)
+            .is_err());
+    }
+
+    #[cfg(feature = "experimental")]
+    #[test]
+    fn artifact_commitment_is_signed_and_proves_inclusion() {
+        let key = PrivateKey::from_pkcs8(ED25519_1_PK8, SignatureScheme::Ed25519).unwrap();
+        let products = LinkMetadataBuilder::new()
+            .add_product_from_path("tests/ed25519/ed25519-1.pub", &[HashAlgorithm::Sha256])
+            .unwrap()
+            .add_product_from_path("tests/ed25519/ed25519-1.pk8.der", &[HashAlgorithm::Sha256])
+            .unwrap()
+            .build()
+            .unwrap()
+            .products()
+            .clone();
+
+        let signed = LinkMetadataBuilder::new()
+            .name("build".to_string())
+            .artifact_commitment(ArtifactCommitment::new(&products).unwrap())
+            .signed::<Json>(&key)
+            .unwrap();
+        let jsn = serde_json::to_value(&signed).unwrap();
+        assert!(jsn["signed"]["products"].as_object().unwrap().is_empty());
+
+        let link = signed.verify(1, vec![key.public()]).unwrap();
+        let (path, hashes) = products.iter().next().unwrap();
+        let proof = InclusionProof::new(&products, path).unwrap();
+        assert_eq!(link.verify_artifact_inclusion(path, hashes, &proof), Ok(()));
+
+        let (other, _) = products.iter().nth(1).unwrap();
+        assert!(link.verify_artifact_inclusion(other, hashes, &proof).is_err());
+
+        let mut tampered = jsn;
+        tampered["signed"]["artifact_commitment"]["size"] = json!(3);
+        let tampered: SignedMetadata<Json, LinkMetadata> =
+            serde_json::from_value(tampered).unwrap();
+        assert!(tampered.verify(1, vec![key.public()]).is_err());
+    }
+}
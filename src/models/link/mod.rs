@@ -4,68 +4,171 @@ use std::collections::{BTreeMap};
 use std::fmt::{Debug};
 use std::str;
 
+use serde::de::{Deserializer, Error as DeserializeError};
 use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+use crate::error::Error;
 use crate::Result;
 
 pub mod metadata;
 pub use metadata::{LinkMetadata, LinkMetadataBuilder};
+mod byproducts;
+pub use byproducts::{Byproducts, CommandOutput};
+mod environment;
+pub use environment::Environment;
 
+#[cfg(feature = "schema")]
+pub mod schema;
+#[cfg(feature = "experimental")]
+pub mod commitment;
+#[cfg(feature = "experimental")]
+pub use commitment::{ArtifactCommitment, InclusionProof};
+
+use crate::crypto::HashValue;
 use crate::models::{VirtualTargetPath, TargetDescription};
 
 // FIXME, we need to tag a spec
 //const SPEC_VERSION: &str = "0.9-dev";
 
-// FIXME: methods will be relevant for layout expiration
-// fn parse_datetime(ts: &str) -> Result<DateTime<Utc>> {
-//     Utc.datetime_from_str(ts, "%FT%TZ")
-//         .map_err(|e| Error::Encoding(format!("Can't parse DateTime: {:?}", e)))
-// }
-//
-// fn format_datetime(ts: &DateTime<Utc>) -> String {
-//     format!(
-//         "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
-//         ts.year(),
-//         ts.month(),
-//         ts.day(),
-//         ts.hour(),
-//         ts.minute(),
-//         ts.second()
-//     )
-// }
-
 pub const FILENAME_FORMAT: &str = "{step_name}.{keyid:.8}.link";
+pub const UNFINISHED_FILENAME_FORMAT: &str = ".{step_name}.{keyid:.8}.link-unfinished";
+/// The directory, next to the sublayout stored under `FILENAME_FORMAT`, holding the links of a
+/// sublayout.
+pub const SUBLAYOUT_LINK_DIR_FORMAT: &str = "{step_name}.{keyid:.8}";
 
+/// The `_type` of links.
+const LINK_TYPE: &str = "link";
+
+/// A link as written by the reference implementation. Links written by earlier versions of this
+/// crate, which put the step name in `_type` and the environment in `env`, are read as well.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Link {
-    // Why is the type named as typ?
     #[serde(rename = "_type")]
+    typ: String,
+    #[serde(default)]
     name: String,
+    #[serde(default)]
+    command: Vec<String>,
+    #[serde(deserialize_with = "deserialize_artifacts")]
     materials: BTreeMap<VirtualTargetPath, TargetDescription>,
+    #[serde(deserialize_with = "deserialize_artifacts")]
     products: BTreeMap<VirtualTargetPath, TargetDescription>,
-    env: BTreeMap<String, String>,
-    byproducts: BTreeMap<String, String>,
+    /// Left out when empty, unless read that way.
+    #[serde(
+        rename = "environment",
+        alias = "env",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    env: Option<BTreeMap<String, String>>,
+    #[serde(default)]
+    byproducts: BTreeMap<String, Value>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    custom: BTreeMap<String, Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    layout_digest: Option<HashValue>,
+    #[cfg(feature = "experimental")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    artifact_commitment: Option<ArtifactCommitment>,
+    #[serde(flatten)]
+    unrecognized: BTreeMap<String, Value>,
+}
 
+/// Read the artifacts of a link, replacing the `\` separators of paths recorded on Windows by
+/// `/`, so that they match the rules of layouts written elsewhere. Fails if two artifacts are
+/// then listed under the same path.
+fn deserialize_artifacts<'de, D>(
+    de: D,
+) -> std::result::Result<BTreeMap<VirtualTargetPath, TargetDescription>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let artifacts: BTreeMap<String, TargetDescription> = serde::Deserialize::deserialize(de)?;
+    let mut normalized = BTreeMap::new();
+    for (path, hashes) in artifacts {
+        let path = VirtualTargetPath::new(path.replace('\\', "/"))
+            .map_err(|e| DeserializeError::custom(format!("{:?}", e)))?;
+        if normalized.contains_key(&path) {
+            return Err(DeserializeError::custom(format!(
+                "Artifact {:?} is listed more than once",
+                path.value()
+            )));
+        }
+        let _ = normalized.insert(path, hashes);
+    }
+    Ok(normalized)
 }
 
 impl Link {
     pub fn from(meta: &LinkMetadata) -> Result<Self> {
         Ok(Link {
+            typ: LINK_TYPE.to_string(),
             name: meta.name().to_string(),
+            command: meta.command().to_vec(),
             materials: (*meta.materials()).clone(),
             products: (*meta.products()).clone(),
-            env: (*meta.env()).clone(),
-            byproducts: (*meta.byproducts()).clone()
+            env: match meta.env() {
+                env if env.is_empty() && !meta.writes_empty_env() => None,
+                env => Some(env.clone()),
+            },
+            byproducts: meta
+                .byproducts()
+                .iter()
+                .map(|(k, v)| (k.clone(), Value::String(v.clone())))
+                .chain(meta.structured_byproducts().clone())
+                .collect(),
+            custom: (*meta.custom()).clone(),
+            layout_digest: meta.layout_digest().cloned(),
+            #[cfg(feature = "experimental")]
+            artifact_commitment: meta.artifact_commitment().cloned(),
+            unrecognized: meta.unrecognized_fields().clone(),
         })
     }
 
     pub fn try_into(self) -> Result<LinkMetadata> {
-        LinkMetadata::new(
-            self.name,
+        let name = match (self.typ.as_str(), self.name) {
+            (LINK_TYPE, name) => name,
+            // Earlier versions of this crate wrote the step name as the type.
+            (typ, name) if name.is_empty() => typ.to_string(),
+            (typ, _) => {
+                return Err(Error::Encoding(format!(
+                    "Expected a link, found {:?}",
+                    typ
+                )));
+            }
+        };
+
+        // Plain string byproducts (stdout, stderr, ...) are kept apart from structured ones.
+        let mut byproducts = BTreeMap::new();
+        let mut structured_byproducts = BTreeMap::new();
+        for (key, value) in self.byproducts {
+            match value {
+                Value::String(value) => {
+                    byproducts.insert(key, value);
+                }
+                value => {
+                    structured_byproducts.insert(key, value);
+                }
+            }
+        }
+
+        let write_empty_env = self.env.as_ref().is_some_and(BTreeMap::is_empty);
+        let meta = LinkMetadata::new(
+            name,
             self.materials,
             self.products,
-            self.env,
-            self.byproducts
-        )
+            self.env.unwrap_or_default(),
+            byproducts,
+            self.custom,
+            structured_byproducts,
+        )?
+        .with_layout_digest(self.layout_digest)
+        .with_command(self.command)
+        .with_unrecognized_fields(self.unrecognized)
+        .with_empty_env_written(write_empty_env);
+        #[cfg(feature = "experimental")]
+        let meta = meta.with_artifact_commitment(self.artifact_commitment);
+        Ok(meta)
     }
 }
 
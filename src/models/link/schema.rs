@@ -0,0 +1,86 @@
+//! Validation of link metablocks against a JSON Schema.
+
+use jsonschema::JSONSchema;
+use serde_json::Value;
+
+use crate::error::{Error, SchemaViolation};
+use crate::interchange::DataInterchange;
+use crate::models::{LinkMetadata, SignedMetadata};
+use crate::Result;
+
+/// JSON Schema of a signed link, as serialized by this crate.
+pub const LINK_SCHEMA: &str = include_str!("link.schema.json");
+
+impl<D> SignedMetadata<D, LinkMetadata>
+where
+    D: DataInterchange<RawData = Value>,
+{
+    /// Validate this link against `LINK_SCHEMA` without parsing the signed metadata.
+    ///
+    /// This catches structural problems that deserialization tolerates, such as artifacts without
+    /// any hashes. On failure, `Error::Schema` lists every violation along with the JSON pointer
+    /// of the offending value.
+    pub fn validate_schema(&self) -> Result<()> {
+        let schema: Value = serde_json::from_str(LINK_SCHEMA)?;
+        let schema = JSONSchema::compile(&schema)
+            .map_err(|e| Error::Programming(format!("Invalid link schema: {}", e)))?;
+        let instance = serde_json::to_value(self)?;
+
+        let result = schema.validate(&instance);
+        match result {
+            Ok(()) => Ok(()),
+            Err(errors) => Err(Error::Schema(
+                errors
+                    .map(|e| SchemaViolation {
+                        pointer: e.instance_path.to_string(),
+                        message: e.to_string(),
+                    })
+                    .collect(),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::crypto::{PrivateKey, SignatureScheme};
+    use crate::interchange::Json;
+    use crate::models::LinkMetadataBuilder;
+    use crate::runlib::record_artifacts;
+
+    const ED25519_1_PK8: &[u8] = include_bytes!("../../../tests/ed25519/ed25519-1.pk8.der");
+
+    fn signed_link() -> SignedMetadata<Json, LinkMetadata> {
+        let key = PrivateKey::from_pkcs8(ED25519_1_PK8, SignatureScheme::Ed25519).unwrap();
+        LinkMetadataBuilder::new()
+            .name("package".to_string())
+            .materials(record_artifacts(&["tests/test_runlib/release"], None).unwrap())
+            .signed::<Json>(&key)
+            .unwrap()
+    }
+
+    #[test]
+    fn valid_link_passes() {
+        assert_eq!(signed_link().validate_schema(), Ok(()));
+    }
+
+    #[test]
+    fn artifact_without_hashes_fails_with_pointer() {
+        let mut jsn = serde_json::to_value(signed_link()).unwrap();
+        jsn["signed"]["materials"]["tests/test_runlib/release/artifact.txt"] =
+            serde_json::json!({});
+        let malformed: SignedMetadata<Json, LinkMetadata> = serde_json::from_value(jsn).unwrap();
+
+        match malformed.validate_schema() {
+            Err(Error::Schema(violations)) => {
+                assert_eq!(violations.len(), 1);
+                assert_eq!(
+                    violations[0].pointer,
+                    "/signed/materials/tests~1test_runlib~1release~1artifact.txt"
+                );
+            }
+            result => panic!("Unexpected result {:?}", result),
+        }
+    }
+}
@@ -9,12 +9,16 @@ use log::{debug, warn};
 use serde::de::{Deserialize, DeserializeOwned, Deserializer, Error as DeserializeError};
 use serde::ser::{Serialize};
 use serde_derive::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::fmt::{self, Debug, Display};
+use std::fs::File;
+use std::io::{BufReader, Read};
 use std::marker::PhantomData;
+use std::path::Path;
 use std::str;
 
 use crate::crypto::{HashValue, KeyId, PrivateKey, PublicKey, Signature};
+use crate::diagnostics::{Diagnostic, DiagnosticCategory, Diagnostics};
 use crate::error::Error;
 use crate::interchange::DataInterchange;
 use crate::Result;
@@ -109,6 +113,21 @@ where
         Ok(self)
     }
 
+    /// Sign the metadata using the ephemeral `private_key`, attaching `cert_chain`, the PEM
+    /// certificates binding the key to an identity, leaf first. See `crypto::keyless`.
+    #[cfg(feature = "keyless")]
+    pub fn sign_with_cert_chain(
+        mut self,
+        private_key: &PrivateKey,
+        cert_chain: Vec<String>,
+    ) -> Result<Self> {
+        let sig = private_key
+            .sign(&self.metadata_bytes)?
+            .with_cert_chain(cert_chain);
+        let _ = self.signatures.insert(sig.key_id().clone(), sig);
+        Ok(self)
+    }
+
     /// Construct a new `SignedMetadata` using the included signatures, sorting the signatures by
     /// `KeyId`.
     pub fn build(self) -> SignedMetadata<D, M> {
@@ -171,6 +190,31 @@ where
         })
     }
 
+    /// Read signed metadata, such as a `.link` file written by the reference implementation, from
+    /// `rdr`. The payload must parse as `M`, but its signatures are not checked.
+    pub fn from_reader<R: Read>(rdr: R) -> Result<Self> {
+        let signed: Self = D::from_reader(rdr)?;
+        let _ensure_metadata_parses: M = D::deserialize(&signed.metadata)?;
+        Ok(signed)
+    }
+
+    /// Read signed metadata from the file at `path`, like `from_reader`.
+    ///
+    /// ```
+    /// # use in_toto::interchange::Json;
+    /// # use in_toto::models::{LinkMetadata, SignedMetadata};
+    /// let signed = SignedMetadata::<Json, LinkMetadata>::from_file(
+    ///     "tests/test_verifylib/links/write-code.fd7b7741.link",
+    /// )
+    /// .unwrap();
+    /// assert_eq!(signed.signatures().len(), 1);
+    /// ```
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path).map_err(|e| Error::from_io(&e, path))?;
+        Self::from_reader(BufReader::new(file))
+    }
+
     /// Serialize this metadata to canonical bytes suitable for serialization. Note that this
     /// method is only intended to serialize signed metadata generated by this crate, not to
     /// re-serialize metadata that was originally obtained from a remote source.
@@ -215,11 +259,41 @@ where
         Ok(())
     }
 
+    /// Sign the metadata with `private_key` as well, so that it can meet a threshold above one.
+    /// The signed payload is left untouched, keeping the existing signatures valid. A signature
+    /// already made with the same `KeyId` is replaced.
+    pub fn add_signature(&mut self, private_key: &PrivateKey) -> Result<()> {
+        let sig = private_key.sign(&D::canonicalize(&self.metadata)?)?;
+        match self
+            .signatures
+            .iter_mut()
+            .find(|s| s.key_id() == sig.key_id())
+        {
+            Some(existing) => *existing = sig,
+            None => self.signatures.push(sig),
+        }
+        Ok(())
+    }
+
     /// An immutable reference to the signatures.
     pub fn signatures(&self) -> &[Signature] {
         &self.signatures
     }
 
+    /// Rebuild the signed payload from the parsed metadata using the current serializer and
+    /// canonicalizer, and sign it afresh with `private_key`. Existing signatures are dropped.
+    ///
+    /// This is meant for migrating metadata signed by tools with older canonicalization rules.
+    /// It does not verify the existing signatures, so verify the metadata first. Along with the
+    /// re-signed metadata, returns whether the rebuilt payload canonicalizes differently from the
+    /// loaded one, in which case the old signatures were made over different bytes than the new
+    /// one.
+    pub fn recanonicalize_and_resign(&self, private_key: &PrivateKey) -> Result<(Self, bool)> {
+        let original_bytes = D::canonicalize(&self.metadata)?;
+        let resigned = Self::new(&self.assume_valid()?, private_key)?;
+        let changed = D::canonicalize(&resigned.metadata)? != original_bytes;
+        Ok((resigned, changed))
+    }
 
     /// Parse this metadata without verifying signatures.
     ///
@@ -228,13 +302,40 @@ where
         D::deserialize(&self.metadata)
     }
 
-    /// Verify this metadata.
+    /// The IDs of the keys in `keys` that produced a valid signature on this metadata, in
+    /// `KeyId` order.
+    ///
+    /// Unlike `verify`, this does not apply a threshold: it reports exactly who signed. Signatures
+    /// from keys missing from `keys` and signatures that fail to verify are skipped.
+    pub fn valid_signers(&self, keys: &BTreeMap<KeyId, PublicKey>) -> Result<Vec<KeyId>> {
+        let canonical_bytes = D::canonicalize(&self.metadata)?;
+        let signers = self
+            .signatures
+            .iter()
+            .filter(|sig| match keys.get(sig.key_id()) {
+                Some(key) => {
+                    key.key_id() == sig.key_id() && key.verify(&canonical_bytes, sig).is_ok()
+                }
+                None => false,
+            })
+            .map(|sig| sig.key_id().clone())
+            .collect::<BTreeSet<_>>();
+        Ok(signers.into_iter().collect())
+    }
+
+    /// Verify this metadata: the canonical form of the payload must carry good signatures from
+    /// at least `threshold` distinct keys in `authorized_keys`.
+    ///
+    /// If the threshold is not met, the error says why, checking in this order: a signature from
+    /// a revoked key gives `Error::RevokedKey`, a signature that does not verify gives
+    /// `Error::BadSignature`, a signature from a key outside `authorized_keys` gives
+    /// `Error::UnknownKeyId`, and otherwise `Error::ThresholdNotMet` is returned.
     ///
     /// ```
-    /// # use chrono::prelude::*;
-    /// # use in_toto::crypto::{PrivateKey, SignatureScheme, HashAlgorithm};
+    /// # use in_toto::crypto::{PrivateKey, SignatureScheme};
+    /// # use in_toto::error::Error;
     /// # use in_toto::interchange::Json;
-    /// # use in_toto::models::MetadataPath;
+    /// # use in_toto::models::{LinkMetadataBuilder, SignedMetadataBuilder};
     ///
     /// # fn main() {
     /// let key_1: &[u8] = include_bytes!("../../tests/ed25519/ed25519-1.pk8.der");
@@ -243,18 +344,65 @@ where
     /// let key_2: &[u8] = include_bytes!("../../tests/ed25519/ed25519-2.pk8.der");
     /// let key_2 = PrivateKey::from_pkcs8(&key_2, SignatureScheme::Ed25519).unwrap();
     ///
+    /// let link = LinkMetadataBuilder::new().name("build".into()).build().unwrap();
+    /// let signed = SignedMetadataBuilder::<Json, _>::from_metadata(&link)
+    ///     .unwrap()
+    ///     .sign(&key_1)
+    ///     .unwrap()
+    ///     .build();
     ///
+    /// assert!(signed.verify(1, vec![key_1.public()]).is_ok());
+    /// assert_eq!(
+    ///     signed.verify(2, vec![key_1.public(), key_2.public()]),
+    ///     Err(Error::ThresholdNotMet { valid: 1, threshold: 2 })
+    /// );
+    /// assert_eq!(
+    ///     signed.verify(1, vec![key_2.public()]),
+    ///     Err(Error::UnknownKeyId(key_1.public().key_id().clone()))
+    /// );
     /// # }
+    /// ```
     pub fn verify<'a, I>(&self, threshold: u32, authorized_keys: I) -> Result<M>
     where
         I: IntoIterator<Item = &'a PublicKey>,
     {
-        if self.signatures.is_empty() {
-            return Err(Error::VerificationFailure(
-                "The metadata was not signed with any authorized keys.".into(),
-            ));
-        }
+        self.verify_with_revoked_keys(threshold, authorized_keys, &HashSet::new())
+    }
+
+    /// Verify this metadata, ignoring signatures from any key in `revoked_keys`.
+    ///
+    /// Signatures from revoked keys never count toward `threshold`, even if the key is still
+    /// listed in `authorized_keys`. If the threshold is not met and at least one signature came
+    /// from a revoked key, `Error::RevokedKey` is returned.
+    pub fn verify_with_revoked_keys<'a, I>(
+        &self,
+        threshold: u32,
+        authorized_keys: I,
+        revoked_keys: &HashSet<KeyId>,
+    ) -> Result<M>
+    where
+        I: IntoIterator<Item = &'a PublicKey>,
+    {
+        self.verify_with_diagnostics(
+            threshold,
+            authorized_keys,
+            revoked_keys,
+            &mut Diagnostics::new(),
+        )
+    }
 
+    /// Verify this metadata like `verify_with_revoked_keys`, reporting ignored and rejected
+    /// signatures into `diagnostics`.
+    pub fn verify_with_diagnostics<'a, I>(
+        &self,
+        threshold: u32,
+        authorized_keys: I,
+        revoked_keys: &HashSet<KeyId>,
+        diagnostics: &mut Diagnostics,
+    ) -> Result<M>
+    where
+        I: IntoIterator<Item = &'a PublicKey>,
+    {
         if threshold < 1 {
             return Err(Error::VerificationFailure(
                 "Threshold must be strictly greater than zero".into(),
@@ -275,7 +423,19 @@ where
             .iter()
             .map(|sig| (sig.key_id(), sig))
             .collect::<HashMap<&KeyId, &Signature>>();
+        let mut revoked = Vec::new();
+        let mut bad = false;
+        let mut unknown = Vec::new();
         for (key_id, sig) in signatures {
+            if revoked_keys.contains(key_id) {
+                warn!("Ignoring signature from revoked key ID {:?}", key_id);
+                diagnostics.push(Diagnostic::new(
+                    DiagnosticCategory::RevokedKey,
+                    format!("Ignored signature from revoked key ID {:?}", key_id),
+                ));
+                revoked.push(key_id);
+                continue;
+            }
             match authorized_keys.get(key_id) {
                 Some(ref pub_key) => match pub_key.verify(&canonical_bytes, sig) {
                     Ok(()) => {
@@ -284,6 +444,11 @@ where
                     }
                     Err(e) => {
                         warn!("Bad signature from key ID {:?}: {:?}", pub_key.key_id(), e);
+                        bad = true;
+                        diagnostics.push(Diagnostic::new(
+                            DiagnosticCategory::BadSignature,
+                            format!("Bad signature from key ID {:?}: {}", pub_key.key_id(), e),
+                        ));
                     }
                 },
                 None => {
@@ -291,6 +456,11 @@ where
                         "Key ID {:?} was not found in the set of authorized keys.",
                         sig.key_id()
                     );
+                    diagnostics.push(Diagnostic::new(
+                        DiagnosticCategory::UnknownKey,
+                        format!("Signature from unauthorized key ID {:?}", sig.key_id()),
+                    ));
+                    unknown.push(key_id);
                 }
             }
             if signatures_needed == 0 {
@@ -298,17 +468,110 @@ where
             }
         }
         if signatures_needed > 0 {
-            return Err(Error::VerificationFailure(format!(
-                "Signature threshold not met: {}/{}",
-                threshold - signatures_needed,
-                threshold
-            )));
+            if let Some(key_id) = revoked.into_iter().min() {
+                return Err(Error::RevokedKey(key_id.clone()));
+            }
+            if bad {
+                return Err(Error::BadSignature);
+            }
+            if let Some(key_id) = unknown.into_iter().min() {
+                return Err(Error::UnknownKeyId(key_id.clone()));
+            }
+            return Err(Error::ThresholdNotMet {
+                valid: threshold - signatures_needed,
+                threshold,
+            });
         }
 
         // "assume" the metadata is valid because we just verified that it is.
         self.assume_valid()
     }
 }
+
+/// Independent groups of keys that must each approve a piece of metadata, such as a layout that
+/// needs sign-off from both a security team and a release team.
+///
+/// ```
+/// # use in_toto::crypto::{PrivateKey, SignatureScheme};
+/// # use in_toto::interchange::Json;
+/// # use in_toto::models::{LinkMetadataBuilder, RootTrustConfig, SignedMetadataBuilder};
+/// let security: &[u8] = include_bytes!("../../tests/ed25519/ed25519-1.pk8.der");
+/// let security = PrivateKey::from_pkcs8(&security, SignatureScheme::Ed25519).unwrap();
+/// let release: &[u8] = include_bytes!("../../tests/ed25519/ed25519-2.pk8.der");
+/// let release = PrivateKey::from_pkcs8(&release, SignatureScheme::Ed25519).unwrap();
+///
+/// let config = RootTrustConfig::new()
+///     .group(vec![security.public().clone()], 1)
+///     .group(vec![release.public().clone()], 1);
+///
+/// let link = LinkMetadataBuilder::new().name("build".into()).build().unwrap();
+/// let signed = SignedMetadataBuilder::<Json, _>::from_metadata(&link)
+///     .unwrap()
+///     .sign(&security)
+///     .unwrap()
+///     .sign(&release)
+///     .unwrap()
+///     .build();
+/// assert!(signed.verify_with_trust_config(&config).is_ok());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct RootTrustConfig {
+    groups: Vec<(Vec<PublicKey>, u32)>,
+}
+
+impl RootTrustConfig {
+    /// Create a configuration without any groups.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Require `threshold` signatures from `keys`, in addition to every other group.
+    pub fn group(mut self, keys: Vec<PublicKey>, threshold: u32) -> Self {
+        self.groups.push((keys, threshold));
+        self
+    }
+}
+
+impl<D, M> SignedMetadata<D, M>
+where
+    D: DataInterchange,
+    M: Metadata,
+{
+    /// Verify this metadata against every group in `config`. Each group's threshold must be met
+    /// by its own keys; a signature counts toward every group that lists its key.
+    pub fn verify_with_trust_config(&self, config: &RootTrustConfig) -> Result<M> {
+        if config.groups.is_empty() {
+            return Err(Error::VerificationFailure(
+                "The trust configuration has no key groups".into(),
+            ));
+        }
+
+        for (index, (keys, threshold)) in config.groups.iter().enumerate() {
+            self.verify(*threshold, keys).map_err(|e| {
+                Error::VerificationFailure(format!("Key group {} not satisfied: {}", index, e))
+            })?;
+        }
+        self.assume_valid()
+    }
+
+    /// Verify this metadata during a key rotation window: it is trusted if it satisfies either
+    /// `primary`, typically the new key set, or `fallback`, the key set being rotated out.
+    pub fn verify_with_rotation(
+        &self,
+        primary: &RootTrustConfig,
+        fallback: &RootTrustConfig,
+    ) -> Result<M> {
+        match self.verify_with_trust_config(primary) {
+            Ok(metadata) => Ok(metadata),
+            Err(primary_error) => self.verify_with_trust_config(fallback).map_err(|e| {
+                Error::VerificationFailure(format!(
+                    "Neither the primary nor the fallback keys are satisfied: {}; {}",
+                    primary_error, e
+                ))
+            }),
+        }
+    }
+}
 /// Wrapper for a path to metadata.
 ///
 /// Note: This should **not** contain the file extension. This is automatically added by the
@@ -402,3 +665,238 @@ impl TargetPath {
     }
 }
 
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::crypto::SignatureScheme;
+    use crate::interchange::Json;
+    use crate::models::{LinkMetadata, LinkMetadataBuilder};
+
+    const ED25519_1_PK8: &[u8] = include_bytes!("../../tests/ed25519/ed25519-1.pk8.der");
+    const ED25519_2_PK8: &[u8] = include_bytes!("../../tests/ed25519/ed25519-2.pk8.der");
+    const ED25519_3_PK8: &[u8] = include_bytes!("../../tests/ed25519/ed25519-3.pk8.der");
+
+    fn key(pk8: &[u8]) -> PrivateKey {
+        PrivateKey::from_pkcs8(pk8, SignatureScheme::Ed25519).unwrap()
+    }
+
+    fn signed_link(keys: &[&PrivateKey]) -> SignedMetadata<Json, LinkMetadata> {
+        let link = LinkMetadataBuilder::new()
+            .name("build".to_string())
+            .build()
+            .unwrap();
+        let mut builder = SignedMetadataBuilder::<Json, _>::from_metadata(&link).unwrap();
+        for key in keys {
+            builder = builder.sign(key).unwrap();
+        }
+        builder.build()
+    }
+
+    #[test]
+    fn valid_signers_skips_invalid_and_unknown_signatures() {
+        let (key_1, key_2, key_3) = (key(ED25519_1_PK8), key(ED25519_2_PK8), key(ED25519_3_PK8));
+        let signed = signed_link(&[&key_1, &key_2, &key_3]);
+
+        // Replace key 2's signature with one over different metadata.
+        let other = LinkMetadataBuilder::new()
+            .name("other".to_string())
+            .build()
+            .unwrap();
+        let forged = SignedMetadata::<Json, _>::new(&other, &key_2).unwrap();
+        let mut jsn = serde_json::to_value(&signed).unwrap();
+        let forged_sig = serde_json::to_value(&forged.signatures()[0]).unwrap();
+        for sig in jsn["signatures"].as_array_mut().unwrap() {
+            if sig["keyid"] == forged_sig["keyid"] {
+                *sig = forged_sig.clone();
+            }
+        }
+        let signed: SignedMetadata<Json, LinkMetadata> = serde_json::from_value(jsn).unwrap();
+
+        let keys = vec![&key_1, &key_2]
+            .into_iter()
+            .map(|k| (k.public().key_id().clone(), k.public().clone()))
+            .collect::<BTreeMap<_, _>>();
+        assert_eq!(
+            signed.valid_signers(&keys),
+            Ok(vec![key_1.public().key_id().clone()])
+        );
+    }
+
+    #[test]
+    fn add_signature_meets_threshold() {
+        let (key_1, key_2) = (key(ED25519_1_PK8), key(ED25519_2_PK8));
+        let mut signed = signed_link(&[&key_1]);
+        let payload = serde_json::to_value(&signed).unwrap()["signed"].clone();
+        let authorized = vec![key_1.public(), key_2.public()];
+        assert_eq!(
+            signed.verify(2, authorized.clone()),
+            Err(Error::ThresholdNotMet {
+                valid: 1,
+                threshold: 2
+            })
+        );
+
+        signed.add_signature(&key_2).unwrap();
+        signed.add_signature(&key_2).unwrap();
+        assert_eq!(serde_json::to_value(&signed).unwrap()["signed"], payload);
+        let key_ids = signed
+            .signatures()
+            .iter()
+            .map(|sig| sig.key_id())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            key_ids,
+            vec![key_1.public().key_id(), key_2.public().key_id()]
+        );
+        assert!(signed.verify(2, authorized).is_ok());
+        assert_eq!(
+            signed.verify(2, vec![key_1.public()]),
+            Err(Error::UnknownKeyId(key_2.public().key_id().clone()))
+        );
+    }
+
+    #[test]
+    fn verify_distinguishes_bad_signatures() {
+        let (key_1, key_2) = (key(ED25519_1_PK8), key(ED25519_2_PK8));
+        let mut jsn = serde_json::to_value(signed_link(&[&key_1, &key_2])).unwrap();
+        jsn["signed"]["environment"] = serde_json::json!({"tampered": "yes"});
+        let tampered: SignedMetadata<Json, LinkMetadata> = serde_json::from_value(jsn).unwrap();
+
+        assert_eq!(
+            tampered.verify(1, vec![key_1.public(), key_2.public()]),
+            Err(Error::BadSignature)
+        );
+        let unsigned = signed_link(&[]);
+        assert_eq!(
+            unsigned.verify(1, vec![key_1.public()]),
+            Err(Error::ThresholdNotMet {
+                valid: 0,
+                threshold: 1
+            })
+        );
+    }
+
+    #[test]
+    fn verify_with_trust_config_requires_every_group() {
+        let (key_1, key_2, key_3) = (key(ED25519_1_PK8), key(ED25519_2_PK8), key(ED25519_3_PK8));
+        let config = RootTrustConfig::new()
+            .group(vec![key_1.public().clone(), key_2.public().clone()], 1)
+            .group(vec![key_3.public().clone()], 1);
+
+        assert!(signed_link(&[&key_1, &key_2])
+            .verify_with_trust_config(&config)
+            .is_err());
+        assert!(signed_link(&[&key_2, &key_3])
+            .verify_with_trust_config(&config)
+            .is_ok());
+        assert!(signed_link(&[&key_1, &key_2, &key_3])
+            .verify_with_trust_config(&RootTrustConfig::new())
+            .is_err());
+    }
+
+    #[test]
+    fn verify_with_diagnostics_reports_unknown_keys() {
+        let (key_1, key_2) = (key(ED25519_1_PK8), key(ED25519_2_PK8));
+        let signed = signed_link(&[&key_1, &key_2]);
+
+        // An unmet threshold means every signature is looked at.
+        let mut diagnostics = Diagnostics::new();
+        assert!(signed
+            .verify_with_diagnostics(2, vec![key_1.public()], &HashSet::new(), &mut diagnostics)
+            .is_err());
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics.records()[0].category(),
+            DiagnosticCategory::UnknownKey
+        );
+    }
+
+    #[test]
+    fn verify_with_rotation_accepts_either_key_set() {
+        let (old, new, other) = (key(ED25519_1_PK8), key(ED25519_2_PK8), key(ED25519_3_PK8));
+        let primary = RootTrustConfig::new().group(vec![new.public().clone()], 1);
+        let fallback = RootTrustConfig::new().group(vec![old.public().clone()], 1);
+
+        for signers in &[vec![&new], vec![&old], vec![&old, &new]] {
+            assert!(signed_link(signers)
+                .verify_with_rotation(&primary, &fallback)
+                .is_ok());
+        }
+        assert!(signed_link(&[&other])
+            .verify_with_rotation(&primary, &fallback)
+            .is_err());
+    }
+
+    #[test]
+    fn recanonicalize_and_resign_unchanged() {
+        let (key_1, key_2) = (key(ED25519_1_PK8), key(ED25519_2_PK8));
+        let signed = signed_link(&[&key_1]);
+
+        let (resigned, changed) = signed.recanonicalize_and_resign(&key_2).unwrap();
+        assert!(!changed);
+        assert_eq!(
+            serde_json::to_value(&resigned).unwrap()["signed"],
+            serde_json::to_value(&signed).unwrap()["signed"]
+        );
+        assert_eq!(resigned.signatures().len(), 1);
+        assert!(resigned.verify(1, vec![key_2.public()]).is_ok());
+        assert!(resigned.verify(1, vec![key_1.public()]).is_err());
+    }
+
+    #[test]
+    fn recanonicalize_and_resign_changed() {
+        let (key_1, key_2) = (key(ED25519_1_PK8), key(ED25519_2_PK8));
+        // Earlier versions wrote the step name as the type and the environment as `env`.
+        let mut jsn = serde_json::to_value(signed_link(&[&key_1])).unwrap();
+        jsn["signed"] = serde_json::json!({
+            "_type": "build",
+            "byproducts": {},
+            "env": {},
+            "materials": {},
+            "products": {},
+        });
+        let legacy: SignedMetadata<Json, LinkMetadata> = serde_json::from_value(jsn).unwrap();
+
+        let (resigned, changed) = legacy.recanonicalize_and_resign(&key_2).unwrap();
+        assert!(changed);
+        let resigned_jsn = serde_json::to_value(&resigned).unwrap();
+        assert_eq!(resigned_jsn["signed"]["_type"], "link");
+        assert_eq!(resigned_jsn["signed"]["name"], "build");
+        assert!(resigned_jsn["signed"].get("env").is_none());
+        assert!(resigned.verify(1, vec![key_2.public()]).is_ok());
+    }
+
+    #[test]
+    fn verify_rejects_revoked_key() {
+        let key_1 = key(ED25519_1_PK8);
+        let signed = signed_link(&[&key_1]);
+        let revoked = vec![key_1.public().key_id().clone()]
+            .into_iter()
+            .collect::<HashSet<_>>();
+
+        assert!(signed.verify(1, vec![key_1.public()]).is_ok());
+        assert_eq!(
+            signed.verify_with_revoked_keys(1, vec![key_1.public()], &revoked),
+            Err(Error::RevokedKey(key_1.public().key_id().clone()))
+        );
+    }
+
+    #[test]
+    fn verify_counts_only_unrevoked_keys() {
+        let (key_1, key_2, key_3) = (key(ED25519_1_PK8), key(ED25519_2_PK8), key(ED25519_3_PK8));
+        let signed = signed_link(&[&key_1, &key_2, &key_3]);
+        let authorized = vec![key_1.public(), key_2.public(), key_3.public()];
+        let revoked = vec![key_2.public().key_id().clone()]
+            .into_iter()
+            .collect::<HashSet<_>>();
+
+        assert!(signed
+            .verify_with_revoked_keys(2, authorized.clone(), &revoked)
+            .is_ok());
+        assert_eq!(
+            signed.verify_with_revoked_keys(3, authorized, &revoked),
+            Err(Error::RevokedKey(key_2.public().key_id().clone()))
+        );
+    }
+}
@@ -4,8 +4,13 @@ mod link;
 mod metadata;
 mod layout;
 mod helpers;
+mod envelope;
+mod statement;
+pub mod slsa;
 
 pub use link::*;
 pub use metadata::*;
 pub use layout::*;
 pub use helpers::*;
+pub use envelope::*;
+pub use statement::*;
@@ -0,0 +1,782 @@
+//! SLSA provenance predicates, to be published in a `Statement`.
+
+use chrono::{DateTime, Utc};
+use data_encoding::BASE64;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashSet};
+use std::convert::TryFrom;
+
+use crate::error::Error;
+use crate::models::{Byproducts, LinkMetadata, Statement, Subject, TargetDescription};
+use crate::Result;
+
+/// The predicate type of `ProvenancePredicateV1`.
+pub const PREDICATE_TYPE_V1: &str = "https://slsa.dev/provenance/v1";
+
+/// The predicate type of `ProvenancePredicateV02`.
+pub const PREDICATE_TYPE_V02: &str = "https://slsa.dev/provenance/v0.2";
+
+fn is_null(value: &Value) -> bool {
+    value.is_null()
+}
+
+/// A reference to an artifact, such as a dependency or a log, by name, location and digest.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ResourceDescriptor {
+    /// The name of the artifact, e.g. its path.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Where the artifact can be found.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uri: Option<String>,
+    /// The digests of the artifact.
+    #[serde(default, skip_serializing_if = "TargetDescription::is_empty")]
+    pub digest: TargetDescription,
+    /// The contents of the artifact, base64 encoded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+}
+
+impl ResourceDescriptor {
+    /// The artifact recorded at `name` with the digests `digest`.
+    pub fn artifact(name: &str, digest: TargetDescription) -> Self {
+        ResourceDescriptor {
+            name: Some(name.to_string()),
+            digest,
+            ..Self::default()
+        }
+    }
+
+    /// The artifact `name` holding `content`.
+    pub fn content(name: &str, content: &[u8]) -> Self {
+        ResourceDescriptor {
+            name: Some(name.to_string()),
+            content: Some(BASE64.encode(content)),
+            ..Self::default()
+        }
+    }
+}
+
+/// The inputs of a build: what was asked for and what it resolved to.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildDefinition {
+    /// The URI of the template the build follows, which gives the parameters their meaning.
+    pub build_type: String,
+    /// The parameters under the control of whoever requested the build.
+    pub external_parameters: Value,
+    /// The parameters under the control of the builder.
+    #[serde(default, skip_serializing_if = "is_null")]
+    pub internal_parameters: Value,
+    /// The artifacts the build used, such as its sources.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub resolved_dependencies: Vec<ResourceDescriptor>,
+}
+
+/// The builder that ran a build.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Builder {
+    /// The URI identifying the builder.
+    pub id: String,
+    /// The versions of the components of the builder.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub version: BTreeMap<String, String>,
+    /// The artifacts the builder itself is made of.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub builder_dependencies: Vec<ResourceDescriptor>,
+}
+
+/// When and as which invocation a build ran.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildMetadata {
+    /// The identifier of the invocation, unique to the builder.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub invocation_id: Option<String>,
+    /// When the build started.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub started_on: Option<DateTime<Utc>>,
+    /// When the build finished.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finished_on: Option<DateTime<Utc>>,
+}
+
+impl BuildMetadata {
+    fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+/// How a build ran.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RunDetails {
+    /// The builder that ran the build.
+    pub builder: Builder,
+    /// When and as which invocation the build ran.
+    #[serde(default, skip_serializing_if = "BuildMetadata::is_empty")]
+    pub metadata: BuildMetadata,
+    /// What the build produced besides its outputs, such as logs.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub byproducts: Vec<ResourceDescriptor>,
+}
+
+/// A SLSA v1.0 provenance predicate, of type `PREDICATE_TYPE_V1`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProvenancePredicateV1 {
+    /// The inputs of the build.
+    pub build_definition: BuildDefinition,
+    /// How the build ran.
+    pub run_details: RunDetails,
+}
+
+impl ProvenancePredicateV1 {
+    /// A statement of this provenance about `subject`, the outputs of the build.
+    pub fn to_statement(&self, subject: Vec<Subject>) -> Result<Statement> {
+        to_statement(self, subject, PREDICATE_TYPE_V1)
+    }
+}
+
+fn to_statement<P: serde::Serialize>(
+    predicate: &P,
+    subject: Vec<Subject>,
+    predicate_type: &str,
+) -> Result<Statement> {
+    let predicate = serde_json::to_value(predicate)
+        .map_err(|e| Error::Encoding(format!("Provenance: {}", e)))?;
+    Ok(Statement::new(subject, predicate_type, predicate))
+}
+
+fn check_uri(what: &str, uri: &str) -> Result<()> {
+    if uri.is_empty() {
+        return Err(Error::IllegalArgument(format!(
+            "Provenance needs a {}",
+            what
+        )));
+    }
+    Ok(())
+}
+
+/// Helper to construct a `ProvenancePredicateV1`.
+///
+/// ```
+/// # use in_toto::models::slsa::ProvenanceBuilderV1;
+/// # use in_toto::models::LinkMetadataBuilder;
+/// # use serde_json::json;
+/// let link = LinkMetadataBuilder::new().name("build".into()).build().unwrap();
+/// let provenance = ProvenanceBuilderV1::new("https://example.com/make@v1", "https://example.com/ci")
+///     .external_parameters(json!({ "target": "release" }))
+///     .link(&link)
+///     .unwrap()
+///     .build()
+///     .unwrap();
+/// assert_eq!(provenance.run_details.builder.id, "https://example.com/ci");
+/// ```
+pub struct ProvenanceBuilderV1 {
+    predicate: ProvenancePredicateV1,
+}
+
+impl ProvenanceBuilderV1 {
+    /// Start building the provenance of a build of type `build_type` run by the builder
+    /// `builder_id`, without parameters.
+    pub fn new(build_type: &str, builder_id: &str) -> Self {
+        ProvenanceBuilderV1 {
+            predicate: ProvenancePredicateV1 {
+                build_definition: BuildDefinition {
+                    build_type: build_type.to_string(),
+                    external_parameters: Value::Object(Map::new()),
+                    internal_parameters: Value::Null,
+                    resolved_dependencies: Vec::new(),
+                },
+                run_details: RunDetails {
+                    builder: Builder {
+                        id: builder_id.to_string(),
+                        version: BTreeMap::new(),
+                        builder_dependencies: Vec::new(),
+                    },
+                    metadata: BuildMetadata::default(),
+                    byproducts: Vec::new(),
+                },
+            },
+        }
+    }
+
+    /// Set the parameters under the control of whoever requested the build.
+    pub fn external_parameters(mut self, parameters: Value) -> Self {
+        self.predicate.build_definition.external_parameters = parameters;
+        self
+    }
+
+    /// Set the parameters under the control of the builder.
+    pub fn internal_parameters(mut self, parameters: Value) -> Self {
+        self.predicate.build_definition.internal_parameters = parameters;
+        self
+    }
+
+    /// Add an artifact the build used.
+    pub fn add_resolved_dependency(mut self, dependency: ResourceDescriptor) -> Self {
+        self.predicate
+            .build_definition
+            .resolved_dependencies
+            .push(dependency);
+        self
+    }
+
+    /// Add an artifact the build produced besides its outputs.
+    pub fn add_byproduct(mut self, byproduct: ResourceDescriptor) -> Self {
+        self.predicate.run_details.byproducts.push(byproduct);
+        self
+    }
+
+    /// Set when and as which invocation the build ran.
+    pub fn metadata(mut self, metadata: BuildMetadata) -> Self {
+        self.predicate.run_details.metadata = metadata;
+        self
+    }
+
+    /// Fill in what the link of a step, e.g. one recorded by `runlib::in_toto_run`, says about
+    /// the build: its materials become resolved dependencies, and what its command wrote to
+    /// stdout and stderr, if anything, becomes byproducts.
+    pub fn link(mut self, link: &LinkMetadata) -> Result<Self> {
+        for (path, digest) in link.materials() {
+            self = self.add_resolved_dependency(ResourceDescriptor::artifact(
+                path.value(),
+                digest.clone(),
+            ));
+        }
+        let byproducts = Byproducts::try_from(link)?;
+        for (name, output) in &[("stdout", byproducts.stdout), ("stderr", byproducts.stderr)] {
+            if !output.as_bytes().is_empty() {
+                self = self.add_byproduct(ResourceDescriptor::content(name, output.as_bytes()));
+            }
+        }
+        Ok(self)
+    }
+
+    /// Construct the `ProvenancePredicateV1`. The build type and builder ID must not be empty.
+    pub fn build(self) -> Result<ProvenancePredicateV1> {
+        check_uri("build type", &self.predicate.build_definition.build_type)?;
+        check_uri("builder ID", &self.predicate.run_details.builder.id)?;
+        Ok(self.predicate)
+    }
+}
+
+/// The builder that ran a SLSA v0.2 build.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BuilderV02 {
+    /// The URI identifying the builder.
+    pub id: String,
+}
+
+/// Where the top-level build configuration of a SLSA v0.2 build came from.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfigSource {
+    /// Where the configuration was fetched from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uri: Option<String>,
+    /// The digests of the configuration.
+    #[serde(default, skip_serializing_if = "TargetDescription::is_empty")]
+    pub digest: TargetDescription,
+    /// The entry point of the build within the configuration.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub entry_point: Option<String>,
+}
+
+/// What started a SLSA v0.2 build.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Invocation {
+    /// Where the top-level build configuration came from.
+    pub config_source: ConfigSource,
+    /// The parameters of the build.
+    #[serde(default, skip_serializing_if = "is_null")]
+    pub parameters: Value,
+    /// The environment the build ran in.
+    #[serde(default, skip_serializing_if = "is_null")]
+    pub environment: Value,
+}
+
+/// Metadata about a SLSA v0.2 build.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildMetadataV02 {
+    /// The identifier of the invocation, unique to the builder.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub build_invocation_id: Option<String>,
+    /// When the build started.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub build_started_on: Option<DateTime<Utc>>,
+    /// When the build finished.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub build_finished_on: Option<DateTime<Utc>>,
+}
+
+impl BuildMetadataV02 {
+    fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+/// A material of a SLSA v0.2 build.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Material {
+    /// Where the material came from.
+    pub uri: String,
+    /// The digests of the material.
+    #[serde(default, skip_serializing_if = "TargetDescription::is_empty")]
+    pub digest: TargetDescription,
+}
+
+/// A SLSA v0.2 provenance predicate, of type `PREDICATE_TYPE_V02`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProvenancePredicateV02 {
+    /// The builder that ran the build.
+    pub builder: BuilderV02,
+    /// The URI of the template the build follows.
+    pub build_type: String,
+    /// What started the build.
+    #[serde(default)]
+    pub invocation: Invocation,
+    /// The steps of the build, in a form defined by the build type.
+    #[serde(default, skip_serializing_if = "is_null")]
+    pub build_config: Value,
+    /// Metadata about the build.
+    #[serde(default, skip_serializing_if = "BuildMetadataV02::is_empty")]
+    pub metadata: BuildMetadataV02,
+    /// The artifacts the build used.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub materials: Vec<Material>,
+}
+
+impl ProvenancePredicateV02 {
+    /// A statement of this provenance about `subject`, the outputs of the build.
+    pub fn to_statement(&self, subject: Vec<Subject>) -> Result<Statement> {
+        to_statement(self, subject, PREDICATE_TYPE_V02)
+    }
+}
+
+/// Helper to construct a `ProvenancePredicateV02`.
+pub struct ProvenanceBuilderV02 {
+    predicate: ProvenancePredicateV02,
+}
+
+impl ProvenanceBuilderV02 {
+    /// Start building the provenance of a build of type `build_type` run by the builder
+    /// `builder_id`.
+    pub fn new(build_type: &str, builder_id: &str) -> Self {
+        ProvenanceBuilderV02 {
+            predicate: ProvenancePredicateV02 {
+                builder: BuilderV02 {
+                    id: builder_id.to_string(),
+                },
+                build_type: build_type.to_string(),
+                invocation: Invocation::default(),
+                build_config: Value::Null,
+                metadata: BuildMetadataV02::default(),
+                materials: Vec::new(),
+            },
+        }
+    }
+
+    /// Set what started the build.
+    pub fn invocation(mut self, invocation: Invocation) -> Self {
+        self.predicate.invocation = invocation;
+        self
+    }
+
+    /// Set the steps of the build.
+    pub fn build_config(mut self, build_config: Value) -> Self {
+        self.predicate.build_config = build_config;
+        self
+    }
+
+    /// Set metadata about the build.
+    pub fn metadata(mut self, metadata: BuildMetadataV02) -> Self {
+        self.predicate.metadata = metadata;
+        self
+    }
+
+    /// Add an artifact the build used.
+    pub fn add_material(mut self, material: Material) -> Self {
+        self.predicate.materials.push(material);
+        self
+    }
+
+    /// Fill in what the link of a step, e.g. one recorded by `runlib::in_toto_run`, says about
+    /// the build: its materials, and the environment it recorded as the invocation's
+    /// environment.
+    pub fn link(mut self, link: &LinkMetadata) -> Self {
+        for (path, digest) in link.materials() {
+            self = self.add_material(Material {
+                uri: path.value().to_string(),
+                digest: digest.clone(),
+            });
+        }
+        if !link.env().is_empty() {
+            self.predicate.invocation.environment = link
+                .env()
+                .iter()
+                .map(|(name, value)| (name.clone(), Value::String(value.clone())))
+                .collect::<Map<_, _>>()
+                .into();
+        }
+        self
+    }
+
+    /// Construct the `ProvenancePredicateV02`. The build type and builder ID must not be empty.
+    pub fn build(self) -> Result<ProvenancePredicateV02> {
+        check_uri("build type", &self.predicate.build_type)?;
+        check_uri("builder ID", &self.predicate.builder.id)?;
+        Ok(self.predicate)
+    }
+}
+
+/// A SLSA build level, as checked by `check_build_level`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SlsaLevel {
+    /// Provenance exists: it names the builder and build type and has subjects.
+    L1,
+    /// The provenance was generated by a hosted build platform.
+    L2,
+    /// The provenance was generated by a hardened build platform.
+    L3,
+}
+
+/// The highest `SlsaLevel` the SLSA v1.0 provenance in `statement` meets, or an error if it
+/// does not meet `required_level`.
+///
+/// Only what the provenance says about itself can be checked, and only the level 1 checks are
+/// strict:
+///
+/// * Level 1 (strict): the predicate is of type `PREDICATE_TYPE_V1` and decodes as a
+///   `ProvenancePredicateV1` with a build type and builder ID, and every subject has a digest.
+/// * Level 2 (heuristic): the builder ID is an `https://` URI, i.e. it names a hosted platform
+///   rather than a workstation. Level 2 also requires signed provenance, which is not checked
+///   here: verify the envelope the statement came in, e.g. with `DsseEnvelope::verify`, first.
+/// * Level 3 (heuristic): the build metadata has an invocation ID and a start and finish time,
+///   in that order, and every resolved dependency is pinned by a digest. This is what a platform
+///   generating provenance on its own records; whether builds are isolated from each other
+///   cannot be seen in provenance at all.
+///
+/// ```
+/// # use in_toto::models::slsa::{check_build_level, ProvenanceBuilderV1, SlsaLevel};
+/// # use in_toto::models::Subject;
+/// # use in_toto::crypto::{HashAlgorithm, HashValue};
+/// # use std::collections::HashMap;
+/// let mut digest = HashMap::new();
+/// let _ = digest.insert(HashAlgorithm::Sha256, HashValue::new(vec![0; 32]));
+/// let statement = ProvenanceBuilderV1::new("https://example.com/make@v1", "https://ci.example")
+///     .build()
+///     .unwrap()
+///     .to_statement(vec![Subject::new("app", digest)])
+///     .unwrap();
+/// assert_eq!(check_build_level(&statement, SlsaLevel::L2).unwrap(), SlsaLevel::L2);
+/// assert!(check_build_level(&statement, SlsaLevel::L3).is_err());
+/// ```
+pub fn check_build_level(statement: &Statement, required_level: SlsaLevel) -> Result<SlsaLevel> {
+    let level = build_level(statement)?;
+    if level < required_level {
+        return Err(Error::VerificationFailure(format!(
+            "Provenance meets SLSA build {:?}, {:?} is required",
+            level, required_level
+        )));
+    }
+    Ok(level)
+}
+
+fn build_level(statement: &Statement) -> Result<SlsaLevel> {
+    if statement.predicate_type() != PREDICATE_TYPE_V1 {
+        return Err(Error::VerificationFailure(format!(
+            "Expected a {} predicate, found {}",
+            PREDICATE_TYPE_V1,
+            statement.predicate_type()
+        )));
+    }
+    let predicate: ProvenancePredicateV1 = serde_json::from_value(statement.predicate().clone())
+        .map_err(|e| Error::VerificationFailure(format!("Invalid provenance: {}", e)))?;
+    check_uri("build type", &predicate.build_definition.build_type)?;
+    check_uri("builder ID", &predicate.run_details.builder.id)?;
+    if statement.subject().is_empty() {
+        return Err(Error::VerificationFailure(
+            "Provenance has no subjects".into(),
+        ));
+    }
+    if let Some(subject) = statement.subject().iter().find(|s| s.digest().is_empty()) {
+        return Err(Error::VerificationFailure(format!(
+            "Subject {} has no digest",
+            subject.name()
+        )));
+    }
+
+    if !predicate.run_details.builder.id.starts_with("https://") {
+        return Ok(SlsaLevel::L1);
+    }
+
+    let metadata = &predicate.run_details.metadata;
+    let timed = match (metadata.started_on, metadata.finished_on) {
+        (Some(started_on), Some(finished_on)) => started_on <= finished_on,
+        _ => false,
+    };
+    let pinned = predicate
+        .build_definition
+        .resolved_dependencies
+        .iter()
+        .all(|dependency| !dependency.digest.is_empty());
+    if metadata.invocation_id.is_none() || !timed || !pinned {
+        return Ok(SlsaLevel::L2);
+    }
+    Ok(SlsaLevel::L3)
+}
+
+/// Check that the SLSA provenance in `statement`, of type `PREDICATE_TYPE_V1` or
+/// `PREDICATE_TYPE_V02`, was generated by one of `trusted_builders`, identified by their builder
+/// IDs. Fails with `Error::UntrustedBuilder` otherwise.
+///
+/// Like `check_build_level`, this only checks what the provenance claims; verify the envelope
+/// the statement came in first.
+///
+/// ```
+/// # use in_toto::models::slsa::{check_trusted_builder, ProvenanceBuilderV1};
+/// # use std::collections::HashSet;
+/// let statement = ProvenanceBuilderV1::new("https://example.com/make@v1", "https://ci.example")
+///     .build()
+///     .unwrap()
+///     .to_statement(Vec::new())
+///     .unwrap();
+/// let trusted: HashSet<String> = vec!["https://ci.example".to_string()].into_iter().collect();
+/// check_trusted_builder(&statement, &trusted).unwrap();
+/// ```
+pub fn check_trusted_builder(
+    statement: &Statement,
+    trusted_builders: &HashSet<String>,
+) -> Result<()> {
+    let predicate = statement.predicate().clone();
+    let decode_error =
+        |e: serde_json::Error| Error::VerificationFailure(format!("Invalid provenance: {}", e));
+    let builder_id = match statement.predicate_type() {
+        PREDICATE_TYPE_V1 => {
+            serde_json::from_value::<ProvenancePredicateV1>(predicate)
+                .map_err(decode_error)?
+                .run_details
+                .builder
+                .id
+        }
+        PREDICATE_TYPE_V02 => {
+            serde_json::from_value::<ProvenancePredicateV02>(predicate)
+                .map_err(decode_error)?
+                .builder
+                .id
+        }
+        other => {
+            return Err(Error::VerificationFailure(format!(
+                "Expected a SLSA provenance predicate, found {}",
+                other
+            )))
+        }
+    };
+    if !trusted_builders.contains(&builder_id) {
+        return Err(Error::UntrustedBuilder(builder_id));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::crypto::{PrivateKey, SignatureScheme};
+    use crate::runlib::{in_toto_run_with_options, RunOptions};
+    use chrono::TimeZone;
+    use serde_json::json;
+
+    const ED25519_1_PK8: &[u8] = include_bytes!("../../tests/ed25519/ed25519-1.pk8.der");
+    const ARTIFACT_SHA256: &str =
+        "2459cfc17228ee4883b0a5516980c0c12d2220f4da0e08a608f31bdeae59f92c";
+
+    fn link() -> LinkMetadata {
+        let key = PrivateKey::from_pkcs8(ED25519_1_PK8, SignatureScheme::Ed25519).unwrap();
+        let mut env = BTreeMap::new();
+        let _ = env.insert("CC".to_string(), "clang".to_string());
+        in_toto_run_with_options(
+            "build",
+            &["tests/test_runlib/release"],
+            &[],
+            &["echo", "built"],
+            &key,
+            None,
+            &RunOptions::new().env(env),
+        )
+        .unwrap()
+        .verify(1, vec![key.public()])
+        .unwrap()
+    }
+
+    #[test]
+    fn provenance_v1_from_link() {
+        let started_on = Utc.with_ymd_and_hms(2024, 1, 2, 3, 4, 5).unwrap();
+        let provenance =
+            ProvenanceBuilderV1::new("https://example.com/make@v1", "https://example.com/ci")
+                .external_parameters(json!({ "target": "release" }))
+                .metadata(BuildMetadata {
+                    invocation_id: Some("42".into()),
+                    started_on: Some(started_on),
+                    finished_on: None,
+                })
+                .link(&link())
+                .unwrap()
+                .build()
+                .unwrap();
+
+        let expected = json!({
+            "buildDefinition": {
+                "buildType": "https://example.com/make@v1",
+                "externalParameters": { "target": "release" },
+                "resolvedDependencies": [{
+                    "name": "tests/test_runlib/release/artifact.txt",
+                    "digest": { "sha256": ARTIFACT_SHA256 },
+                }],
+            },
+            "runDetails": {
+                "builder": { "id": "https://example.com/ci" },
+                "metadata": { "invocationId": "42", "startedOn": "2024-01-02T03:04:05Z" },
+                "byproducts": [{ "name": "stdout", "content": "YnVpbHQK" }],
+            },
+        });
+        assert_eq!(serde_json::to_value(&provenance).unwrap(), expected);
+        let decoded: ProvenancePredicateV1 = serde_json::from_value(expected).unwrap();
+        assert_eq!(decoded, provenance);
+
+        let statement = provenance.to_statement(Vec::new()).unwrap();
+        assert_eq!(statement.predicate_type(), PREDICATE_TYPE_V1);
+
+        assert!(ProvenanceBuilderV1::new("", "https://example.com/ci")
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn check_build_level_of_each_level() {
+        let subject = || Subject::from_artifacts(link().materials());
+        let statement = |builder_id: &str, metadata: BuildMetadata| {
+            ProvenanceBuilderV1::new("https://example.com/make@v1", builder_id)
+                .metadata(metadata)
+                .link(&link())
+                .unwrap()
+                .build()
+                .unwrap()
+                .to_statement(subject())
+                .unwrap()
+        };
+        let started_on = Utc.with_ymd_and_hms(2024, 1, 2, 3, 4, 5).unwrap();
+        let hardened = BuildMetadata {
+            invocation_id: Some("42".into()),
+            started_on: Some(started_on),
+            finished_on: Some(started_on + chrono::Duration::minutes(1)),
+        };
+
+        let l1 = statement("local:alice-laptop", hardened.clone());
+        assert_eq!(
+            check_build_level(&l1, SlsaLevel::L1).unwrap(),
+            SlsaLevel::L1
+        );
+        assert!(check_build_level(&l1, SlsaLevel::L2).is_err());
+
+        let l2 = statement("https://example.com/ci", BuildMetadata::default());
+        assert_eq!(
+            check_build_level(&l2, SlsaLevel::L2).unwrap(),
+            SlsaLevel::L2
+        );
+        assert!(check_build_level(&l2, SlsaLevel::L3).is_err());
+
+        let l3 = statement("https://example.com/ci", hardened.clone());
+        assert_eq!(
+            check_build_level(&l3, SlsaLevel::L3).unwrap(),
+            SlsaLevel::L3
+        );
+        assert_eq!(
+            check_build_level(&l3, SlsaLevel::L1).unwrap(),
+            SlsaLevel::L3
+        );
+
+        let unpinned = ProvenanceBuilderV1::new("https://example.com/make@v1", "https://ci")
+            .metadata(hardened)
+            .add_resolved_dependency(ResourceDescriptor {
+                uri: Some("git+https://example.com/repo".into()),
+                ..ResourceDescriptor::default()
+            })
+            .build()
+            .unwrap()
+            .to_statement(subject())
+            .unwrap();
+        assert_eq!(
+            check_build_level(&unpinned, SlsaLevel::L1).unwrap(),
+            SlsaLevel::L2
+        );
+
+        let no_subjects = l1.predicate().clone();
+        let no_subjects = Statement::new(Vec::new(), PREDICATE_TYPE_V1, no_subjects);
+        assert!(check_build_level(&no_subjects, SlsaLevel::L1).is_err());
+        let v02 = Statement::new(subject(), PREDICATE_TYPE_V02, l1.predicate().clone());
+        assert!(check_build_level(&v02, SlsaLevel::L1).is_err());
+    }
+
+    #[test]
+    fn check_trusted_builder_by_id() {
+        let trusted: HashSet<String> = vec!["https://example.com/ci".to_string()]
+            .into_iter()
+            .collect();
+        let v1 = |builder_id: &str| {
+            ProvenanceBuilderV1::new("https://example.com/make@v1", builder_id)
+                .build()
+                .unwrap()
+                .to_statement(Vec::new())
+                .unwrap()
+        };
+
+        check_trusted_builder(&v1("https://example.com/ci"), &trusted).unwrap();
+        match check_trusted_builder(&v1("https://example.com/fork-ci"), &trusted) {
+            Err(Error::UntrustedBuilder(id)) => assert_eq!(id, "https://example.com/fork-ci"),
+            other => panic!("expected an untrusted builder, got {:?}", other),
+        }
+
+        let v02 =
+            ProvenanceBuilderV02::new("https://example.com/make@v1", "https://example.com/ci")
+                .build()
+                .unwrap()
+                .to_statement(Vec::new())
+                .unwrap();
+        check_trusted_builder(&v02, &trusted).unwrap();
+        assert!(check_trusted_builder(&v02, &HashSet::new()).is_err());
+    }
+
+    #[test]
+    fn provenance_v02_from_link() {
+        let provenance =
+            ProvenanceBuilderV02::new("https://example.com/make@v1", "https://example.com/ci")
+                .link(&link())
+                .build()
+                .unwrap();
+
+        assert_eq!(
+            serde_json::to_value(&provenance).unwrap(),
+            json!({
+                "builder": { "id": "https://example.com/ci" },
+                "buildType": "https://example.com/make@v1",
+                "invocation": {
+                    "configSource": {},
+                    "environment": { "CC": "clang" },
+                },
+                "materials": [{
+                    "uri": "tests/test_runlib/release/artifact.txt",
+                    "digest": { "sha256": ARTIFACT_SHA256 },
+                }],
+            })
+        );
+        let statement = provenance.to_statement(Vec::new()).unwrap();
+        assert_eq!(statement.predicate_type(), PREDICATE_TYPE_V02);
+    }
+}
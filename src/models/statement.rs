@@ -0,0 +1,196 @@
+//! in-toto Attestation Framework statements.
+
+use serde::de::{Deserialize, Deserializer, Error as DeserializeError};
+use serde::ser::{Serialize, Serializer};
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+use crate::models::{TargetDescription, VirtualTargetPath};
+
+/// The `_type` of version 1 statements.
+pub const STATEMENT_V1_TYPE: &str = "https://in-toto.io/Statement/v1";
+
+/// An artifact a statement is about.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Subject {
+    name: String,
+    digest: TargetDescription,
+}
+
+impl Subject {
+    /// The subject `name` with the digests in `digest`.
+    pub fn new(name: &str, digest: TargetDescription) -> Self {
+        Subject {
+            name: name.to_string(),
+            digest,
+        }
+    }
+
+    /// One subject per artifact, e.g. the products recorded by `runlib::record_artifacts`, in
+    /// path order.
+    pub fn from_artifacts(artifacts: &BTreeMap<VirtualTargetPath, TargetDescription>) -> Vec<Self> {
+        artifacts
+            .iter()
+            .map(|(path, digest)| Subject::new(path.value(), digest.clone()))
+            .collect()
+    }
+
+    /// The name of the artifact.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The digests of the artifact.
+    pub fn digest(&self) -> &TargetDescription {
+        &self.digest
+    }
+}
+
+/// An ITE-6 statement: a typed `predicate` about a list of subjects.
+///
+/// ```
+/// # use in_toto::models::{Statement, Subject};
+/// # use serde_json::json;
+/// let statement = Statement::new(
+///     Vec::new(),
+///     "https://example.com/TestResult/v1",
+///     json!({ "passed": true }),
+/// );
+/// assert_eq!(
+///     serde_json::to_value(&statement).unwrap()["_type"],
+///     "https://in-toto.io/Statement/v1"
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Statement {
+    subject: Vec<Subject>,
+    predicate_type: String,
+    predicate: Value,
+}
+
+impl Statement {
+    /// A statement of type `predicate_type` about `subject`.
+    pub fn new(subject: Vec<Subject>, predicate_type: &str, predicate: Value) -> Self {
+        Statement {
+            subject,
+            predicate_type: predicate_type.to_string(),
+            predicate,
+        }
+    }
+
+    /// The artifacts the statement is about.
+    pub fn subject(&self) -> &[Subject] {
+        &self.subject
+    }
+
+    /// The URI identifying the type of the predicate.
+    pub fn predicate_type(&self) -> &str {
+        &self.predicate_type
+    }
+
+    /// The predicate, whose shape is given by its type.
+    pub fn predicate(&self) -> &Value {
+        &self.predicate
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct StatementJson {
+    #[serde(rename = "_type")]
+    typ: String,
+    subject: Vec<Subject>,
+    #[serde(rename = "predicateType")]
+    predicate_type: String,
+    #[serde(default)]
+    predicate: Value,
+}
+
+impl Serialize for Statement {
+    fn serialize<S>(&self, ser: S) -> ::std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        StatementJson {
+            typ: STATEMENT_V1_TYPE.into(),
+            subject: self.subject.clone(),
+            predicate_type: self.predicate_type.clone(),
+            predicate: self.predicate.clone(),
+        }
+        .serialize(ser)
+    }
+}
+
+impl<'de> Deserialize<'de> for Statement {
+    fn deserialize<D: Deserializer<'de>>(de: D) -> ::std::result::Result<Self, D::Error> {
+        let intermediate: StatementJson = Deserialize::deserialize(de)?;
+        if intermediate.typ != STATEMENT_V1_TYPE {
+            return Err(DeserializeError::custom(format!(
+                "Expected a v1 statement, found {:?}",
+                intermediate.typ
+            )));
+        }
+        Ok(Statement {
+            subject: intermediate.subject,
+            predicate_type: intermediate.predicate_type,
+            predicate: intermediate.predicate,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::crypto::{PrivateKey, SignatureScheme};
+    use crate::models::{DsseEnvelope, PAYLOAD_TYPE_IN_TOTO};
+    use crate::runlib::record_artifacts;
+    use serde_json::json;
+
+    const ED25519_1_PK8: &[u8] = include_bytes!("../../tests/ed25519/ed25519-1.pk8.der");
+
+    #[test]
+    fn slsa_provenance_statement() {
+        let products = record_artifacts(&["tests/test_runlib/release"], None).unwrap();
+        let predicate = json!({
+            "buildDefinition": {
+                "buildType": "https://example.com/Makefile@v1",
+                "externalParameters": { "target": "release" },
+            },
+            "runDetails": {
+                "builder": { "id": "https://example.com/builder" },
+            },
+        });
+        let statement = Statement::new(
+            Subject::from_artifacts(&products),
+            "https://slsa.dev/provenance/v1",
+            predicate.clone(),
+        );
+
+        assert_eq!(
+            serde_json::to_value(&statement).unwrap(),
+            json!({
+                "_type": "https://in-toto.io/Statement/v1",
+                "subject": [{
+                    "name": "tests/test_runlib/release/artifact.txt",
+                    "digest": {
+                        "sha256":
+                            "2459cfc17228ee4883b0a5516980c0c12d2220f4da0e08a608f31bdeae59f92c",
+                    },
+                }],
+                "predicateType": "https://slsa.dev/provenance/v1",
+                "predicate": predicate,
+            })
+        );
+
+        let key = PrivateKey::from_pkcs8(ED25519_1_PK8, SignatureScheme::Ed25519).unwrap();
+        let envelope = DsseEnvelope::from_statement(&statement, &key).unwrap();
+        assert_eq!(envelope.payload_type(), PAYLOAD_TYPE_IN_TOTO);
+        let payload = envelope.verify(1, vec![key.public()]).unwrap();
+        let decoded: Statement = serde_json::from_slice(payload).unwrap();
+        assert_eq!(decoded, statement);
+
+        let mut v01 = serde_json::to_value(&statement).unwrap();
+        v01["_type"] = json!("https://in-toto.io/Statement/v0.1");
+        assert!(serde_json::from_value::<Statement>(v01).is_err());
+    }
+}
@@ -1 +1,2503 @@
 //! A tool that functionaries can use to create link metadata about a step.
+
+use log::warn;
+use parking_lot::Mutex;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::mem;
+use std::path::{self, Component, Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, ExitStatus, Stdio};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use wait_timeout::ChildExt;
+use walkdir::WalkDir;
+
+use crate::crypto::{self, HashAlgorithm, PrivateKey};
+use crate::diagnostics::{Diagnostic, DiagnosticCategory, Diagnostics};
+use crate::error::Error;
+use crate::interchange::Json;
+use crate::models::{
+    Byproducts, CommandOutput, DsseEnvelope, Environment, LinkMetadata, LinkMetadataBuilder,
+    SignedMetadata, TargetDescription, VirtualTargetPath,
+};
+use crate::Result;
+
+mod bundle;
+mod cache;
+mod file_metadata;
+#[cfg(feature = "git")]
+pub mod git;
+mod ignore;
+mod line_endings;
+#[cfg(feature = "oci")]
+pub mod oci;
+mod record;
+#[cfg(feature = "tokio")]
+mod run_async;
+#[cfg(feature = "sniff")]
+mod sniff;
+#[cfg(feature = "watch")]
+mod watch;
+
+pub use bundle::Bundle;
+pub use cache::HashCache;
+use file_metadata::read_file_metadata;
+pub use file_metadata::{FileMetadata, FileMetadataMap, LinkFileMetadata, FILE_METADATA_KEY};
+use ignore::{ExcludeSet, IgnoreStack};
+pub use record::{in_toto_record_start, in_toto_record_stop, unfinished_link_path};
+#[cfg(feature = "tokio")]
+pub use run_async::{in_toto_run_async, record_artifacts_async};
+#[cfg(feature = "sniff")]
+pub use sniff::{sniff_content_types, CONTENT_TYPES_BYPRODUCT};
+#[cfg(feature = "watch")]
+pub use watch::{record_changes_during, record_changes_during_with_options, WatchOptions};
+
+/// Options controlling how `record_artifacts_with_options` walks and records artifacts.
+///
+/// ```
+/// # use in_toto::runlib::{record_artifacts_with_options, RecordOptions};
+/// let options = RecordOptions::new().honor_intoto_ignore(true);
+/// let materials = record_artifacts_with_options(&["tests/test_runlib"], None, &options).unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct RecordOptions {
+    honor_intoto_ignore: bool,
+    hash_cache: Option<HashCache>,
+    exclude_patterns: Vec<String>,
+    lstrip_paths: Vec<String>,
+    follow_symlinks: bool,
+    max_depth: Option<usize>,
+    skip_hidden: bool,
+    normalize_line_endings: bool,
+    binary_patterns: Vec<String>,
+    buffer_size: usize,
+    #[cfg(feature = "mmap")]
+    mmap_threshold: Option<u64>,
+    #[cfg(feature = "rayon")]
+    parallel: bool,
+}
+
+impl Default for RecordOptions {
+    fn default() -> Self {
+        RecordOptions {
+            honor_intoto_ignore: false,
+            hash_cache: None,
+            exclude_patterns: Vec::new(),
+            lstrip_paths: Vec::new(),
+            follow_symlinks: true,
+            max_depth: None,
+            skip_hidden: false,
+            normalize_line_endings: false,
+            binary_patterns: Vec::new(),
+            buffer_size: crypto::DEFAULT_HASH_BUFFER_SIZE,
+            #[cfg(feature = "mmap")]
+            mmap_threshold: Some(crypto::DEFAULT_MMAP_THRESHOLD),
+            #[cfg(feature = "rayon")]
+            parallel: false,
+        }
+    }
+}
+
+impl RecordOptions {
+    /// Create options matching the behavior of `record_artifacts`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Exclude the files and directories matched by `.in-toto-ignore` files found in the walked
+    /// directories. Each ignore file applies to the directory it is in and everything below it.
+    /// Disabled by default.
+    pub fn honor_intoto_ignore(mut self, honor: bool) -> Self {
+        self.honor_intoto_ignore = honor;
+        self
+    }
+
+    /// Skip files and directories matching any of `patterns`, which use the syntax of
+    /// `.in-toto-ignore` files and are matched against the cleaned path of each entry, before
+    /// `lstrip_paths` applies. The whole subtree of a matching directory is skipped.
+    pub fn exclude_patterns(mut self, patterns: &[&str]) -> Self {
+        self.exclude_patterns = patterns.iter().map(|p| p.to_string()).collect();
+        self
+    }
+
+    /// Strip the first of `prefixes` that a recorded path starts with, along with any `/` left
+    /// at its start, so that recorded paths do not depend on the build directory. Prefixes are
+    /// compared as strings against the cleaned path. Recording fails if two different files
+    /// would be recorded under the same stripped path.
+    pub fn lstrip_paths(mut self, prefixes: &[&str]) -> Self {
+        self.lstrip_paths = prefixes.iter().map(|p| p.to_string()).collect();
+        self
+    }
+
+    /// Whether to follow symbolic links, which is the default. When disabled, a symbolic link is
+    /// recorded as itself: its hashes are computed over its target path, as returned by
+    /// `readlink`, rather than over the content it points to, and links to directories are not
+    /// descended into. Symbolic links given as the paths to record are still followed.
+    pub fn follow_symlinks(mut self, follow: bool) -> Self {
+        self.follow_symlinks = follow;
+        self
+    }
+
+    /// Only descend `max_depth` levels below each of the paths to record, or without limit if
+    /// `None`, the default. The paths themselves are at depth 0, so `Some(1)` records only the
+    /// files directly inside a directory. Directories reached through a followed symbolic link
+    /// count towards the depth like any other.
+    pub fn max_depth(mut self, max_depth: Option<usize>) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Skip files and directories whose name starts with a `.`, along with the whole subtree of
+    /// such directories. Disabled by default. Only the name of an entry counts: a symbolic link
+    /// named `.config` is skipped, while a visible link to a hidden file is followed and
+    /// recorded under its own name. The paths to record are never skipped themselves.
+    pub fn skip_hidden(mut self, skip: bool) -> Self {
+        self.skip_hidden = skip;
+        self
+    }
+
+    /// Replace `\r\n` and lone `\r` line endings of text files by `\n` before hashing them, like
+    /// `--normalize-line-endings` of the reference implementation, so that the same source
+    /// checked out on Windows and on Linux records the same hashes. Disabled by default.
+    ///
+    /// A file is treated as binary, and hashed as is, if a NUL byte occurs in its first 8000
+    /// bytes or it matches one of `binary_patterns`. The hash cache is not used while enabled.
+    pub fn normalize_line_endings(mut self, normalize: bool) -> Self {
+        self.normalize_line_endings = normalize;
+        self
+    }
+
+    /// Always treat files matching any of `patterns` as binary when normalizing line endings.
+    /// Patterns use the syntax of `.in-toto-ignore` files and are matched against the cleaned
+    /// path of each file, before `lstrip_paths` applies.
+    pub fn binary_patterns(mut self, patterns: &[&str]) -> Self {
+        self.binary_patterns = patterns.iter().map(|p| p.to_string()).collect();
+        self
+    }
+
+    /// Hash files on the rayon thread pool once the walk is complete. The recorded artifacts,
+    /// and the order events are reported in, do not depend on thread scheduling.
+    #[cfg(feature = "rayon")]
+    pub fn parallel(mut self, parallel: bool) -> Self {
+        self.parallel = parallel;
+        self
+    }
+
+    /// Read files through a buffer of `buffer_size` bytes while hashing them, instead of
+    /// `crypto::DEFAULT_HASH_BUFFER_SIZE`. Larger buffers mean fewer reads on large files; memory
+    /// use per file stays bounded by the buffer either way.
+    pub fn buffer_size(mut self, buffer_size: usize) -> Self {
+        self.buffer_size = buffer_size;
+        self
+    }
+
+    /// Memory-map files of at least `threshold` bytes to hash them, instead of reading them
+    /// through the buffer, or never if `None`. Defaults to `crypto::DEFAULT_MMAP_THRESHOLD`. The
+    /// recorded hashes are the same either way.
+    ///
+    /// If another process truncates a file while it is mapped, the process recording it is
+    /// killed by `SIGBUS` rather than getting an error. Use `None` when recording files that may
+    /// still be written to, such as the output of a build running in parallel.
+    #[cfg(feature = "mmap")]
+    pub fn mmap_threshold(mut self, threshold: Option<u64>) -> Self {
+        self.mmap_threshold = threshold;
+        self
+    }
+
+    /// Reuse and fill `cache` so that files recorded by an earlier pass are not hashed again if
+    /// they are unchanged.
+    pub fn hash_cache(mut self, cache: HashCache) -> Self {
+        self.hash_cache = Some(cache);
+        self
+    }
+}
+
+/// Traverse `paths`, hash the content of every file encountered, and return the recorded
+/// artifacts keyed by their path.
+///
+/// Directories are walked recursively and symbolic links are followed. Each recorded path keeps
+/// the prefix its root was given with, even when the root is itself a symlink to another
+/// directory, so that keys stay stable across hosts where the link resolves elsewhere. Symbolic
+/// link cycles are skipped with a logged warning; use `record_artifacts_cb` to collect them.
+///
+/// Recorded paths must be valid UTF-8; recording fails on files whose names are not, since they
+/// cannot be represented in a link. Use `record_artifacts_paths` to give `paths` as `Path`s.
+///
+/// `hash_algorithms` names the algorithms to use (e.g. `"sha256"`), parsed like
+/// `HashAlgorithm::from_str`; `sha256` is used if `None` is provided.
+///
+/// ```
+/// # use in_toto::runlib::record_artifacts;
+/// let materials = record_artifacts(&["tests/test_runlib"], None).unwrap();
+/// ```
+pub fn record_artifacts(
+    paths: &[&str],
+    hash_algorithms: Option<&[&str]>,
+) -> Result<BTreeMap<VirtualTargetPath, TargetDescription>> {
+    record_artifacts_with_options(paths, hash_algorithms, &RecordOptions::default())
+}
+
+/// Like `record_artifacts`, with the walk and recording customized by `options`.
+pub fn record_artifacts_with_options(
+    paths: &[&str],
+    hash_algorithms: Option<&[&str]>,
+    options: &RecordOptions,
+) -> Result<BTreeMap<VirtualTargetPath, TargetDescription>> {
+    record_artifacts_paths(paths, hash_algorithms, options)
+}
+
+/// Like `record_artifacts_with_options`, with `paths` given as `Path`s or anything else that
+/// converts to a `Path`.
+///
+/// ```
+/// # use in_toto::runlib::{record_artifacts, record_artifacts_paths, RecordOptions};
+/// # use std::path::PathBuf;
+/// let paths = [PathBuf::from("tests/test_runlib")];
+/// let materials = record_artifacts_paths(&paths, None, &RecordOptions::new()).unwrap();
+/// assert_eq!(materials, record_artifacts(&["tests/test_runlib"], None).unwrap());
+/// ```
+pub fn record_artifacts_paths<P: AsRef<Path>>(
+    paths: &[P],
+    hash_algorithms: Option<&[&str]>,
+    options: &RecordOptions,
+) -> Result<BTreeMap<VirtualTargetPath, TargetDescription>> {
+    let hash_algorithms = parse_hash_algorithms(hash_algorithms)?;
+    record_with_algorithms(paths, &hash_algorithms, options)
+}
+
+/// Like `record_artifacts_with_options`, also returning the size and, on unix, the permission
+/// bits of each artifact. These are not added to the target descriptions, which are the same as
+/// without file metadata; `LinkFileMetadata` stores them in the custom fields of a link instead.
+///
+/// ```
+/// # use in_toto::runlib::{record_artifacts_with_file_metadata, RecordOptions};
+/// let (artifacts, metadata) = record_artifacts_with_file_metadata(
+///     &["tests/test_runlib/release"],
+///     None,
+///     &RecordOptions::new(),
+/// )
+/// .unwrap();
+/// let (path, _) = artifacts.iter().next().unwrap();
+/// assert_eq!(metadata[path].size, 17);
+/// ```
+pub fn record_artifacts_with_file_metadata(
+    paths: &[&str],
+    hash_algorithms: Option<&[&str]>,
+    options: &RecordOptions,
+) -> Result<(
+    BTreeMap<VirtualTargetPath, TargetDescription>,
+    FileMetadataMap,
+)> {
+    let hash_algorithms = parse_hash_algorithms(hash_algorithms)?;
+    let mut file_metadata = FileMetadataMap::new();
+    let artifacts = walk_artifacts(
+        paths,
+        &hash_algorithms,
+        options,
+        Some(&mut file_metadata),
+        warn_on_skipped_cycle,
+    )?;
+    Ok((artifacts, file_metadata))
+}
+
+/// `record_artifacts_with_options`, with the hash algorithms already parsed.
+fn record_with_algorithms<P: AsRef<Path>>(
+    paths: &[P],
+    hash_algorithms: &[HashAlgorithm],
+    options: &RecordOptions,
+) -> Result<BTreeMap<VirtualTargetPath, TargetDescription>> {
+    walk_artifacts(paths, hash_algorithms, options, None, warn_on_skipped_cycle)
+}
+
+/// Fail on the first file that cannot be recorded, and warn about skipped symlink cycles.
+fn warn_on_skipped_cycle(event: RecordEvent) -> Result<()> {
+    match event {
+        RecordEvent::Failed { error, .. } => Err(error),
+        RecordEvent::Skipped {
+            path,
+            reason: SkipReason::SymlinkCycle,
+        } => {
+            warn!(
+                "Skipped {:?}: following it would close a symlink cycle",
+                path
+            );
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// The paths `record_artifacts_best_effort` failed to record, with their errors, in walk order.
+pub type RecordErrors = Vec<(String, Error)>;
+
+/// Record artifacts like `record_artifacts_with_options`, but in best effort mode: files that
+/// fail to be recorded, e.g. because they are unreadable or were deleted during the walk, are
+/// returned along with their error instead of aborting recording, and the other files are still
+/// recorded.
+///
+/// Errors that are not specific to one file, such as an invalid exclude pattern or two files
+/// recorded under the same path, still fail recording.
+///
+/// ```
+/// # use in_toto::runlib::{record_artifacts_best_effort, RecordOptions};
+/// let (artifacts, errors) = record_artifacts_best_effort(
+///     &["tests/test_runlib/release", "tests/test_runlib/missing"],
+///     None,
+///     &RecordOptions::new(),
+/// )
+/// .unwrap();
+/// assert_eq!(artifacts.len(), 1);
+/// assert_eq!(errors[0].0, "tests/test_runlib/missing");
+/// ```
+pub fn record_artifacts_best_effort(
+    paths: &[&str],
+    hash_algorithms: Option<&[&str]>,
+    options: &RecordOptions,
+) -> Result<(BTreeMap<VirtualTargetPath, TargetDescription>, RecordErrors)> {
+    let hash_algorithms = parse_hash_algorithms(hash_algorithms)?;
+    let mut errors = Vec::new();
+    let artifacts = walk_artifacts(paths, &hash_algorithms, options, None, |event| {
+        match event {
+            RecordEvent::Failed { path, error } => errors.push((path, error)),
+            RecordEvent::Skipped {
+                path,
+                reason: SkipReason::SymlinkCycle,
+            } => warn!(
+                "Skipped {:?}: following it would close a symlink cycle",
+                path
+            ),
+            _ => (),
+        }
+        Ok(())
+    })?;
+    Ok((artifacts, errors))
+}
+
+/// Record artifacts that only exist in memory, given as pairs of the path to record each under
+/// and its content. Paths are cleaned like those of files on disk, and the content is hashed the
+/// same way, so recording bytes gives the same result as writing them to a file and recording
+/// that file.
+///
+/// `hash_algorithms` is interpreted as for `record_artifacts`. Two entries recorded under the
+/// same path are an error.
+///
+/// ```
+/// # use in_toto::runlib::record_artifacts_from_bytes;
+/// let artifacts =
+///     record_artifacts_from_bytes(&[("./dist/hello.txt", b"hello\n")], None).unwrap();
+/// assert_eq!(artifacts.keys().next().unwrap().value(), "dist/hello.txt");
+/// ```
+pub fn record_artifacts_from_bytes(
+    entries: &[(&str, &[u8])],
+    hash_algorithms: Option<&[&str]>,
+) -> Result<BTreeMap<VirtualTargetPath, TargetDescription>> {
+    let hash_algorithms = parse_hash_algorithms(hash_algorithms)?;
+    let mut artifacts = BTreeMap::new();
+    let mut sources: HashMap<String, &str> = HashMap::new();
+    for (path, content) in entries {
+        let virtual_target_path = virtual_target_path(Path::new(path), &[])?;
+        let recorded = virtual_target_path.value().to_string();
+        if let Some(other) = sources.insert(recorded.clone(), path) {
+            return Err(Error::IllegalArgument(format!(
+                "{:?} and {:?} are both recorded as {:?}",
+                other, path, recorded
+            )));
+        }
+        let (_, hashes) = crypto::calculate_hashes(*content, &hash_algorithms)?;
+        let _ = artifacts.insert(virtual_target_path, hashes);
+    }
+    Ok(artifacts)
+}
+
+/// What happened to a file or directory encountered while recording artifacts.
+#[derive(Debug, PartialEq)]
+pub enum RecordEvent {
+    /// The file was hashed and recorded under `path`.
+    Hashed {
+        /// The recorded path.
+        path: String,
+        /// The size of the file in bytes.
+        len: u64,
+    },
+    /// The file or directory at `path` was deliberately not recorded.
+    Skipped {
+        /// The path of the skipped file or directory.
+        path: String,
+        /// Why it was skipped.
+        reason: SkipReason,
+    },
+    /// The file or directory at `path` could not be recorded.
+    Failed {
+        /// The path of the file or directory.
+        path: String,
+        /// The error encountered.
+        error: Error,
+    },
+}
+
+/// Why `record_artifacts_cb` skipped a path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SkipReason {
+    /// Following the symbolic link would close a cycle.
+    SymlinkCycle,
+    /// The path is excluded by an `.in-toto-ignore` file.
+    Ignored,
+    /// The path matches one of the exclude patterns in `RecordOptions`.
+    Excluded,
+    /// The path is hidden and `RecordOptions::skip_hidden` is set.
+    Hidden,
+}
+
+/// Record artifacts like `record_artifacts_with_options`, calling `on_file` for every file that
+/// is hashed, skipped, or fails, in walk order.
+///
+/// Failures do not abort recording: the failed path is reported to `on_file` and left out of
+/// the returned artifacts. Directories are walked in file name order.
+///
+/// ```
+/// # use in_toto::runlib::{record_artifacts_cb, RecordEvent, RecordOptions};
+/// let materials = record_artifacts_cb(
+///     &["tests/test_runlib"],
+///     None,
+///     &RecordOptions::new(),
+///     |event| match event {
+///         RecordEvent::Hashed { path, len } => println!("{} ({} bytes)", path, len),
+///         RecordEvent::Skipped { path, reason } => println!("{}: skipped, {:?}", path, reason),
+///         RecordEvent::Failed { path, error } => eprintln!("{}: {}", path, error),
+///     },
+/// )
+/// .unwrap();
+/// ```
+pub fn record_artifacts_cb<F>(
+    paths: &[&str],
+    hash_algorithms: Option<&[&str]>,
+    options: &RecordOptions,
+    mut on_file: F,
+) -> Result<BTreeMap<VirtualTargetPath, TargetDescription>>
+where
+    F: FnMut(RecordEvent),
+{
+    let hash_algorithms = parse_hash_algorithms(hash_algorithms)?;
+    walk_artifacts(paths, &hash_algorithms, options, None, |event| {
+        on_file(event);
+        Ok(())
+    })
+}
+
+/// Record artifacts like `record_artifacts_cb`, but stop as soon as `on_file` returns an error
+/// and return that error, e.g. to cancel a long recording from a progress bar.
+///
+/// `RecordEvent::Hashed` carries the number of bytes hashed for each file. When files are hashed
+/// in parallel, they are all hashed before the first event is reported, so cancelling only saves
+/// the remaining callbacks.
+///
+/// ```
+/// # use in_toto::error::Error;
+/// # use in_toto::runlib::{record_artifacts_try_cb, RecordEvent, RecordOptions};
+/// let mut hashed = 0;
+/// let cancelled = record_artifacts_try_cb(
+///     &["tests/test_runlib"],
+///     None,
+///     &RecordOptions::new(),
+///     |event| match event {
+///         RecordEvent::Hashed { .. } if hashed == 2 => {
+///             Err(Error::Opaque("cancelled".to_string()))
+///         }
+///         RecordEvent::Hashed { .. } => {
+///             hashed += 1;
+///             Ok(())
+///         }
+///         _ => Ok(()),
+///     },
+/// );
+/// assert!(cancelled.is_err());
+/// ```
+pub fn record_artifacts_try_cb<F>(
+    paths: &[&str],
+    hash_algorithms: Option<&[&str]>,
+    options: &RecordOptions,
+    on_file: F,
+) -> Result<BTreeMap<VirtualTargetPath, TargetDescription>>
+where
+    F: FnMut(RecordEvent) -> Result<()>,
+{
+    let hash_algorithms = parse_hash_algorithms(hash_algorithms)?;
+    walk_artifacts(paths, &hash_algorithms, options, None, on_file)
+}
+
+/// Record artifacts like `record_artifacts_with_options`, reporting skipped paths into
+/// `diagnostics`.
+pub fn record_artifacts_with_diagnostics(
+    paths: &[&str],
+    hash_algorithms: Option<&[&str]>,
+    options: &RecordOptions,
+    diagnostics: &mut Diagnostics,
+) -> Result<BTreeMap<VirtualTargetPath, TargetDescription>> {
+    let hash_algorithms = parse_hash_algorithms(hash_algorithms)?;
+    walk_artifacts(
+        paths,
+        &hash_algorithms,
+        options,
+        None,
+        |event| match event {
+            RecordEvent::Hashed { .. } => Ok(()),
+            RecordEvent::Skipped { path, reason } => {
+                let (category, message) = match reason {
+                    SkipReason::SymlinkCycle => (
+                        DiagnosticCategory::SymlinkCycle,
+                        format!(
+                            "Skipped {:?}: following it would close a symlink cycle",
+                            path
+                        ),
+                    ),
+                    SkipReason::Ignored => (
+                        DiagnosticCategory::Excluded,
+                        format!("Excluded {:?} by an ignore file", path),
+                    ),
+                    SkipReason::Excluded => (
+                        DiagnosticCategory::Excluded,
+                        format!("Excluded {:?} by an exclude pattern", path),
+                    ),
+                    SkipReason::Hidden => (
+                        DiagnosticCategory::Excluded,
+                        format!("Excluded hidden path {:?}", path),
+                    ),
+                };
+                diagnostics.push(Diagnostic::new(category, message));
+                Ok(())
+            }
+            RecordEvent::Failed { error, .. } => Err(error),
+        },
+    )
+}
+
+/// An entry found while walking the paths to record, in walk order.
+enum WalkStep {
+    /// An entry that is not hashed, and the event reporting it.
+    Event(RecordEvent),
+    /// A file to hash, under its cleaned path.
+    File {
+        entry_path: String,
+        path: PathBuf,
+        symlink: bool,
+    },
+}
+
+/// Walk `paths` and record artifacts, reporting each event to `on_event`. Recording stops with
+/// the error returned by `on_event`, if any. The metadata of each artifact is added to
+/// `file_metadata`, if given.
+///
+/// The walk completes before any file is hashed, so that files can be hashed in parallel; events
+/// are still reported in walk order.
+fn walk_artifacts<P, F>(
+    paths: &[P],
+    hash_algorithms: &[HashAlgorithm],
+    options: &RecordOptions,
+    mut file_metadata: Option<&mut FileMetadataMap>,
+    mut on_event: F,
+) -> Result<BTreeMap<VirtualTargetPath, TargetDescription>>
+where
+    P: AsRef<Path>,
+    F: FnMut(RecordEvent) -> Result<()>,
+{
+    let steps = walk_steps(paths, options)?;
+    let with_file_metadata = file_metadata.is_some();
+    let record = |path: &Path, symlink: bool| {
+        let recorded = if symlink {
+            record_symlink(path, hash_algorithms, &options.lstrip_paths)
+        } else {
+            record_file(path, hash_algorithms, options)
+        };
+        recorded.and_then(|(virtual_target_path, hashes, len)| {
+            let metadata = if with_file_metadata {
+                Some(read_file_metadata(path, symlink, len)?)
+            } else {
+                None
+            };
+            Ok((virtual_target_path, hashes, len, metadata))
+        })
+    };
+
+    #[cfg(feature = "rayon")]
+    let mut hashed = if options.parallel {
+        use rayon::prelude::*;
+        steps
+            .par_iter()
+            .map(|step| match step {
+                WalkStep::File { path, symlink, .. } => Some(record(path, *symlink)),
+                WalkStep::Event(_) => None,
+            })
+            .collect()
+    } else {
+        Vec::new()
+    };
+    #[cfg(not(feature = "rayon"))]
+    let mut hashed: Vec<Option<Result<_>>> = Vec::new();
+
+    let mut artifacts = BTreeMap::new();
+    let mut sources = HashMap::new();
+    for (index, step) in steps.into_iter().enumerate() {
+        let (entry_path, path, symlink) = match step {
+            WalkStep::Event(event) => {
+                on_event(event)?;
+                continue;
+            }
+            WalkStep::File {
+                entry_path,
+                path,
+                symlink,
+            } => (entry_path, path, symlink),
+        };
+        let recorded = match hashed.get_mut(index).and_then(Option::take) {
+            Some(recorded) => recorded,
+            None => record(&path, symlink),
+        };
+        match recorded {
+            Ok((virtual_target_path, hashes, len, metadata)) => {
+                let path = virtual_target_path.value().to_string();
+                if let Some(other) = sources.insert(path.clone(), entry_path.clone()) {
+                    if other != entry_path {
+                        return Err(Error::IllegalArgument(format!(
+                            "{:?} and {:?} are both recorded as {:?}",
+                            other, entry_path, path
+                        )));
+                    }
+                }
+                if let (Some(file_metadata), Some(metadata)) = (file_metadata.as_mut(), metadata) {
+                    let _ = file_metadata.insert(virtual_target_path.clone(), metadata);
+                }
+                let _ = artifacts.insert(virtual_target_path, hashes);
+                on_event(RecordEvent::Hashed { path, len })?;
+            }
+            Err(error) => on_event(RecordEvent::Failed {
+                path: entry_path,
+                error,
+            })?,
+        }
+    }
+
+    Ok(artifacts)
+}
+
+/// Walk `paths`, applying the ignore files and exclude patterns of `options`.
+fn walk_steps<P: AsRef<Path>>(paths: &[P], options: &RecordOptions) -> Result<Vec<WalkStep>> {
+    let excludes = ExcludeSet::new(&options.exclude_patterns)?;
+    let mut steps = Vec::new();
+
+    for path in paths {
+        let mut walker = WalkDir::new(path.as_ref())
+            .follow_links(options.follow_symlinks)
+            .sort_by_file_name();
+        if let Some(max_depth) = options.max_depth {
+            walker = walker.max_depth(max_depth);
+        }
+        let mut walker = walker.into_iter();
+        let mut ignores = IgnoreStack::default();
+        while let Some(entry) = walker.next() {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(err) => {
+                    steps.push(WalkStep::Event(walk_error_event(err)));
+                    continue;
+                }
+            };
+            let entry_path = clean_path(entry.path());
+            let is_dir = entry.file_type().is_dir();
+
+            let skipped = if options.honor_intoto_ignore {
+                match ignores.excludes(&entry) {
+                    Ok(false) => None,
+                    Ok(true) => Some(SkipReason::Ignored),
+                    Err(error) => {
+                        steps.push(WalkStep::Event(RecordEvent::Failed {
+                            path: entry_path,
+                            error,
+                        }));
+                        continue;
+                    }
+                }
+            } else {
+                None
+            };
+            let skipped = skipped.or_else(|| {
+                if excludes.excludes(&entry_path, is_dir) {
+                    Some(SkipReason::Excluded)
+                } else {
+                    None
+                }
+            });
+            let skipped = skipped.or_else(|| {
+                if options.skip_hidden && entry.depth() > 0 && is_hidden(&entry) {
+                    Some(SkipReason::Hidden)
+                } else {
+                    None
+                }
+            });
+            if let Some(reason) = skipped {
+                if is_dir {
+                    walker.skip_current_dir();
+                }
+                steps.push(WalkStep::Event(RecordEvent::Skipped {
+                    path: entry_path,
+                    reason,
+                }));
+                continue;
+            }
+            if is_dir {
+                continue;
+            }
+
+            steps.push(WalkStep::File {
+                entry_path,
+                path: entry.path().to_path_buf(),
+                // Only links that are not followed are reported as symlinks by `WalkDir`.
+                symlink: entry.file_type().is_symlink(),
+            });
+        }
+    }
+
+    Ok(steps)
+}
+
+/// Whether the name of `entry` marks it as hidden.
+fn is_hidden(entry: &walkdir::DirEntry) -> bool {
+    entry.file_name().to_string_lossy().starts_with('.')
+}
+
+/// Turn a `WalkDir` error into the event reported for it. Entries that would close a symbolic
+/// link cycle are skipped rather than failed.
+fn walk_error_event(err: walkdir::Error) -> RecordEvent {
+    let path = err.path().map(clean_path).unwrap_or_default();
+    if err.loop_ancestor().is_some() {
+        return RecordEvent::Skipped {
+            path,
+            reason: SkipReason::SymlinkCycle,
+        };
+    }
+    let error = Error::from_io(&io::Error::from(err), Path::new(&path));
+    RecordEvent::Failed { path, error }
+}
+
+/// Hash the file at `path` and return its `VirtualTargetPath` alongside the computed hashes.
+///
+/// The recorded path is the lexically cleaned form of `path` (e.g. `./foo/bar` is recorded as
+/// `foo/bar`); symlinks are never resolved when building it.
+pub fn record_artifact<P: AsRef<Path>>(
+    path: P,
+    hash_algorithms: &[HashAlgorithm],
+) -> Result<(VirtualTargetPath, TargetDescription)> {
+    record_file(path.as_ref(), hash_algorithms, &RecordOptions::default())
+        .map(|(virtual_target_path, hashes, _len)| (virtual_target_path, hashes))
+}
+
+/// Like `record_artifact`, with the file hashed as configured by `options`, e.g. with its line
+/// endings normalized, and its path stripped by `lstrip_paths`. Options about walking
+/// directories do not apply.
+///
+/// ```
+/// # use in_toto::crypto::HashAlgorithm;
+/// # use in_toto::runlib::{record_artifact_with_options, RecordOptions};
+/// let options = RecordOptions::new().normalize_line_endings(true);
+/// let (path, hashes) = record_artifact_with_options(
+///     "tests/test_runlib/release/artifact.txt",
+///     &[HashAlgorithm::Sha256],
+///     &options,
+/// )
+/// .unwrap();
+/// ```
+pub fn record_artifact_with_options<P: AsRef<Path>>(
+    path: P,
+    hash_algorithms: &[HashAlgorithm],
+    options: &RecordOptions,
+) -> Result<(VirtualTargetPath, TargetDescription)> {
+    record_file(path.as_ref(), hash_algorithms, options)
+        .map(|(virtual_target_path, hashes, _len)| (virtual_target_path, hashes))
+}
+
+/// Record the file at `path`, also returning its size.
+fn record_file(
+    path: &Path,
+    hash_algorithms: &[HashAlgorithm],
+    options: &RecordOptions,
+) -> Result<(VirtualTargetPath, TargetDescription, u64)> {
+    let virtual_target_path = virtual_target_path(path, &options.lstrip_paths)?;
+    if options.normalize_line_endings {
+        let binary = ExcludeSet::new(&options.binary_patterns)?.excludes(&clean_path(path), false);
+        let file = File::open(path).map_err(|e| Error::from_io(&e, path))?;
+        let (len, hashes) = line_endings::calculate_normalized_hashes(
+            file,
+            binary,
+            hash_algorithms,
+            options.buffer_size,
+        )?;
+        return Ok((virtual_target_path, hashes, len));
+    }
+
+    let cache = &options.hash_cache;
+    if let Some((hashes, len)) = cache
+        .as_ref()
+        .and_then(|cache| cache.get(path, hash_algorithms))
+    {
+        return Ok((virtual_target_path, hashes, len));
+    }
+
+    let file = File::open(path).map_err(|e| Error::from_io(&e, path))?;
+    let before = match cache {
+        Some(_) => Some(file.metadata().map_err(|e| Error::from_io(&e, path))?),
+        None => None,
+    };
+    #[cfg(feature = "mmap")]
+    let (len, hashes) = crypto::calculate_file_hashes(
+        &file,
+        hash_algorithms,
+        options.buffer_size,
+        options.mmap_threshold.unwrap_or(u64::MAX),
+    )?;
+    #[cfg(not(feature = "mmap"))]
+    let (len, hashes) =
+        crypto::calculate_hashes_with_buffer_size(file, hash_algorithms, options.buffer_size)?;
+    if let (Some(cache), Some(before)) = (cache, before) {
+        cache.insert(path, &before, &hashes);
+    }
+    Ok((virtual_target_path, hashes, len))
+}
+
+/// Record the symbolic link at `path` by hashing its target path, also returning the target's
+/// length.
+fn record_symlink(
+    path: &Path,
+    hash_algorithms: &[HashAlgorithm],
+    prefixes: &[String],
+) -> Result<(VirtualTargetPath, TargetDescription, u64)> {
+    let virtual_target_path = virtual_target_path(path, prefixes)?;
+    let target = fs::read_link(path).map_err(|e| Error::from_io(&e, path))?;
+    let target = target.to_str().ok_or_else(|| {
+        Error::IllegalArgument(format!("Target of {:?} is not valid UTF-8", path))
+    })?;
+    let (len, hashes) = crypto::calculate_hashes(target.as_bytes(), hash_algorithms)?;
+    Ok((virtual_target_path, hashes, len))
+}
+
+/// Run the command in `cmd_args`, from `run_dir` if given, and return its byproducts: its
+/// output and exit code, or the signal that terminated it.
+///
+/// If `timeout` is given and the command has not exited by then, it is killed and an
+/// `Error::CommandTimeout` holding the output it produced so far is returned. Only the command
+/// itself is killed, not processes it spawned.
+///
+/// If `stdin` is given, it is written to the standard input of the command, which is closed
+/// afterwards. Otherwise the command reads nothing from its standard input.
+pub fn run_command(
+    cmd_args: &[&str],
+    run_dir: Option<&str>,
+    timeout: Option<Duration>,
+    stdin: Option<&[u8]>,
+) -> Result<Byproducts> {
+    execute(
+        prepare_command(cmd_args, run_dir)?,
+        cmd_args[0],
+        timeout,
+        stdin,
+    )
+}
+
+/// How long to keep collecting the output of a killed command, in case processes it spawned hold
+/// its pipes open.
+const KILL_GRACE: Duration = Duration::from_millis(100);
+
+/// Run a command built by `prepare_command`, `program` being its first argument.
+fn execute(
+    mut cmd: Command,
+    program: &str,
+    timeout: Option<Duration>,
+    stdin: Option<&[u8]>,
+) -> Result<Byproducts> {
+    let mut child = cmd
+        .stdin(stdin.map_or_else(Stdio::null, |_| Stdio::piped()))
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| spawn_error(&e, program))?;
+    // Feed stdin and drain both output pipes as the command runs, so neither side blocks on a
+    // full pipe and the output is still at hand if the command has to be killed.
+    let feeder = feed(child.stdin.take(), stdin);
+    let stdout = capture(child.stdout.take());
+    let stderr = capture(child.stderr.take());
+
+    let status = match timeout {
+        Some(timeout) => match child.wait_timeout(timeout) {
+            Ok(None) => return Err(kill(child, timeout, &stdout, &stderr)),
+            waited => waited.map(Option::unwrap),
+        },
+        None => child.wait(),
+    }
+    .map_err(|e| Error::from_io(&e, Path::new(program)))?;
+
+    // A command may exit without reading all of its input, which is not an error.
+    match feeder.map(thread::JoinHandle::join) {
+        Some(Ok(Err(e))) if e.kind() != io::ErrorKind::BrokenPipe => {
+            return Err(Error::from_io(&e, Path::new(program)));
+        }
+        Some(Err(_)) => return Err(Error::Programming("Feeding stdin panicked".into())),
+        _ => {}
+    }
+
+    let output = |(output, reader): Captured| {
+        let _ = reader.join();
+        mem::take(&mut *output.lock())
+    };
+    command_byproducts(program, status, output(stdout), output(stderr))
+}
+
+/// The error for failing to start `program`: `Error::CommandNotFound` if it does not exist.
+fn spawn_error(err: &io::Error, program: &str) -> Error {
+    match err.kind() {
+        io::ErrorKind::NotFound => Error::CommandNotFound(program.to_string()),
+        _ => Error::from_io(err, Path::new(program)),
+    }
+}
+
+/// The byproducts of `program`, which exited with `status` after writing `stdout` and `stderr`.
+fn command_byproducts(
+    program: &str,
+    status: ExitStatus,
+    stdout: Vec<u8>,
+    stderr: Vec<u8>,
+) -> Result<Byproducts> {
+    let exit_code = status.code();
+    let signal = signal_name(&status);
+    if exit_code.is_none() && signal.is_none() {
+        return Err(Error::Opaque(format!(
+            "Command {:?} exited without an exit code",
+            program
+        )));
+    }
+
+    Ok(Byproducts {
+        stdout: CommandOutput::from_bytes(stdout),
+        stderr: CommandOutput::from_bytes(stderr),
+        exit_code,
+        signal,
+    })
+}
+
+/// The name of the signal that terminated a command with `status`, or its number if it has no
+/// name that is the same across Unix platforms.
+#[cfg(unix)]
+fn signal_name(status: &ExitStatus) -> Option<String> {
+    use std::os::unix::process::ExitStatusExt;
+
+    let signal = status.signal()?;
+    let name = match signal {
+        1 => "SIGHUP",
+        2 => "SIGINT",
+        3 => "SIGQUIT",
+        4 => "SIGILL",
+        5 => "SIGTRAP",
+        6 => "SIGABRT",
+        8 => "SIGFPE",
+        9 => "SIGKILL",
+        11 => "SIGSEGV",
+        13 => "SIGPIPE",
+        14 => "SIGALRM",
+        15 => "SIGTERM",
+        _ => return Some(signal.to_string()),
+    };
+    Some(name.to_string())
+}
+
+/// Commands are only terminated by signals on Unix.
+#[cfg(not(unix))]
+fn signal_name(_status: &ExitStatus) -> Option<String> {
+    None
+}
+
+/// Kill `child` for exceeding `timeout`, and return the error holding its output so far.
+fn kill(mut child: Child, timeout: Duration, stdout: &Captured, stderr: &Captured) -> Error {
+    // Only fails if the command exited in the meantime, which `wait` then picks up.
+    let _ = child.kill();
+    let _ = child.wait();
+    let deadline = Instant::now() + KILL_GRACE;
+    while !(stdout.1.is_finished() && stderr.1.is_finished()) && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(5));
+    }
+
+    let partial = |(output, _): &Captured| String::from_utf8_lossy(&output.lock()).into_owned();
+    Error::CommandTimeout {
+        timeout,
+        stdout: partial(stdout),
+        stderr: partial(stderr),
+    }
+}
+
+/// Write `input` to `pipe` on a new thread, closing the pipe once done.
+fn feed(
+    pipe: Option<ChildStdin>,
+    input: Option<&[u8]>,
+) -> Option<thread::JoinHandle<io::Result<()>>> {
+    let (mut pipe, input) = match (pipe, input) {
+        (Some(pipe), Some(input)) => (pipe, input.to_vec()),
+        _ => return None,
+    };
+    Some(thread::spawn(move || pipe.write_all(&input)))
+}
+
+/// The output read from a pipe so far, and the thread reading it.
+type Captured = (Arc<Mutex<Vec<u8>>>, thread::JoinHandle<()>);
+
+/// Read `pipe` to its end on a new thread.
+fn capture<R: Read + Send + 'static>(pipe: Option<R>) -> Captured {
+    let output = Arc::new(Mutex::new(Vec::new()));
+    let sink = Arc::clone(&output);
+    let reader = thread::spawn(move || {
+        let mut pipe = match pipe {
+            Some(pipe) => pipe,
+            None => return,
+        };
+        let mut buf = [0; 8 * 1024];
+        loop {
+            match pipe.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => sink.lock().extend_from_slice(&buf[..n]),
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(_) => break,
+            }
+        }
+    });
+    (output, reader)
+}
+
+fn prepare_command(cmd_args: &[&str], run_dir: Option<&str>) -> Result<Command> {
+    let (program, args) = cmd_args
+        .split_first()
+        .ok_or_else(|| Error::IllegalArgument("No command given".into()))?;
+    let mut cmd = Command::new(program);
+    let _ = cmd.args(args);
+    if let Some(run_dir) = run_dir {
+        let _ = cmd.current_dir(run_dir);
+    }
+    Ok(cmd)
+}
+
+/// Options controlling how `in_toto_run_with_options` runs a step.
+///
+/// ```
+/// # use in_toto::runlib::RunOptions;
+/// # use std::collections::BTreeMap;
+/// let mut env = BTreeMap::new();
+/// let _ = env.insert("CC".to_string(), "clang".to_string());
+/// let options = RunOptions::new().env(env).record_env(&["PATH", "CC"]);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct RunOptions {
+    run_dir: Option<String>,
+    env: BTreeMap<String, String>,
+    record_env: Option<Vec<String>>,
+    fail_on_nonzero_exit: bool,
+}
+
+impl RunOptions {
+    /// Create options matching the behavior of `in_toto_run`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run the command from `run_dir` instead of the current directory.
+    pub fn run_dir(mut self, run_dir: &str) -> Self {
+        self.run_dir = Some(run_dir.to_string());
+        self
+    }
+
+    /// Set the variables in `env` for the command, on top of the inherited environment. Every
+    /// variable set this way is recorded in the link's environment.
+    pub fn env(mut self, env: BTreeMap<String, String>) -> Self {
+        self.env = env;
+        self
+    }
+
+    /// Record the values the command sees for the variables in `allowlist`, and its working
+    /// directory as `workdir`, in the link's environment. Variables that are unset, or whose
+    /// value is not valid UTF-8, are left out. Nothing is recorded by default.
+    pub fn record_env(mut self, allowlist: &[&str]) -> Self {
+        self.record_env = Some(allowlist.iter().map(|name| name.to_string()).collect());
+        self
+    }
+
+    /// Fail with `Error::CommandFailed` if the command exits with a non-zero exit code or is
+    /// terminated by a signal, instead of recording the link. Disabled by default, since the
+    /// exit code is recorded in the byproducts and a failing command can be a legitimate step.
+    pub fn fail_on_nonzero_exit(mut self, fail: bool) -> Self {
+        self.fail_on_nonzero_exit = fail;
+        self
+    }
+
+    /// The environment to record in the link.
+    fn recorded_env(&self) -> Result<Environment> {
+        let mut recorded = Environment {
+            workdir: None,
+            variables: self.env.clone(),
+        };
+        let allowlist = match &self.record_env {
+            Some(allowlist) => allowlist,
+            None => return Ok(recorded),
+        };
+
+        for name in allowlist {
+            if let Ok(value) = std::env::var(name) {
+                let _ = recorded.variables.entry(name.clone()).or_insert(value);
+            }
+        }
+        let cwd = std::env::current_dir().map_err(|e| Error::from_io(&e, Path::new(".")))?;
+        let workdir = match &self.run_dir {
+            Some(run_dir) => cwd.join(run_dir),
+            None => cwd,
+        };
+        recorded.workdir = Some(to_slashes(&workdir.to_string_lossy(), path::MAIN_SEPARATOR));
+        Ok(recorded)
+    }
+}
+
+/// Record the materials of step `name`, run `cmd_args`, record its products, and return the
+/// resulting link signed with `key`.
+///
+/// Materials are recorded while the command is being prepared, and the product pass reuses the
+/// digests of materials the command left untouched instead of hashing them again. If `cmd_args`
+/// is empty, no command is run and the link has no byproducts.
+///
+/// Recording is reproducible: links hold no timestamps, and are written with sorted keys, so
+/// running the same command on the same files with the same options gives the same bytes when
+/// written with `SignedMetadata::write`. This holds as long as `key` signs deterministically, as
+/// Ed25519 does, whereas RSA-PSS and ECDSA signatures are randomized.
+///
+/// ```
+/// # use in_toto::crypto::{PrivateKey, SignatureScheme};
+/// # use in_toto::runlib::in_toto_run;
+/// # let key: &[u8] = include_bytes!("../tests/ed25519/ed25519-1.pk8.der");
+/// let key = PrivateKey::from_pkcs8(&key, SignatureScheme::Ed25519).unwrap();
+/// let link = in_toto_run(
+///     "package",
+///     None,
+///     &["tests/test_runlib/release"],
+///     &["tests/test_runlib/release"],
+///     &[],
+///     &key,
+///     None,
+/// )
+/// .unwrap();
+/// ```
+pub fn in_toto_run(
+    name: &str,
+    run_dir: Option<&str>,
+    material_paths: &[&str],
+    product_paths: &[&str],
+    cmd_args: &[&str],
+    key: &PrivateKey,
+    hash_algorithms: Option<&[&str]>,
+) -> Result<SignedMetadata<Json, LinkMetadata>> {
+    let options = match run_dir {
+        Some(run_dir) => RunOptions::new().run_dir(run_dir),
+        None => RunOptions::new(),
+    };
+    in_toto_run_with_options(
+        name,
+        material_paths,
+        product_paths,
+        cmd_args,
+        key,
+        hash_algorithms,
+        &options,
+    )
+}
+
+/// Like `in_toto_run`, but running the command as configured by `options`, and recording the
+/// environment it selects in the link.
+pub fn in_toto_run_with_options(
+    name: &str,
+    material_paths: &[&str],
+    product_paths: &[&str],
+    cmd_args: &[&str],
+    key: &PrivateKey,
+    hash_algorithms: Option<&[&str]>,
+    options: &RunOptions,
+) -> Result<SignedMetadata<Json, LinkMetadata>> {
+    in_toto_run_with_algorithms(
+        name,
+        material_paths,
+        product_paths,
+        cmd_args,
+        key,
+        &parse_hash_algorithms(hash_algorithms)?,
+        options,
+    )
+}
+
+/// Like `in_toto_run_with_options`, but hashing artifacts with `hash_algorithms` as given rather
+/// than by name.
+///
+/// ```
+/// # use in_toto::crypto::{HashAlgorithm, PrivateKey, SignatureScheme};
+/// # use in_toto::runlib::{in_toto_run_with_algorithms, RunOptions};
+/// # let key: &[u8] = include_bytes!("../tests/ed25519/ed25519-1.pk8.der");
+/// let key = PrivateKey::from_pkcs8(&key, SignatureScheme::Ed25519).unwrap();
+/// let link = in_toto_run_with_algorithms(
+///     "package",
+///     &["tests/test_runlib/release"],
+///     &["tests/test_runlib/release"],
+///     &[],
+///     &key,
+///     &[HashAlgorithm::Sha256, HashAlgorithm::Sha512],
+///     &RunOptions::new(),
+/// )
+/// .unwrap();
+/// ```
+pub fn in_toto_run_with_algorithms(
+    name: &str,
+    material_paths: &[&str],
+    product_paths: &[&str],
+    cmd_args: &[&str],
+    key: &PrivateKey,
+    hash_algorithms: &[HashAlgorithm],
+    options: &RunOptions,
+) -> Result<SignedMetadata<Json, LinkMetadata>> {
+    let link = run_step(
+        name,
+        material_paths,
+        product_paths,
+        cmd_args,
+        hash_algorithms,
+        options,
+    )?;
+    SignedMetadata::new(&link, key)
+}
+
+/// Like `in_toto_run_with_options`, but return the link as an in-toto statement signed in a DSSE
+/// envelope, as built by `DsseEnvelope::from_link`.
+pub fn in_toto_run_dsse(
+    name: &str,
+    material_paths: &[&str],
+    product_paths: &[&str],
+    cmd_args: &[&str],
+    key: &PrivateKey,
+    hash_algorithms: Option<&[&str]>,
+    options: &RunOptions,
+) -> Result<DsseEnvelope> {
+    let link = run_step(
+        name,
+        material_paths,
+        product_paths,
+        cmd_args,
+        &parse_hash_algorithms(hash_algorithms)?,
+        options,
+    )?;
+    DsseEnvelope::from_link(&link, key)
+}
+
+/// A format `in_toto_run_multi` can return a link in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OutputFormat {
+    /// A signed link metadata file, as returned by `in_toto_run`.
+    Link,
+    /// An in-toto statement signed in a DSSE envelope, as returned by `in_toto_run_dsse`.
+    Dsse,
+}
+
+/// A link signed in one of the `OutputFormat`s.
+#[derive(Debug, Clone)]
+pub enum SignedOutput {
+    /// The link as signed metadata.
+    Link(SignedMetadata<Json, LinkMetadata>),
+    /// The link as a statement in a DSSE envelope.
+    Dsse(DsseEnvelope),
+}
+
+/// Like `in_toto_run_with_options`, but sign the link in each of `formats`, in the same order.
+///
+/// The step is only run once, so every output describes the same materials, products and
+/// command.
+///
+/// ```
+/// # use in_toto::crypto::{PrivateKey, SignatureScheme};
+/// # use in_toto::runlib::{in_toto_run_multi, OutputFormat, RunOptions, SignedOutput};
+/// # let key: &[u8] = include_bytes!("../tests/ed25519/ed25519-1.pk8.der");
+/// let key = PrivateKey::from_pkcs8(&key, SignatureScheme::Ed25519).unwrap();
+/// let outputs = in_toto_run_multi(
+///     "package",
+///     &["tests/test_runlib/release"],
+///     &["tests/test_runlib/release"],
+///     &[],
+///     &key,
+///     None,
+///     &RunOptions::new(),
+///     &[OutputFormat::Link, OutputFormat::Dsse],
+/// )
+/// .unwrap();
+/// assert!(matches!(outputs[..], [SignedOutput::Link(_), SignedOutput::Dsse(_)]));
+/// ```
+pub fn in_toto_run_multi(
+    name: &str,
+    material_paths: &[&str],
+    product_paths: &[&str],
+    cmd_args: &[&str],
+    key: &PrivateKey,
+    hash_algorithms: Option<&[&str]>,
+    options: &RunOptions,
+    formats: &[OutputFormat],
+) -> Result<Vec<SignedOutput>> {
+    let link = run_step(
+        name,
+        material_paths,
+        product_paths,
+        cmd_args,
+        &parse_hash_algorithms(hash_algorithms)?,
+        options,
+    )?;
+    formats
+        .iter()
+        .map(|format| match format {
+            OutputFormat::Link => SignedMetadata::new(&link, key).map(SignedOutput::Link),
+            OutputFormat::Dsse => DsseEnvelope::from_link(&link, key).map(SignedOutput::Dsse),
+        })
+        .collect()
+}
+
+/// Record the materials, run the command and record the products of step `name`.
+fn run_step(
+    name: &str,
+    material_paths: &[&str],
+    product_paths: &[&str],
+    cmd_args: &[&str],
+    hash_algorithms: &[HashAlgorithm],
+    options: &RunOptions,
+) -> Result<LinkMetadata> {
+    let record_options = RecordOptions::new().hash_cache(HashCache::new());
+    let env = options.recorded_env()?;
+
+    let (materials, cmd) = thread::scope(|scope| {
+        let materials = scope
+            .spawn(|| record_with_algorithms(material_paths, hash_algorithms, &record_options));
+        let cmd = match cmd_args {
+            [] => Ok(None),
+            _ => prepare_command(cmd_args, options.run_dir.as_deref()).map(|mut cmd| {
+                let _ = cmd.envs(&options.env);
+                Some(cmd)
+            }),
+        };
+        let materials = materials
+            .join()
+            .unwrap_or_else(|_| Err(Error::Programming("Material recording panicked".into())));
+        (materials, cmd)
+    });
+    let materials = materials?;
+
+    let byproducts = match cmd? {
+        Some(cmd) => {
+            let byproducts = execute(cmd, cmd_args[0], None, None)?;
+            if options.fail_on_nonzero_exit && byproducts.exit_code != Some(0) {
+                return Err(Error::CommandFailed {
+                    code: byproducts.exit_code,
+                    stderr: String::from_utf8_lossy(byproducts.stderr.as_bytes()).into_owned(),
+                });
+            }
+            byproducts.into()
+        }
+        None => BTreeMap::new(),
+    };
+    let products = record_with_algorithms(product_paths, hash_algorithms, &record_options)?;
+
+    LinkMetadataBuilder::new()
+        .name(name.to_string())
+        .command(cmd_args.iter().map(|arg| arg.to_string()).collect())
+        .materials(materials)
+        .products(products)
+        .environment(env)
+        .byproducts(byproducts)
+        .build()
+}
+
+/// Map the names in `hash_algorithms` to `HashAlgorithm`s, defaulting to `sha256`.
+fn parse_hash_algorithms(hash_algorithms: Option<&[&str]>) -> Result<Vec<HashAlgorithm>> {
+    match hash_algorithms {
+        Some(names) => names.iter().map(|name| name.parse()).collect(),
+        None => Ok(vec![HashAlgorithm::Sha256]),
+    }
+}
+
+/// Strip the first of `prefixes` that the cleaned path `path` starts with from it.
+fn lstrip<'a>(path: &'a str, prefixes: &[String]) -> &'a str {
+    match prefixes
+        .iter()
+        .find(|prefix| path.starts_with(prefix.as_str()))
+    {
+        Some(prefix) => path[prefix.len()..].trim_start_matches('/'),
+        None => path,
+    }
+}
+
+/// The path `path` is recorded under, with the first matching of `prefixes` stripped. Prefixes
+/// are stripped before the path is validated, so that an absolute path can be recorded relative
+/// to a prefix. Names that are not valid UTF-8 cannot be represented in a link, so they are an
+/// error rather than recorded lossily.
+fn virtual_target_path(path: &Path, prefixes: &[String]) -> Result<VirtualTargetPath> {
+    if path.to_str().is_none() {
+        return Err(Error::IllegalArgument(format!(
+            "Path {:?} is not valid UTF-8 and cannot be recorded",
+            path.to_string_lossy()
+        )));
+    }
+    VirtualTargetPath::new(lstrip(&clean_path(path), prefixes).to_string())
+}
+
+/// Lexically clean `path` into the `/`-separated form used for `VirtualTargetPath`s, dropping
+/// `.` components and redundant separators.
+fn clean_path(path: &Path) -> String {
+    let mut cleaned = String::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => continue,
+            Component::RootDir => cleaned.push('/'),
+            component => {
+                if !cleaned.is_empty() && !cleaned.ends_with('/') {
+                    cleaned.push('/');
+                }
+                let component = component.as_os_str().to_string_lossy();
+                cleaned.push_str(&to_slashes(&component, path::MAIN_SEPARATOR));
+            }
+        }
+    }
+    cleaned
+}
+
+/// Replace `separator`, the native path separator, by `/` so that paths recorded on Windows match
+/// layouts written elsewhere. On other platforms `separator` is `/` and this is a no-op, leaving
+/// backslashes, which are valid in file names there, alone.
+fn to_slashes(path: &str, separator: char) -> String {
+    if separator == '/' {
+        path.to_string()
+    } else {
+        path.replace(separator, "/")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::crypto::{calculate_hash, SignatureScheme};
+    use crate::diagnostics::Severity;
+    use crate::interchange::DataInterchange;
+    use std::fs;
+
+    const ED25519_1_PK8: &[u8] = include_bytes!("../tests/ed25519/ed25519-1.pk8.der");
+
+    /// Recorded paths must be relative, so temporary directories are created in the crate root.
+    pub(super) fn relative(path: &Path) -> String {
+        let cwd = std::env::current_dir().unwrap();
+        clean_path(path.strip_prefix(cwd).unwrap())
+    }
+
+    #[test]
+    fn record_artifact_hashes_file() {
+        let (path, hashes) = record_artifact(
+            "./tests/test_runlib/release/artifact.txt",
+            &[HashAlgorithm::Sha256],
+        )
+        .unwrap();
+        assert_eq!(path.value(), "tests/test_runlib/release/artifact.txt");
+        assert_eq!(
+            hashes.get(&HashAlgorithm::Sha256),
+            Some(&calculate_hash(
+                b"release artifact\n",
+                HashAlgorithm::Sha256
+            ))
+        );
+    }
+
+    #[test]
+    fn record_artifacts_keeps_symlinked_root_prefix() {
+        let artifacts = record_artifacts(&["tests/test_runlib/current"], None).unwrap();
+        let paths = artifacts.keys().map(|p| p.value()).collect::<Vec<_>>();
+        assert_eq!(paths, vec!["tests/test_runlib/current/artifact.txt"]);
+
+        let respelled = record_artifacts(&["./tests/test_runlib/current/"], None).unwrap();
+        assert_eq!(respelled, artifacts);
+
+        let resolved = record_artifacts(&["tests/test_runlib/release"], None).unwrap();
+        assert_eq!(
+            resolved.values().collect::<Vec<_>>(),
+            artifacts.values().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn record_artifacts_with_hash_algorithms() {
+        let artifacts = record_artifacts(
+            &["tests/test_runlib/release"],
+            Some(&["sha256", "sha384", "sha512"]),
+        )
+        .unwrap();
+        for hashes in artifacts.values() {
+            assert!(hashes.contains_key(&HashAlgorithm::Sha256));
+            assert!(hashes.contains_key(&HashAlgorithm::Sha384));
+            assert!(hashes.contains_key(&HashAlgorithm::Sha512));
+        }
+
+        assert_eq!(
+            record_artifacts(&["tests/test_runlib/release"], Some(&["md5"])),
+            Err(Error::UnknownHashAlgorithm("md5".to_string()))
+        );
+        assert_eq!(
+            record_artifacts(&["tests/test_runlib/release"], Some(&["SHA-256"])),
+            record_artifacts(&["tests/test_runlib/release"], Some(&["sha256"]))
+        );
+    }
+
+    #[test]
+    fn record_artifacts_honors_intoto_ignore() {
+        let recorded = |options: &RecordOptions| {
+            record_artifacts_with_options(&["tests/test_runlib/ignore"], None, options)
+                .unwrap()
+                .keys()
+                .map(|p| p.value().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            recorded(&RecordOptions::new().honor_intoto_ignore(true)),
+            vec![
+                "tests/test_runlib/ignore/.in-toto-ignore",
+                "tests/test_runlib/ignore/docs/.in-toto-ignore",
+                "tests/test_runlib/ignore/docs/index.md",
+                "tests/test_runlib/ignore/drafts/notes.md",
+                "tests/test_runlib/ignore/src/main.c",
+            ]
+        );
+        assert_eq!(recorded(&RecordOptions::new()).len(), 8);
+    }
+
+    #[test]
+    fn record_artifacts_cb_reports_events_in_walk_order() {
+        let mut events = Vec::new();
+        let options = RecordOptions::new().honor_intoto_ignore(true);
+        let artifacts = record_artifacts_cb(
+            &["tests/test_runlib/ignore", "tests/test_runlib/missing"],
+            None,
+            &options,
+            |event| events.push(event),
+        )
+        .unwrap();
+        assert_eq!(artifacts.len(), 5);
+
+        let failed = events.pop().unwrap();
+        match failed {
+            RecordEvent::Failed { ref path, .. } => assert_eq!(path, "tests/test_runlib/missing"),
+            _ => panic!("unexpected event {:?}", failed),
+        }
+
+        let hashed = |path: &str, len| RecordEvent::Hashed {
+            path: format!("tests/test_runlib/ignore/{}", path),
+            len,
+        };
+        let ignored = |path: &str| RecordEvent::Skipped {
+            path: format!("tests/test_runlib/ignore/{}", path),
+            reason: SkipReason::Ignored,
+        };
+        assert_eq!(
+            events,
+            vec![
+                hashed(".in-toto-ignore", 64),
+                ignored("build"),
+                hashed("docs/.in-toto-ignore", 8),
+                ignored("docs/drafts"),
+                hashed("docs/index.md", 7),
+                hashed("drafts/notes.md", 6),
+                hashed("src/main.c", 29),
+                ignored("src/scratch.tmp"),
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn record_artifacts_best_effort_collects_errors() {
+        let dir = tempfile::tempdir_in(".").unwrap();
+        let root = PathBuf::from(relative(dir.path()));
+        fs::write(root.join("a.txt"), b"artifact").unwrap();
+        fs::write(root.join("c.txt"), b"artifact").unwrap();
+        // A link to a deleted file cannot be read, like a file removed during the walk.
+        std::os::unix::fs::symlink("deleted.txt", root.join("b.txt")).unwrap();
+        let root = root.to_str().unwrap();
+
+        assert!(record_artifacts(&[root], None).is_err());
+
+        let (artifacts, errors) =
+            record_artifacts_best_effort(&[root], None, &RecordOptions::new()).unwrap();
+        let recorded: Vec<_> = artifacts.keys().map(|path| path.value()).collect();
+        assert_eq!(
+            recorded,
+            vec![format!("{}/a.txt", root), format!("{}/c.txt", root)]
+        );
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0, format!("{}/b.txt", root));
+    }
+
+    #[test]
+    fn record_artifacts_try_cb_stops_on_error() {
+        let dir = tempfile::tempdir_in(".").unwrap();
+        let root = PathBuf::from(relative(dir.path()));
+        for index in 0..5 {
+            fs::write(root.join(format!("{}.txt", index)), b"artifact").unwrap();
+        }
+        let root = root.to_str().unwrap();
+
+        let mut hashed = Vec::new();
+        let result = record_artifacts_try_cb(&[root], None, &RecordOptions::new(), |event| {
+            if let RecordEvent::Hashed { path, len } = event {
+                if hashed.len() == 2 {
+                    return Err(Error::Opaque("cancelled".to_string()));
+                }
+                assert_eq!(len, 8);
+                hashed.push(path);
+            }
+            Ok(())
+        });
+        match result {
+            Err(Error::Opaque(message)) => assert_eq!(message, "cancelled"),
+            result => panic!("unexpected result {:?}", result),
+        }
+        assert_eq!(
+            hashed,
+            vec![format!("{}/0.txt", root), format!("{}/1.txt", root)]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn record_artifacts_reports_symlink_cycle_diagnostic() {
+        let dir = tempfile::tempdir_in(".").unwrap();
+        fs::write(dir.path().join("artifact.txt"), b"artifact").unwrap();
+        std::os::unix::fs::symlink(".", dir.path().join("loop")).unwrap();
+        let root = &relative(dir.path());
+
+        let mut diagnostics = Diagnostics::new();
+        let artifacts = record_artifacts_with_diagnostics(
+            &[root],
+            None,
+            &RecordOptions::new(),
+            &mut diagnostics,
+        )
+        .unwrap();
+        assert_eq!(artifacts.len(), 1);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics.records()[0].category(),
+            DiagnosticCategory::SymlinkCycle
+        );
+        assert_eq!(diagnostics.max_severity(), Some(Severity::Warning));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn record_artifacts_cb_reports_symlink_cycles() {
+        let dir = tempfile::tempdir_in(".").unwrap();
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        std::os::unix::fs::symlink("..", dir.path().join("sub/up")).unwrap();
+        std::os::unix::fs::symlink("sub", dir.path().join("down")).unwrap();
+        let root = &relative(dir.path());
+
+        let mut cycles = Vec::new();
+        let artifacts = record_artifacts_cb(&[root], None, &RecordOptions::new(), |event| {
+            if let RecordEvent::Skipped {
+                path,
+                reason: SkipReason::SymlinkCycle,
+            } = event
+            {
+                cycles.push(path);
+            }
+        })
+        .unwrap();
+        assert!(artifacts.is_empty());
+        assert_eq!(
+            cycles,
+            vec![format!("{}/down/up", root), format!("{}/sub/up", root)]
+        );
+    }
+
+    #[test]
+    fn record_artifacts_skips_exclude_patterns() {
+        let mut skipped = Vec::new();
+        let options = RecordOptions::new().exclude_patterns(&[
+            "build/",
+            "*.tmp",
+            "tests/test_runlib/ignore/docs/drafts",
+        ]);
+        let recorded = record_artifacts_cb(&["tests/test_runlib/ignore"], None, &options, |e| {
+            if let RecordEvent::Skipped { path, .. } = e {
+                skipped.push(path);
+            }
+        })
+        .unwrap();
+        assert_eq!(
+            recorded.keys().map(|p| p.value()).collect::<Vec<_>>(),
+            vec![
+                "tests/test_runlib/ignore/.in-toto-ignore",
+                "tests/test_runlib/ignore/docs/.in-toto-ignore",
+                "tests/test_runlib/ignore/docs/index.md",
+                "tests/test_runlib/ignore/drafts/notes.md",
+                "tests/test_runlib/ignore/src/main.c",
+            ]
+        );
+        // Excluded directories are pruned rather than walked.
+        assert_eq!(
+            skipped,
+            vec![
+                "tests/test_runlib/ignore/build",
+                "tests/test_runlib/ignore/docs/drafts",
+                "tests/test_runlib/ignore/src/scratch.tmp",
+            ]
+        );
+
+        let invalid = RecordOptions::new().exclude_patterns(&["!keep"]);
+        assert!(record_artifacts_with_options(&["tests/test_runlib"], None, &invalid).is_err());
+    }
+
+    #[test]
+    fn record_artifacts_lstrips_paths() {
+        let recorded = |paths: &[&str], prefixes: &[&str]| {
+            let options = RecordOptions::new().lstrip_paths(prefixes);
+            record_artifacts_with_options(paths, None, &options).map(|artifacts| {
+                artifacts
+                    .keys()
+                    .map(|p| p.value().to_string())
+                    .collect::<Vec<_>>()
+            })
+        };
+        let release = "tests/test_runlib/release";
+
+        // The first matching prefix wins, and a missing trailing slash is tolerated.
+        assert_eq!(
+            recorded(&[release], &["tests/", "tests/test_runlib/"]),
+            Ok(vec!["test_runlib/release/artifact.txt".to_string()])
+        );
+        assert_eq!(
+            recorded(&[release], &["tests/test_runlib/", "tests/"]),
+            Ok(vec!["release/artifact.txt".to_string()])
+        );
+        assert_eq!(
+            recorded(&[release], &[release]),
+            Ok(vec!["artifact.txt".to_string()])
+        );
+
+        // Recording the same file twice is not a collision, two files stripped to one name is.
+        assert!(recorded(&[release, release], &[release]).is_ok());
+        assert!(recorded(
+            &[release, "tests/test_runlib/current"],
+            &[release, "tests/test_runlib/current/"]
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn record_artifacts_lstrips_absolute_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        fs::write(dir.path().join("sub").join("file.txt"), b"content").unwrap();
+        let root = clean_path(dir.path());
+        assert!(root.starts_with('/'));
+
+        // Without a prefix the absolute path cannot be recorded.
+        assert!(record_artifacts(&[root.as_str()], None).is_err());
+
+        let prefix = format!("{}/", root);
+        let options = RecordOptions::new().lstrip_paths(&[prefix.as_str()]);
+        let artifacts = record_artifacts_with_options(&[root.as_str()], None, &options).unwrap();
+        let paths = artifacts.keys().map(|p| p.value()).collect::<Vec<_>>();
+        assert_eq!(paths, vec!["sub/file.txt"]);
+
+        let file = dir.path().join("sub").join("file.txt");
+        let (path, hashes) =
+            record_artifact_with_options(&file, &[HashAlgorithm::Sha256], &options).unwrap();
+        assert_eq!(path.value(), "sub/file.txt");
+        assert_eq!(Some(&hashes), artifacts.values().next());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn record_artifacts_lstrips_absolute_symlinked_root() {
+        let dir = tempfile::tempdir().unwrap();
+        let release = dir.path().join("v1.2");
+        fs::create_dir_all(release.join("bin")).unwrap();
+        fs::write(release.join("bin").join("app"), b"app").unwrap();
+        let current = dir.path().join("current");
+        std::os::unix::fs::symlink(&release, &current).unwrap();
+
+        // Keys start with the root as given, not with the directory it resolves to.
+        let root = clean_path(&current);
+        let prefix = clean_path(dir.path());
+        let options = RecordOptions::new().lstrip_paths(&[prefix.as_str()]);
+        let recorded = |root: &str| {
+            record_artifacts_with_options(&[root], None, &options)
+                .unwrap()
+                .into_keys()
+                .map(|p| p.value().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(recorded(&root), vec!["current/bin/app"]);
+        assert_eq!(recorded(&format!("{}/./", root)), vec!["current/bin/app"]);
+        assert_eq!(recorded(&clean_path(&release)), vec!["v1.2/bin/app"]);
+    }
+
+    #[test]
+    fn record_artifacts_skips_hidden_and_limits_depth() {
+        let recorded = |paths: &[&str], options: &RecordOptions| {
+            record_artifacts_with_options(paths, None, options)
+                .unwrap()
+                .keys()
+                .map(|p| p.value().to_string())
+                .collect::<Vec<_>>()
+        };
+        let hidden = "tests/test_runlib/.hidden/.bar".to_string();
+
+        assert!(recorded(&["tests/test_runlib"], &RecordOptions::new()).contains(&hidden));
+        let visible = recorded(
+            &["tests/test_runlib"],
+            &RecordOptions::new().skip_hidden(true),
+        );
+        assert!(!visible.contains(&hidden));
+        assert!(!visible.iter().any(|p| p.contains("/.")));
+        assert!(visible.contains(&"tests/test_runlib/release/artifact.txt".to_string()));
+
+        // A hidden directory prunes its subtree, but paths given to record are kept.
+        let mut events = Vec::new();
+        let options = RecordOptions::new().skip_hidden(true);
+        record_artifacts_cb(&["tests/test_runlib"], None, &options, |event| {
+            events.push(event)
+        })
+        .unwrap();
+        assert!(events.contains(&RecordEvent::Skipped {
+            path: "tests/test_runlib/.hidden".to_string(),
+            reason: SkipReason::Hidden,
+        }));
+        assert!(!events
+            .iter()
+            .any(|event| format!("{:?}", event).contains(".hidden/.bar")));
+        assert_eq!(
+            recorded(&["tests/test_runlib/.hidden"], &options),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            recorded(&["tests/test_runlib/.hidden/.bar"], &options),
+            vec![hidden]
+        );
+
+        let top_level = RecordOptions::new().max_depth(Some(1));
+        assert_eq!(
+            recorded(&["tests/test_runlib/ignore"], &top_level),
+            vec!["tests/test_runlib/ignore/.in-toto-ignore"]
+        );
+        assert_eq!(
+            recorded(&["tests/test_runlib/release/artifact.txt"], &top_level),
+            vec!["tests/test_runlib/release/artifact.txt"]
+        );
+    }
+
+    #[test]
+    fn record_artifacts_normalizes_line_endings() {
+        let dir = tempfile::tempdir_in(".").unwrap();
+        fs::write(dir.path().join("unix.txt"), b"one\ntwo\n").unwrap();
+        fs::write(dir.path().join("windows.txt"), b"one\r\ntwo\r\n").unwrap();
+        fs::write(dir.path().join("windows.bin"), b"one\r\ntwo\r\n").unwrap();
+        fs::write(dir.path().join("image.png"), b"\x89PNG\r\n\x1a\n\0").unwrap();
+        let root = &relative(dir.path());
+        let sha256 = |options: &RecordOptions, name: &str| {
+            let path = format!("{}/{}", root, name);
+            record_artifact_with_options(&path, &[HashAlgorithm::Sha256], options)
+                .unwrap()
+                .1[&HashAlgorithm::Sha256]
+                .clone()
+        };
+        let raw = |name: &str| {
+            calculate_hash(
+                &fs::read(dir.path().join(name)).unwrap(),
+                HashAlgorithm::Sha256,
+            )
+        };
+
+        let default = RecordOptions::new();
+        assert_ne!(
+            sha256(&default, "unix.txt"),
+            sha256(&default, "windows.txt")
+        );
+
+        let options = RecordOptions::new()
+            .normalize_line_endings(true)
+            .binary_patterns(&["*.bin"]);
+        assert_eq!(sha256(&options, "windows.txt"), raw("unix.txt"));
+        assert_eq!(sha256(&options, "unix.txt"), raw("unix.txt"));
+        assert_eq!(sha256(&options, "windows.bin"), raw("windows.bin"));
+        assert_eq!(sha256(&options, "image.png"), raw("image.png"));
+
+        let artifacts = record_artifacts_with_options(&[root], None, &options).unwrap();
+        assert_eq!(
+            artifacts[&VirtualTargetPath::new(format!("{}/windows.txt", root)).unwrap()],
+            artifacts[&VirtualTargetPath::new(format!("{}/unix.txt", root)).unwrap()]
+        );
+    }
+
+    #[test]
+    fn record_artifacts_keeps_file_metadata_out_of_hashes() {
+        let path = "tests/test_runlib/release/artifact.txt";
+        let target = VirtualTargetPath::new(path.to_string()).unwrap();
+
+        let default = record_artifacts(&[path], None).unwrap();
+        let (artifacts, metadata) =
+            record_artifacts_with_file_metadata(&[path], None, &RecordOptions::new()).unwrap();
+        // Target descriptions only hold hashes either way, so links recorded with and without
+        // file metadata match.
+        assert_eq!(artifacts, default);
+        assert_eq!(
+            artifacts[&target].keys().collect::<Vec<_>>(),
+            vec![&HashAlgorithm::Sha256]
+        );
+
+        let fs_metadata = fs::metadata(path).unwrap();
+        assert_eq!(metadata[&target].size, fs_metadata.len());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(
+                metadata[&target].mode,
+                Some(fs_metadata.permissions().mode() & 0o7777)
+            );
+        }
+
+        // The metadata is stored in the custom fields, covered by the signature.
+        let file_metadata = LinkFileMetadata {
+            materials: BTreeMap::new(),
+            products: metadata,
+        };
+        let mut custom = BTreeMap::new();
+        file_metadata.add_to(&mut custom).unwrap();
+        let key = PrivateKey::from_pkcs8(ED25519_1_PK8, SignatureScheme::Ed25519).unwrap();
+        let signed = LinkMetadataBuilder::new()
+            .name("build".to_string())
+            .products(artifacts.clone())
+            .custom(custom)
+            .signed::<Json>(&key)
+            .unwrap();
+        let json = serde_json::to_value(&signed).unwrap();
+        assert_eq!(
+            json["signed"]["custom"][FILE_METADATA_KEY]["products"][path]["size"],
+            17
+        );
+        let read: SignedMetadata<Json, LinkMetadata> = serde_json::from_value(json).unwrap();
+        let link = read.verify(1, vec![key.public()]).unwrap();
+        assert_eq!(link.products(), &artifacts);
+        assert_eq!(
+            LinkFileMetadata::from_link(&link).unwrap(),
+            Some(file_metadata)
+        );
+
+        let plain = LinkMetadataBuilder::new().build().unwrap();
+        assert_eq!(LinkFileMetadata::from_link(&plain).unwrap(), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn record_artifacts_without_following_symlinks() {
+        let dir = tempfile::tempdir_in(".").unwrap();
+        fs::write(dir.path().join("artifact.txt"), b"artifact").unwrap();
+        std::os::unix::fs::symlink("artifact.txt", dir.path().join("link")).unwrap();
+        std::os::unix::fs::symlink(".", dir.path().join("loop")).unwrap();
+        let root = &relative(dir.path());
+        let sha256 = |artifacts: &BTreeMap<VirtualTargetPath, TargetDescription>, name: &str| {
+            let path = VirtualTargetPath::new(format!("{}/{}", root, name)).unwrap();
+            artifacts[&path][&HashAlgorithm::Sha256].clone()
+        };
+
+        let mut diagnostics = Diagnostics::new();
+        let options = RecordOptions::new().follow_symlinks(false);
+        let links =
+            record_artifacts_with_diagnostics(&[root], None, &options, &mut diagnostics).unwrap();
+        assert_eq!(links.len(), 3);
+        assert_eq!(
+            sha256(&links, "link"),
+            calculate_hash(b"artifact.txt", HashAlgorithm::Sha256)
+        );
+        assert_eq!(
+            sha256(&links, "loop"),
+            calculate_hash(b".", HashAlgorithm::Sha256)
+        );
+        assert!(diagnostics.is_empty());
+
+        let followed = record_artifacts(&[root], None).unwrap();
+        assert_eq!(followed.len(), 2);
+        assert_eq!(sha256(&followed, "link"), sha256(&followed, "artifact.txt"));
+    }
+
+    #[test]
+    fn record_artifacts_accepts_paths_with_spaces_and_unicode() {
+        let dir = tempfile::tempdir_in(".").unwrap();
+        let sub = PathBuf::from(relative(dir.path())).join("caf\u{e9} cr\u{e8}me");
+        fs::create_dir(&sub).unwrap();
+        fs::write(sub.join("r\u{e9}sum\u{e9} \u{1f980}.txt"), b"artifact").unwrap();
+        let expected = VirtualTargetPath::new(format!(
+            "{}/caf\u{e9} cr\u{e8}me/r\u{e9}sum\u{e9} \u{1f980}.txt",
+            relative(dir.path())
+        ))
+        .unwrap();
+
+        let artifacts = record_artifacts_paths(&[&sub], None, &RecordOptions::new()).unwrap();
+        assert_eq!(artifacts.keys().collect::<Vec<_>>(), vec![&expected]);
+        assert_eq!(
+            record_artifacts(&[sub.to_str().unwrap()], None).unwrap(),
+            artifacts
+        );
+        let (path, hashes) = record_artifact(
+            sub.join("r\u{e9}sum\u{e9} \u{1f980}.txt"),
+            &[HashAlgorithm::Sha256],
+        )
+        .unwrap();
+        assert_eq!(path, expected);
+        assert_eq!(&hashes, &artifacts[&expected]);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn record_artifacts_rejects_names_that_are_not_utf8() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let dir = tempfile::tempdir_in(".").unwrap();
+        let root = PathBuf::from(relative(dir.path()));
+        fs::write(root.join(OsStr::from_bytes(b"bad\xffname")), b"artifact").unwrap();
+        let root = root.to_str().unwrap();
+
+        let mut events = Vec::new();
+        let artifacts =
+            record_artifacts_cb(&[root], None, &RecordOptions::new(), |e| events.push(e)).unwrap();
+        assert!(artifacts.is_empty());
+        match &events[..] {
+            [RecordEvent::Failed { path, error }] => {
+                assert!(path.ends_with("bad\u{fffd}name"));
+                assert!(error.to_string().contains("not valid UTF-8"));
+            }
+            events => panic!("unexpected events {:?}", events),
+        }
+        assert!(record_artifacts(&[root], None).is_err());
+    }
+
+    #[test]
+    fn record_artifacts_of_no_paths() {
+        assert!(record_artifacts(&[], None).unwrap().is_empty());
+        assert!(
+            record_artifacts_with_options(&[], None, &RecordOptions::new())
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn recorded_paths_use_forward_slashes() {
+        assert_eq!(
+            to_slashes(r"src\models\helpers.rs", '\\'),
+            "src/models/helpers.rs"
+        );
+        assert_eq!(to_slashes(r"back\slash.txt", '/'), r"back\slash.txt");
+        assert_eq!(
+            clean_path(Path::new("./src//models/./helpers.rs")),
+            "src/models/helpers.rs"
+        );
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn record_artifacts_in_parallel_matches_serial() {
+        let events = |options: &RecordOptions| {
+            let mut events = Vec::new();
+            let artifacts = record_artifacts_cb(
+                &["tests/test_runlib/ignore", "tests/test_runlib/release"],
+                None,
+                options,
+                |event| events.push(event),
+            )
+            .unwrap();
+            (artifacts, events)
+        };
+        let serial = RecordOptions::new().honor_intoto_ignore(true);
+        assert_eq!(events(&serial.clone().parallel(true)), events(&serial));
+
+        let missing = RecordOptions::new().parallel(true);
+        assert!(
+            record_artifacts_with_options(&["tests/test_runlib/missing"], None, &missing).is_err()
+        );
+    }
+
+    #[test]
+    fn record_artifacts_reuses_hash_cache() {
+        let dir = tempfile::tempdir_in(".").unwrap();
+        let path = dir.path().join("artifact.txt");
+        fs::write(&path, b"before").unwrap();
+        let root = &relative(dir.path());
+
+        let cache = HashCache::new();
+        let options = RecordOptions::new().hash_cache(cache.clone());
+        let first = record_artifacts_with_options(&[root], None, &options).unwrap();
+        assert_eq!(cache.len(), 1);
+        assert_eq!(
+            record_artifacts_with_options(&[root], None, &options).unwrap(),
+            first
+        );
+
+        fs::write(&path, b"after, and longer").unwrap();
+        let second = record_artifacts_with_options(&[root], None, &options).unwrap();
+        assert_eq!(second, record_artifacts(&[root], None).unwrap());
+        assert_ne!(second, first);
+    }
+
+    #[test]
+    fn run_command_records_byproducts() {
+        let byproducts = run_command(
+            &["sh", "-c", "echo out; echo err >&2; exit 3"],
+            None,
+            None,
+            None,
+        )
+        .unwrap();
+        assert_eq!(byproducts.stdout.as_text(), Some("out\n"));
+        assert_eq!(byproducts.stderr.as_text(), Some("err\n"));
+        assert_eq!(byproducts.exit_code, Some(3));
+        assert_eq!(byproducts.signal, None);
+
+        let byproducts = run_command(&["pwd"], Some("tests/test_runlib"), None, None).unwrap();
+        let pwd = byproducts.stdout.as_text().unwrap();
+        assert!(pwd.trim_end().ends_with("tests/test_runlib"));
+
+        assert!(run_command(&[], None, None, None).is_err());
+        assert_eq!(
+            run_command(&["in-toto-no-such-command"], None, None, None).unwrap_err(),
+            Error::CommandNotFound("in-toto-no-such-command".to_string())
+        );
+    }
+
+    #[test]
+    fn run_command_feeds_stdin() {
+        // Larger than a pipe buffer, so `cat` blocks writing stdout unless it is drained while
+        // stdin is still being fed.
+        let input = b"in-toto\n".repeat(256 * 1024);
+        let byproducts = run_command(&["cat"], None, None, Some(&input)).unwrap();
+        assert_eq!(byproducts.stdout.as_bytes(), &input[..]);
+        assert_eq!(byproducts.exit_code, Some(0));
+
+        let byproducts = run_command(&["true"], None, None, Some(&input)).unwrap();
+        assert_eq!(byproducts.exit_code, Some(0));
+    }
+
+    #[test]
+    fn run_command_records_binary_output() {
+        let byproducts = run_command(&["printf", "\\377\\376"], None, None, None).unwrap();
+        assert_eq!(byproducts.stdout, CommandOutput::Binary(vec![0xff, 0xfe]));
+        assert_eq!(byproducts.exit_code, Some(0));
+
+        let map = BTreeMap::from(byproducts);
+        assert!(!map.contains_key("stdout"));
+        assert_eq!(map["stdout-b64"], "//4=");
+        assert_eq!(map["stderr"], "");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn run_command_records_signal() {
+        let byproducts =
+            run_command(&["sh", "-c", "echo dying; kill -9 $$"], None, None, None).unwrap();
+        assert_eq!(byproducts.stdout.as_text(), Some("dying\n"));
+        assert_eq!(byproducts.exit_code, None);
+        assert_eq!(byproducts.signal.as_deref(), Some("SIGKILL"));
+
+        let map = BTreeMap::from(byproducts);
+        assert!(!map.contains_key("return-value"));
+        assert_eq!(map["signal"], "SIGKILL");
+    }
+
+    #[test]
+    fn run_command_times_out() {
+        let timeout = Some(Duration::from_secs(10));
+        let byproducts = run_command(&["sh", "-c", "echo fast"], None, timeout, None).unwrap();
+        assert_eq!(byproducts.stdout.as_text(), Some("fast\n"));
+
+        let timeout = Duration::from_millis(200);
+        let started = Instant::now();
+        let err = run_command(
+            &["sh", "-c", "echo partial; echo oops >&2; exec sleep 30"],
+            None,
+            Some(timeout),
+            None,
+        )
+        .unwrap_err();
+        assert!(started.elapsed() < Duration::from_secs(10));
+        assert_eq!(
+            err,
+            Error::CommandTimeout {
+                timeout,
+                stdout: "partial\n".into(),
+                stderr: "oops\n".into(),
+            }
+        );
+    }
+
+    #[test]
+    fn in_toto_run_records_step() {
+        let key = PrivateKey::from_pkcs8(ED25519_1_PK8, SignatureScheme::Ed25519).unwrap();
+        let dir = tempfile::tempdir_in(".").unwrap();
+        let product = dir.path().join("copy.txt");
+        let product_dir = &relative(dir.path());
+
+        let signed = in_toto_run(
+            "copy",
+            None,
+            &["tests/test_runlib/release"],
+            &[product_dir],
+            &[
+                "cp",
+                "tests/test_runlib/release/artifact.txt",
+                product.to_str().unwrap(),
+            ],
+            &key,
+            None,
+        )
+        .unwrap();
+        let link = signed.verify(1, vec![key.public()]).unwrap();
+
+        assert_eq!(link.name(), "copy");
+        assert_eq!(
+            link.materials(),
+            &record_artifacts(&["tests/test_runlib/release"], None).unwrap()
+        );
+        assert_eq!(
+            link.products().values().collect::<Vec<_>>(),
+            link.materials().values().collect::<Vec<_>>()
+        );
+        assert_eq!(link.byproducts()["return-value"], "0");
+    }
+
+    #[test]
+    fn in_toto_run_dsse_wraps_link() {
+        let key = PrivateKey::from_pkcs8(ED25519_1_PK8, SignatureScheme::Ed25519).unwrap();
+        let envelope = in_toto_run_dsse(
+            "package",
+            &["tests/test_runlib/release"],
+            &["tests/test_runlib/release"],
+            &[],
+            &key,
+            None,
+            &RunOptions::new(),
+        )
+        .unwrap();
+
+        let payload = envelope.verify(1, vec![key.public()]).unwrap();
+        let statement: serde_json::Value = serde_json::from_slice(payload).unwrap();
+        let link: LinkMetadata = serde_json::from_value(statement["predicate"].clone()).unwrap();
+        assert_eq!(link.name(), "package");
+        assert_eq!(
+            statement["subject"][0]["name"],
+            "tests/test_runlib/release/artifact.txt"
+        );
+    }
+
+    #[test]
+    fn in_toto_run_multi_signs_one_recording() {
+        let key = PrivateKey::from_pkcs8(ED25519_1_PK8, SignatureScheme::Ed25519).unwrap();
+        let outputs = in_toto_run_multi(
+            "package",
+            &["tests/test_runlib/release"],
+            &["tests/test_runlib/release"],
+            &[],
+            &key,
+            None,
+            &RunOptions::new(),
+            &[OutputFormat::Link, OutputFormat::Dsse],
+        )
+        .unwrap();
+
+        let (signed, envelope) = match &outputs[..] {
+            [SignedOutput::Link(signed), SignedOutput::Dsse(envelope)] => (signed, envelope),
+            other => panic!("unexpected outputs: {:?}", other),
+        };
+        let link = signed.verify(1, vec![key.public()]).unwrap();
+        let payload = envelope.verify(1, vec![key.public()]).unwrap();
+        let statement: serde_json::Value = serde_json::from_slice(payload).unwrap();
+        let wrapped: LinkMetadata = serde_json::from_value(statement["predicate"].clone()).unwrap();
+        assert!(!link.products().is_empty());
+        assert_eq!(wrapped.products(), link.products());
+        assert_eq!(wrapped.materials(), link.materials());
+        let subjects = statement["subject"].as_array().unwrap();
+        assert_eq!(subjects.len(), link.products().len());
+    }
+
+    #[test]
+    fn in_toto_run_with_algorithms_matches_named_algorithms() {
+        let key = PrivateKey::from_pkcs8(ED25519_1_PK8, SignatureScheme::Ed25519).unwrap();
+        let paths = ["tests/test_runlib/release"];
+        let run = |algorithms: &[HashAlgorithm]| {
+            in_toto_run_with_algorithms(
+                "package",
+                &paths,
+                &paths,
+                &[],
+                &key,
+                algorithms,
+                &RunOptions::new(),
+            )
+        };
+
+        let link = run(&[HashAlgorithm::Sha256, HashAlgorithm::Sha512])
+            .unwrap()
+            .assume_valid()
+            .unwrap();
+        let named = in_toto_run_with_options(
+            "package",
+            &paths,
+            &paths,
+            &[],
+            &key,
+            Some(&["sha256", "sha512"]),
+            &RunOptions::new(),
+        )
+        .unwrap()
+        .assume_valid()
+        .unwrap();
+        assert_eq!(link, named);
+        assert_eq!(link.materials().values().next().unwrap().len(), 2);
+
+        assert!(run(&[]).is_err());
+    }
+
+    #[test]
+    fn in_toto_run_records_env() {
+        let key = PrivateKey::from_pkcs8(ED25519_1_PK8, SignatureScheme::Ed25519).unwrap();
+        let mut env = BTreeMap::new();
+        let _ = env.insert("IN_TOTO_TEST_GREETING".to_string(), "hello".to_string());
+        let options = RunOptions::new()
+            .run_dir("tests/test_runlib")
+            .env(env)
+            .record_env(&["PATH", "IN_TOTO_TEST_UNSET"]);
+
+        let signed = in_toto_run_with_options(
+            "greet",
+            &[],
+            &[],
+            &["sh", "-c", "echo $IN_TOTO_TEST_GREETING"],
+            &key,
+            None,
+            &options,
+        )
+        .unwrap();
+        let jsn = serde_json::to_value(&signed).unwrap();
+        let recorded = jsn["signed"]["environment"].as_object().unwrap();
+
+        assert_eq!(jsn["signed"]["byproducts"]["stdout"], "hello\n");
+        assert_eq!(recorded["IN_TOTO_TEST_GREETING"], "hello");
+        assert_eq!(recorded["PATH"], std::env::var("PATH").unwrap().as_str());
+        assert!(recorded["workdir"]
+            .as_str()
+            .unwrap()
+            .ends_with("tests/test_runlib"));
+        assert!(!recorded.contains_key("IN_TOTO_TEST_UNSET"));
+    }
+
+    #[test]
+    fn in_toto_run_fails_on_nonzero_exit_when_asked() {
+        let key = PrivateKey::from_pkcs8(ED25519_1_PK8, SignatureScheme::Ed25519).unwrap();
+        let run = |options: &RunOptions| {
+            in_toto_run_with_options(
+                "fail",
+                &[],
+                &[],
+                &["sh", "-c", "echo oops >&2; exit 3"],
+                &key,
+                None,
+                options,
+            )
+        };
+
+        let link = run(&RunOptions::new()).unwrap().assume_valid().unwrap();
+        assert_eq!(link.byproducts()["return-value"], "3");
+
+        assert_eq!(
+            run(&RunOptions::new().fail_on_nonzero_exit(true)).unwrap_err(),
+            Error::CommandFailed {
+                code: Some(3),
+                stderr: "oops\n".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn record_artifacts_from_bytes_matches_recording_files() {
+        let dir = tempfile::tempdir_in(".").unwrap();
+        let root = relative(dir.path());
+        let entries: &[(&str, &[u8])] = &[
+            ("hello.txt", b"hello\n"),
+            ("nested/empty", b""),
+            ("nested/binary", &[0, 0xff, b'\r', b'\n']),
+        ];
+        for (path, content) in entries {
+            let path = dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        let in_memory: Vec<(String, &[u8])> = entries
+            .iter()
+            .map(|(path, content)| (format!("./{}/{}", root, path), *content))
+            .collect();
+        let in_memory: Vec<(&str, &[u8])> = in_memory
+            .iter()
+            .map(|(path, content)| (path.as_str(), *content))
+            .collect();
+
+        let algorithms: &[&str] = &["sha256", "sha512"];
+        assert_eq!(
+            record_artifacts_from_bytes(&in_memory, Some(algorithms)).unwrap(),
+            record_artifacts(&[&root], Some(algorithms)).unwrap()
+        );
+
+        assert!(record_artifacts_from_bytes(&[("a", b"1"), ("./a", b"2")], None).is_err());
+    }
+
+    #[test]
+    fn in_toto_run_output_is_reproducible() {
+        let key = PrivateKey::from_pkcs8(ED25519_1_PK8, SignatureScheme::Ed25519).unwrap();
+        let options = RunOptions::new().record_env(&["PATH"]);
+        // Several algorithms, so the hashes of each artifact are a map with more than one entry.
+        let run = || {
+            let link = in_toto_run_with_options(
+                "package",
+                &["tests/test_runlib"],
+                &["tests/test_runlib"],
+                &["sh", "-c", "echo built"],
+                &key,
+                Some(&["sha256", "sha512", "sha384"]),
+                &options,
+            )
+            .unwrap();
+            let mut pretty = Vec::new();
+            link.write(&mut pretty).unwrap();
+            let mut canonical = Vec::new();
+            Json::to_writer(&mut canonical, &link).unwrap();
+            (pretty, canonical)
+        };
+
+        let (pretty, canonical) = run();
+        assert_eq!(run(), (pretty.clone(), canonical));
+
+        let pretty = String::from_utf8(pretty).unwrap();
+        let sha256 = pretty.find("\"sha256\"").unwrap();
+        assert!(pretty.find("\"sha384\"").unwrap() > sha256);
+        assert!(pretty.find("\"sha512\"").unwrap() > sha256);
+    }
+
+    #[test]
+    fn record_artifacts_missing_path() {
+        assert!(record_artifacts(&["tests/test_runlib/missing"], None).is_err());
+    }
+}
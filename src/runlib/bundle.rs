@@ -0,0 +1,152 @@
+//! Content-addressed storage for the bytes of recorded artifacts.
+//!
+//! A bundle is a directory shipped alongside a link that holds the content of the link's
+//! artifacts. Each blob is stored at `sha256/<hex digest>`, so artifacts with identical content
+//! are stored once no matter how many paths they were recorded under.
+
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
+
+use crate::crypto::{self, HashAlgorithm};
+use crate::error::Error;
+use crate::models::{LinkMetadata, TargetDescription, VirtualTargetPath};
+use crate::staging::staged_file;
+use crate::Result;
+
+/// Directory, and hash algorithm, blobs are addressed by.
+const BLOB_DIR: &str = "sha256";
+
+/// A content-addressed store of artifact content.
+#[derive(Debug, Clone)]
+pub struct Bundle {
+    dir: PathBuf,
+}
+
+impl Bundle {
+    /// Open the bundle in `dir`, creating it if it does not exist.
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        let blobs = dir.join(BLOB_DIR);
+        fs::create_dir_all(&blobs).map_err(|e| Error::from_io(&e, &blobs))?;
+        Ok(Bundle { dir })
+    }
+
+    /// Store the content of each of `artifacts`, read from the path it was recorded under.
+    /// Content already in the bundle is not stored again.
+    ///
+    /// Every artifact must have a SHA-256 digest, and a file whose content no longer matches its
+    /// recorded hashes is rejected.
+    pub fn add_artifacts(
+        &self,
+        artifacts: &BTreeMap<VirtualTargetPath, TargetDescription>,
+    ) -> Result<()> {
+        for (path, hashes) in artifacts {
+            let blob = self.blob_path(path, hashes)?;
+            if blob.exists() {
+                continue;
+            }
+
+            let source = Path::new(path.value());
+            let mut file = File::open(source).map_err(|e| Error::from_io(&e, source))?;
+            let mut staged = staged_file(&self.dir).map_err(|e| Error::from_io(&e, &self.dir))?;
+            let _ = io::copy(&mut file, &mut staged).map_err(|e| Error::from_io(&e, source))?;
+
+            check_blob(staged.path(), path, hashes)?;
+            let _ = staged
+                .persist(&blob)
+                .map_err(|e| Error::from_io(&e.error, &blob))?;
+        }
+        Ok(())
+    }
+
+    /// The number of distinct blobs in the bundle.
+    pub fn len(&self) -> Result<usize> {
+        let blobs = self.dir.join(BLOB_DIR);
+        let entries = fs::read_dir(&blobs).map_err(|e| Error::from_io(&e, &blobs))?;
+        Ok(entries.count())
+    }
+
+    /// Whether the bundle holds no blobs.
+    pub fn is_empty(&self) -> Result<bool> {
+        self.len().map(|len| len == 0)
+    }
+
+    /// Check that every material and product of `link` has a blob in the bundle whose content
+    /// matches all of the artifact's recorded hashes.
+    pub fn verify_contents(&self, link: &LinkMetadata) -> Result<()> {
+        for (path, hashes) in link.materials().iter().chain(link.products()) {
+            let blob = self.blob_path(path, hashes)?;
+            if !blob.is_file() {
+                return Err(Error::VerificationFailure(format!(
+                    "Bundle has no content for artifact {:?}",
+                    path.value()
+                )));
+            }
+            check_blob(&blob, path, hashes)?;
+        }
+        Ok(())
+    }
+
+    fn blob_path(&self, path: &VirtualTargetPath, hashes: &TargetDescription) -> Result<PathBuf> {
+        let digest = hashes.get(&HashAlgorithm::Sha256).ok_or_else(|| {
+            Error::IllegalArgument(format!(
+                "Artifact {:?} has no sha256 digest to address its content by",
+                path.value()
+            ))
+        })?;
+        Ok(self.dir.join(BLOB_DIR).join(digest.to_string()))
+    }
+}
+
+/// Check that the content of `blob` matches the recorded `hashes` of the artifact at `path`.
+fn check_blob(blob: &Path, path: &VirtualTargetPath, hashes: &TargetDescription) -> Result<()> {
+    let algorithms = hashes.keys().cloned().collect::<Vec<_>>();
+    let file = File::open(blob).map_err(|e| Error::from_io(&e, blob))?;
+    let (_, actual) = crypto::calculate_hashes(BufReader::new(file), &algorithms)?;
+    if &actual != hashes {
+        return Err(Error::VerificationFailure(format!(
+            "Content of artifact {:?} does not match its recorded hashes",
+            path.value()
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::models::LinkMetadataBuilder;
+    use crate::runlib::record_artifacts;
+
+    #[test]
+    fn bundle_stores_duplicate_content_once() {
+        let tree = tempfile::tempdir_in(".").unwrap();
+        fs::write(tree.path().join("a.txt"), b"shared").unwrap();
+        fs::write(tree.path().join("b.txt"), b"shared").unwrap();
+        fs::write(tree.path().join("c.txt"), b"unique").unwrap();
+        let cwd = std::env::current_dir().unwrap();
+        let root = tree.path().strip_prefix(cwd).unwrap().to_str().unwrap();
+        let products = record_artifacts(&[root], None).unwrap();
+        let link = LinkMetadataBuilder::new()
+            .name("build".to_string())
+            .products(products.clone())
+            .build()
+            .unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let bundle = Bundle::open(dir.path()).unwrap();
+        assert!(bundle.verify_contents(&link).is_err());
+        bundle.add_artifacts(&products).unwrap();
+        assert_eq!(bundle.len(), Ok(2));
+        assert_eq!(bundle.verify_contents(&link), Ok(()));
+
+        let (_, shared) = products.iter().next().unwrap();
+        let blob = bundle
+            .blob_path(&VirtualTargetPath::new("a.txt".into()).unwrap(), shared)
+            .unwrap();
+        fs::write(blob, b"tampered").unwrap();
+        assert!(bundle.verify_contents(&link).is_err());
+    }
+}
@@ -0,0 +1,105 @@
+//! A cache of file digests shared between recording passes.
+
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::fs::{self, Metadata};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use crate::crypto::HashAlgorithm;
+use crate::models::TargetDescription;
+
+/// The filesystem state a cached digest was computed for. A file whose stamp changed is hashed
+/// again.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct FileStamp {
+    len: u64,
+    modified: Option<SystemTime>,
+    #[cfg(unix)]
+    inode: (u64, u64),
+    #[cfg(unix)]
+    changed: (i64, i64),
+}
+
+impl FileStamp {
+    fn new(metadata: &Metadata) -> Self {
+        #[cfg(unix)]
+        use std::os::unix::fs::MetadataExt;
+
+        FileStamp {
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+            #[cfg(unix)]
+            inode: (metadata.dev(), metadata.ino()),
+            #[cfg(unix)]
+            changed: (metadata.ctime(), metadata.ctime_nsec()),
+        }
+    }
+}
+
+/// Digests of recorded files, keyed by path and invalidated when a file's size, modification
+/// time, or (on Unix) inode or status change time differ.
+///
+/// Sharing a cache between the material and product passes of a step means files the command
+/// did not touch are hashed only once. Cloning a `HashCache` yields a handle to the same cache.
+///
+/// On Unix, writing to a file always updates its status change time, which cannot be set back
+/// by the writer, so a file modified between passes is never served from the cache. Elsewhere
+/// the cache relies on the modification time and size alone.
+#[derive(Debug, Clone, Default)]
+pub struct HashCache {
+    entries: Arc<Mutex<HashMap<PathBuf, (FileStamp, TargetDescription)>>>,
+}
+
+impl HashCache {
+    /// Create an empty cache.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The number of files with cached digests.
+    pub fn len(&self) -> usize {
+        self.entries.lock().len()
+    }
+
+    /// Whether the cache holds no digests.
+    pub fn is_empty(&self) -> bool {
+        self.entries.lock().is_empty()
+    }
+
+    /// The cached digests of `path` for `hash_algorithms` and its size, if `path` is unchanged
+    /// since they were computed.
+    pub(crate) fn get(
+        &self,
+        path: &Path,
+        hash_algorithms: &[HashAlgorithm],
+    ) -> Option<(TargetDescription, u64)> {
+        let stamp = FileStamp::new(&fs::metadata(path).ok()?);
+        let entries = self.entries.lock();
+        let (cached_stamp, hashes) = entries.get(path)?;
+        if *cached_stamp != stamp {
+            return None;
+        }
+        let hashes = hash_algorithms
+            .iter()
+            .map(|alg| hashes.get(alg).map(|value| (alg.clone(), value.clone())))
+            .collect::<Option<_>>()?;
+        Some((hashes, stamp.len))
+    }
+
+    /// Cache `hashes` for `path`, provided `path` still matches `before`, the metadata read
+    /// before it was hashed.
+    pub(crate) fn insert(&self, path: &Path, before: &Metadata, hashes: &TargetDescription) {
+        let stamp = FileStamp::new(before);
+        match fs::metadata(path) {
+            Ok(after) if FileStamp::new(&after) == stamp => {
+                let _ = self
+                    .entries
+                    .lock()
+                    .insert(path.to_path_buf(), (stamp, hashes.clone()));
+            }
+            _ => (),
+        }
+    }
+}
@@ -0,0 +1,122 @@
+//! Size and mode of recorded artifacts, kept out of their target descriptions.
+//!
+//! Target descriptions only hold hashes, and artifact rules such as `MATCH` compare them as a
+//! whole, so file metadata is stored next to them instead: in the link's `custom` fields, under
+//! `FILE_METADATA_KEY`. Links recorded with and without it match each other, and verifiers that
+//! do not know the entry ignore it like any other custom field.
+
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::error::Error;
+use crate::models::{LinkMetadata, VirtualTargetPath};
+use crate::Result;
+
+/// The custom field of a link holding the `LinkFileMetadata` of its artifacts.
+pub const FILE_METADATA_KEY: &str = "x-in-toto-rs-file-metadata";
+
+/// The size and permission bits of a recorded artifact.
+///
+/// These describe the file as it was on the recording machine and are informational only: the
+/// mode in particular depends on the umask and on how the file was checked out, so the same
+/// source can be recorded with different modes on different machines. Nothing in verification
+/// looks at them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileMetadata {
+    /// The size of the artifact in bytes.
+    pub size: u64,
+    /// The permission bits (`mode & 0o7777`) of the artifact. Only recorded on unix.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<u32>,
+}
+
+/// The `FileMetadata` of recorded artifacts, keyed like the artifacts themselves.
+pub type FileMetadataMap = BTreeMap<VirtualTargetPath, FileMetadata>;
+
+/// The `FileMetadata` of the materials and products of a link, as stored in its custom fields.
+///
+/// ```
+/// # use in_toto::models::LinkMetadataBuilder;
+/// # use in_toto::runlib::{record_artifacts_with_file_metadata, LinkFileMetadata, RecordOptions};
+/// # use std::collections::BTreeMap;
+/// let (products, metadata) = record_artifacts_with_file_metadata(
+///     &["tests/test_runlib/release"],
+///     None,
+///     &RecordOptions::new(),
+/// )
+/// .unwrap();
+/// let file_metadata = LinkFileMetadata {
+///     materials: BTreeMap::new(),
+///     products: metadata,
+/// };
+///
+/// let mut custom = BTreeMap::new();
+/// file_metadata.add_to(&mut custom).unwrap();
+/// let link = LinkMetadataBuilder::new()
+///     .products(products)
+///     .custom(custom)
+///     .build()
+///     .unwrap();
+/// assert_eq!(LinkFileMetadata::from_link(&link).unwrap(), Some(file_metadata));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LinkFileMetadata {
+    /// The metadata of the materials.
+    #[serde(default)]
+    pub materials: FileMetadataMap,
+    /// The metadata of the products.
+    #[serde(default)]
+    pub products: FileMetadataMap,
+}
+
+impl LinkFileMetadata {
+    /// Read the file metadata stored in the custom fields of `link`, if any.
+    pub fn from_link(link: &LinkMetadata) -> Result<Option<Self>> {
+        link.custom()
+            .get(FILE_METADATA_KEY)
+            .map(|value| {
+                serde_json::from_value(value.clone()).map_err(|e| {
+                    Error::Encoding(format!("Invalid {} custom field: {}", FILE_METADATA_KEY, e))
+                })
+            })
+            .transpose()
+    }
+
+    /// Store this metadata in `custom` under `FILE_METADATA_KEY`, e.g. to pass it to
+    /// `LinkMetadataBuilder::custom`.
+    pub fn add_to(&self, custom: &mut BTreeMap<String, Value>) -> Result<()> {
+        let value = serde_json::to_value(self)?;
+        let _ = custom.insert(FILE_METADATA_KEY.to_string(), value);
+        Ok(())
+    }
+}
+
+/// The metadata of the file at `path`, of which `len` bytes were hashed. The mode of a symbolic
+/// link that is recorded as itself is the mode of the link, not of its target.
+pub(super) fn read_file_metadata(path: &Path, symlink: bool, len: u64) -> Result<FileMetadata> {
+    Ok(FileMetadata {
+        size: len,
+        mode: read_mode(path, symlink)?,
+    })
+}
+
+#[cfg(unix)]
+fn read_mode(path: &Path, symlink: bool) -> Result<Option<u32>> {
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+
+    let metadata = if symlink {
+        fs::symlink_metadata(path)
+    } else {
+        fs::metadata(path)
+    }
+    .map_err(|e| Error::from_io(&e, path))?;
+    Ok(Some(metadata.permissions().mode() & 0o7777))
+}
+
+#[cfg(not(unix))]
+fn read_mode(_path: &Path, _symlink: bool) -> Result<Option<u32>> {
+    Ok(None)
+}
@@ -0,0 +1,246 @@
+//! Recording of source trees straight from a git repository.
+
+use git2::{ObjectType, Repository, TreeWalkMode, TreeWalkResult};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::path::Path;
+
+use super::parse_hash_algorithms;
+use crate::crypto;
+use crate::error::Error;
+use crate::models::{TargetDescription, VirtualTargetPath};
+use crate::Result;
+
+/// The object type bits of the mode of an index entry for a symbolic link.
+const SYMLINK: u32 = 0o120000;
+/// The object type bits of the mode of an index entry for a submodule.
+const GITLINK: u32 = 0o160000;
+
+/// Record every file in the tree of `commit_ish` in the repository at `repo_path`, which may be
+/// bare, keyed by its path within the tree.
+///
+/// Files are hashed as git stores them: the raw blob content, before any checkout filter or
+/// line ending conversion, so a recorded digest matches the output of `git cat-file blob` for
+/// the file's object id. Symbolic links are recorded as the blob holding their target, and
+/// submodules are not recorded.
+pub fn record_git_tree(
+    repo_path: &str,
+    commit_ish: &str,
+    hash_algorithms: Option<&[&str]>,
+) -> Result<BTreeMap<VirtualTargetPath, TargetDescription>> {
+    let hash_algorithms = parse_hash_algorithms(hash_algorithms)?;
+    let repo = Repository::open(repo_path).map_err(git_error)?;
+    let tree = repo
+        .revparse_single(commit_ish)
+        .and_then(|object| object.peel_to_commit())
+        .and_then(|commit| commit.tree())
+        .map_err(git_error)?;
+
+    let mut artifacts = BTreeMap::new();
+    let mut result = Ok(());
+    let walked = tree.walk(TreeWalkMode::PreOrder, |root, entry| {
+        if entry.kind() != Some(ObjectType::Blob) {
+            return TreeWalkResult::Ok;
+        }
+        let recorded = match entry.name() {
+            Some(name) => record_blob(
+                &repo,
+                entry.id(),
+                format!("{}{}", root, name),
+                &hash_algorithms,
+            ),
+            None => Err(Error::IllegalArgument(format!(
+                "Path of {} in {:?} is not valid UTF-8",
+                entry.id(),
+                commit_ish
+            ))),
+        };
+        match recorded {
+            Ok((path, hashes)) => {
+                let _ = artifacts.insert(path, hashes);
+                TreeWalkResult::Ok
+            }
+            Err(e) => {
+                result = Err(e);
+                TreeWalkResult::Abort
+            }
+        }
+    });
+    // An error recording a blob aborts the walk, so it takes precedence.
+    result?;
+    walked.map_err(git_error)?;
+
+    Ok(artifacts)
+}
+
+/// Record every file git tracks in the working tree of the repository at `repo_path`, the files
+/// `git ls-files` lists, keyed by its path within the repository.
+///
+/// Unlike `record_git_tree`, the files are hashed as they are checked out, so the digests match
+/// those `record_artifacts` computes for the same files. Untracked files and files matched by
+/// `.gitignore`, such as build output, are not recorded. Symbolic links are recorded as their
+/// target, like git stores them, and submodules are not recorded. A tracked file missing from
+/// the working tree is an error.
+pub fn record_git_tracked(
+    repo_path: &str,
+    hash_algorithms: Option<&[&str]>,
+) -> Result<BTreeMap<VirtualTargetPath, TargetDescription>> {
+    let hash_algorithms = parse_hash_algorithms(hash_algorithms)?;
+    let repo = Repository::open(repo_path).map_err(git_error)?;
+    let workdir = repo.workdir().ok_or_else(|| {
+        Error::IllegalArgument(format!(
+            "Repository {:?} is bare and has no tracked files to record",
+            repo_path
+        ))
+    })?;
+    let index = repo.index().map_err(git_error)?;
+
+    let mut artifacts = BTreeMap::new();
+    for entry in index.iter() {
+        let kind = entry.mode & 0o170000;
+        if kind == GITLINK {
+            continue;
+        }
+        let path = String::from_utf8(entry.path).map_err(|e| {
+            Error::IllegalArgument(format!(
+                "Path {:?} in {:?} is not valid UTF-8",
+                String::from_utf8_lossy(e.as_bytes()),
+                repo_path
+            ))
+        })?;
+        let hashes = record_checked_out(&workdir.join(&path), kind == SYMLINK, &hash_algorithms)?;
+        let _ = artifacts.insert(VirtualTargetPath::new(path)?, hashes);
+    }
+
+    Ok(artifacts)
+}
+
+fn record_checked_out(
+    path: &Path,
+    symlink: bool,
+    hash_algorithms: &[crypto::HashAlgorithm],
+) -> Result<TargetDescription> {
+    let (_, hashes) = if symlink {
+        let target = fs::read_link(path).map_err(|e| Error::from_io(&e, path))?;
+        let target = target.to_str().ok_or_else(|| {
+            Error::IllegalArgument(format!("Target of {:?} is not valid UTF-8", path))
+        })?;
+        crypto::calculate_hashes(target.as_bytes(), hash_algorithms)?
+    } else {
+        let file = File::open(path).map_err(|e| Error::from_io(&e, path))?;
+        crypto::calculate_hashes(file, hash_algorithms)?
+    };
+    Ok(hashes)
+}
+
+fn record_blob(
+    repo: &Repository,
+    id: git2::Oid,
+    path: String,
+    hash_algorithms: &[crypto::HashAlgorithm],
+) -> Result<(VirtualTargetPath, TargetDescription)> {
+    let blob = repo.find_blob(id).map_err(git_error)?;
+    let (_, hashes) = crypto::calculate_hashes(blob.content(), hash_algorithms)?;
+    Ok((VirtualTargetPath::new(path)?, hashes))
+}
+
+fn git_error(err: git2::Error) -> Error {
+    Error::Opaque(format!("git: {}", err.message()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::crypto::{calculate_hash, HashAlgorithm};
+    use git2::Signature;
+
+    /// Commit a tree holding `README` and `src/main.c` to a new bare repository.
+    fn bare_repo(dir: &std::path::Path) {
+        let repo = Repository::init_bare(dir).unwrap();
+        let readme = repo.blob(b"hello\n").unwrap();
+        let main = repo.blob(b"int main(void) { return 0; }\n").unwrap();
+
+        let mut src = repo.treebuilder(None).unwrap();
+        let _ = src.insert("main.c", main, 0o100644).unwrap();
+        let src = src.write().unwrap();
+        let mut root = repo.treebuilder(None).unwrap();
+        let _ = root.insert("README", readme, 0o100644).unwrap();
+        let _ = root.insert("src", src, 0o040000).unwrap();
+        let tree = repo.find_tree(root.write().unwrap()).unwrap();
+
+        let author =
+            Signature::new("Builder", "builder@example.com", &git2::Time::new(0, 0)).unwrap();
+        let _ = repo
+            .commit(
+                Some("refs/heads/main"),
+                &author,
+                &author,
+                "Initial",
+                &tree,
+                &[],
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn record_git_tree_hashes_blobs() {
+        let dir = tempfile::tempdir().unwrap();
+        bare_repo(dir.path());
+        let repo_path = dir.path().to_str().unwrap();
+
+        let artifacts = record_git_tree(repo_path, "main", None).unwrap();
+        let expected = vec![
+            ("README", &b"hello\n"[..]),
+            ("src/main.c", &b"int main(void) { return 0; }\n"[..]),
+        ];
+        assert_eq!(artifacts.len(), expected.len());
+        for (path, content) in expected {
+            let hashes = &artifacts[&VirtualTargetPath::new(path.into()).unwrap()];
+            assert_eq!(
+                hashes.get(&HashAlgorithm::Sha256),
+                Some(&calculate_hash(content, HashAlgorithm::Sha256))
+            );
+        }
+
+        assert!(record_git_tree(repo_path, "missing", None).is_err());
+        assert!(record_git_tracked(repo_path, None).is_err());
+    }
+
+    #[test]
+    fn record_git_tracked_skips_untracked_and_ignored_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        let write = |path: &str, content: &[u8]| {
+            let path = dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        };
+        write(".gitignore", b"target/\n");
+        write("src/main.c", b"int main(void) { return 0; }\n");
+        write("target/main.o", b"object");
+        write("notes.txt", b"untracked");
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new(".gitignore")).unwrap();
+        index.add_path(Path::new("src/main.c")).unwrap();
+        index.write().unwrap();
+        // The tracked copy of a file is recorded as it is checked out, not as committed.
+        write("src/main.c", b"int main(void) { return 1; }\n");
+
+        let artifacts = record_git_tracked(dir.path().to_str().unwrap(), None).unwrap();
+        let expected = vec![
+            (".gitignore", &b"target/\n"[..]),
+            ("src/main.c", &b"int main(void) { return 1; }\n"[..]),
+        ];
+        assert_eq!(artifacts.len(), expected.len());
+        for (path, content) in expected {
+            let hashes = &artifacts[&VirtualTargetPath::new(path.into()).unwrap()];
+            assert_eq!(
+                hashes.get(&HashAlgorithm::Sha256),
+                Some(&calculate_hash(content, HashAlgorithm::Sha256))
+            );
+        }
+
+        fs::remove_file(dir.path().join("src/main.c")).unwrap();
+        assert!(record_git_tracked(dir.path().to_str().unwrap(), None).is_err());
+    }
+}
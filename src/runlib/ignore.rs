@@ -0,0 +1,200 @@
+//! Support for `.in-toto-ignore` files.
+//!
+//! An ignore file holds one glob pattern per line and excludes matching files and directories
+//! below the directory it lives in. Blank lines and lines starting with `#` are skipped. As with
+//! `.gitignore`, a pattern without a `/` matches a name at any depth, a pattern containing a `/`
+//! is matched against the path relative to the ignore file's directory, and a trailing `/`
+//! restricts the pattern to directories. Negated (`!`) patterns are not supported.
+//!
+//! The same patterns are accepted as exclude patterns in `RecordOptions`, where they are matched
+//! against the recorded path of every walked entry.
+
+use glob::{MatchOptions, Pattern};
+use std::fmt::Debug;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use walkdir::DirEntry;
+
+use super::clean_path;
+use crate::error::Error;
+use crate::Result;
+
+/// Name of the file holding ignore patterns for a directory.
+pub(crate) const IGNORE_FILENAME: &str = ".in-toto-ignore";
+
+const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+struct IgnorePattern {
+    pattern: Pattern,
+    anchored: bool,
+    dir_only: bool,
+}
+
+impl IgnorePattern {
+    fn parse<S: Debug + ?Sized>(line: &str, source: &S) -> Result<Option<Self>> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return Ok(None);
+        }
+        if line.starts_with('!') {
+            return Err(Error::IllegalArgument(format!(
+                "{:?}: negated pattern {:?} is not supported",
+                source, line
+            )));
+        }
+
+        let dir_only = line.ends_with('/');
+        let line = line.trim_end_matches('/');
+        let anchored = line.contains('/');
+        let line = line.trim_start_matches('/');
+        let pattern = Pattern::new(line).map_err(|e| {
+            Error::IllegalArgument(format!("{:?}: invalid pattern {:?}: {}", source, line, e))
+        })?;
+
+        Ok(Some(IgnorePattern {
+            pattern,
+            anchored,
+            dir_only,
+        }))
+    }
+
+    fn matches(&self, relative: &str, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        if self.anchored {
+            self.pattern.matches_with(relative, MATCH_OPTIONS)
+        } else {
+            let name = relative.rsplit('/').next().unwrap_or(relative);
+            self.pattern.matches_with(name, MATCH_OPTIONS)
+        }
+    }
+}
+
+/// Exclude patterns given directly rather than read from an ignore file.
+#[derive(Default)]
+pub(crate) struct ExcludeSet {
+    patterns: Vec<IgnorePattern>,
+}
+
+impl ExcludeSet {
+    pub(crate) fn new(patterns: &[String]) -> Result<Self> {
+        let patterns = patterns
+            .iter()
+            .filter_map(|line| IgnorePattern::parse(line, "exclude pattern").transpose())
+            .collect::<Result<Vec<_>>>()?;
+        Ok(ExcludeSet { patterns })
+    }
+
+    /// Check whether the entry recorded as `path` is excluded.
+    pub(crate) fn excludes(&self, path: &str, is_dir: bool) -> bool {
+        self.patterns
+            .iter()
+            .any(|pattern| pattern.matches(path, is_dir))
+    }
+}
+
+/// The patterns of a single ignore file, along with the directory they apply to.
+struct IgnoreFile {
+    dir: PathBuf,
+    depth: usize,
+    patterns: Vec<IgnorePattern>,
+}
+
+/// The ignore files in effect at the current position of a directory walk.
+#[derive(Default)]
+pub(crate) struct IgnoreStack {
+    files: Vec<IgnoreFile>,
+}
+
+impl IgnoreStack {
+    /// Check whether `entry` is excluded by an ignore file in one of its ancestors. Entries must
+    /// be passed in walk order; when a directory is not excluded, its own ignore file is loaded
+    /// and applies to the entries below it.
+    pub(crate) fn excludes(&mut self, entry: &DirEntry) -> Result<bool> {
+        self.files.retain(|file| file.depth < entry.depth());
+
+        let is_dir = entry.file_type().is_dir();
+        let excluded = self.files.iter().any(|file| {
+            let relative = match entry.path().strip_prefix(&file.dir) {
+                Ok(relative) => clean_path(relative),
+                Err(_) => return false,
+            };
+            file.patterns
+                .iter()
+                .any(|pattern| pattern.matches(&relative, is_dir))
+        });
+
+        if is_dir && !excluded {
+            let source = entry.path().join(IGNORE_FILENAME);
+            let content = match fs::read_to_string(&source) {
+                Ok(content) => Some(content),
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound => None,
+                Err(e) => return Err(Error::from_io(&e, &source)),
+            };
+            if let Some(content) = content {
+                let patterns = content
+                    .lines()
+                    .filter_map(|line| IgnorePattern::parse(line, &source).transpose())
+                    .collect::<Result<Vec<_>>>()?;
+                self.files.push(IgnoreFile {
+                    dir: entry.path().to_path_buf(),
+                    depth: entry.depth(),
+                    patterns,
+                });
+            }
+        }
+
+        Ok(excluded)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::path::Path;
+
+    fn pattern(line: &str) -> IgnorePattern {
+        IgnorePattern::parse(line, Path::new(IGNORE_FILENAME))
+            .unwrap()
+            .unwrap()
+    }
+
+    #[test]
+    fn parse_skips_comments_and_blank_lines() {
+        let source = Path::new(IGNORE_FILENAME);
+        assert!(IgnorePattern::parse("", source).unwrap().is_none());
+        assert!(IgnorePattern::parse("   ", source).unwrap().is_none());
+        assert!(IgnorePattern::parse("# comment", source).unwrap().is_none());
+        assert!(IgnorePattern::parse("!keep.tmp", source).is_err());
+    }
+
+    #[test]
+    fn unanchored_patterns_match_names_at_any_depth() {
+        let tmp = pattern("*.tmp");
+        assert!(tmp.matches("scratch.tmp", false));
+        assert!(tmp.matches("src/scratch.tmp", false));
+        assert!(!tmp.matches("src/scratch.tmp.c", false));
+    }
+
+    #[test]
+    fn anchored_and_directory_patterns() {
+        let generated = pattern("/src/generated");
+        assert!(generated.matches("src/generated", true));
+        assert!(!generated.matches("lib/src/generated", true));
+
+        let nested = pattern("docs/*.md");
+        assert!(nested.matches("docs/index.md", false));
+        assert!(!nested.matches("docs/drafts/wip.md", false));
+
+        let build = pattern("build/");
+        assert!(build.matches("build", true));
+        assert!(build.matches("src/build", true));
+        assert!(!build.matches("build", false));
+    }
+}
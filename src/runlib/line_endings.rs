@@ -0,0 +1,134 @@
+//! Line ending normalization of text artifacts, as done by `--normalize-line-endings` in the
+//! reference implementation.
+
+use std::io::{self, Cursor, Read};
+
+use crate::crypto::{self, HashAlgorithm};
+use crate::models::TargetDescription;
+use crate::Result;
+
+/// How many leading bytes of a file are inspected to tell text from binary, as git does.
+const SNIFF_LEN: u64 = 8000;
+
+/// Hash `read` with `\r\n` and lone `\r` line endings replaced by `\n`, unless it is `binary` or
+/// looks binary because a NUL byte occurs in its first `SNIFF_LEN` bytes. Returns the number of
+/// bytes read, before normalization, along with the hashes.
+pub(super) fn calculate_normalized_hashes<R: Read>(
+    mut read: R,
+    binary: bool,
+    hash_algorithms: &[HashAlgorithm],
+    buffer_size: usize,
+) -> Result<(u64, TargetDescription)> {
+    let mut head = Vec::new();
+    let _ = read.by_ref().take(SNIFF_LEN).read_to_end(&mut head)?;
+    let binary = binary || head.contains(&0);
+    let read = Cursor::new(head).chain(read);
+    if binary {
+        return crypto::calculate_hashes_with_buffer_size(read, hash_algorithms, buffer_size);
+    }
+
+    let mut normalized = NormalizeLineEndings {
+        inner: read,
+        after_cr: false,
+        consumed: 0,
+    };
+    let (_, hashes) =
+        crypto::calculate_hashes_with_buffer_size(&mut normalized, hash_algorithms, buffer_size)?;
+    Ok((normalized.consumed, hashes))
+}
+
+/// A reader replacing the line endings of `inner` by `\n`, keeping track of how many bytes it
+/// consumed from `inner`.
+struct NormalizeLineEndings<R> {
+    inner: R,
+    /// Whether the last byte read was a `\r`, so that a `\n` right after it is dropped even when
+    /// the two are read separately.
+    after_cr: bool,
+    consumed: u64,
+}
+
+impl<R: Read> Read for NormalizeLineEndings<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let n = self.inner.read(buf)?;
+            if n == 0 {
+                return Ok(0);
+            }
+            self.consumed += n as u64;
+
+            // Normalizing never grows the data, so it is done in place.
+            let mut len = 0;
+            for i in 0..n {
+                let byte = buf[i];
+                if self.after_cr && byte == b'\n' {
+                    self.after_cr = false;
+                    continue;
+                }
+                self.after_cr = byte == b'\r';
+                buf[len] = if self.after_cr { b'\n' } else { byte };
+                len += 1;
+            }
+            // A read of only the `\n` of a `\r\n` produces nothing, which must not look like EOF.
+            if len > 0 {
+                return Ok(len);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::crypto::calculate_hash;
+
+    /// A reader returning one byte per read, to split every `\r\n`.
+    struct Bytewise<'a>(&'a [u8]);
+
+    impl Read for Bytewise<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            match self.0.split_first() {
+                Some((byte, rest)) if !buf.is_empty() => {
+                    buf[0] = *byte;
+                    self.0 = rest;
+                    Ok(1)
+                }
+                _ => Ok(0),
+            }
+        }
+    }
+
+    #[test]
+    fn normalizes_line_endings_across_reads() {
+        let mut normalized = NormalizeLineEndings {
+            inner: Bytewise(b"a\r\nb\rc\n\r\r\n\r"),
+            after_cr: false,
+            consumed: 0,
+        };
+        let mut out = Vec::new();
+        let _ = normalized.read_to_end(&mut out).unwrap();
+        assert_eq!(out, b"a\nb\nc\n\n\n\n");
+        assert_eq!(normalized.consumed, 11);
+    }
+
+    #[test]
+    fn leaves_binary_content_alone() {
+        let sha256 = |content: &[u8], binary: bool| {
+            let (len, hashes) =
+                calculate_normalized_hashes(content, binary, &[HashAlgorithm::Sha256], 4).unwrap();
+            assert_eq!(len, content.len() as u64);
+            hashes[&HashAlgorithm::Sha256].clone()
+        };
+        let hash = |content: &[u8]| calculate_hash(content, HashAlgorithm::Sha256);
+
+        assert_eq!(sha256(b"one\r\ntwo\r\n", false), hash(b"one\ntwo\n"));
+        assert_eq!(sha256(b"one\r\ntwo\r\n", true), hash(b"one\r\ntwo\r\n"));
+        assert_eq!(sha256(b"\0one\r\n", false), hash(b"\0one\r\n"));
+
+        // Only NUL bytes within the sniffed head make content binary.
+        let mut late_nul = vec![b'a'; SNIFF_LEN as usize];
+        late_nul.extend_from_slice(b"\r\n\0");
+        let mut expected = vec![b'a'; SNIFF_LEN as usize];
+        expected.extend_from_slice(b"\n\0");
+        assert_eq!(sha256(&late_nul, false), hash(&expected));
+    }
+}
@@ -0,0 +1,276 @@
+//! Recording of container images saved with `docker save`.
+
+use serde_derive::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::{Component, Path, PathBuf};
+use tar::{Archive, EntryType};
+
+use crate::crypto::{self, HashAlgorithm, HashValue};
+use crate::error::Error;
+use crate::models::{TargetDescription, VirtualTargetPath};
+use crate::Result;
+
+/// Name of the index describing the images contained in a `docker save` tarball.
+const MANIFEST_PATH: &str = "manifest.json";
+
+/// Maximum number of symlinks followed when resolving a tarball member.
+const MAX_LINK_DEPTH: usize = 8;
+
+#[derive(Deserialize)]
+struct ManifestEntry {
+    #[serde(rename = "Config")]
+    config: String,
+    #[serde(rename = "Layers")]
+    layers: Vec<String>,
+}
+
+/// The digests that make up a container image: its config blob and its layers, base layer first.
+///
+/// Layer digests are computed over the uncompressed layer tarballs, so they match the
+/// `RootFS.Layers` reported by `docker inspect`, and the config digest is the image ID.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageDigests {
+    config: HashValue,
+    layers: Vec<HashValue>,
+}
+
+impl ImageDigests {
+    /// Read the digests of the single image contained in the `docker save` tarball at `path`.
+    pub fn from_tarball(path: &str) -> Result<Self> {
+        let file = File::open(path).map_err(|e| Error::from_io(&e, Path::new(path)))?;
+        Self::from_reader(BufReader::new(file))
+    }
+
+    /// Read the digests of the single image contained in a `docker save` tarball.
+    ///
+    /// The tarball is streamed once; layers are hashed as they are read and never buffered.
+    pub fn from_reader<R: Read>(reader: R) -> Result<Self> {
+        let mut archive = Archive::new(reader);
+        let mut digests = HashMap::new();
+        let mut links = HashMap::new();
+        let mut manifest = None;
+
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let member = normalize(&entry.path()?);
+
+            match entry.header().entry_type() {
+                EntryType::Regular if member == Path::new(MANIFEST_PATH) => {
+                    let mut buf = Vec::new();
+                    let _ = entry.read_to_end(&mut buf)?;
+                    manifest = Some(buf);
+                }
+                EntryType::Regular => {
+                    let (_length, mut hashes) =
+                        crypto::calculate_hashes(&mut entry, &[HashAlgorithm::Sha256])?;
+                    let digest = hashes.remove(&HashAlgorithm::Sha256).ok_or_else(|| {
+                        Error::Programming("calculate_hashes omitted sha256".into())
+                    })?;
+                    let _ = digests.insert(member, digest);
+                }
+                EntryType::Symlink | EntryType::Link => {
+                    if let Some(target) = entry.link_name()? {
+                        // Symlinks are relative to their own directory, hard links to the root.
+                        let target = match entry.header().entry_type() {
+                            EntryType::Symlink => member
+                                .parent()
+                                .unwrap_or_else(|| Path::new(""))
+                                .join(target),
+                            _ => target.into_owned(),
+                        };
+                        let _ = links.insert(member, normalize(&target));
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        let manifest = manifest
+            .ok_or_else(|| Error::Encoding(format!("Image tarball has no {}", MANIFEST_PATH)))?;
+        let mut manifest: Vec<ManifestEntry> = serde_json::from_slice(&manifest)?;
+        if manifest.len() != 1 {
+            return Err(Error::IllegalArgument(format!(
+                "Expected exactly one image in the tarball, found {}",
+                manifest.len()
+            )));
+        }
+        let image = manifest.remove(0);
+
+        let lookup = |member: &str| -> Result<HashValue> {
+            let mut path = normalize(Path::new(member));
+            for _ in 0..MAX_LINK_DEPTH {
+                match links.get(&path) {
+                    Some(target) => path = target.clone(),
+                    None => break,
+                }
+            }
+            digests
+                .get(&path)
+                .cloned()
+                .ok_or_else(|| Error::Encoding(format!("Image tarball is missing {:?}", member)))
+        };
+
+        Ok(ImageDigests {
+            config: lookup(&image.config)?,
+            layers: image
+                .layers
+                .iter()
+                .map(|layer| lookup(layer))
+                .collect::<Result<Vec<_>>>()?,
+        })
+    }
+
+    /// The config digest in the `sha256:<hex>` form used by registries.
+    pub fn config(&self) -> String {
+        registry_digest(&self.config)
+    }
+
+    /// The layer digests, base layer first, in the `sha256:<hex>` form used by registries.
+    pub fn layers(&self) -> Vec<String> {
+        self.layers.iter().map(registry_digest).collect()
+    }
+
+    /// Convert the digests into artifacts suitable for a link's materials or products.
+    ///
+    /// The config is recorded as `<name>/config` and each layer as `<name>/layers/<index>`, so
+    /// the artifacts attest the order of the layers as well as their content.
+    pub fn to_artifacts(
+        &self,
+        name: &str,
+    ) -> Result<BTreeMap<VirtualTargetPath, TargetDescription>> {
+        let mut artifacts = BTreeMap::new();
+        let _ = artifacts.insert(
+            VirtualTargetPath::new(format!("{}/config", name))?,
+            description(&self.config),
+        );
+        for (index, layer) in self.layers.iter().enumerate() {
+            let _ = artifacts.insert(
+                VirtualTargetPath::new(format!("{}/layers/{}", name, index))?,
+                description(layer),
+            );
+        }
+        Ok(artifacts)
+    }
+}
+
+/// Record the config and layers of the `docker save` tarball at `path` as artifacts named after
+/// `name`. See `ImageDigests::to_artifacts` for the layout of the result.
+pub fn record_image_tarball(
+    path: &str,
+    name: &str,
+) -> Result<BTreeMap<VirtualTargetPath, TargetDescription>> {
+    ImageDigests::from_tarball(path)?.to_artifacts(name)
+}
+
+fn registry_digest(value: &HashValue) -> String {
+    format!("sha256:{}", value)
+}
+
+fn description(value: &HashValue) -> TargetDescription {
+    let mut description = TargetDescription::new();
+    let _ = description.insert(HashAlgorithm::Sha256, value.clone());
+    description
+}
+
+/// Lexically resolve `.` and `..` components of a tarball member path.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => normalized.push(part),
+            Component::ParentDir => {
+                let _ = normalized.pop();
+            }
+            _ => (),
+        }
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::crypto::calculate_hash;
+    use serde_json::json;
+    use tar::{Builder, Header};
+
+    fn append(builder: &mut Builder<Vec<u8>>, path: &str, data: &[u8]) {
+        let mut header = Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, path, data).unwrap();
+    }
+
+    fn saved_image() -> Vec<u8> {
+        let manifest = json!([{
+            "Config": "c0nf16.json",
+            "RepoTags": ["example:latest"],
+            "Layers": ["1a/layer.tar", "2b/layer.tar", "3c/layer.tar"],
+        }]);
+
+        let mut builder = Builder::new(Vec::new());
+        append(&mut builder, "1a/layer.tar", b"base layer");
+        append(&mut builder, "2b/layer.tar", b"app layer");
+        let mut header = Header::new_gnu();
+        header.set_entry_type(EntryType::Symlink);
+        header.set_size(0);
+        header.set_mode(0o777);
+        builder
+            .append_link(&mut header, "3c/layer.tar", "../1a/layer.tar")
+            .unwrap();
+        append(&mut builder, "c0nf16.json", b"{\"architecture\":\"amd64\"}");
+        append(&mut builder, MANIFEST_PATH, manifest.to_string().as_bytes());
+        builder.into_inner().unwrap()
+    }
+
+    #[test]
+    fn image_digests_from_saved_image() {
+        let digests = ImageDigests::from_reader(&saved_image()[..]).unwrap();
+        let sha256 = |data: &[u8]| registry_digest(&calculate_hash(data, HashAlgorithm::Sha256));
+
+        assert_eq!(digests.config(), sha256(b"{\"architecture\":\"amd64\"}"));
+        assert_eq!(
+            digests.layers(),
+            vec![
+                sha256(b"base layer"),
+                sha256(b"app layer"),
+                sha256(b"base layer"),
+            ]
+        );
+        assert!(digests.config().starts_with("sha256:"));
+    }
+
+    #[test]
+    fn image_digests_to_artifacts() {
+        let digests = ImageDigests::from_reader(&saved_image()[..]).unwrap();
+        let artifacts = digests.to_artifacts("image").unwrap();
+
+        let paths = artifacts.keys().map(|p| p.value()).collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            vec![
+                "image/config",
+                "image/layers/0",
+                "image/layers/1",
+                "image/layers/2"
+            ]
+        );
+        assert_eq!(
+            artifacts[&VirtualTargetPath::new("image/layers/1".into()).unwrap()]
+                [&HashAlgorithm::Sha256],
+            calculate_hash(b"app layer", HashAlgorithm::Sha256)
+        );
+    }
+
+    #[test]
+    fn image_digests_requires_manifest() {
+        let mut builder = Builder::new(Vec::new());
+        append(&mut builder, "1a/layer.tar", b"base layer");
+        let tarball = builder.into_inner().unwrap();
+
+        assert!(ImageDigests::from_reader(&tarball[..]).is_err());
+    }
+}
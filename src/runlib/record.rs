@@ -0,0 +1,147 @@
+//! Recording a step in two phases, for work that is not a single command.
+//!
+//! `in_toto_record_start` records the materials of a step into an unfinished link, stored signed
+//! in the link directory under `UNFINISHED_FILENAME_FORMAT`. Once the work is done,
+//! `in_toto_record_stop` loads that link back, checks that it was signed by the same key,
+//! records the products, and returns the finished link.
+
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+use super::record_artifacts;
+use crate::crypto::PrivateKey;
+use crate::error::Error;
+use crate::interchange::{DataInterchange, Json};
+use crate::models::{LinkMetadata, LinkMetadataBuilder, SignedMetadata};
+use crate::staging::staged_file;
+use crate::Result;
+
+/// The path of the unfinished link of step `name` signed with `key` in `link_dir`.
+pub fn unfinished_link_path(link_dir: &str, name: &str, key: &PrivateKey) -> PathBuf {
+    Path::new(link_dir).join(format!(
+        ".{}.{:.8}.link-unfinished",
+        name,
+        key.key_id().as_str()
+    ))
+}
+
+/// Record the materials of step `name`, and store them in an unfinished link signed with `key`
+/// in `link_dir`, replacing any unfinished link the step already had there. Returns the path of
+/// the unfinished link.
+pub fn in_toto_record_start(
+    name: &str,
+    material_paths: &[&str],
+    key: &PrivateKey,
+    hash_algorithms: Option<&[&str]>,
+    link_dir: &str,
+) -> Result<PathBuf> {
+    let materials = record_artifacts(material_paths, hash_algorithms)?;
+    let signed = LinkMetadataBuilder::new()
+        .name(name.to_string())
+        .materials(materials)
+        .signed::<Json>(key)?;
+
+    let path = unfinished_link_path(link_dir, name, key);
+    let mut staged = staged_file(Path::new(link_dir)).map_err(|e| Error::from_io(&e, &path))?;
+    Json::to_writer(&mut staged, &signed)?;
+    let _ = staged
+        .persist(&path)
+        .map_err(|e| Error::from_io(&e.error, &path))?;
+    Ok(path)
+}
+
+/// Finish the step `name` started by `in_toto_record_start` with the same `key` and
+/// `link_dir`: record its products, and return the link holding both, signed with `key`.
+///
+/// The unfinished link must verify with `key` and be for step `name`. It is removed once the
+/// finished link is signed.
+pub fn in_toto_record_stop(
+    name: &str,
+    product_paths: &[&str],
+    key: &PrivateKey,
+    hash_algorithms: Option<&[&str]>,
+    link_dir: &str,
+) -> Result<SignedMetadata<Json, LinkMetadata>> {
+    let path = unfinished_link_path(link_dir, name, key);
+    let file = File::open(&path).map_err(|e| Error::from_io(&e, &path))?;
+    let unfinished: SignedMetadata<Json, LinkMetadata> = Json::from_reader(BufReader::new(file))?;
+    let unfinished = unfinished.verify(1, vec![key.public()])?;
+    if unfinished.name() != name {
+        return Err(Error::VerificationFailure(format!(
+            "Unfinished link {:?} is for step {:?}, not {:?}",
+            path,
+            unfinished.name(),
+            name
+        )));
+    }
+
+    let products = record_artifacts(product_paths, hash_algorithms)?;
+    let signed = LinkMetadataBuilder::new()
+        .name(name.to_string())
+        .materials(unfinished.materials().clone())
+        .products(products)
+        .env(unfinished.env().clone())
+        .signed::<Json>(key)?;
+
+    fs::remove_file(&path).map_err(|e| Error::from_io(&e, &path))?;
+    Ok(signed)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::crypto::SignatureScheme;
+    use crate::runlib::test::relative;
+    #[cfg(unix)]
+    use crate::staging::mode;
+
+    #[test]
+    fn record_start_and_stop() {
+        let key = include_bytes!("../../tests/ed25519/ed25519-1.pk8.der");
+        let key = PrivateKey::from_pkcs8(key, SignatureScheme::Ed25519).unwrap();
+        let other = include_bytes!("../../tests/ed25519/ed25519-2.pk8.der");
+        let other = PrivateKey::from_pkcs8(other, SignatureScheme::Ed25519).unwrap();
+        let tree = tempfile::tempdir_in(".").unwrap();
+        let root = &relative(tree.path());
+        fs::write(tree.path().join("draft.txt"), b"draft").unwrap();
+        let links = tempfile::tempdir().unwrap();
+        let link_dir = links.path().to_str().unwrap();
+
+        let unfinished = in_toto_record_start("edit", &[root], &key, None, link_dir).unwrap();
+        assert!(unfinished
+            .file_name()
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .ends_with(".link-unfinished"));
+        let materials = record_artifacts(&[root], None).unwrap();
+
+        // The work happening between the two calls.
+        fs::write(tree.path().join("draft.txt"), b"final").unwrap();
+
+        assert!(in_toto_record_stop("edit", &[root], &other, None, link_dir).is_err());
+        let signed = in_toto_record_stop("edit", &[root], &key, None, link_dir).unwrap();
+        let link = signed.verify(1, vec![key.public()]).unwrap();
+        assert_eq!(link.name(), "edit");
+        assert_eq!(link.materials(), &materials);
+        assert_eq!(link.products(), &record_artifacts(&[root], None).unwrap());
+        assert_ne!(link.products(), link.materials());
+        assert!(!unfinished.exists());
+        assert!(in_toto_record_stop("edit", &[root], &key, None, link_dir).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn record_start_creates_link_like_file_create() {
+        let key = include_bytes!("../../tests/ed25519/ed25519-1.pk8.der");
+        let key = PrivateKey::from_pkcs8(key, SignatureScheme::Ed25519).unwrap();
+        let links = tempfile::tempdir().unwrap();
+        let link_dir = links.path().to_str().unwrap();
+        let created = links.path().join("created");
+        let _ = File::create(&created).unwrap();
+
+        let unfinished = in_toto_record_start("edit", &[], &key, None, link_dir).unwrap();
+        assert_eq!(mode(&unfinished), mode(&created));
+    }
+}
@@ -0,0 +1,157 @@
+//! Running steps from async code without blocking the executor.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use tokio::fs::{self, File};
+use tokio::io::AsyncReadExt;
+use tokio::process::Command;
+
+use super::{
+    command_byproducts, parse_hash_algorithms, prepare_command, spawn_error, virtual_target_path,
+    walk_steps, RecordEvent, RecordOptions, WalkStep,
+};
+use crate::crypto::{self, HashAlgorithm, Hasher, PrivateKey};
+use crate::error::Error;
+use crate::interchange::Json;
+use crate::models::{
+    LinkMetadata, LinkMetadataBuilder, SignedMetadata, TargetDescription, VirtualTargetPath,
+};
+use crate::Result;
+
+/// Like `record_artifacts`, but reading files asynchronously. Directories are walked on the
+/// blocking thread pool, so this must be called from within a tokio runtime.
+pub async fn record_artifacts_async(
+    paths: &[&str],
+    hash_algorithms: Option<&[&str]>,
+) -> Result<BTreeMap<VirtualTargetPath, TargetDescription>> {
+    let hash_algorithms = parse_hash_algorithms(hash_algorithms)?;
+    let paths = paths.iter().map(PathBuf::from).collect::<Vec<PathBuf>>();
+    let steps = tokio::task::spawn_blocking(move || walk_steps(&paths, &RecordOptions::new()))
+        .await
+        .map_err(|e| Error::Programming(format!("Artifact walk did not complete: {}", e)))??;
+
+    let mut artifacts = BTreeMap::new();
+    for step in steps {
+        let (path, symlink) = match step {
+            WalkStep::Event(RecordEvent::Failed { error, .. }) => return Err(error),
+            WalkStep::Event(_) => continue,
+            WalkStep::File { path, symlink, .. } => (path, symlink),
+        };
+        let virtual_target_path = virtual_target_path(&path, &[])?;
+        let hashes = if symlink {
+            hash_symlink(&path, &hash_algorithms).await?
+        } else {
+            hash_file(&path, &hash_algorithms).await?
+        };
+        let _ = artifacts.insert(virtual_target_path, hashes);
+    }
+    Ok(artifacts)
+}
+
+/// Like `in_toto_run`, but reading artifacts and waiting for the command asynchronously. The
+/// resulting link, including the order of its artifacts, is the same as `in_toto_run` records.
+pub async fn in_toto_run_async(
+    name: &str,
+    run_dir: Option<&str>,
+    material_paths: &[&str],
+    product_paths: &[&str],
+    cmd_args: &[&str],
+    key: &PrivateKey,
+    hash_algorithms: Option<&[&str]>,
+) -> Result<SignedMetadata<Json, LinkMetadata>> {
+    let materials = record_artifacts_async(material_paths, hash_algorithms).await?;
+
+    let byproducts = match cmd_args {
+        [] => BTreeMap::new(),
+        _ => {
+            let program = cmd_args[0];
+            let output = Command::from(prepare_command(cmd_args, run_dir)?)
+                .stdin(Stdio::null())
+                .output()
+                .await
+                .map_err(|e| spawn_error(&e, program))?;
+            command_byproducts(program, output.status, output.stdout, output.stderr)?.into()
+        }
+    };
+    let products = record_artifacts_async(product_paths, hash_algorithms).await?;
+
+    LinkMetadataBuilder::new()
+        .name(name.to_string())
+        .command(cmd_args.iter().map(|arg| arg.to_string()).collect())
+        .materials(materials)
+        .products(products)
+        .byproducts(byproducts)
+        .signed::<Json>(key)
+}
+
+async fn hash_file(path: &Path, hash_algorithms: &[HashAlgorithm]) -> Result<TargetDescription> {
+    let mut hasher = Hasher::new(hash_algorithms)?;
+    let mut file = File::open(path)
+        .await
+        .map_err(|e| Error::from_io(&e, path))?;
+    let mut buf = vec![0; crypto::DEFAULT_HASH_BUFFER_SIZE];
+    loop {
+        let read = file
+            .read(&mut buf)
+            .await
+            .map_err(|e| Error::from_io(&e, path))?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buf[..read]);
+    }
+
+    Ok(hasher.finalize())
+}
+
+async fn hash_symlink(path: &Path, hash_algorithms: &[HashAlgorithm]) -> Result<TargetDescription> {
+    let target = fs::read_link(path)
+        .await
+        .map_err(|e| Error::from_io(&e, path))?;
+    let target = target.to_str().ok_or_else(|| {
+        Error::IllegalArgument(format!("Target of {:?} is not valid UTF-8", path))
+    })?;
+    let (_, hashes) = crypto::calculate_hashes(target.as_bytes(), hash_algorithms)?;
+    Ok(hashes)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::crypto::SignatureScheme;
+    use crate::runlib::in_toto_run;
+
+    #[test]
+    fn in_toto_run_async_matches_sync() {
+        let key = include_bytes!("../../tests/ed25519/ed25519-1.pk8.der");
+        let key = PrivateKey::from_pkcs8(key, SignatureScheme::Ed25519).unwrap();
+        let paths = &["tests/test_runlib"];
+        let cmd_args = &["sh", "-c", "echo out; echo err >&2"];
+        let hash_algorithms = Some(&["sha256", "sha512"][..]);
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let signed = runtime
+            .block_on(in_toto_run_async(
+                "async",
+                None,
+                paths,
+                paths,
+                cmd_args,
+                &key,
+                hash_algorithms,
+            ))
+            .unwrap();
+        let expected =
+            in_toto_run("async", None, paths, paths, cmd_args, &key, hash_algorithms).unwrap();
+
+        // Ed25519 signatures are deterministic, so the whole link must match byte for byte.
+        assert_eq!(
+            serde_json::to_string(&signed).unwrap(),
+            serde_json::to_string(&expected).unwrap()
+        );
+    }
+}
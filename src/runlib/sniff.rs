@@ -0,0 +1,61 @@
+//! Detection of the content type of recorded artifacts.
+
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::error::Error;
+use crate::models::{TargetDescription, VirtualTargetPath};
+use crate::Result;
+
+/// Byproduct key to store the result of `sniff_content_types` under.
+pub const CONTENT_TYPES_BYPRODUCT: &str = "content-types";
+
+/// Detect the MIME type of each of `artifacts` from its leading bytes, reading each from the
+/// path it was recorded under. Extensions are not consulted, so a renamed executable is still
+/// reported as one.
+///
+/// The result maps paths to MIME types and is meant to be stored with
+/// `LinkMetadataBuilder::structured_byproduct` under `CONTENT_TYPES_BYPRODUCT`. Artifacts whose
+/// type is not recognized, such as plain text, are left out.
+pub fn sniff_content_types(
+    artifacts: &BTreeMap<VirtualTargetPath, TargetDescription>,
+) -> Result<Value> {
+    let mut types = Map::new();
+    for path in artifacts.keys() {
+        let file = Path::new(path.value());
+        let detected = infer::get_from_path(file).map_err(|e| Error::from_io(&e, file))?;
+        if let Some(detected) = detected {
+            let _ = types.insert(
+                path.value().to_string(),
+                Value::String(detected.mime_type().to_string()),
+            );
+        }
+    }
+    Ok(Value::Object(types))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::runlib::record_artifacts;
+    use serde_json::json;
+    use std::fs;
+
+    #[test]
+    fn sniff_detects_renamed_executable() {
+        let dir = tempfile::tempdir_in(".").unwrap();
+        let mut elf = b"\x7fELF\x02\x01\x01".to_vec();
+        elf.resize(64, 0);
+        fs::write(dir.path().join("foo.txt"), elf).unwrap();
+        fs::write(dir.path().join("notes.txt"), b"plain text\n").unwrap();
+        let cwd = std::env::current_dir().unwrap();
+        let root = dir.path().strip_prefix(cwd).unwrap().to_str().unwrap();
+
+        let artifacts = record_artifacts(&[root], None).unwrap();
+        assert_eq!(
+            sniff_content_types(&artifacts).unwrap(),
+            json!({ format!("{}/foo.txt", root): "application/x-executable" })
+        );
+    }
+}
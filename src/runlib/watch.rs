@@ -0,0 +1,311 @@
+//! Recording the artifacts a command changes by watching a directory while it runs.
+
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use std::collections::BTreeMap;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::{parse_hash_algorithms, record_with_algorithms, run_command, RecordOptions};
+use crate::crypto::HashAlgorithm;
+use crate::error::Error;
+use crate::models::{Byproducts, TargetDescription, VirtualTargetPath};
+use crate::Result;
+
+/// Options controlling how `record_changes_during_with_options` records changes.
+///
+/// ```
+/// # use in_toto::runlib::WatchOptions;
+/// # use std::time::Duration;
+/// let options = WatchOptions::new().debounce(Duration::from_millis(500));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct WatchOptions {
+    debounce: Option<Duration>,
+}
+
+impl WatchOptions {
+    /// Create options matching the behavior of `record_changes_during`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Hash each changed file while the command still runs, once it has not changed for
+    /// `debounce`, instead of hashing every changed file after the command exits. A file that
+    /// changes again after it was hashed is hashed again, so only its final content is
+    /// recorded either way, and one that is rewritten in quick succession is only hashed once.
+    pub fn debounce(mut self, debounce: Duration) -> Self {
+        self.debounce = Some(debounce);
+        self
+    }
+}
+
+/// Run the command in `cmd_args` while watching `watch_dir`, and record only the files under it
+/// that the command created or modified, hashed with `hash_algorithms` as in
+/// `record_artifacts`. Returns them with the byproducts of the command.
+///
+/// Files are recorded under the path they have below `watch_dir`, as given, like a walk of
+/// `watch_dir` would record them, and a directory the command created is recorded as a whole.
+/// Files the command created and removed again are left out. Where the directory cannot be
+/// watched, and when the watcher drops events, e.g. because its event queue overflowed, every
+/// file under `watch_dir` is recorded instead.
+///
+/// ```
+/// # use in_toto::runlib::record_changes_during;
+/// let dir = tempfile::tempdir_in(".").unwrap();
+/// let dir = dir.path().file_name().unwrap().to_str().unwrap();
+/// let _ = std::fs::write(format!("{}/old.txt", dir), "old");
+/// let (products, _) =
+///     record_changes_during(&["touch", &format!("{}/new.txt", dir)], dir, None).unwrap();
+/// assert_eq!(products.len(), 1);
+/// ```
+pub fn record_changes_during(
+    cmd_args: &[&str],
+    watch_dir: &str,
+    hash_algorithms: Option<&[&str]>,
+) -> Result<(BTreeMap<VirtualTargetPath, TargetDescription>, Byproducts)> {
+    record_changes_during_with_options(cmd_args, watch_dir, hash_algorithms, &WatchOptions::new())
+}
+
+/// Like `record_changes_during`, with the recording customized by `options`.
+pub fn record_changes_during_with_options(
+    cmd_args: &[&str],
+    watch_dir: &str,
+    hash_algorithms: Option<&[&str]>,
+    options: &WatchOptions,
+) -> Result<(BTreeMap<VirtualTargetPath, TargetDescription>, Byproducts)> {
+    let hash_algorithms = parse_hash_algorithms(hash_algorithms)?;
+    let record_all =
+        || record_with_algorithms(&[watch_dir], &hash_algorithms, &RecordOptions::new());
+    let (tx, rx) = mpsc::channel();
+    let watcher = notify::recommended_watcher(tx).and_then(|mut watcher| {
+        watcher.watch(Path::new(watch_dir), RecursiveMode::Recursive)?;
+        Ok(watcher)
+    });
+    let watcher = match watcher {
+        Ok(watcher) => watcher,
+        Err(e) => {
+            log::warn!("Cannot watch {:?}, recording all of it: {}", watch_dir, e);
+            let byproducts = run_command(cmd_args, None, None, None)?;
+            return Ok((record_all()?, byproducts));
+        }
+    };
+
+    let mut changes = Changes::new(watch_dir)?;
+    let mut recorded = BTreeMap::new();
+    let byproducts = thread::scope(|scope| {
+        let command = scope.spawn(|| run_command(cmd_args, None, None, None));
+        let poll = options
+            .debounce
+            .map_or(SETTLE, |debounce| debounce.min(SETTLE));
+        while !command.is_finished() {
+            if let Ok(event) = rx.recv_timeout(poll) {
+                changes.add(event);
+            }
+            if let Some(debounce) = options.debounce {
+                let settled = changes.settled(debounce);
+                match record_paths(&settled, &hash_algorithms) {
+                    Ok(artifacts) => recorded.extend(artifacts),
+                    // Most likely removed while being hashed: try again once the command exits.
+                    Err(_) => changes.retry(settled),
+                }
+            }
+        }
+        command
+            .join()
+            .unwrap_or_else(|_| Err(Error::Programming("Watched command panicked".into())))
+    })?;
+    drain(&rx, &mut changes);
+    drop(watcher);
+
+    if changes.missed {
+        log::warn!("Missed changes to {:?}, recording all of it", watch_dir);
+        return Ok((record_all()?, byproducts));
+    }
+    let settled = changes.settled(Duration::ZERO);
+    recorded.extend(record_paths(&settled, &hash_algorithms)?);
+    // Files hashed while the command ran may have been removed since.
+    recorded.retain(|path, _| Path::new(path.value()).symlink_metadata().is_ok());
+    Ok((recorded, byproducts))
+}
+
+/// How long the watcher has to be quiet after the command exits before its events are taken as
+/// complete, since they are delivered asynchronously.
+const SETTLE: Duration = Duration::from_millis(100);
+
+/// Add the events received on `rx` to `changes`, until none arrived for `SETTLE`.
+fn drain(rx: &Receiver<notify::Result<Event>>, changes: &mut Changes) {
+    while let Ok(event) = rx.recv_timeout(SETTLE) {
+        changes.add(event);
+    }
+}
+
+/// The paths changed under a watched directory that are not recorded yet.
+struct Changes {
+    watch_dir: String,
+    roots: Vec<PathBuf>,
+    pending: BTreeMap<PathBuf, Instant>,
+    missed: bool,
+}
+
+impl Changes {
+    fn new(watch_dir: &str) -> Result<Self> {
+        let path = Path::new(watch_dir);
+        let mut roots = vec![env::current_dir()
+            .map_err(|e| Error::from_io(&e, path))?
+            .join(path)];
+        if let Ok(canonical) = path.canonicalize() {
+            roots.push(canonical);
+        }
+        Ok(Changes {
+            watch_dir: watch_dir.to_string(),
+            roots,
+            pending: BTreeMap::new(),
+            missed: false,
+        })
+    }
+
+    /// Note the paths `event` created or modified, or that the watcher missed events.
+    fn add(&mut self, event: notify::Result<Event>) {
+        let event = match event {
+            Ok(event) if !event.need_rescan() => event,
+            _ => {
+                self.missed = true;
+                return;
+            }
+        };
+        if let EventKind::Create(_) | EventKind::Modify(_) = event.kind {
+            let now = Instant::now();
+            for path in &event.paths {
+                let _ = self.pending.insert(self.relative(path), now);
+            }
+        }
+    }
+
+    /// `path`, as reported by the watcher, below the watched directory as given.
+    fn relative(&self, path: &Path) -> PathBuf {
+        self.roots
+            .iter()
+            .find_map(|root| path.strip_prefix(root).ok())
+            .map_or_else(
+                || path.to_path_buf(),
+                |rest| Path::new(&self.watch_dir).join(rest),
+            )
+    }
+
+    /// Take the pending paths that did not change for `debounce`.
+    fn settled(&mut self, debounce: Duration) -> Vec<PathBuf> {
+        let now = Instant::now();
+        let settled = self
+            .pending
+            .iter()
+            .filter(|(_, changed)| now.duration_since(**changed) >= debounce)
+            .map(|(path, _)| path.clone())
+            .collect::<Vec<_>>();
+        for path in &settled {
+            let _ = self.pending.remove(path);
+        }
+        settled
+    }
+
+    /// Put `paths` back, to be recorded once the command exits.
+    fn retry(&mut self, paths: Vec<PathBuf>) {
+        let now = Instant::now();
+        self.pending
+            .extend(paths.into_iter().map(|path| (path, now)));
+    }
+}
+
+/// Record those of the changed `paths` that still exist. Files inside a changed directory are
+/// recorded with it.
+fn record_paths(
+    paths: &[PathBuf],
+    hash_algorithms: &[HashAlgorithm],
+) -> Result<BTreeMap<VirtualTargetPath, TargetDescription>> {
+    let dirs = paths
+        .iter()
+        .filter(|path| path.is_dir())
+        .collect::<Vec<_>>();
+    let paths = paths
+        .iter()
+        .filter(|path| path.symlink_metadata().is_ok())
+        .filter(|path| !dirs.iter().any(|dir| path != dir && path.starts_with(dir)))
+        .collect::<Vec<_>>();
+    record_with_algorithms(&paths, hash_algorithms, &RecordOptions::new())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::crypto::{calculate_hash, HashValue};
+    use std::fs;
+
+    fn sha256(content: &str) -> HashValue {
+        calculate_hash(content.as_bytes(), HashAlgorithm::Sha256)
+    }
+
+    fn hashes(
+        products: &BTreeMap<VirtualTargetPath, TargetDescription>,
+    ) -> BTreeMap<String, HashValue> {
+        products
+            .iter()
+            .map(|(path, hashes)| {
+                (
+                    path.value().to_string(),
+                    hashes[&HashAlgorithm::Sha256].clone(),
+                )
+            })
+            .collect()
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn record_changes_during_records_changed_files() {
+        let dir = tempfile::tempdir_in(".").unwrap();
+        let root = dir.path().file_name().unwrap().to_str().unwrap();
+        fs::write(dir.path().join("untouched.txt"), "same").unwrap();
+        fs::write(dir.path().join("edited.txt"), "before").unwrap();
+        let script = format!(
+            "echo after > {0}/edited.txt; mkdir {0}/out; echo built > {0}/out/app; \
+             echo tmp > {0}/tmp; rm {0}/tmp",
+            root
+        );
+
+        let (products, byproducts) =
+            record_changes_during(&["sh", "-c", &script], root, None).unwrap();
+        assert_eq!(byproducts.exit_code, Some(0));
+        assert_eq!(
+            hashes(&products),
+            BTreeMap::from([
+                (format!("{}/edited.txt", root), sha256("after\n")),
+                (format!("{}/out/app", root), sha256("built\n")),
+            ])
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn debounce_records_final_content() {
+        let dir = tempfile::tempdir_in(".").unwrap();
+        let root = dir.path().file_name().unwrap().to_str().unwrap();
+        let record = |script: &str| {
+            let options = WatchOptions::new().debounce(Duration::from_millis(200));
+            let script = script.replace("{}", root);
+            let (products, _) =
+                record_changes_during_with_options(&["sh", "-c", &script], root, None, &options)
+                    .unwrap();
+            hashes(&products)
+        };
+        let out = format!("{}/out.txt", root);
+
+        // Written twice within the debounce window.
+        let products = record("echo first > {}/out.txt; echo final > {}/out.txt");
+        assert_eq!(products, BTreeMap::from([(out.clone(), sha256("final\n"))]));
+
+        // Rewritten after the first write settled and was hashed.
+        let products = record("echo first > {}/out.txt; sleep 0.5; echo final > {}/out.txt");
+        assert_eq!(products, BTreeMap::from([(out, sha256("final\n"))]));
+    }
+}
@@ -0,0 +1,29 @@
+//! Files written next to their destination and then persisted over it, so that readers never see
+//! a partially written file.
+
+use std::io;
+use std::path::Path;
+use tempfile::{Builder, NamedTempFile};
+
+/// Create a temporary file in `dir`, to be persisted as a regular file. `NamedTempFile::new_in`
+/// creates files only their owner can read, and persisting keeps that mode; this one gets the
+/// mode `File::create` would give it instead, `0o666` masked by the umask.
+pub(crate) fn staged_file(dir: &Path) -> io::Result<NamedTempFile> {
+    let mut builder = Builder::new();
+    #[cfg(unix)]
+    {
+        use std::fs::Permissions;
+        use std::os::unix::fs::PermissionsExt;
+
+        let _ = builder.permissions(Permissions::from_mode(0o666));
+    }
+    builder.tempfile_in(dir)
+}
+
+/// The permission bits of `path`, for tests comparing them to those of a created file.
+#[cfg(all(test, unix))]
+pub(crate) fn mode(path: &Path) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+
+    std::fs::metadata(path).unwrap().permissions().mode() & 0o7777
+}
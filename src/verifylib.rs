@@ -1 +1,1687 @@
 //! A tool to be used by the client to perform verification on the final product.
+
+use chrono::{DateTime, Utc};
+use log::warn;
+use ring::constant_time::verify_slices_are_equal;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashSet};
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::Path;
+
+use crate::crypto::{self, HashAlgorithm, HashValue, KeyId, PublicKey};
+use crate::error::Error;
+use crate::interchange::{CanonicalJson, DataInterchange, Json};
+use crate::models::{
+    ArtifactType, Layout, LinkMetadata, LinkMetadataBuilder, RootTrustConfig, SignedMetadata, Step,
+    SupplyChainRule, TargetDescription, VirtualTargetPath,
+};
+use crate::runlib::{record_artifacts_with_options, run_command, RecordOptions};
+use crate::Result;
+
+mod custom;
+mod parameters;
+mod queue;
+mod report;
+mod rules;
+
+pub use custom::{CustomRule, CustomRuleRegistry, LinkMap};
+pub use queue::ArtifactQueue;
+pub use report::{Failure, Verdict, VerificationReport};
+pub use rules::{RuleOutcome, RulesOutcome, UnmatchedPolicy};
+
+use parameters::substitute_parameters;
+use rules::verify_item_rules;
+
+/// How many levels of sublayouts `in_toto_verify` follows below the layout it is given.
+pub const MAX_SUBLAYOUT_DEPTH: usize = 8;
+
+/// Check whether the file at `path` matches an attested `TargetDescription`.
+///
+/// The file is hashed once with every algorithm present in `expected`, and each digest is
+/// compared in constant time. Returns `Ok(false)` if any digest differs, and an `Err` if the file
+/// cannot be read or `expected` names no usable hash algorithm.
+///
+/// ```no_run
+/// # use in_toto::crypto::{HashAlgorithm, HashValue};
+/// # use in_toto::models::TargetDescription;
+/// # use in_toto::verifylib::verify_artifact_hash;
+/// let mut expected = TargetDescription::new();
+/// expected.insert(HashAlgorithm::Sha256, HashValue::new(vec![0x00, 0x01]));
+/// assert!(!verify_artifact_hash("foo.tar.gz", &expected).unwrap());
+/// ```
+pub fn verify_artifact_hash<P: AsRef<Path>>(path: P, expected: &TargetDescription) -> Result<bool> {
+    if expected.is_empty() {
+        return Err(Error::IllegalArgument(
+            "Cannot verify an artifact against an empty set of hashes".into(),
+        ));
+    }
+
+    let path = path.as_ref();
+    let algorithms = expected.keys().cloned().collect::<Vec<HashAlgorithm>>();
+    let file = File::open(path).map_err(|e| Error::from_io(&e, path))?;
+    let (_length, actual) = crypto::calculate_hashes(BufReader::new(file), &algorithms)?;
+
+    // Compare every digest rather than bailing on the first mismatch.
+    let mut matches = true;
+    for (algorithm, expected_value) in expected {
+        matches &= match actual.get(algorithm) {
+            Some(value) => verify_slices_are_equal(value.value(), expected_value.value()).is_ok(),
+            None => false,
+        };
+    }
+
+    Ok(matches)
+}
+
+/// Check whether `command`, as recorded in a link, is the `expected_command` of its step. The
+/// two are compared argument by argument.
+///
+/// As in the reference implementation, a mismatch is not a verification failure: it is logged
+/// as a warning and reported by returning `false`. `in_toto_verify` checks every link it loads.
+///
+/// ```
+/// # use in_toto::verifylib::verify_command_alignment;
+/// let expected = vec!["make".to_string(), "release".to_string()];
+/// assert!(verify_command_alignment(&expected, &expected));
+/// assert!(!verify_command_alignment(&["make".to_string()], &expected));
+/// ```
+pub fn verify_command_alignment(command: &[String], expected_command: &[String]) -> bool {
+    if command == expected_command {
+        return true;
+    }
+    warn!(
+        "Run command {:?} differs from expected command {:?}",
+        command, expected_command
+    );
+    false
+}
+
+/// The outcome of a successful `in_toto_verify`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VerificationSummary {
+    layout: Layout,
+    links: BTreeMap<String, LinkMetadata>,
+    signers: BTreeMap<String, Vec<KeyId>>,
+    material_rules: BTreeMap<String, RulesOutcome>,
+    product_rules: BTreeMap<String, RulesOutcome>,
+    materials: BTreeMap<VirtualTargetPath, TargetDescription>,
+    products: BTreeMap<VirtualTargetPath, TargetDescription>,
+    warnings: Vec<String>,
+}
+
+impl VerificationSummary {
+    /// The layout the supply chain was verified against, with its parameters substituted.
+    pub fn layout(&self) -> &Layout {
+        &self.layout
+    }
+
+    /// The links the supply chain was verified with, by step or inspection name. For a step with
+    /// a threshold above one, this is the link signed by the first of its keys in `KeyId` order.
+    pub fn links(&self) -> &BTreeMap<String, LinkMetadata> {
+        &self.links
+    }
+
+    /// The key IDs whose links counted towards the threshold of each step, by step name, in
+    /// `KeyId` order. Links that were missing or did not verify are left out.
+    pub fn signers(&self) -> &BTreeMap<String, Vec<KeyId>> {
+        &self.signers
+    }
+
+    /// What the material rules of each step and inspection consumed, by name.
+    pub fn material_rules(&self) -> &BTreeMap<String, RulesOutcome> {
+        &self.material_rules
+    }
+
+    /// What the product rules of each step and inspection consumed, by name.
+    pub fn product_rules(&self) -> &BTreeMap<String, RulesOutcome> {
+        &self.product_rules
+    }
+
+    /// The materials of each step and inspection that passed its material rules without any
+    /// rule looking at them, as given by `RulesOutcome::untracked`, by name. Steps without such
+    /// materials are left out.
+    pub fn untracked_materials(&self) -> BTreeMap<String, Vec<VirtualTargetPath>> {
+        untracked(&self.material_rules)
+    }
+
+    /// The products of each step and inspection that passed its product rules without any rule
+    /// looking at them, like `untracked_materials`.
+    pub fn untracked_products(&self) -> BTreeMap<String, Vec<VirtualTargetPath>> {
+        untracked(&self.product_rules)
+    }
+
+    /// The materials of the first step of the supply chain.
+    pub fn materials(&self) -> &BTreeMap<VirtualTargetPath, TargetDescription> {
+        &self.materials
+    }
+
+    /// The products of the last step of the supply chain.
+    pub fn products(&self) -> &BTreeMap<VirtualTargetPath, TargetDescription> {
+        &self.products
+    }
+
+    /// What verification warned about without failing, such as ignored links or commands that
+    /// differ from the expected ones, including the warnings of sublayouts.
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    /// A SHA-256 digest pinning the verified supply chain: the layout, every link and the final
+    /// products. Any change to any of them changes the digest, while the order in which links
+    /// were found does not.
+    ///
+    /// The digest is over the canonical JSON, as written by `CanonicalJson`, of the object
+    /// `{"layout": ..., "links": {<name>: <link>, ...}, "products": {<path>: <hashes>, ...}}`,
+    /// where the layout and the links are in the form they are signed in.
+    pub fn chain_digest(&self) -> Result<HashValue> {
+        let chain = serde_json::json!({
+            "layout": self.layout,
+            "links": self.links,
+            "products": self.products,
+        });
+        let bytes = CanonicalJson::to_vec(&chain)?;
+        let (_, mut digests) = crypto::calculate_hashes(&bytes[..], &[HashAlgorithm::Sha256])?;
+        digests
+            .remove(&HashAlgorithm::Sha256)
+            .ok_or_else(|| Error::Programming("No SHA-256 digest of the chain".into()))
+    }
+}
+
+fn untracked(rules: &BTreeMap<String, RulesOutcome>) -> BTreeMap<String, Vec<VirtualTargetPath>> {
+    rules
+        .iter()
+        .map(|(name, outcome)| (name.clone(), outcome.untracked()))
+        .filter(|(_, untracked)| !untracked.is_empty())
+        .collect()
+}
+
+/// Options controlling how `in_toto_verify_with_options` verifies a supply chain.
+///
+/// ```
+/// # use in_toto::crypto::{PrivateKey, SignatureScheme};
+/// # use in_toto::verifylib::VerifyOptions;
+/// # use std::collections::HashSet;
+/// # let compromised = PrivateKey::new(SignatureScheme::Ed25519).unwrap();
+/// let options = VerifyOptions::new().revoked_keys(HashSet::from([compromised.key_id().clone()]));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct VerifyOptions {
+    revoked_keys: HashSet<KeyId>,
+    unmatched_artifacts: UnmatchedPolicy,
+    custom_rules: CustomRuleRegistry,
+    substitutions: BTreeMap<String, String>,
+    steps: Option<Vec<String>>,
+    inspection_dir: Option<String>,
+}
+
+impl VerifyOptions {
+    /// Create options matching the behavior of `in_toto_verify`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Do not count links or sublayouts signed by any of `key_ids` towards the threshold of a
+    /// step, even though the layout still authorizes them, e.g. after a functionary key was
+    /// compromised. A step whose threshold is not met because of revoked keys fails with
+    /// `Error::RevokedKey`. The signatures of the layout itself are not affected.
+    pub fn revoked_keys(mut self, key_ids: HashSet<KeyId>) -> Self {
+        self.revoked_keys = key_ids;
+        self
+    }
+
+    /// What to do with the artifacts of a step or inspection that none of its artifact rules
+    /// consumed, after all of them are applied. Defaults to `UnmatchedPolicy::Allow`.
+    pub fn unmatched_artifacts(mut self, policy: UnmatchedPolicy) -> Self {
+        self.unmatched_artifacts = policy;
+        self
+    }
+
+    /// Apply artifact rules whose keyword is not built in with the custom rules of `registry`.
+    /// Without a registered custom rule, verification fails with `Error::UnknownRule` when it
+    /// reaches such a rule.
+    pub fn custom_rules(mut self, registry: CustomRuleRegistry) -> Self {
+        self.custom_rules = registry;
+        self
+    }
+
+    /// Replace the `{KEY}` placeholders in the names, artifact rules and commands of the steps
+    /// and inspections of the layout, and of any sublayout, by the values of `substitutions`,
+    /// e.g. `{GITHUB_SHA}` by the commit being verified, once the signatures of the layout are
+    /// verified. Keys consist of ASCII letters, digits and `_`. If any substitution is given,
+    /// verification fails with `Error::IllegalArgument` on a placeholder without one.
+    pub fn substitutions(mut self, substitutions: BTreeMap<String, String>) -> Self {
+        self.substitutions = substitutions;
+        self
+    }
+}
+
+/// The link a step is verified with, as loaded by `load_step_link`.
+struct StepLink {
+    link: LinkMetadata,
+    signers: Vec<KeyId>,
+    warnings: Vec<String>,
+}
+
+/// The keys a layout must be signed with.
+#[derive(Clone, Copy)]
+enum LayoutTrust<'a> {
+    /// Every one of the keys.
+    Keys(&'a [PublicKey]),
+    /// Every key group of the configuration.
+    Config(&'a RootTrustConfig),
+}
+
+impl LayoutTrust<'_> {
+    /// Check the signatures of `signed_layout` and return the layout it holds.
+    fn verify(self, signed_layout: &SignedMetadata<Json, Layout>) -> Result<Layout> {
+        match self {
+            LayoutTrust::Keys(keys) => {
+                let keys = keys
+                    .iter()
+                    .map(|key| (key.key_id().clone(), key))
+                    .collect::<BTreeMap<_, _>>();
+                if keys.is_empty() {
+                    return Err(Error::IllegalArgument(
+                        "At least one key is needed to verify a layout".into(),
+                    ));
+                }
+                signed_layout.verify(keys.len() as u32, keys.values().cloned())
+            }
+            LayoutTrust::Config(config) => signed_layout.verify_with_trust_config(config),
+        }
+    }
+}
+
+/// Log `message` as a warning and keep it for the `VerificationSummary`.
+fn record_warning(warnings: &mut Vec<String>, message: String) {
+    warn!("{}", message);
+    warnings.push(message);
+}
+
+/// Verify the final product of a supply chain against its signed `layout`.
+///
+/// The layout must be signed by every key in `layout_keys` and must not have expired. The links
+/// of each step are loaded from `link_dir`, under the names given by `FILENAME_FORMAT`, and only
+/// those signed by a key the step authorizes count towards its threshold; when the threshold is
+/// above one, all of them must agree on their materials and products. A link whose command is
+/// not the expected command of its step only produces a warning, see
+/// `verify_command_alignment`. The artifact rules of every step are then applied to its link.
+/// Finally, each inspection is run from the current directory, recording all of it as materials
+/// and products, and its rules are applied to the resulting link.
+///
+/// A step can be carried out under a layout of its own: a functionary then signs that sublayout
+/// and stores it in place of their link. The sublayout is verified like the layout, with the key
+/// of the functionary as its only layout key and its own links in the directory named by
+/// `SUBLAYOUT_LINK_DIR_FORMAT` within `link_dir`. It stands for a link with the materials of its
+/// first step and the products of its last one. Verification fails if sublayouts nest more than
+/// `MAX_SUBLAYOUT_DEPTH` levels deep, or if a sublayout is found below itself.
+///
+/// Returns the links the supply chain was verified with, or the first failure encountered.
+pub fn in_toto_verify(
+    layout: &SignedMetadata<Json, Layout>,
+    layout_keys: &[PublicKey],
+    link_dir: &str,
+) -> Result<VerificationSummary> {
+    in_toto_verify_at(layout, layout_keys, link_dir, Utc::now())
+}
+
+/// `in_toto_verify`, checking the expiration of the layout and of any sublayout against
+/// `reference_time` instead of the current time. A layout expiring at `reference_time` has not
+/// expired yet.
+///
+/// ```
+/// # use chrono::{Duration, TimeZone, Utc};
+/// # use in_toto::crypto::{PrivateKey, SignatureScheme};
+/// # use in_toto::interchange::Json;
+/// # use in_toto::models::LayoutBuilder;
+/// # use in_toto::verifylib::in_toto_verify_at;
+/// # use in_toto::Error;
+/// # let key = PrivateKey::new(SignatureScheme::Ed25519).unwrap();
+/// let expires = Utc.with_ymd_and_hms(2030, 1, 1, 0, 0, 0).unwrap();
+/// let layout = LayoutBuilder::new().expires(expires).signed::<Json>(&key).unwrap();
+/// let keys = [key.public().clone()];
+/// assert!(in_toto_verify_at(&layout, &keys, ".", expires).is_ok());
+/// assert_eq!(
+///     in_toto_verify_at(&layout, &keys, ".", expires + Duration::seconds(1)),
+///     Err(Error::ExpiredLayout(expires))
+/// );
+/// ```
+pub fn in_toto_verify_at(
+    layout: &SignedMetadata<Json, Layout>,
+    layout_keys: &[PublicKey],
+    link_dir: &str,
+    reference_time: DateTime<Utc>,
+) -> Result<VerificationSummary> {
+    verify_layout(
+        layout,
+        LayoutTrust::Keys(layout_keys),
+        Path::new(link_dir),
+        &VerifyOptions::new(),
+        reference_time,
+        &[],
+    )
+}
+
+/// `in_toto_verify`, with the layout trusted if it satisfies every key group of `trust_config`
+/// rather than if every layout key signed it, e.g. to require two signatures of the security
+/// team and one of the release team. Sublayouts are still verified with the key of the
+/// functionary that signed them.
+///
+/// ```
+/// # use in_toto::crypto::{PrivateKey, SignatureScheme};
+/// # use in_toto::interchange::Json;
+/// # use in_toto::models::{LayoutBuilder, RootTrustConfig};
+/// # use in_toto::verifylib::in_toto_verify_with_trust_config;
+/// # let security = PrivateKey::new(SignatureScheme::Ed25519).unwrap();
+/// # let release = PrivateKey::new(SignatureScheme::Ed25519).unwrap();
+/// let layout = LayoutBuilder::new().signed::<Json>(&security).unwrap();
+/// let config = RootTrustConfig::new()
+///     .group(vec![security.public().clone()], 1)
+///     .group(vec![release.public().clone()], 1);
+/// assert!(in_toto_verify_with_trust_config(&layout, &config, ".").is_err());
+/// ```
+pub fn in_toto_verify_with_trust_config(
+    layout: &SignedMetadata<Json, Layout>,
+    trust_config: &RootTrustConfig,
+    link_dir: &str,
+) -> Result<VerificationSummary> {
+    in_toto_verify_with_options(layout, trust_config, link_dir, &VerifyOptions::new())
+}
+
+/// `in_toto_verify_with_trust_config`, with verification adjusted by `options`.
+///
+/// ```
+/// # use in_toto::crypto::{PrivateKey, SignatureScheme};
+/// # use in_toto::interchange::Json;
+/// # use in_toto::models::{LayoutBuilder, RootTrustConfig};
+/// # use in_toto::verifylib::{in_toto_verify_with_options, VerifyOptions};
+/// # use std::collections::HashSet;
+/// # let key = PrivateKey::new(SignatureScheme::Ed25519).unwrap();
+/// # let compromised = PrivateKey::new(SignatureScheme::Ed25519).unwrap();
+/// let layout = LayoutBuilder::new().signed::<Json>(&key).unwrap();
+/// let config = RootTrustConfig::new().group(vec![key.public().clone()], 1);
+/// let options = VerifyOptions::new().revoked_keys(HashSet::from([compromised.key_id().clone()]));
+/// assert!(in_toto_verify_with_options(&layout, &config, ".", &options).is_ok());
+/// ```
+pub fn in_toto_verify_with_options(
+    layout: &SignedMetadata<Json, Layout>,
+    trust_config: &RootTrustConfig,
+    link_dir: &str,
+    options: &VerifyOptions,
+) -> Result<VerificationSummary> {
+    verify_layout(
+        layout,
+        LayoutTrust::Config(trust_config),
+        Path::new(link_dir),
+        options,
+        Utc::now(),
+        &[],
+    )
+}
+
+/// `in_toto_verify`, restricted to the layout steps named in `steps`, for a supply chain that
+/// is only partly carried out.
+///
+/// Only the links of those steps are loaded and checked against their threshold, and only
+/// their artifact rules are applied. A `MATCH` rule against a step left out is taken as
+/// satisfied by every artifact it filters, with a logged warning. Inspections are not run. The
+/// summary holds the materials of the first and the products of the last of `steps` in layout
+/// order. Fails if `steps` names a step the layout does not have.
+///
+/// ```
+/// # use in_toto::interchange::{DataInterchange, Json};
+/// # use in_toto::crypto::{PrivateKey, SignatureScheme};
+/// # use in_toto::verifylib::in_toto_verify_steps;
+/// # use std::fs::File;
+/// # let key: &[u8] = include_bytes!("../tests/ed25519/ed25519-1.pk8.der");
+/// let key = PrivateKey::from_pkcs8(key, SignatureScheme::Ed25519).unwrap();
+/// let layout = File::open("tests/test_verifylib/root.layout").unwrap();
+/// let layout = Json::from_reader(layout).unwrap();
+/// let summary = in_toto_verify_steps(
+///     &layout,
+///     &[key.public().clone()],
+///     "tests/test_verifylib/links",
+///     &["write-code"],
+/// )
+/// .unwrap();
+/// assert_eq!(summary.links().keys().collect::<Vec<_>>(), vec!["write-code"]);
+/// ```
+pub fn in_toto_verify_steps(
+    layout: &SignedMetadata<Json, Layout>,
+    layout_keys: &[PublicKey],
+    link_dir: &str,
+    steps: &[&str],
+) -> Result<VerificationSummary> {
+    let options = VerifyOptions {
+        steps: Some(steps.iter().map(|step| step.to_string()).collect()),
+        ..VerifyOptions::default()
+    };
+    verify_layout(
+        layout,
+        LayoutTrust::Keys(layout_keys),
+        Path::new(link_dir),
+        &options,
+        Utc::now(),
+        &[],
+    )
+}
+
+/// `in_toto_verify`, with inspections run from `inspection_dir` rather than the current
+/// directory.
+#[cfg(test)]
+fn verify_supply_chain(
+    layout: &SignedMetadata<Json, Layout>,
+    layout_keys: &[PublicKey],
+    link_dir: &str,
+    inspection_dir: Option<&str>,
+) -> Result<VerificationSummary> {
+    let options = VerifyOptions {
+        inspection_dir: inspection_dir.map(str::to_string),
+        ..VerifyOptions::default()
+    };
+    verify_layout(
+        layout,
+        LayoutTrust::Keys(layout_keys),
+        Path::new(link_dir),
+        &options,
+        Utc::now(),
+        &[],
+    )
+}
+
+/// Verify the supply chain of `signed_layout`, signed as `trust` requires, as adjusted by
+/// `options` and at `reference_time`, for a layout found below the layouts whose canonical bytes
+/// are in `parents`, outermost first.
+fn verify_layout(
+    signed_layout: &SignedMetadata<Json, Layout>,
+    trust: LayoutTrust,
+    link_dir: &Path,
+    options: &VerifyOptions,
+    reference_time: DateTime<Utc>,
+    parents: &[Vec<u8>],
+) -> Result<VerificationSummary> {
+    let steps = options.steps.as_deref();
+    let layout = trust.verify(signed_layout)?;
+    if *layout.expires() < reference_time {
+        return Err(Error::ExpiredLayout(*layout.expires()));
+    }
+    let layout = substitute_parameters(layout, &options.substitutions)?;
+    verify_step_dependencies(&layout)?;
+
+    if let Some(unknown) = steps.unwrap_or_default().iter().find(|name| {
+        !layout
+            .steps()
+            .iter()
+            .any(|step| step.name() == name.as_str())
+    }) {
+        return Err(Error::IllegalArgument(format!(
+            "The layout has no step {:?} to verify",
+            unknown
+        )));
+    }
+    let (in_scope, out_of_scope): (Vec<&Step>, Vec<&Step>) = layout
+        .steps()
+        .iter()
+        .partition(|step| steps.is_none_or(|steps| steps.iter().any(|name| name == step.name())));
+    let out_of_scope = out_of_scope
+        .iter()
+        .map(|step| step.name())
+        .collect::<Vec<_>>();
+
+    let mut chain = parents.to_vec();
+    chain.push(signed_layout.to_raw()?.as_bytes().to_vec());
+    let mut links = BTreeMap::new();
+    let mut signers = BTreeMap::new();
+    let mut warnings = Vec::new();
+    for step in &in_scope {
+        let loaded = load_step_link(&layout, step, link_dir, options, reference_time, &chain)?;
+        let _ = links.insert(step.name().to_string(), loaded.link);
+        let _ = signers.insert(step.name().to_string(), loaded.signers);
+        warnings.extend(loaded.warnings);
+    }
+    let mut material_rules = BTreeMap::new();
+    let mut product_rules = BTreeMap::new();
+    for step in &in_scope {
+        let materials = verify_item_rules(
+            step.name(),
+            ArtifactType::Materials,
+            step.expected_materials(),
+            &links,
+            &out_of_scope,
+            options,
+            &mut warnings,
+        )?;
+        let products = verify_item_rules(
+            step.name(),
+            ArtifactType::Products,
+            step.expected_products(),
+            &links,
+            &out_of_scope,
+            options,
+            &mut warnings,
+        )?;
+        let _ = material_rules.insert(step.name().to_string(), materials);
+        let _ = product_rules.insert(step.name().to_string(), products);
+    }
+
+    let inspections = match steps {
+        Some(_) => &[],
+        None => layout.inspections(),
+    };
+    for inspection in inspections {
+        let run = inspection
+            .run()
+            .iter()
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        let link = run_inspection(inspection.name(), &run, options.inspection_dir.as_deref())?;
+        let _ = links.insert(inspection.name().to_string(), link);
+    }
+    for inspection in inspections {
+        let materials = verify_item_rules(
+            inspection.name(),
+            ArtifactType::Materials,
+            inspection.expected_materials(),
+            &links,
+            &[],
+            options,
+            &mut warnings,
+        )?;
+        let products = verify_item_rules(
+            inspection.name(),
+            ArtifactType::Products,
+            inspection.expected_products(),
+            &links,
+            &[],
+            options,
+            &mut warnings,
+        )?;
+        let _ = material_rules.insert(inspection.name().to_string(), materials);
+        let _ = product_rules.insert(inspection.name().to_string(), products);
+    }
+
+    let artifacts = |step: Option<&&Step>, artifact_type| {
+        step.map(|step| match artifact_type {
+            ArtifactType::Materials => links[step.name()].materials().clone(),
+            ArtifactType::Products => links[step.name()].products().clone(),
+        })
+        .unwrap_or_default()
+    };
+    let materials = artifacts(in_scope.first(), ArtifactType::Materials);
+    let products = artifacts(in_scope.last(), ArtifactType::Products);
+    Ok(VerificationSummary {
+        layout,
+        materials,
+        products,
+        links,
+        signers,
+        material_rules,
+        product_rules,
+        warnings,
+    })
+}
+
+/// Check that the artifact rules of `layout` follow its step dependencies: no step may match
+/// artifacts of a step that depends on it, and so runs after it.
+fn verify_step_dependencies(layout: &Layout) -> Result<()> {
+    for step in layout.steps() {
+        let rules = step
+            .expected_materials()
+            .iter()
+            .chain(step.expected_products());
+        for rule in rules {
+            if let SupplyChainRule::Match { dest_name, .. } = rule {
+                if layout.dependencies_of(dest_name).contains(step.name()) {
+                    return Err(Error::DependencyCycleOrViolation(format!(
+                        "Step {:?} matches artifacts of {:?}, which runs after it, with rule {}",
+                        step.name(),
+                        dest_name,
+                        rule
+                    )));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Load the links of `step` from `link_dir`, keeping those that verify with the key they are
+/// named after, and check them against the threshold of the step. Sublayouts are verified and
+/// summarized into links; `parents` holds the layouts they are found below. Links and
+/// sublayouts signed by a key revoked in `options` are ignored.
+fn load_step_link(
+    layout: &Layout,
+    step: &Step,
+    link_dir: &Path,
+    options: &VerifyOptions,
+    reference_time: DateTime<Utc>,
+    parents: &[Vec<u8>],
+) -> Result<StepLink> {
+    let mut verified: BTreeMap<&KeyId, LinkMetadata> = BTreeMap::new();
+    let mut revoked = None;
+    let mut warnings = Vec::new();
+    for key_id in step.pubkeys() {
+        let key = match layout.keys().get(key_id) {
+            Some(key) => key,
+            None => {
+                record_warning(
+                    &mut warnings,
+                    format!(
+                        "Step {:?} authorizes key ID {:?}, which the layout does not list",
+                        step.name(),
+                        key_id.as_str()
+                    ),
+                );
+                continue;
+            }
+        };
+        let path = link_dir.join(format!("{}.{:.8}.link", step.name(), key_id.as_str()));
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(Error::from_io(&e, &path)),
+        };
+        let signed: Value = Json::from_reader(BufReader::new(file))?;
+        let link = if signed["signed"]["_type"] == "layout" {
+            let sublayout: SignedMetadata<Json, Layout> = Json::deserialize(&signed)?;
+            if let Err(e) = sublayout.verify_with_revoked_keys(1, vec![key], &options.revoked_keys)
+            {
+                if let Error::RevokedKey(key_id) = &e {
+                    revoked = Some(key_id.clone());
+                }
+                record_warning(
+                    &mut warnings,
+                    format!("Ignoring sublayout {:?}: {:?}", path, e),
+                );
+                continue;
+            }
+            let sublayout_dir = link_dir.join(format!("{}.{:.8}", step.name(), key_id.as_str()));
+            let (link, sublayout_warnings) = verify_sublayout(
+                &sublayout,
+                step,
+                key,
+                &sublayout_dir,
+                options,
+                reference_time,
+                parents,
+            )?;
+            warnings.extend(sublayout_warnings);
+            link
+        } else {
+            let signed: SignedMetadata<Json, LinkMetadata> = Json::deserialize(&signed)?;
+            match signed.verify_with_revoked_keys(1, vec![key], &options.revoked_keys) {
+                Ok(link) => link,
+                Err(e) => {
+                    if let Error::RevokedKey(key_id) = &e {
+                        revoked = Some(key_id.clone());
+                    }
+                    record_warning(&mut warnings, format!("Ignoring link {:?}: {:?}", path, e));
+                    continue;
+                }
+            }
+        };
+        if !verify_command_alignment(link.command(), step.expected_command()) {
+            warnings.push(format!(
+                "The link of step {:?} signed by {:?} ran {:?} instead of {:?}",
+                step.name(),
+                key_id.as_str(),
+                link.command(),
+                step.expected_command()
+            ));
+        }
+        let _ = verified.insert(key_id, link);
+    }
+
+    if (verified.len() as u32) < step.threshold() {
+        if let Some(key_id) = revoked {
+            return Err(Error::RevokedKey(key_id));
+        }
+        return Err(Error::VerificationFailure(format!(
+            "Step {:?} requires links from {} authorized keys, found {}",
+            step.name(),
+            step.threshold(),
+            verified.len()
+        )));
+    }
+    let signers = verified.keys().map(|key_id| (*key_id).clone()).collect();
+    let mut links = verified.into_values();
+    let first = links.next().ok_or_else(|| {
+        Error::VerificationFailure(format!("No link found for step {:?}", step.name()))
+    })?;
+    for link in links {
+        if link.materials() != first.materials() || link.products() != first.products() {
+            return Err(Error::VerificationFailure(format!(
+                "The links of step {:?} disagree on its materials or products",
+                step.name()
+            )));
+        }
+    }
+    Ok(StepLink {
+        link: first,
+        signers,
+        warnings,
+    })
+}
+
+/// Verify `sublayout`, stored as the link of `step` signed by `key`, with its links in
+/// `link_dir`, and summarize it into a link for `step`: the materials of its first step, and the
+/// products, command and byproducts of its last one. Also returns the warnings of the sublayout.
+fn verify_sublayout(
+    sublayout: &SignedMetadata<Json, Layout>,
+    step: &Step,
+    key: &PublicKey,
+    link_dir: &Path,
+    options: &VerifyOptions,
+    reference_time: DateTime<Utc>,
+    parents: &[Vec<u8>],
+) -> Result<(LinkMetadata, Vec<String>)> {
+    if parents.contains(&sublayout.to_raw()?.as_bytes().to_vec()) {
+        return Err(Error::VerificationFailure(format!(
+            "The sublayout of step {:?} signed by {:?} is found below itself",
+            step.name(),
+            key.key_id().as_str()
+        )));
+    }
+    if parents.len() > MAX_SUBLAYOUT_DEPTH {
+        return Err(Error::VerificationFailure(format!(
+            "The sublayout of step {:?} is nested more than {} levels deep",
+            step.name(),
+            MAX_SUBLAYOUT_DEPTH
+        )));
+    }
+
+    let summary = verify_layout(
+        sublayout,
+        LayoutTrust::Keys(std::slice::from_ref(key)),
+        link_dir,
+        &VerifyOptions {
+            steps: None,
+            ..options.clone()
+        },
+        reference_time,
+        parents,
+    )?;
+    let last = sublayout
+        .assume_valid()?
+        .steps()
+        .last()
+        .and_then(|last| summary.links.get(last.name()));
+    let mut link = LinkMetadataBuilder::new()
+        .name(step.name().to_string())
+        .materials(summary.materials.clone())
+        .products(summary.products.clone());
+    if let Some(last) = last {
+        link = link
+            .command(last.command().to_vec())
+            .byproducts(last.byproducts().clone());
+        for (key, value) in last.structured_byproducts() {
+            link = link.structured_byproduct(key.clone(), value.clone());
+        }
+    }
+    Ok((link.build()?, summary.warnings))
+}
+
+/// Run the command of inspection `name` from `run_dir`, or the current directory, recording all
+/// of that directory before and after into an unsigned link. The command must exit with zero.
+fn run_inspection(name: &str, run: &[&str], run_dir: Option<&str>) -> Result<LinkMetadata> {
+    if run.is_empty() {
+        return Err(Error::IllegalArgument(format!(
+            "Inspection {:?} has no command to run",
+            name
+        )));
+    }
+    let (root, options) = match run_dir {
+        Some(dir) => (dir, RecordOptions::new().lstrip_paths(&[dir])),
+        None => (".", RecordOptions::new()),
+    };
+
+    let materials = record_artifacts_with_options(&[root], None, &options)?;
+    let byproducts = run_command(run, run_dir, None, None)?;
+    if byproducts.exit_code != Some(0) {
+        return Err(Error::VerificationFailure(format!(
+            "Inspection {:?} failed: {:?} exited with {:?}",
+            name, run, byproducts.exit_code
+        )));
+    }
+    let products = record_artifacts_with_options(&[root], None, &options)?;
+
+    LinkMetadataBuilder::new()
+        .name(name.to_string())
+        .materials(materials)
+        .products(products)
+        .byproducts(byproducts)
+        .build()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::crypto::{calculate_hash, HashValue, PrivateKey, SignatureScheme};
+    use crate::models::{Byproducts, LayoutBuilder, SignedMetadataBuilder, StepBuilder};
+    use crate::runlib::record_artifacts;
+    use chrono::{Duration, TimeZone};
+    use std::fs;
+    use std::io::Write;
+    use tempfile::{NamedTempFile, TempDir};
+
+    const LINK_DIR: &str = "tests/test_verifylib/links";
+    const PACKAGE: &[u8] = include_bytes!("../tests/test_verifylib/foo.tar.gz");
+
+    fn artifact(content: &[u8]) -> NamedTempFile {
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(content).unwrap();
+        file
+    }
+
+    #[test]
+    fn verify_artifact_hash_match() {
+        let file = artifact(b"in-toto");
+        let mut expected = TargetDescription::new();
+        expected.insert(
+            HashAlgorithm::Sha256,
+            calculate_hash(b"in-toto", HashAlgorithm::Sha256),
+        );
+        expected.insert(
+            HashAlgorithm::Sha512,
+            calculate_hash(b"in-toto", HashAlgorithm::Sha512),
+        );
+
+        assert_eq!(verify_artifact_hash(file.path(), &expected), Ok(true));
+    }
+
+    #[test]
+    fn verify_artifact_hash_mismatch() {
+        let file = artifact(b"in-toto");
+        let mut expected = TargetDescription::new();
+        expected.insert(
+            HashAlgorithm::Sha256,
+            calculate_hash(b"in-toto", HashAlgorithm::Sha256),
+        );
+        expected.insert(
+            HashAlgorithm::Sha512,
+            calculate_hash(b"tampered", HashAlgorithm::Sha512),
+        );
+
+        assert_eq!(verify_artifact_hash(file.path(), &expected), Ok(false));
+
+        expected.insert(HashAlgorithm::Sha512, HashValue::new(vec![0x00]));
+        assert_eq!(verify_artifact_hash(file.path(), &expected), Ok(false));
+    }
+
+    #[test]
+    fn verify_artifact_hash_rejects_empty_expectation() {
+        let file = artifact(b"in-toto");
+        assert!(verify_artifact_hash(file.path(), &TargetDescription::new()).is_err());
+    }
+
+    #[test]
+    fn verify_command_alignment_is_positional() {
+        let command = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+        let expected = command(&["tar", "czf", "foo.tar.gz", "foo.py"]);
+
+        assert!(verify_command_alignment(&expected, &expected));
+        assert!(!verify_command_alignment(
+            &command(&["tar", "czf", "foo.py", "foo.tar.gz"]),
+            &expected
+        ));
+        assert!(!verify_command_alignment(
+            &command(&["tar", "czf", "foo.tar.gz"]),
+            &expected
+        ));
+
+        // An empty expected command only matches a link that recorded no command.
+        assert!(verify_command_alignment(&[], &[]));
+        assert!(!verify_command_alignment(&expected, &[]));
+    }
+
+    fn sample_layout() -> SignedMetadata<Json, Layout> {
+        let file = File::open("tests/test_verifylib/root.layout").unwrap();
+        Json::from_reader(file).unwrap()
+    }
+
+    fn key(pk8: &[u8]) -> PublicKey {
+        let key = PrivateKey::from_pkcs8(pk8, SignatureScheme::Ed25519).unwrap();
+        key.public().clone()
+    }
+
+    fn owner() -> PublicKey {
+        key(include_bytes!("../tests/ed25519/ed25519-1.pk8.der"))
+    }
+
+    /// Recorded paths must be relative, so inspections run in the crate root.
+    fn inspection_dir(package: &[u8]) -> TempDir {
+        let dir = tempfile::tempdir_in(".").unwrap();
+        fs::write(dir.path().join("foo.tar.gz"), package).unwrap();
+        dir
+    }
+
+    fn verify_in(dir: &TempDir, layout_keys: &[PublicKey], link_dir: &str) -> Result<()> {
+        let dir = dir.path().file_name().unwrap().to_str().unwrap();
+        verify_supply_chain(&sample_layout(), layout_keys, link_dir, Some(dir)).map(|_| ())
+    }
+
+    #[test]
+    fn in_toto_verify_sample_supply_chain() {
+        let dir = inspection_dir(PACKAGE);
+        let root = dir.path().file_name().unwrap().to_str().unwrap();
+        let summary = verify_supply_chain(&sample_layout(), &[owner()], LINK_DIR, Some(root));
+        let summary = summary.unwrap();
+
+        let names = summary.links().keys().cloned().collect::<Vec<_>>();
+        assert_eq!(names, vec!["package", "untar", "write-code"]);
+        assert!(summary.materials().is_empty());
+        let products = summary
+            .products()
+            .keys()
+            .map(|p| p.value())
+            .collect::<Vec<_>>();
+        assert_eq!(products, vec!["foo.py", "foo.tar.gz"]);
+        assert_eq!(
+            summary.links()["untar"].products(),
+            summary.links()["package"].products()
+        );
+        assert_eq!(summary.links()["untar"].byproducts()["return-value"], "0");
+    }
+
+    #[test]
+    fn verification_summary_reports_consumed_artifacts() {
+        let dir = inspection_dir(PACKAGE);
+        let root = dir.path().file_name().unwrap().to_str().unwrap();
+        let summary =
+            verify_supply_chain(&sample_layout(), &[owner()], LINK_DIR, Some(root)).unwrap();
+        let paths = |paths: &[VirtualTargetPath]| {
+            paths
+                .iter()
+                .map(|p| p.value().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        // package: MATCH foo.py WITH PRODUCTS FROM write-code, then DISALLOW *.
+        let package = &summary.material_rules()["package"];
+        assert_eq!(package.rules.len(), 2);
+        assert!(matches!(
+            package.rules[0].rule,
+            SupplyChainRule::Match { .. }
+        ));
+        assert_eq!(paths(&package.rules[0].consumed), vec!["foo.py"]);
+        assert_eq!(package.rules[1].rule, SupplyChainRule::Disallow("*".into()));
+        assert!(package.rules[1].consumed.is_empty());
+        assert!(package.leftover.is_empty());
+
+        // untar: MATCH foo.py WITH PRODUCTS FROM write-code, then DISALLOW foo.py.
+        let untar = &summary.product_rules()["untar"];
+        assert_eq!(paths(&untar.rules[0].consumed), vec!["foo.py"]);
+        assert_eq!(paths(&untar.leftover), vec!["foo.tar.gz"]);
+
+        let layout = sample_layout().assume_valid().unwrap();
+        for step in layout.steps() {
+            assert_eq!(&summary.signers()[step.name()], step.pubkeys());
+        }
+        // The sample links record no command.
+        let warnings = summary.warnings();
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[0].contains("write-code") && warnings[0].contains("vi"));
+        assert!(warnings[1].contains("package") && warnings[1].contains("tar"));
+    }
+
+    #[test]
+    fn run_inspection_records_untarred_contents() {
+        let dir = inspection_dir(PACKAGE);
+        let root = dir.path().file_name().unwrap().to_str().unwrap();
+        let link = run_inspection("untar", &["tar", "xzf", "foo.tar.gz"], Some(root)).unwrap();
+
+        let paths = |artifacts: &BTreeMap<VirtualTargetPath, TargetDescription>| {
+            artifacts
+                .keys()
+                .map(|p| p.value().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(link.name(), "untar");
+        assert_eq!(paths(link.materials()), vec!["foo.tar.gz"]);
+        assert_eq!(paths(link.products()), vec!["foo.py", "foo.tar.gz"]);
+        let package: SignedMetadata<Json, LinkMetadata> =
+            Json::from_reader(File::open(format!("{}/package.40e35e8f.link", LINK_DIR)).unwrap())
+                .unwrap();
+        let package = package.assume_valid().unwrap();
+        assert_eq!(
+            link.materials()[&VirtualTargetPath::new("foo.tar.gz".into()).unwrap()],
+            package.products()[&VirtualTargetPath::new("foo.tar.gz".into()).unwrap()]
+        );
+
+        match run_inspection("untar", &["tar", "xzf", "missing.tar.gz"], Some(root)) {
+            Err(Error::VerificationFailure(msg)) => assert!(msg.contains("untar"), "{}", msg),
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert!(run_inspection("untar", &[], Some(root)).is_err());
+    }
+
+    #[test]
+    fn in_toto_verify_rejects_expired_layouts() {
+        let expires = Utc.with_ymd_and_hms(2100, 1, 1, 0, 0, 0).unwrap();
+        let links = tempfile::tempdir().unwrap();
+        let link_dir = links.path().to_str().unwrap();
+        let second = Duration::seconds(1);
+
+        // Expiration is checked before any link is loaded or inspection is run.
+        let layout = sample_layout();
+        assert_eq!(
+            in_toto_verify_at(&layout, &[owner()], link_dir, expires + second),
+            Err(Error::ExpiredLayout(expires))
+        );
+        assert!(matches!(
+            in_toto_verify_at(&layout, &[owner()], link_dir, expires - second),
+            Err(Error::VerificationFailure(_))
+        ));
+
+        // A sublayout is checked against the same time.
+        let owner = private(include_bytes!("../tests/ed25519/ed25519-1.pk8.der"));
+        let builder = private(include_bytes!("../tests/ed25519/ed25519-2.pk8.der"));
+        let sublayout = LayoutBuilder::new()
+            .expires(expires - Duration::days(1))
+            .signed::<Json>(&builder)
+            .unwrap();
+        write_sublayout(links.path(), "build", &sublayout);
+        let step = StepBuilder::new("build")
+            .add_pubkey(builder.key_id().clone())
+            .build()
+            .unwrap();
+        let layout = LayoutBuilder::new()
+            .add_key(builder.public().clone())
+            .add_step(step)
+            .expires(expires)
+            .signed::<Json>(&owner)
+            .unwrap();
+        let keys = [owner.public().clone()];
+        assert!(in_toto_verify_at(&layout, &keys, link_dir, expires - Duration::days(2)).is_ok());
+        assert_eq!(
+            in_toto_verify_at(&layout, &keys, link_dir, expires - second),
+            Err(Error::ExpiredLayout(expires - Duration::days(1)))
+        );
+    }
+
+    #[test]
+    fn in_toto_verify_requires_every_layout_key() {
+        let dir = inspection_dir(PACKAGE);
+        let other = key(include_bytes!("../tests/ed25519/ed25519-4.pk8.der"));
+
+        assert!(verify_in(&dir, &[], LINK_DIR).is_err());
+        assert!(verify_in(&dir, std::slice::from_ref(&other), LINK_DIR).is_err());
+        assert!(verify_in(&dir, &[owner(), other], LINK_DIR).is_err());
+        assert_eq!(verify_in(&dir, &[owner(), owner()], LINK_DIR), Ok(()));
+    }
+
+    #[test]
+    fn in_toto_verify_with_trust_config_requires_every_group() {
+        let security = private(include_bytes!("../tests/ed25519/ed25519-1.pk8.der"));
+        let release = private(include_bytes!("../tests/ed25519/ed25519-2.pk8.der"));
+        let other = private(include_bytes!("../tests/ed25519/ed25519-3.pk8.der"));
+        let links = tempfile::tempdir().unwrap();
+        let link_dir = links.path().to_str().unwrap();
+        let layout = LayoutBuilder::new().build().unwrap();
+        let layout = SignedMetadataBuilder::<Json, _>::from_metadata(&layout)
+            .unwrap()
+            .sign(&security)
+            .unwrap()
+            .sign(&release)
+            .unwrap()
+            .build();
+
+        let security_group = vec![security.public().clone(), other.public().clone()];
+        let config = RootTrustConfig::new()
+            .group(security_group.clone(), 1)
+            .group(vec![release.public().clone()], 1);
+        assert!(in_toto_verify_with_trust_config(&layout, &config, link_dir).is_ok());
+
+        let config = RootTrustConfig::new()
+            .group(security_group, 1)
+            .group(vec![other.public().clone()], 1);
+        match in_toto_verify_with_trust_config(&layout, &config, link_dir) {
+            Err(Error::VerificationFailure(msg)) => assert!(msg.contains("group 1"), "{}", msg),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn in_toto_verify_ignores_revoked_functionaries() {
+        let owner = private(include_bytes!("../tests/ed25519/ed25519-1.pk8.der"));
+        let compromised = private(include_bytes!("../tests/ed25519/ed25519-2.pk8.der"));
+        let builder = private(include_bytes!("../tests/ed25519/ed25519-3.pk8.der"));
+        let links = tempfile::tempdir().unwrap();
+        let link_dir = links.path().to_str().unwrap();
+
+        let step = StepBuilder::new("build")
+            .add_pubkey(compromised.key_id().clone())
+            .add_pubkey(builder.key_id().clone())
+            .threshold(1)
+            .build()
+            .unwrap();
+        let layout = LayoutBuilder::new()
+            .add_key(compromised.public().clone())
+            .add_key(builder.public().clone())
+            .add_step(step)
+            .signed::<Json>(&owner)
+            .unwrap();
+        let config = RootTrustConfig::new().group(vec![owner.public().clone()], 1);
+        let options =
+            VerifyOptions::new().revoked_keys(HashSet::from([compromised.key_id().clone()]));
+        let link = |key: &PrivateKey| {
+            LinkMetadataBuilder::new()
+                .name("build".to_string())
+                .signed::<Json>(key)
+                .unwrap()
+                .to_file(links.path())
+                .unwrap()
+        };
+        let _ = link(&compromised);
+        let valid = link(&builder);
+
+        // The threshold is still met by the link of the other functionary.
+        let summary = in_toto_verify_with_options(&layout, &config, link_dir, &options).unwrap();
+        assert_eq!(summary.signers()["build"], vec![builder.key_id().clone()]);
+
+        fs::remove_file(valid).unwrap();
+        assert!(in_toto_verify_with_trust_config(&layout, &config, link_dir).is_ok());
+        assert_eq!(
+            in_toto_verify_with_options(&layout, &config, link_dir, &options),
+            Err(Error::RevokedKey(compromised.key_id().clone()))
+        );
+    }
+
+    /// Links of a "clone" step producing the release artifacts, and of a "build" step using them
+    /// as materials, both signed by `functionary`.
+    fn clone_and_build_links(functionary: &PrivateKey) -> TempDir {
+        let links = tempfile::tempdir().unwrap();
+        let artifacts = record_artifacts(&["tests/test_runlib/release"], None).unwrap();
+        for (name, link) in [
+            (
+                "clone",
+                LinkMetadataBuilder::new().products(artifacts.clone()),
+            ),
+            ("build", LinkMetadataBuilder::new().materials(artifacts)),
+        ] {
+            let _ = link
+                .name(name.to_string())
+                .signed::<Json>(functionary)
+                .unwrap()
+                .to_file(links.path())
+                .unwrap();
+        }
+        links
+    }
+
+    #[test]
+    fn in_toto_verify_matches_windows_style_links() {
+        let owner = private(include_bytes!("../tests/ed25519/ed25519-1.pk8.der"));
+        let functionary = private(include_bytes!("../tests/ed25519/ed25519-2.pk8.der"));
+        let links = clone_and_build_links(&functionary);
+        let link_dir = links.path().to_str().unwrap();
+
+        // Rewrite the build link as if recorded on Windows, and sign it again.
+        let path = links
+            .path()
+            .join(format!("build.{:.8}.link", functionary.key_id().as_str()));
+        let signed: Value = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+        let mut link = signed["signed"].clone();
+        let materials = link["materials"]
+            .as_object()
+            .unwrap()
+            .iter()
+            .map(|(path, hashes)| (path.replace('/', "\\"), hashes.clone()))
+            .collect::<serde_json::Map<_, _>>();
+        assert!(materials.contains_key("tests\\test_runlib\\release\\artifact.txt"));
+        link["materials"] = Value::Object(materials);
+        let signature = functionary
+            .sign(&Json::canonicalize(&link).unwrap())
+            .unwrap();
+        let signed = serde_json::json!({ "signed": link, "signatures": [signature] });
+        fs::write(&path, serde_json::to_vec(&signed).unwrap()).unwrap();
+
+        let step = |name: &str| StepBuilder::new(name).add_pubkey(functionary.key_id().clone());
+        let build = step("build")
+            .add_material_rule(
+                SupplyChainRule::from_tokens(&[
+                    "MATCH",
+                    "tests/test_runlib/release/*",
+                    "WITH",
+                    "PRODUCTS",
+                    "FROM",
+                    "clone",
+                ])
+                .unwrap(),
+            )
+            .add_material_rule(SupplyChainRule::Disallow("*".into()));
+        let layout = LayoutBuilder::new()
+            .add_key(functionary.public().clone())
+            .add_step(step("clone").build().unwrap())
+            .add_step(build.build().unwrap())
+            .signed::<Json>(&owner)
+            .unwrap();
+
+        let summary = in_toto_verify(&layout, &[owner.public().clone()], link_dir).unwrap();
+        let materials = summary.links()["build"]
+            .materials()
+            .keys()
+            .map(|path| path.value())
+            .collect::<Vec<_>>();
+        assert_eq!(materials, vec!["tests/test_runlib/release/artifact.txt"]);
+    }
+
+    #[test]
+    fn in_toto_verify_substitutes_layout_parameters() {
+        let owner = private(include_bytes!("../tests/ed25519/ed25519-1.pk8.der"));
+        let functionary = private(include_bytes!("../tests/ed25519/ed25519-2.pk8.der"));
+        let links = clone_and_build_links(&functionary);
+        let link_dir = links.path().to_str().unwrap();
+
+        let step = |name: &str| StepBuilder::new(name).add_pubkey(functionary.key_id().clone());
+        let build = step("{STEP}")
+            .add_material_rule(
+                SupplyChainRule::from_tokens(&[
+                    "MATCH",
+                    "{RELEASE}/*",
+                    "WITH",
+                    "PRODUCTS",
+                    "FROM",
+                    "clone",
+                ])
+                .unwrap(),
+            )
+            .add_material_rule(SupplyChainRule::Disallow("*".into()));
+        let layout = LayoutBuilder::new()
+            .add_key(functionary.public().clone())
+            .add_step(step("clone").build().unwrap())
+            .add_step(build.build().unwrap())
+            .signed::<Json>(&owner)
+            .unwrap();
+        let config = RootTrustConfig::new().group(vec![owner.public().clone()], 1);
+        let verify = |substitutions: &[(&str, &str)]| {
+            let substitutions = substitutions
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect();
+            let options = VerifyOptions::new().substitutions(substitutions);
+            in_toto_verify_with_options(&layout, &config, link_dir, &options)
+        };
+
+        let summary = verify(&[("STEP", "build"), ("RELEASE", "tests/test_runlib/release")]);
+        let names = summary.unwrap().links().keys().cloned().collect::<Vec<_>>();
+        assert_eq!(names, vec!["build", "clone"]);
+
+        // The pattern no longer matches, leaving the materials of build to DISALLOW.
+        let mismatched = verify(&[("STEP", "build"), ("RELEASE", "tests/test_runlib/current")]);
+        assert!(matches!(mismatched, Err(Error::VerificationFailure(_))));
+
+        match verify(&[("STEP", "build")]) {
+            Err(Error::IllegalArgument(msg)) => assert!(msg.contains("{RELEASE}"), "{}", msg),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn in_toto_verify_reports_untracked_artifacts() {
+        let owner = private(include_bytes!("../tests/ed25519/ed25519-1.pk8.der"));
+        let functionary = private(include_bytes!("../tests/ed25519/ed25519-2.pk8.der"));
+        let links = clone_and_build_links(&functionary);
+        let link_dir = links.path().to_str().unwrap();
+
+        let verify = |clone_products: &[&[&str]]| {
+            let mut clone = StepBuilder::new("clone").add_pubkey(functionary.key_id().clone());
+            for rule in clone_products {
+                clone = clone.add_product_rule(SupplyChainRule::from_tokens(rule).unwrap());
+            }
+            let build = StepBuilder::new("build")
+                .add_pubkey(functionary.key_id().clone())
+                .add_material_rule(
+                    SupplyChainRule::from_tokens(&[
+                        "MATCH", "*", "WITH", "PRODUCTS", "FROM", "clone",
+                    ])
+                    .unwrap(),
+                )
+                .add_material_rule(SupplyChainRule::Allow("*".into()));
+            let layout = LayoutBuilder::new()
+                .add_key(functionary.public().clone())
+                .add_step(clone.build().unwrap())
+                .add_step(build.build().unwrap())
+                .signed::<Json>(&owner)
+                .unwrap();
+            in_toto_verify(&layout, &[owner.public().clone()], link_dir).unwrap()
+        };
+
+        // The product of clone is only let through by the trailing `ALLOW *`.
+        let summary = verify(&[&["ALLOW", "*"]]);
+        let artifact = VirtualTargetPath::new("tests/test_runlib/release/artifact.txt".into());
+        assert_eq!(
+            summary.untracked_products(),
+            BTreeMap::from([("clone".to_string(), vec![artifact.unwrap()])])
+        );
+        assert!(summary.untracked_materials().is_empty());
+
+        let summary = verify(&[&["CREATE", "*"], &["ALLOW", "*"]]);
+        assert!(summary.untracked_products().is_empty());
+    }
+
+    #[test]
+    fn chain_digest_ignores_link_order() {
+        let owner = private(include_bytes!("../tests/ed25519/ed25519-1.pk8.der"));
+        let functionary = private(include_bytes!("../tests/ed25519/ed25519-2.pk8.der"));
+        let links = clone_and_build_links(&functionary);
+        let step = |name: &str| {
+            StepBuilder::new(name)
+                .add_pubkey(functionary.key_id().clone())
+                .build()
+                .unwrap()
+        };
+        let layout = LayoutBuilder::new()
+            .add_key(functionary.public().clone())
+            .add_step(step("clone"))
+            .add_step(step("build"))
+            .signed::<Json>(&owner)
+            .unwrap();
+        let summary = in_toto_verify(
+            &layout,
+            &[owner.public().clone()],
+            links.path().to_str().unwrap(),
+        )
+        .unwrap();
+        let digest = summary.chain_digest().unwrap();
+
+        let mut reordered = summary.clone();
+        reordered.links = BTreeMap::new();
+        for (name, link) in summary.links().iter().rev() {
+            let _ = reordered.links.insert(name.clone(), link.clone());
+        }
+        assert_eq!(reordered.chain_digest().unwrap(), digest);
+
+        // A build link without its materials.
+        let mut changed = summary.clone();
+        let build = LinkMetadataBuilder::new().name("build".into()).build();
+        let _ = changed.links.insert("build".into(), build.unwrap());
+        assert_ne!(changed.chain_digest().unwrap(), digest);
+
+        let mut relaid = summary;
+        relaid.layout = LayoutBuilder::new().build().unwrap();
+        assert_ne!(relaid.chain_digest().unwrap(), digest);
+    }
+
+    #[test]
+    fn in_toto_verify_checks_step_dependencies() {
+        let owner = private(include_bytes!("../tests/ed25519/ed25519-1.pk8.der"));
+        let functionary = private(include_bytes!("../tests/ed25519/ed25519-2.pk8.der"));
+        let links = clone_and_build_links(&functionary);
+        let link_dir = links.path().to_str().unwrap();
+
+        let step = |name: &str| StepBuilder::new(name).add_pubkey(functionary.key_id().clone());
+        let clone = step("clone").build().unwrap();
+        let build = step("build")
+            .add_material_rule(
+                SupplyChainRule::from_tokens(&["MATCH", "*", "WITH", "PRODUCTS", "FROM", "clone"])
+                    .unwrap(),
+            )
+            .build()
+            .unwrap();
+        let layout = |step: &str, depends_on: &str| {
+            LayoutBuilder::new()
+                .add_key(functionary.public().clone())
+                .add_step(clone.clone())
+                .add_step(build.clone())
+                .add_step_dependency(step, depends_on)
+                .signed::<Json>(&owner)
+                .unwrap()
+        };
+        let keys = [owner.public().clone()];
+
+        assert!(in_toto_verify(&layout("build", "clone"), &keys, link_dir).is_ok());
+
+        // clone is declared to run after build, which matches its products.
+        match in_toto_verify(&layout("clone", "build"), &keys, link_dir) {
+            Err(Error::DependencyCycleOrViolation(msg)) => {
+                assert!(msg.contains("FROM clone"), "{}", msg)
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn in_toto_verify_requires_authorized_links() {
+        let dir = inspection_dir(PACKAGE);
+        let links = tempfile::tempdir().unwrap();
+        let link_dir = links.path().to_str().unwrap();
+        for entry in fs::read_dir(LINK_DIR).unwrap() {
+            let path = entry.unwrap().path();
+            fs::copy(&path, links.path().join(path.file_name().unwrap())).unwrap();
+        }
+        assert_eq!(verify_in(&dir, &[owner()], link_dir), Ok(()));
+
+        // A link signed by a key the step authorizes, but stored under another key ID.
+        let package = fs::read_dir(link_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .find(|path| path.to_str().unwrap().contains("package."))
+            .unwrap();
+        let renamed = links.path().join("package.00000000.link");
+        fs::rename(&package, &renamed).unwrap();
+        assert!(verify_in(&dir, &[owner()], link_dir).is_err());
+
+        // A link stored under an authorized key ID, but signed by another key.
+        let write_code = links.path().join(format!(
+            "write-code.{:.8}.link",
+            sample_layout().assume_valid().unwrap().steps()[0].pubkeys()[0].as_str()
+        ));
+        fs::copy(&write_code, &package).unwrap();
+        assert!(verify_in(&dir, &[owner()], link_dir).is_err());
+    }
+
+    #[test]
+    fn in_toto_verify_steps_verifies_a_single_step() {
+        let links = tempfile::tempdir().unwrap();
+        let link_dir = links.path().to_str().unwrap();
+        let package = "package.40e35e8f.link";
+        fs::copy(
+            Path::new(LINK_DIR).join(package),
+            links.path().join(package),
+        )
+        .unwrap();
+        let layout = sample_layout();
+        assert!(in_toto_verify(&layout, &[owner()], link_dir).is_err());
+
+        // The materials of package are matched against write-code, which is left out.
+        let summary = in_toto_verify_steps(&layout, &[owner()], link_dir, &["package"]).unwrap();
+        assert_eq!(summary.links().keys().collect::<Vec<_>>(), vec!["package"]);
+        let paths = |artifacts: &BTreeMap<VirtualTargetPath, TargetDescription>| {
+            artifacts
+                .keys()
+                .map(|p| p.value().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(paths(summary.materials()), vec!["foo.py"]);
+        assert_eq!(paths(summary.products()), vec!["foo.py", "foo.tar.gz"]);
+        let skipped = summary
+            .warnings()
+            .iter()
+            .filter(|warning| warning.starts_with("Skipping rule"))
+            .collect::<Vec<_>>();
+        assert_eq!(skipped.len(), 1);
+        assert!(skipped[0].contains("write-code"));
+
+        assert!(in_toto_verify_steps(&layout, &[owner()], link_dir, &["write-code"]).is_err());
+        match in_toto_verify_steps(&layout, &[owner()], link_dir, &["untar"]) {
+            Err(Error::IllegalArgument(msg)) => assert!(msg.contains("untar"), "{}", msg),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    fn private(pk8: &[u8]) -> PrivateKey {
+        PrivateKey::from_pkcs8(pk8, SignatureScheme::Ed25519).unwrap()
+    }
+
+    /// A layout with the single step `name`, whose links `functionary` signs.
+    fn single_step_layout(
+        name: &str,
+        functionary: &PrivateKey,
+        readme: &str,
+        signer: &PrivateKey,
+    ) -> SignedMetadata<Json, Layout> {
+        let step = StepBuilder::new(name)
+            .add_pubkey(functionary.key_id().clone())
+            .expected_command(&["make"])
+            .add_product_rule(SupplyChainRule::Allow("*".into()))
+            .build()
+            .unwrap();
+        LayoutBuilder::new()
+            .add_key(functionary.public().clone())
+            .add_step(step)
+            .readme(readme.to_string())
+            .signed::<Json>(signer)
+            .unwrap()
+    }
+
+    fn write_sublayout(dir: &Path, step: &str, signed: &SignedMetadata<Json, Layout>) {
+        let key_id = signed.signatures()[0].key_id().as_str().to_string();
+        let path = dir.join(format!("{}.{:.8}.link", step, key_id));
+        fs::write(path, serde_json::to_vec(signed).unwrap()).unwrap();
+    }
+
+    #[test]
+    fn in_toto_verify_sublayout() {
+        let owner = private(include_bytes!("../tests/ed25519/ed25519-1.pk8.der"));
+        let builder = private(include_bytes!("../tests/ed25519/ed25519-2.pk8.der"));
+        let developer = private(include_bytes!("../tests/ed25519/ed25519-3.pk8.der"));
+        let links = tempfile::tempdir().unwrap();
+        let link_dir = links.path().to_str().unwrap();
+
+        // The builder carries out "build" under their own layout, with a "compile" step.
+        let products = record_artifacts(&["tests/test_runlib/release"], None).unwrap();
+        let sublayout_dir = links
+            .path()
+            .join(format!("build.{:.8}", builder.key_id().as_str()));
+        fs::create_dir(&sublayout_dir).unwrap();
+        let compile = LinkMetadataBuilder::new()
+            .name("compile".to_string())
+            .command(vec!["make".to_string()])
+            .products(products.clone())
+            .byproducts(Byproducts {
+                exit_code: Some(0),
+                ..Byproducts::default()
+            })
+            .signed::<Json>(&developer)
+            .unwrap();
+        let _ = compile.to_file(&sublayout_dir).unwrap();
+        let sublayout = single_step_layout("compile", &developer, "build", &builder);
+        write_sublayout(links.path(), "build", &sublayout);
+
+        let layout = single_step_layout("build", &builder, "root", &owner);
+        let summary = verify_supply_chain(&layout, &[owner.public().clone()], link_dir, None);
+        let summary = summary.unwrap();
+        let build = &summary.links()["build"];
+        assert_eq!(build.name(), "build");
+        assert_eq!(build.command(), ["make"]);
+        assert!(build.materials().is_empty());
+        assert_eq!(build.products(), &products);
+        assert_eq!(build.byproducts()["return-value"], "0");
+        assert_eq!(summary.products(), &products);
+
+        // The sublayout is only verified with the key it is stored under.
+        let forged = single_step_layout("compile", &developer, "build", &developer);
+        let forged_dir = tempfile::tempdir().unwrap();
+        fs::write(
+            forged_dir
+                .path()
+                .join(format!("build.{:.8}.link", builder.key_id().as_str())),
+            serde_json::to_vec(&forged).unwrap(),
+        )
+        .unwrap();
+        let forged_dir = forged_dir.path().to_str().unwrap();
+        assert!(verify_supply_chain(&layout, &[owner.public().clone()], forged_dir, None).is_err());
+
+        // The links of the sublayout must satisfy it.
+        fs::remove_dir_all(&sublayout_dir).unwrap();
+        fs::create_dir(&sublayout_dir).unwrap();
+        assert!(verify_supply_chain(&layout, &[owner.public().clone()], link_dir, None).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn in_toto_verify_rejects_sublayout_cycles() {
+        let owner = private(include_bytes!("../tests/ed25519/ed25519-1.pk8.der"));
+        let builder = private(include_bytes!("../tests/ed25519/ed25519-2.pk8.der"));
+        let links = tempfile::tempdir().unwrap();
+        let link_dir = links.path().to_str().unwrap();
+
+        // The links of the sublayout are looked up in a directory linking back to its own.
+        let sublayout = single_step_layout("build", &builder, "build", &builder);
+        write_sublayout(links.path(), "build", &sublayout);
+        std::os::unix::fs::symlink(
+            ".",
+            links
+                .path()
+                .join(format!("build.{:.8}", builder.key_id().as_str())),
+        )
+        .unwrap();
+
+        let layout = single_step_layout("build", &builder, "root", &owner);
+        match verify_supply_chain(&layout, &[owner.public().clone()], link_dir, None) {
+            Err(Error::VerificationFailure(msg)) => {
+                assert!(msg.contains("below itself"), "{}", msg)
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn in_toto_verify_limits_sublayout_depth() {
+        let owner = private(include_bytes!("../tests/ed25519/ed25519-1.pk8.der"));
+        let builder = private(include_bytes!("../tests/ed25519/ed25519-2.pk8.der"));
+        let links = tempfile::tempdir().unwrap();
+        let link_dir = links.path().to_str().unwrap();
+
+        let nest = |depth: usize| {
+            let mut dir = links.path().to_path_buf();
+            for level in 0..=depth {
+                write_sublayout(
+                    &dir,
+                    "build",
+                    &single_step_layout("build", &builder, &level.to_string(), &builder),
+                );
+                dir = dir.join(format!("build.{:.8}", builder.key_id().as_str()));
+                fs::create_dir_all(&dir).unwrap();
+            }
+        };
+        let layout = single_step_layout("build", &builder, "root", &owner);
+        let verify = || verify_supply_chain(&layout, &[owner.public().clone()], link_dir, None);
+
+        // Within the limit, verification fails for want of a link below the last sublayout.
+        nest(MAX_SUBLAYOUT_DEPTH - 1);
+        match verify() {
+            Err(Error::VerificationFailure(msg)) => {
+                assert!(msg.contains("requires links"), "{}", msg)
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+        nest(MAX_SUBLAYOUT_DEPTH);
+        match verify() {
+            Err(Error::VerificationFailure(msg)) => assert!(msg.contains("nested"), "{}", msg),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn in_toto_verify_rejects_tampered_product() {
+        let dir = inspection_dir(PACKAGE);
+        fs::write(dir.path().join("foo.py"), b"print('tampered')\n").unwrap();
+        let root = dir.path().file_name().unwrap().to_str().unwrap();
+        let byproducts = run_command(
+            &["tar", "czf", "foo.tar.gz", "foo.py"],
+            Some(root),
+            None,
+            None,
+        );
+        assert_eq!(byproducts.unwrap().exit_code, Some(0));
+        fs::remove_file(dir.path().join("foo.py")).unwrap();
+
+        match verify_in(&dir, &[owner()], LINK_DIR) {
+            Err(Error::VerificationFailure(msg)) => assert!(msg.contains("foo.tar.gz"), "{}", msg),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+}
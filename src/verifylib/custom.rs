@@ -0,0 +1,95 @@
+//! Artifact rules with keywords of their own, registered at verification time.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+
+use super::ArtifactQueue;
+use crate::error::Error;
+use crate::models::LinkMetadata;
+use crate::Result;
+
+/// The links of a supply chain, by step or inspection name.
+pub type LinkMap = BTreeMap<String, LinkMetadata>;
+
+/// An artifact rule specific to an organization, applied in turn with the built-in rules.
+///
+/// Like a built-in rule, it sees the artifacts earlier rules left in `queue`, consumes the ones
+/// it accounts for, and fails verification by returning an error. `links` holds the links of
+/// every step being verified, to compare against.
+pub trait CustomRule: Send + Sync {
+    /// Apply the rule to the artifacts of `queue`.
+    fn evaluate(&self, queue: &mut ArtifactQueue, links: &LinkMap) -> Result<()>;
+}
+
+/// Builds the `CustomRule` for the tokens following its keyword, or fails if they are invalid.
+type Factory = Arc<dyn Fn(&[String]) -> Result<Box<dyn CustomRule>> + Send + Sync>;
+
+/// The custom rules known to verification, by keyword.
+///
+/// ```
+/// # use in_toto::verifylib::{ArtifactQueue, CustomRule, CustomRuleRegistry, LinkMap};
+/// # use in_toto::Result;
+/// /// `ACCEPT-ALL`: consume every artifact left.
+/// struct AcceptAll;
+///
+/// impl CustomRule for AcceptAll {
+///     fn evaluate(&self, queue: &mut ArtifactQueue, _links: &LinkMap) -> Result<()> {
+///         let remaining = queue.remaining().into_iter().cloned().collect::<Vec<_>>();
+///         queue.consume(&remaining);
+///         Ok(())
+///     }
+/// }
+///
+/// let registry = CustomRuleRegistry::new().register("ACCEPT-ALL", |_args: &[String]| {
+///     Ok(Box::new(AcceptAll) as Box<dyn CustomRule>)
+/// });
+/// assert_eq!(registry.keywords(), vec!["ACCEPT-ALL"]);
+/// ```
+#[derive(Clone, Default)]
+pub struct CustomRuleRegistry {
+    factories: BTreeMap<String, Factory>,
+}
+
+impl CustomRuleRegistry {
+    /// Create a registry without any custom rules.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Apply the rule built by `factory` from its arguments to rules with `keyword`, which is
+    /// case insensitive. Built-in keywords always parse as built-in rules, so registering one
+    /// has no effect. A later registration for the same keyword replaces an earlier one.
+    pub fn register<F>(mut self, keyword: &str, factory: F) -> Self
+    where
+        F: Fn(&[String]) -> Result<Box<dyn CustomRule>> + Send + Sync + 'static,
+    {
+        let _ = self
+            .factories
+            .insert(keyword.to_ascii_uppercase(), Arc::new(factory));
+        self
+    }
+
+    /// The registered keywords, in upper case and in order.
+    pub fn keywords(&self) -> Vec<&str> {
+        self.factories.keys().map(String::as_str).collect()
+    }
+
+    /// Build the rule registered for `keyword` from `args`. Fails with `Error::UnknownRule` if
+    /// nothing is registered for it.
+    pub(crate) fn build(&self, keyword: &str, args: &[String]) -> Result<Box<dyn CustomRule>> {
+        let factory = self
+            .factories
+            .get(keyword)
+            .ok_or_else(|| Error::UnknownRule(keyword.to_string()))?;
+        factory(args)
+    }
+}
+
+impl fmt::Debug for CustomRuleRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CustomRuleRegistry")
+            .field("keywords", &self.keywords())
+            .finish()
+    }
+}
@@ -0,0 +1,116 @@
+//! Layout parameters, substituted into a layout at verification time.
+
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+use crate::error::Error;
+use crate::models::Layout;
+use crate::Result;
+
+/// The fields of steps and of inspections that parameters are substituted into.
+const STEP_FIELDS: &[&str] = &[
+    "name",
+    "expected_materials",
+    "expected_products",
+    "expected_command",
+];
+const INSPECTION_FIELDS: &[&str] = &["name", "expected_materials", "expected_products", "run"];
+
+/// Replace the `{KEY}` placeholders in the names, artifact rules and commands of the steps and
+/// inspections of `layout` by the values of `substitutions`. Keys consist of ASCII letters,
+/// digits and `_`. Fails if a placeholder has no substitution, unless `substitutions` is empty:
+/// `layout` is then returned as is.
+pub(super) fn substitute_parameters(
+    layout: Layout,
+    substitutions: &BTreeMap<String, String>,
+) -> Result<Layout> {
+    if substitutions.is_empty() {
+        return Ok(layout);
+    }
+    let mut value = serde_json::to_value(&layout)?;
+    for (items, fields) in [("steps", STEP_FIELDS), ("inspect", INSPECTION_FIELDS)] {
+        let items = value.get_mut(items).and_then(Value::as_array_mut);
+        for item in items.into_iter().flatten() {
+            for field in fields {
+                if let Some(value) = item.get_mut(*field) {
+                    substitute_value(value, substitutions)?;
+                }
+            }
+        }
+    }
+    serde_json::from_value(value)
+        .map_err(|e| Error::Encoding(format!("Substituted layout is invalid: {}", e)))
+}
+
+fn substitute_value(value: &mut Value, substitutions: &BTreeMap<String, String>) -> Result<()> {
+    match value {
+        Value::String(text) => *text = substitute(text, substitutions)?,
+        Value::Array(values) => {
+            for value in values {
+                substitute_value(value, substitutions)?;
+            }
+        }
+        _ => (),
+    }
+    Ok(())
+}
+
+/// Replace the placeholders of `text`. Substituted values are not searched for placeholders.
+fn substitute(text: &str, substitutions: &BTreeMap<String, String>) -> Result<String> {
+    let mut substituted = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('{') {
+        substituted.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        match after.split_once('}') {
+            Some((key, remainder)) if is_key(key) => {
+                let value = substitutions.get(key).ok_or_else(|| {
+                    Error::IllegalArgument(format!(
+                        "No substitution for the parameter {{{}}} in {:?}",
+                        key, text
+                    ))
+                })?;
+                substituted.push_str(value);
+                rest = remainder;
+            }
+            _ => {
+                substituted.push('{');
+                rest = after;
+            }
+        }
+    }
+    substituted.push_str(rest);
+    Ok(substituted)
+}
+
+fn is_key(key: &str) -> bool {
+    !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn substitutes_placeholders() {
+        let substitutions = vec![
+            ("SHA".to_string(), "abc123".to_string()),
+            ("DIR".to_string(), "{SHA}".to_string()),
+        ]
+        .into_iter()
+        .collect();
+
+        assert_eq!(
+            substitute("out/{DIR}/app-{SHA}.tar", &substitutions),
+            Ok("out/{SHA}/app-abc123.tar".to_string())
+        );
+        assert_eq!(
+            substitute("{a,b} {} {not a key} {", &substitutions),
+            Ok("{a,b} {} {not a key} {".to_string())
+        );
+        match substitute("app-{TAG}.tar", &substitutions) {
+            Err(Error::IllegalArgument(msg)) => assert!(msg.contains("{TAG}"), "{}", msg),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,122 @@
+//! The working set of artifacts the artifact rules of a step are applied to.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::models::{TargetDescription, VirtualTargetPath};
+
+/// The materials or products of a step that no artifact rule has consumed yet.
+///
+/// Rules are applied in order: each one filters the queue with its pattern, checks the matched
+/// artifacts, and consumes the ones it accounts for so that later rules no longer see them.
+/// Patterns are matched with `VirtualTargetPath::matches`, so `*` also matches `/`, as in the
+/// reference implementation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArtifactQueue {
+    artifacts: BTreeMap<VirtualTargetPath, TargetDescription>,
+    remaining: BTreeSet<VirtualTargetPath>,
+}
+
+impl ArtifactQueue {
+    /// Create a queue holding all of `artifacts`.
+    pub fn new(artifacts: &BTreeMap<VirtualTargetPath, TargetDescription>) -> Self {
+        ArtifactQueue {
+            artifacts: artifacts.clone(),
+            remaining: artifacts.keys().cloned().collect(),
+        }
+    }
+
+    /// The remaining artifacts whose path matches `pattern`, in path order.
+    pub fn filter(&self, pattern: &str) -> Vec<&VirtualTargetPath> {
+        self.remaining
+            .iter()
+            .filter(|path| path.matches(pattern))
+            .collect()
+    }
+
+    /// Remove `paths` from the queue. Paths that were already consumed, or never queued, are
+    /// ignored.
+    pub fn consume<'a, I>(&mut self, paths: I)
+    where
+        I: IntoIterator<Item = &'a VirtualTargetPath>,
+    {
+        for path in paths {
+            let _ = self.remaining.remove(path);
+        }
+    }
+
+    /// The artifacts that have not been consumed, in path order.
+    pub fn remaining(&self) -> Vec<&VirtualTargetPath> {
+        self.remaining.iter().collect()
+    }
+
+    /// Whether every artifact has been consumed.
+    pub fn is_empty(&self) -> bool {
+        self.remaining.is_empty()
+    }
+
+    /// The recorded hashes of the queued artifact at `path`, whether or not it was consumed.
+    pub fn hashes(&self, path: &VirtualTargetPath) -> Option<&TargetDescription> {
+        self.artifacts.get(path)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn queue(paths: &[&str]) -> ArtifactQueue {
+        let artifacts = paths
+            .iter()
+            .map(|path| {
+                (
+                    VirtualTargetPath::new(path.to_string()).unwrap(),
+                    TargetDescription::new(),
+                )
+            })
+            .collect();
+        ArtifactQueue::new(&artifacts)
+    }
+
+    fn values(paths: Vec<&VirtualTargetPath>) -> Vec<&str> {
+        paths.into_iter().map(|path| path.value()).collect()
+    }
+
+    #[test]
+    fn consume_removes_artifacts_from_later_filters() {
+        let mut queue = queue(&["README.md", "src/lib.rs", "src/main.rs"]);
+        let sources = queue
+            .filter("src/*")
+            .into_iter()
+            .cloned()
+            .collect::<Vec<_>>();
+        assert_eq!(
+            values(sources.iter().collect()),
+            vec!["src/lib.rs", "src/main.rs"]
+        );
+
+        queue.consume(&sources);
+        assert!(queue.filter("src/*").is_empty());
+        assert_eq!(values(queue.filter("*")), vec!["README.md"]);
+        assert_eq!(values(queue.remaining()), vec!["README.md"]);
+        assert!(queue.hashes(&sources[0]).is_some());
+
+        // Consuming again, or consuming unknown paths, changes nothing.
+        queue.consume(&sources);
+        queue.consume(&[VirtualTargetPath::new("missing".into()).unwrap()]);
+        assert_eq!(values(queue.remaining()), vec!["README.md"]);
+
+        queue.consume(&[VirtualTargetPath::new("README.md".into()).unwrap()]);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn wildcards_match_across_directories() {
+        let queue = queue(&["README.md", "docs/guide/intro.md", "src/lib.rs"]);
+        assert_eq!(
+            values(queue.filter("*.md")),
+            vec!["README.md", "docs/guide/intro.md"]
+        );
+        // An unterminated set is a literal `[`, as in the reference, not an error.
+        assert!(queue.filter("[").is_empty());
+    }
+}
@@ -0,0 +1,293 @@
+//! Verdicts on the steps of a supply chain, as reported by one or more verifiers.
+
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+
+use super::VerificationSummary;
+use crate::error::Error;
+use crate::Result;
+
+/// The verdict on a step or inspection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    /// It verified.
+    Passed,
+    /// It failed to verify.
+    Failed(Failure),
+}
+
+/// Why a step or inspection failed to verify.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Failure {
+    /// The kind of failure, such as `threshold-not-met` or `expired-metadata`, as given by
+    /// `VerificationReport::from_error`.
+    pub rule_id: String,
+    /// The error message.
+    pub message: String,
+}
+
+/// The verdicts on the steps and inspections of a supply chain, by name.
+///
+/// Verifiers that each verify some of the steps, e.g. with `in_toto_verify_steps`, produce
+/// partial reports, which `merge` combines into the report on the whole supply chain.
+///
+/// ```
+/// # use in_toto::error::Error;
+/// # use in_toto::verifylib::VerificationReport;
+/// let build = VerificationReport::from_error(&["build"], &Error::BadSignature);
+/// let package = VerificationReport::from_error(&["package"], &Error::BadSignature);
+/// let report = build.merge(vec![package]).unwrap();
+/// assert_eq!(report.verdicts().len(), 2);
+/// assert!(!report.passed());
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VerificationReport {
+    verdicts: BTreeMap<String, Verdict>,
+}
+
+impl VerificationReport {
+    /// A report that every step and inspection `summary` holds a link for passed.
+    pub fn from_summary(summary: &VerificationSummary) -> Self {
+        VerificationReport {
+            verdicts: summary
+                .links()
+                .keys()
+                .map(|name| (name.clone(), Verdict::Passed))
+                .collect(),
+        }
+    }
+
+    /// A report that each of `steps` failed with `error`, as when verifying them together failed.
+    ///
+    /// The rule ID of the failure is given by the kind of error: `bad-signature`,
+    /// `threshold-not-met`, `revoked-key`, `expired-metadata`, `step-dependency`, `unknown-rule`,
+    /// `inspection-failed` for inspections whose command failed, and `verification-failure` for
+    /// the rest, such as artifact rule violations.
+    pub fn from_error(steps: &[&str], error: &Error) -> Self {
+        let failure = Failure {
+            rule_id: rule_id(error).to_string(),
+            message: error.to_string(),
+        };
+        VerificationReport {
+            verdicts: steps
+                .iter()
+                .map(|name| (name.to_string(), Verdict::Failed(failure.clone())))
+                .collect(),
+        }
+    }
+
+    /// The verdict on each step and inspection, by name.
+    pub fn verdicts(&self) -> &BTreeMap<String, Verdict> {
+        &self.verdicts
+    }
+
+    /// Whether every step and inspection in the report passed.
+    pub fn passed(&self) -> bool {
+        self.verdicts
+            .values()
+            .all(|verdict| *verdict == Verdict::Passed)
+    }
+
+    /// Combine this report with the partial reports `others`, of other verifiers.
+    ///
+    /// A step may be in several reports, as long as it passed in all of them or failed in all of
+    /// them; the first failure is kept. Fails with `Error::VerificationFailure` if a step passed
+    /// in one report and failed in another. The merged report only passes if all of the partial
+    /// reports pass.
+    pub fn merge<I>(mut self, others: I) -> Result<VerificationReport>
+    where
+        I: IntoIterator<Item = VerificationReport>,
+    {
+        for other in others {
+            for (name, verdict) in other.verdicts {
+                match self.verdicts.get(&name) {
+                    None => {
+                        let _ = self.verdicts.insert(name, verdict);
+                    }
+                    Some(existing)
+                        if (*existing == Verdict::Passed) != (verdict == Verdict::Passed) =>
+                    {
+                        return Err(Error::VerificationFailure(format!(
+                            "Conflicting verdicts for {:?}: {:?} and {:?}",
+                            name, existing, verdict
+                        )));
+                    }
+                    Some(_) => (),
+                }
+            }
+        }
+        Ok(self)
+    }
+
+    /// The failures of the report as a SARIF 2.1.0 log, e.g. for code scanning dashboards.
+    ///
+    /// Each failing step or inspection is a result of level `error`, with the rule ID of its
+    /// failure and a logical location naming the step. Steps that passed are left out.
+    pub fn to_sarif(&self) -> Value {
+        let failures = self
+            .verdicts
+            .iter()
+            .filter_map(|(name, verdict)| match verdict {
+                Verdict::Passed => None,
+                Verdict::Failed(failure) => Some((name, failure)),
+            })
+            .collect::<Vec<_>>();
+        let mut rule_ids = failures
+            .iter()
+            .map(|(_, failure)| failure.rule_id.as_str())
+            .collect::<Vec<_>>();
+        rule_ids.sort_unstable();
+        rule_ids.dedup();
+
+        let results = failures
+            .iter()
+            .map(|(name, failure)| {
+                json!({
+                    "ruleId": failure.rule_id,
+                    "level": "error",
+                    "message": { "text": failure.message },
+                    "locations": [{
+                        "logicalLocations": [{ "name": name, "kind": "module" }],
+                    }],
+                })
+            })
+            .collect::<Vec<_>>();
+        json!({
+            "$schema": SARIF_SCHEMA,
+            "version": "2.1.0",
+            "runs": [{
+                "tool": {
+                    "driver": {
+                        "name": "in-toto",
+                        "version": env!("CARGO_PKG_VERSION"),
+                        "informationUri": "https://in-toto.io",
+                        "rules": rule_ids
+                            .iter()
+                            .map(|id| json!({ "id": id }))
+                            .collect::<Vec<_>>(),
+                    },
+                },
+                "results": results,
+            }],
+        })
+    }
+}
+
+const SARIF_SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+
+fn rule_id(error: &Error) -> &'static str {
+    match error {
+        Error::BadSignature | Error::UnknownKeyId(_) => "bad-signature",
+        Error::ThresholdNotMet { .. } => "threshold-not-met",
+        Error::RevokedKey(_) => "revoked-key",
+        Error::ExpiredLayout(_) => "expired-metadata",
+        Error::DependencyCycleOrViolation(_) => "step-dependency",
+        Error::UnknownRule(_) => "unknown-rule",
+        Error::CommandFailed { .. } | Error::CommandNotFound(_) | Error::CommandTimeout { .. } => {
+            "inspection-failed"
+        }
+        _ => "verification-failure",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::models::{LayoutBuilder, LinkMetadataBuilder};
+    use chrono::Utc;
+
+    fn passed(names: &[&str]) -> VerificationReport {
+        let links = names
+            .iter()
+            .map(|name| {
+                let link = LinkMetadataBuilder::new()
+                    .name(name.to_string())
+                    .build()
+                    .unwrap();
+                (name.to_string(), link)
+            })
+            .collect();
+        VerificationReport::from_summary(&VerificationSummary {
+            layout: LayoutBuilder::new().build().unwrap(),
+            links,
+            signers: BTreeMap::new(),
+            material_rules: BTreeMap::new(),
+            product_rules: BTreeMap::new(),
+            materials: BTreeMap::new(),
+            products: BTreeMap::new(),
+            warnings: Vec::new(),
+        })
+    }
+
+    fn failed(names: &[&str]) -> VerificationReport {
+        VerificationReport::from_error(names, &Error::VerificationFailure("no link".into()))
+    }
+
+    #[test]
+    fn merge_consistent_reports() {
+        let report = passed(&["clone"])
+            .merge(vec![passed(&["build", "clone"]), passed(&["package"])])
+            .unwrap();
+        assert_eq!(
+            report.verdicts().keys().collect::<Vec<_>>(),
+            vec!["build", "clone", "package"]
+        );
+        assert!(report.passed());
+
+        let report = passed(&["clone", "build"])
+            .merge(vec![failed(&["package"]), failed(&["package"])])
+            .unwrap();
+        assert!(!report.passed());
+        assert_eq!(report.verdicts()["build"], Verdict::Passed);
+        assert!(matches!(report.verdicts()["package"], Verdict::Failed(_)));
+    }
+
+    #[test]
+    fn to_sarif_reports_each_failure() {
+        let threshold = Error::ThresholdNotMet {
+            valid: 0,
+            threshold: 1,
+        };
+        let report = passed(&["clone"])
+            .merge(vec![
+                VerificationReport::from_error(&["build"], &threshold),
+                VerificationReport::from_error(&["package"], &Error::ExpiredLayout(Utc::now())),
+            ])
+            .unwrap();
+
+        let sarif = report.to_sarif();
+        assert_eq!(sarif["version"], "2.1.0");
+        let results = sarif["runs"][0]["results"].as_array().unwrap();
+        let results = results
+            .iter()
+            .map(|result| {
+                (
+                    result["ruleId"].as_str().unwrap(),
+                    result["locations"][0]["logicalLocations"][0]["name"]
+                        .as_str()
+                        .unwrap(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            results,
+            vec![
+                ("threshold-not-met", "build"),
+                ("expired-metadata", "package")
+            ]
+        );
+        assert_eq!(
+            sarif["runs"][0]["tool"]["driver"]["rules"],
+            json!([{ "id": "expired-metadata" }, { "id": "threshold-not-met" }])
+        );
+    }
+
+    #[test]
+    fn merge_conflicting_reports() {
+        match passed(&["clone", "build"]).merge(vec![failed(&["build"])]) {
+            Err(Error::VerificationFailure(msg)) => assert!(msg.contains("build"), "{}", msg),
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert!(failed(&["build"]).merge(vec![passed(&["build"])]).is_err());
+    }
+}
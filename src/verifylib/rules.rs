@@ -0,0 +1,521 @@
+//! Application of artifact rules to the links of a supply chain.
+
+use log::warn;
+use std::collections::BTreeMap;
+
+use super::{ArtifactQueue, VerifyOptions};
+use crate::error::Error;
+use crate::models::{
+    ArtifactType, LinkMetadata, SupplyChainRule, TargetDescription, VirtualTargetPath,
+};
+use crate::Result;
+
+/// The artifacts one artifact rule consumed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuleOutcome {
+    /// The rule.
+    pub rule: SupplyChainRule,
+    /// The artifacts the rule accounted for, in path order. Always empty for `DISALLOW` and
+    /// `REQUIRE`, which only check what is left.
+    pub consumed: Vec<VirtualTargetPath>,
+}
+
+/// What the artifact rules of a step or inspection did with its materials or its products.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RulesOutcome {
+    /// Each rule with the artifacts it consumed, in rule order.
+    pub rules: Vec<RuleOutcome>,
+    /// The artifacts no rule consumed, in path order.
+    pub leftover: Vec<VirtualTargetPath>,
+}
+
+impl RulesOutcome {
+    /// The artifacts that passed without any rule looking at them: those consumed by a
+    /// catch-all `ALLOW`, such as `ALLOW *`, and those left over, which the default
+    /// `UnmatchedPolicy::Allow` lets through. These are where a layout is under-specified.
+    ///
+    /// ```
+    /// # use in_toto::models::{SupplyChainRule, VirtualTargetPath};
+    /// # use in_toto::verifylib::{RuleOutcome, RulesOutcome};
+    /// let path = |path: &str| VirtualTargetPath::new(path.into()).unwrap();
+    /// let outcome = RulesOutcome {
+    ///     rules: vec![
+    ///         RuleOutcome {
+    ///             rule: SupplyChainRule::Create("*.tar.gz".into()),
+    ///             consumed: vec![path("app.tar.gz")],
+    ///         },
+    ///         RuleOutcome {
+    ///             rule: SupplyChainRule::Allow("*".into()),
+    ///             consumed: vec![path("build.log")],
+    ///         },
+    ///     ],
+    ///     leftover: Vec::new(),
+    /// };
+    /// assert_eq!(outcome.untracked(), vec![path("build.log")]);
+    /// ```
+    pub fn untracked(&self) -> Vec<VirtualTargetPath> {
+        let mut untracked = self
+            .rules
+            .iter()
+            .filter(|outcome| match &outcome.rule {
+                SupplyChainRule::Allow(pattern) => pattern.chars().all(|c| c == '*'),
+                _ => false,
+            })
+            .flat_map(|outcome| outcome.consumed.iter())
+            .chain(&self.leftover)
+            .cloned()
+            .collect::<Vec<_>>();
+        untracked.sort();
+        untracked
+    }
+}
+
+/// What to do with the artifacts that none of the artifact rules of a step or inspection
+/// consumed, applied after all of its rules.
+///
+/// A policy is equivalent to an implicit trailing rule: `Allow` to `ALLOW *` and `Disallow` to
+/// `DISALLOW *`, appended to every rule list, so that layouts do not have to end each of them
+/// with a catch-all rule.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UnmatchedPolicy {
+    /// Ignore the artifacts left over, as rule lists without a catch-all rule do.
+    #[default]
+    Allow,
+    /// Fail if any artifact is left over.
+    Disallow,
+}
+
+fn artifacts(
+    link: &LinkMetadata,
+    artifact_type: ArtifactType,
+) -> &BTreeMap<VirtualTargetPath, TargetDescription> {
+    match artifact_type {
+        ArtifactType::Materials => link.materials(),
+        ArtifactType::Products => link.products(),
+    }
+}
+
+/// Apply `rules`, in order, to the `source_type` artifacts of the link of `source_name`.
+///
+/// Each rule consumes the queued artifacts it accounts for, so later rules only see what is
+/// left: a trailing `DISALLOW *` fails on any artifact no earlier rule consumed. `MATCH` rules
+/// look up the link of the other step in `links`, and consume nothing if there is none, unless
+/// that step is one of `out_of_scope`, the steps left out of a partial verification: the rule
+/// is then taken as satisfied by every artifact it filters, with a warning added to `warnings`.
+/// Custom rules are applied with the registry of `options`, and its `UnmatchedPolicy` is
+/// applied to what the rules leave over. Returns what each rule consumed.
+pub(crate) fn verify_item_rules(
+    source_name: &str,
+    source_type: ArtifactType,
+    rules: &[SupplyChainRule],
+    links: &BTreeMap<String, LinkMetadata>,
+    out_of_scope: &[&str],
+    options: &VerifyOptions,
+    warnings: &mut Vec<String>,
+) -> Result<RulesOutcome> {
+    let link = links.get(source_name).ok_or_else(|| {
+        Error::VerificationFailure(format!("No link for {:?} to apply rules to", source_name))
+    })?;
+    let source = artifacts(link, source_type);
+    let mut queue = ArtifactQueue::new(source);
+    let mut outcome = RulesOutcome::default();
+
+    for rule in rules {
+        let consumed: Vec<VirtualTargetPath> = match rule {
+            SupplyChainRule::Match {
+                pattern,
+                source_prefix,
+                dest_prefix,
+                dest_type,
+                dest_name,
+            } => {
+                let pattern = match source_prefix {
+                    Some(prefix) => format!("{}/{}", prefix.trim_end_matches('/'), pattern),
+                    None => pattern.clone(),
+                };
+                match links.get(dest_name) {
+                    Some(dest) => {
+                        let dest = artifacts(dest, *dest_type);
+                        queue
+                            .filter(&pattern)
+                            .into_iter()
+                            .filter(|path| {
+                                let dest_path = match_dest_path(
+                                    path.value(),
+                                    source_prefix.as_deref(),
+                                    dest_prefix.as_deref(),
+                                );
+                                let dest_hashes = VirtualTargetPath::new(dest_path)
+                                    .ok()
+                                    .and_then(|dest_path| dest.get(&dest_path));
+                                dest_hashes.is_some() && dest_hashes == source.get(path)
+                            })
+                            .cloned()
+                            .collect()
+                    }
+                    None if out_of_scope.contains(&dest_name.as_str()) => {
+                        let message = format!(
+                            "Skipping rule {} of {:?}: {:?} is not verified",
+                            rule, source_name, dest_name
+                        );
+                        warn!("{}", message);
+                        warnings.push(message);
+                        queue.filter(&pattern).into_iter().cloned().collect()
+                    }
+                    None => Vec::new(),
+                }
+            }
+            SupplyChainRule::Create(pattern) => queue
+                .filter(pattern)
+                .into_iter()
+                .filter(|path| {
+                    !link.materials().contains_key(path) && link.products().contains_key(path)
+                })
+                .cloned()
+                .collect(),
+            SupplyChainRule::Delete(pattern) => queue
+                .filter(pattern)
+                .into_iter()
+                .filter(|path| {
+                    link.materials().contains_key(path) && !link.products().contains_key(path)
+                })
+                .cloned()
+                .collect(),
+            SupplyChainRule::Modify(pattern) => queue
+                .filter(pattern)
+                .into_iter()
+                .filter(
+                    |path| match (link.materials().get(path), link.products().get(path)) {
+                        (Some(material), Some(product)) => material != product,
+                        _ => false,
+                    },
+                )
+                .cloned()
+                .collect(),
+            SupplyChainRule::Allow(pattern) => queue.filter(pattern).into_iter().cloned().collect(),
+            SupplyChainRule::Disallow(pattern) => {
+                if let Some(path) = queue.filter(pattern).first() {
+                    return Err(Error::VerificationFailure(format!(
+                        "Artifact {:?} of {:?} is disallowed by rule {}",
+                        path.value(),
+                        source_name,
+                        rule
+                    )));
+                }
+                Vec::new()
+            }
+            SupplyChainRule::Require(required) => {
+                if !queue
+                    .remaining()
+                    .iter()
+                    .any(|path| path.value() == required)
+                {
+                    return Err(Error::VerificationFailure(format!(
+                        "{:?} is missing artifact {:?} required by rule {}",
+                        source_name, required, rule
+                    )));
+                }
+                Vec::new()
+            }
+            SupplyChainRule::Custom { keyword, args } => {
+                let custom = options.custom_rules.build(keyword, args)?;
+                let before = queue.remaining().into_iter().cloned().collect::<Vec<_>>();
+                custom.evaluate(&mut queue, links)?;
+                let after = queue.remaining();
+                before
+                    .into_iter()
+                    .filter(|path| !after.contains(&path))
+                    .collect()
+            }
+        };
+        queue.consume(&consumed);
+        outcome.rules.push(RuleOutcome {
+            rule: rule.clone(),
+            consumed,
+        });
+    }
+
+    outcome.leftover = queue.remaining().into_iter().cloned().collect();
+    if let (UnmatchedPolicy::Disallow, Some(path)) =
+        (options.unmatched_artifacts, outcome.leftover.first())
+    {
+        return Err(Error::VerificationFailure(format!(
+            "Artifact {:?} of {:?} is not consumed by any rule, and unmatched artifacts are \
+             disallowed",
+            path.value(),
+            source_name
+        )));
+    }
+    Ok(outcome)
+}
+
+/// The path the artifact at `path` is expected under in the other step of a `MATCH` rule.
+fn match_dest_path(path: &str, source_prefix: Option<&str>, dest_prefix: Option<&str>) -> String {
+    let path = match source_prefix {
+        Some(prefix) => path
+            .strip_prefix(prefix.trim_end_matches('/'))
+            .map_or(path, |path| path.trim_start_matches('/')),
+        None => path,
+    };
+    match dest_prefix {
+        Some(prefix) => format!("{}/{}", prefix.trim_end_matches('/'), path),
+        None => path.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::crypto::{calculate_hash, HashAlgorithm};
+    use crate::models::LinkMetadataBuilder;
+    use crate::verifylib::{CustomRule, CustomRuleRegistry, LinkMap};
+
+    fn artifacts(entries: &[(&str, &str)]) -> BTreeMap<VirtualTargetPath, TargetDescription> {
+        entries
+            .iter()
+            .map(|(path, content)| {
+                let mut hashes = TargetDescription::new();
+                let _ = hashes.insert(
+                    HashAlgorithm::Sha256,
+                    calculate_hash(content.as_bytes(), HashAlgorithm::Sha256),
+                );
+                (VirtualTargetPath::new(path.to_string()).unwrap(), hashes)
+            })
+            .collect()
+    }
+
+    fn links() -> BTreeMap<String, LinkMetadata> {
+        let clone = LinkMetadataBuilder::new()
+            .name("clone".into())
+            .products(artifacts(&[("src/foo.py", "foo"), ("README", "readme")]))
+            .build()
+            .unwrap();
+        let build = LinkMetadataBuilder::new()
+            .name("build".into())
+            .materials(artifacts(&[
+                ("foo.py", "foo"),
+                ("README", "readme"),
+                ("old.txt", "old"),
+                ("config", "before"),
+            ]))
+            .products(artifacts(&[
+                ("foo.py", "foo"),
+                ("README", "readme"),
+                ("config", "after"),
+                ("out/foo.bin", "binary"),
+            ]))
+            .build()
+            .unwrap();
+        vec![("clone".to_string(), clone), ("build".to_string(), build)]
+            .into_iter()
+            .collect()
+    }
+
+    fn rules(rules: &[&[&str]]) -> Vec<SupplyChainRule> {
+        rules
+            .iter()
+            .map(|tokens| SupplyChainRule::from_tokens(tokens).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn rules_consume_artifacts_before_disallow() {
+        let links = links();
+        let materials = rules(&[
+            &[
+                "MATCH", "*", "WITH", "PRODUCTS", "IN", "src", "FROM", "clone",
+            ],
+            &["MATCH", "README", "WITH", "PRODUCTS", "FROM", "clone"],
+            &["DELETE", "old.txt"],
+            &["ALLOW", "config"],
+            &["DISALLOW", "*"],
+        ]);
+        let products = rules(&[
+            &["REQUIRE", "foo.py"],
+            &["CREATE", "out/*"],
+            &["MODIFY", "config"],
+            &["MATCH", "*", "WITH", "MATERIALS", "FROM", "build"],
+            &["DISALLOW", "*"],
+        ]);
+        let verify = |artifact_type, rules: &[SupplyChainRule]| {
+            verify_item_rules(
+                "build",
+                artifact_type,
+                rules,
+                &links,
+                &[],
+                &VerifyOptions::new(),
+                &mut Vec::new(),
+            )
+            .map(|_| ())
+        };
+
+        assert_eq!(verify(ArtifactType::Materials, &materials), Ok(()));
+        assert_eq!(verify(ArtifactType::Products, &products), Ok(()));
+
+        // Without the rule consuming it, `old.txt` is left for DISALLOW.
+        let mut without_delete = materials.clone();
+        let _ = without_delete.remove(2);
+        assert!(verify(ArtifactType::Materials, &without_delete).is_err());
+
+        // Modified files are not matched, and so not consumed, by MATCH.
+        let strict = rules(&[
+            &["MATCH", "*", "WITH", "MATERIALS", "FROM", "build"],
+            &["DISALLOW", "config"],
+        ]);
+        assert!(verify(ArtifactType::Products, &strict).is_err());
+
+        // REQUIRE only looks at what earlier rules left over.
+        let consumed_first = rules(&[&["ALLOW", "foo.py"], &["REQUIRE", "foo.py"]]);
+        assert!(verify(ArtifactType::Products, &consumed_first).is_err());
+    }
+
+    #[test]
+    fn match_rules_apply_prefixes() {
+        assert_eq!(match_dest_path("src/foo.py", Some("src"), None), "foo.py");
+        assert_eq!(match_dest_path("foo.py", None, Some("src/")), "src/foo.py");
+        assert_eq!(
+            match_dest_path("a/foo.py", Some("a/"), Some("b")),
+            "b/foo.py"
+        );
+
+        let links = links();
+        let rules = rules(&[
+            &[
+                "MATCH",
+                "foo.py",
+                "IN",
+                "src",
+                "WITH",
+                "MATERIALS",
+                "FROM",
+                "build",
+            ],
+            &["MATCH", "*", "WITH", "PRODUCTS", "FROM", "missing"],
+            &["ALLOW", "README"],
+            &["DISALLOW", "*"],
+        ]);
+        let outcome = verify_item_rules(
+            "clone",
+            ArtifactType::Products,
+            &rules,
+            &links,
+            &[],
+            &VerifyOptions::new(),
+            &mut Vec::new(),
+        )
+        .unwrap();
+        let consumed = outcome
+            .rules
+            .iter()
+            .map(|rule| rule.consumed.len())
+            .collect::<Vec<_>>();
+        assert_eq!(consumed, vec![1, 0, 1, 0]);
+        assert!(outcome.leftover.is_empty());
+    }
+
+    #[test]
+    fn unmatched_policy_applies_after_rules() {
+        let links = links();
+        let verify = |rules: &[SupplyChainRule], policy| {
+            verify_item_rules(
+                "build",
+                ArtifactType::Materials,
+                rules,
+                &links,
+                &[],
+                &VerifyOptions::new().unmatched_artifacts(policy),
+                &mut Vec::new(),
+            )
+        };
+        let partial = rules(&[&["ALLOW", "*.py"], &["DELETE", "old.txt"]]);
+
+        let outcome = verify(&partial, UnmatchedPolicy::Allow).unwrap();
+        let leftover = outcome
+            .leftover
+            .iter()
+            .map(|path| path.value())
+            .collect::<Vec<_>>();
+        assert_eq!(leftover, vec!["README", "config"]);
+
+        match verify(&partial, UnmatchedPolicy::Disallow) {
+            Err(Error::VerificationFailure(msg)) => assert!(msg.contains("README"), "{}", msg),
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        // The policy behaves like a trailing `DISALLOW *`.
+        let mut explicit = partial.clone();
+        explicit.push(SupplyChainRule::Disallow("*".into()));
+        assert!(verify(&explicit, UnmatchedPolicy::Allow).is_err());
+
+        // Nothing is left over once a rule consumes everything.
+        let complete = rules(&[&["ALLOW", "*"]]);
+        assert!(verify(&complete, UnmatchedPolicy::Disallow).is_ok());
+    }
+
+    /// `SKIP <pattern>`: consume the artifacts matching the pattern.
+    struct Skip(String);
+
+    impl CustomRule for Skip {
+        fn evaluate(&self, queue: &mut ArtifactQueue, _links: &LinkMap) -> Result<()> {
+            let matched = queue
+                .filter(&self.0)
+                .into_iter()
+                .cloned()
+                .collect::<Vec<_>>();
+            queue.consume(&matched);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn custom_rules_apply_in_rule_order() {
+        let links = links();
+        let rules = rules(&[
+            &["ALLOW", "README"],
+            &["skip", "*.py"],
+            &["DISALLOW", "foo.py"],
+            &["ALLOW", "*"],
+        ]);
+        let verify = |options: &VerifyOptions| {
+            verify_item_rules(
+                "build",
+                ArtifactType::Products,
+                &rules,
+                &links,
+                &[],
+                options,
+                &mut Vec::new(),
+            )
+        };
+        assert_eq!(
+            verify(&VerifyOptions::new()),
+            Err(Error::UnknownRule("SKIP".into()))
+        );
+
+        let registry = CustomRuleRegistry::new().register("SKIP", |args: &[String]| match args {
+            [pattern] => Ok(Box::new(Skip(pattern.clone())) as Box<dyn CustomRule>),
+            _ => Err(Error::IllegalArgument("SKIP takes a pattern".into())),
+        });
+        let outcome = verify(&VerifyOptions::new().custom_rules(registry)).unwrap();
+        let consumed = outcome
+            .rules
+            .iter()
+            .map(|rule| {
+                rule.consumed
+                    .iter()
+                    .map(|path| path.value())
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            consumed,
+            vec![
+                vec!["README"],
+                vec!["foo.py"],
+                vec![],
+                vec!["config", "out/foo.bin"]
+            ]
+        );
+    }
+}
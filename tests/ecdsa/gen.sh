@@ -0,0 +1,51 @@
+#!/bin/bash
+set -eux
+
+cd "$(dirname "$0")"
+
+key="ecdsa-p256"
+pk8="$key.pk8.der"
+spki="$key.spki.der"
+key="$key.der"
+
+if [ ! -f "$key" ]; then
+    openssl genpkey -algorithm EC \
+                    -pkeyopt ec_paramgen_curve:P-256 \
+                    -pkeyopt ec_param_enc:named_curve \
+                    -outform der \
+                    -out "$key"
+fi
+
+openssl pkcs8 -topk8 \
+              -inform der \
+              -in "$key" \
+              -outform der \
+              -out "$pk8" \
+              -nocrypt
+
+openssl pkcs8 -topk8 \
+              -inform der \
+              -in "$key" \
+              -outform pem \
+              -out "${pk8%.der}.pem" \
+              -nocrypt
+
+openssl pkey -in "$pk8" \
+             -inform der \
+             -pubout \
+             -outform der \
+             -out "$spki"
+
+openssl pkey -in "$pk8" \
+             -inform der \
+             -pubout \
+             -outform pem \
+             -out "${spki%.der}.pem"
+
+# A signature made by another implementation, over a message without a trailing newline.
+printf 'in-toto' > message
+openssl dgst -sha256 \
+             -keyform der \
+             -sign "$pk8" \
+             -out "message.${pk8%.pk8.der}.sig" \
+             message
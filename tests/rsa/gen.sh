@@ -36,4 +36,25 @@ for key_size in 2048 4096; do
                   -outform der \
                   -out "$pk8" \
                   -nocrypt
+
+    openssl pkcs8 -topk8 \
+                  -inform der \
+                  -in "$key" \
+                  -outform pem \
+                  -out "${pk8%.der}.pem" \
+                  -nocrypt
+
+    openssl rsa -in "$key" \
+                -inform der \
+                -pubout \
+                -outform pem \
+                -out "${spki%.der}.pem"
+
+    openssl pkcs8 -topk8 \
+                  -inform der \
+                  -in "$key" \
+                  -v2 aes-256-cbc \
+                  -passout pass:in-toto \
+                  -outform pem \
+                  -out "${pk8%.der}.enc.pem"
 done
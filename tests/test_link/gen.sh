@@ -0,0 +1,22 @@
+#!/bin/bash
+set -eux
+
+cd "$(dirname "$0")"
+
+# A link in the format written by the reference implementation for the `package` step of its
+# demo, signed over its canonical JSON payload.
+key="../ed25519/ed25519-1.pk8.der"
+keyid="a9f3ebc9b138762563a9c27b6edd439959e559709babd123e8d449ba2c18c61a"
+
+printf '%s' '{"_type":"link","byproducts":{"return-value":0,"stderr":"","stdout":""},"command":["tar","--exclude",".git","-zcvf","demo-project.tar.gz","demo-project"],"environment":{},"materials":{"demo-project/foo.py":{"sha256":"2a0ffef5e9709e6164c629e8b31bae0d3a3f1ded9e1b5f6a5ff0e1dc8d5e1f23"}},"name":"package","products":{"demo-project.tar.gz":{"sha256":"78a2d9b0e4fd5ba1dc83bc5c8bae5e32e4b4cfb5b73e24b0eecc3e85f24a6ec2"}}}' > payload
+openssl pkeyutl -sign \
+                -rawin \
+                -keyform der \
+                -inkey "$key" \
+                -in payload \
+                -out payload.sig
+
+printf '{"signatures":[{"keyid":"%s","sig":"%s"}],"signed":%s}\n' \
+       "$keyid" "$(xxd -p -c 256 payload.sig)" "$(cat payload)" > "package.${keyid:0:8}.link"
+
+rm payload payload.sig